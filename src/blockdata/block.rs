@@ -20,16 +20,19 @@
 //! these blocks and the blockchain.
 //!
 
+use std::{error, fmt};
+use std::collections::HashSet;
+
 use hashes::{sha256d, Hash};
 
 use util;
 use util::Error::{BlockBadTarget, BlockBadProofOfWork};
 use util::hash::{BitcoinHash, MerkleRoot, bitcoin_merkle_root};
 use util::uint::Uint256;
-use consensus::encode::Encodable;
+use consensus::encode::{Encodable, VarInt};
 use network::constants::Network;
 use blockdata::transaction::Transaction;
-use blockdata::constants::max_target;
+use blockdata::constants::{max_money, max_target, MAX_BLOCK_WEIGHT, MAX_BLOCK_SIGOPS_COST, WITNESS_SCALE_FACTOR};
 use hashes::HashEngine;
 
 /// A block header, which contains all the block's information except
@@ -110,6 +113,229 @@ impl Block {
     }
 }
 
+/// A reason for a block or one of its transactions to fail context-free
+/// validation. Transactions are identified by their index in the block.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ValidationError {
+    /// The block has no transactions
+    NoTransactions,
+    /// The block's size without witness data, or its weight, exceeds the
+    /// maximum block weight
+    BlockTooLarge,
+    /// The header's merkle root does not match the transactions
+    BadMerkleRoot,
+    /// The transaction list contains duplicates which leave the merkle root
+    /// unchanged (CVE-2012-2459)
+    MutatedMerkleTree,
+    /// The first transaction is not a coinbase
+    FirstNotCoinbase,
+    /// A transaction other than the first is a coinbase
+    ExtraCoinbase(usize),
+    /// The coinbase script_sig is not 2 to 100 bytes
+    BadCoinbaseLength,
+    /// A transaction has no inputs
+    NoInputs(usize),
+    /// A transaction has no outputs
+    NoOutputs(usize),
+    /// A transaction's size without witness data exceeds the maximum block weight
+    TransactionTooLarge(usize),
+    /// A transaction output's value exceeds the maximum money supply
+    OutputValueTooLarge(usize),
+    /// The sum of a transaction's output values exceeds the maximum money supply
+    TotalOutputValueTooLarge(usize),
+    /// A transaction spends the same outpoint twice
+    DuplicateInput(usize),
+    /// A non-coinbase transaction spends a null outpoint
+    NullPrevout(usize),
+    /// The legacy signature operation count of the block is too high
+    TooManySigops,
+    /// The block has witness data but the coinbase does not commit to it
+    BadWitnessCommitment,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationError::ExtraCoinbase(i) |
+            ValidationError::NoInputs(i) |
+            ValidationError::NoOutputs(i) |
+            ValidationError::TransactionTooLarge(i) |
+            ValidationError::OutputValueTooLarge(i) |
+            ValidationError::TotalOutputValueTooLarge(i) |
+            ValidationError::DuplicateInput(i) |
+            ValidationError::NullPrevout(i) => write!(f, "transaction {}: {}", i, error::Error::description(self)),
+            _ => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for ValidationError {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            ValidationError::NoTransactions => "block has no transactions",
+            ValidationError::BlockTooLarge => "block size or weight too large",
+            ValidationError::BadMerkleRoot => "merkle root mismatch",
+            ValidationError::MutatedMerkleTree => "duplicate transactions in merkle tree",
+            ValidationError::FirstNotCoinbase => "first transaction is not a coinbase",
+            ValidationError::ExtraCoinbase(..) => "more than one coinbase",
+            ValidationError::BadCoinbaseLength => "coinbase script_sig length out of range",
+            ValidationError::NoInputs(..) => "transaction has no inputs",
+            ValidationError::NoOutputs(..) => "transaction has no outputs",
+            ValidationError::TransactionTooLarge(..) => "transaction size too large",
+            ValidationError::OutputValueTooLarge(..) => "output value too large",
+            ValidationError::TotalOutputValueTooLarge(..) => "total output value too large",
+            ValidationError::DuplicateInput(..) => "duplicate transaction input",
+            ValidationError::NullPrevout(..) => "non-coinbase transaction spends null outpoint",
+            ValidationError::TooManySigops => "too many signature operations",
+            ValidationError::BadWitnessCommitment => "witness commitment mismatch",
+        }
+    }
+}
+
+impl Block {
+    /// Checks everything about the block that does not depend on the chain
+    /// it is in, mirroring Bitcoin Core's `CheckBlock` and `CheckTransaction`,
+    /// plus the block weight and witness commitment. The proof of work is not
+    /// checked, see [BlockHeader::validate_pow](struct.BlockHeader.html#method.validate_pow).
+    pub fn validate(&self, network: Network) -> Result<(), ValidationError> {
+        let txids: Vec<sha256d::Hash> = self.txdata.iter().map(|tx| tx.txid()).collect();
+        if self.header.merkle_root != bitcoin_merkle_root(txids.clone()) {
+            return Err(ValidationError::BadMerkleRoot);
+        }
+        if merkle_tree_mutated(txids) {
+            return Err(ValidationError::MutatedMerkleTree);
+        }
+
+        if self.txdata.is_empty() {
+            return Err(ValidationError::NoTransactions);
+        }
+        let max_txs = (MAX_BLOCK_WEIGHT / WITNESS_SCALE_FACTOR) as usize;
+        if self.txdata.len() > max_txs {
+            return Err(ValidationError::BlockTooLarge);
+        }
+        // 80 bytes of header and the transaction count
+        let header_size = 80 + VarInt(self.txdata.len() as u64).len();
+        let mut stripped_size = header_size;
+        let mut weight = header_size * WITNESS_SCALE_FACTOR as usize;
+        for tx in &self.txdata {
+            stripped_size += stripped_size_of(tx);
+            weight += tx.get_weight();
+        }
+        if stripped_size * WITNESS_SCALE_FACTOR as usize > MAX_BLOCK_WEIGHT as usize ||
+           weight > MAX_BLOCK_WEIGHT as usize {
+            return Err(ValidationError::BlockTooLarge);
+        }
+
+        if !self.txdata[0].is_coin_base() {
+            return Err(ValidationError::FirstNotCoinbase);
+        }
+        if let Some(i) = self.txdata.iter().skip(1).position(|tx| tx.is_coin_base()) {
+            return Err(ValidationError::ExtraCoinbase(i + 1));
+        }
+
+        let mut sigops = 0;
+        for (i, tx) in self.txdata.iter().enumerate() {
+            check_transaction(i, tx, network)?;
+            sigops += tx.input.iter().map(|txin| txin.script_sig.legacy_sigop_count()).sum::<usize>();
+            sigops += tx.output.iter().map(|txout| txout.script_pubkey.legacy_sigop_count()).sum::<usize>();
+        }
+        if sigops * WITNESS_SCALE_FACTOR as usize > MAX_BLOCK_SIGOPS_COST as usize {
+            return Err(ValidationError::TooManySigops);
+        }
+
+        if !self.check_witness_commitment() {
+            return Err(ValidationError::BadWitnessCommitment);
+        }
+        Ok(())
+    }
+}
+
+/// The transaction's serialized size without witness data, as Bitcoin Core
+/// computes it. It is summed up field by field since our serializer writes
+/// the segwit marker for transactions without inputs, which Core's
+/// `SERIALIZE_TRANSACTION_NO_WITNESS` never does.
+fn stripped_size_of(tx: &Transaction) -> usize {
+    let inputs: usize = tx.input.iter().map(|input| {
+        // outpoint, scriptSig and sequence
+        36 + VarInt(input.script_sig.len() as u64).len() + input.script_sig.len() + 4
+    }).sum();
+    let outputs: usize = tx.output.iter().map(|output| {
+        8 + VarInt(output.script_pubkey.len() as u64).len() + output.script_pubkey.len()
+    }).sum();
+    // version and lock time
+    4 + VarInt(tx.input.len() as u64).len() + inputs +
+        VarInt(tx.output.len() as u64).len() + outputs + 4
+}
+
+/// Checks that an element of the merkle tree is not the hash of two equal
+/// children, which would let a block with a duplicated run of transactions
+/// share the merkle root of the original block
+fn merkle_tree_mutated(mut hashes: Vec<sha256d::Hash>) -> bool {
+    while hashes.len() > 1 {
+        if hashes.chunks(2).any(|pair| pair.len() == 2 && pair[0] == pair[1]) {
+            return true;
+        }
+        // An odd element out is paired with itself, which is not a mutation
+        if hashes.len() % 2 == 1 {
+            let last = hashes[hashes.len() - 1];
+            hashes.push(last);
+        }
+        hashes = hashes.chunks(2).map(|pair| {
+            let mut encoder = sha256d::Hash::engine();
+            pair[0].consensus_encode(&mut encoder).unwrap();
+            pair[1].consensus_encode(&mut encoder).unwrap();
+            sha256d::Hash::from_engine(encoder)
+        }).collect();
+    }
+    false
+}
+
+/// Bitcoin Core's `CheckTransaction`, for the transaction at `index`
+fn check_transaction(index: usize, tx: &Transaction, network: Network) -> Result<(), ValidationError> {
+    if tx.input.is_empty() {
+        return Err(ValidationError::NoInputs(index));
+    }
+    if tx.output.is_empty() {
+        return Err(ValidationError::NoOutputs(index));
+    }
+    if stripped_size_of(tx) * WITNESS_SCALE_FACTOR as usize > MAX_BLOCK_WEIGHT as usize {
+        return Err(ValidationError::TransactionTooLarge(index));
+    }
+
+    let max_money = max_money(network);
+    let mut total = 0u64;
+    for txout in &tx.output {
+        if txout.value > max_money {
+            return Err(ValidationError::OutputValueTooLarge(index));
+        }
+        total += txout.value;
+        if total > max_money {
+            return Err(ValidationError::TotalOutputValueTooLarge(index));
+        }
+    }
+
+    let mut spent = HashSet::with_capacity(tx.input.len());
+    for txin in &tx.input {
+        if !spent.insert(txin.previous_output) {
+            return Err(ValidationError::DuplicateInput(index));
+        }
+    }
+
+    if tx.is_coin_base() {
+        let len = tx.input[0].script_sig.len();
+        if len < 2 || len > 100 {
+            return Err(ValidationError::BadCoinbaseLength);
+        }
+    } else if tx.input.iter().any(|txin| txin.previous_output.is_null()) {
+        return Err(ValidationError::NullPrevout(index));
+    }
+    Ok(())
+}
+
 impl MerkleRoot for Block {
     fn merkle_root(&self) -> sha256d::Hash {
         bitcoin_merkle_root(self.txdata.iter().map(|obj| obj.txid()).collect())
@@ -212,8 +438,13 @@ serde_struct_impl!(Block, header, txdata);
 mod tests {
     use hex::decode as hex_decode;
 
-    use blockdata::block::{Block, BlockHeader};
+    use blockdata::block::{stripped_size_of, Block, BlockHeader, ValidationError};
+    use blockdata::constants::{genesis_block, max_money};
+    use blockdata::script::Script;
+    use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
     use consensus::encode::{deserialize, serialize};
+    use hashes::{sha256d, Hash};
+    use network::constants::Network;
     use util::hash::MerkleRoot;
 
     #[test]
@@ -241,6 +472,7 @@ mod tests {
 
         // should be also ok for a non-witness block as commitment is optional in that case
         assert!(real_decode.check_witness_commitment());
+        assert_eq!(real_decode.validate(Network::Bitcoin), Ok(()));
 
         assert_eq!(serialize(&real_decode), some_block);
     }
@@ -267,6 +499,7 @@ mod tests {
         // [test] TODO: check the transaction data
 
         assert!(real_decode.check_witness_commitment());
+        assert_eq!(real_decode.validate(Network::Testnet), Ok(()));
 
         assert_eq!(serialize(&real_decode), segwit_block);
     }
@@ -279,5 +512,136 @@ mod tests {
 
         assert_eq!(header.bits, BlockHeader::compact_target_from_u256(&header.target()));
     }
-}
 
+    #[test]
+    fn stripped_size_test() {
+        let mut tx = genesis_block(Network::Bitcoin).txdata[0].clone();
+        assert_eq!(stripped_size_of(&tx), serialize(&tx).len());
+        tx.input[0].witness = vec![vec![0; 32]];
+        assert_eq!(stripped_size_of(&tx), serialize(&tx).len() - 2 - 1 - 1 - 32);
+        assert_eq!(stripped_size_of(&tx) * 3 + serialize(&tx).len(), tx.get_weight());
+
+        // without inputs, the serializer writes the segwit marker and flag
+        // which do not count towards the stripped size
+        tx.input.clear();
+        let outputs = serialize(&tx.output).len();
+        assert_eq!(stripped_size_of(&tx), 4 + 1 + outputs + 4);
+        assert_eq!(serialize(&tx).len(), 4 + 2 + 1 + outputs + 4);
+    }
+
+    #[test]
+    fn validate_test() {
+        fn spend(n: u8) -> Transaction {
+            Transaction {
+                version: 1,
+                lock_time: 0,
+                input: vec![TxIn {
+                    previous_output: OutPoint::new(sha256d::Hash::hash(&[n]), 0),
+                    script_sig: Script::new(),
+                    sequence: 0xffffffff,
+                    witness: vec![],
+                }],
+                output: vec![TxOut {
+                    value: 1000,
+                    script_pubkey: Script::new(),
+                }],
+            }
+        }
+        // Adds the transactions to the genesis block and applies `f` before
+        // fixing up the merkle root
+        fn validate<F: Fn(&mut Block)>(n: u8, f: F) -> Result<(), ValidationError> {
+            let mut block = genesis_block(Network::Bitcoin);
+            block.txdata.extend((0..n).map(spend));
+            f(&mut block);
+            block.header.merkle_root = block.merkle_root();
+            block.validate(Network::Bitcoin)
+        }
+
+        assert_eq!(genesis_block(Network::Bitcoin).validate(Network::Bitcoin), Ok(()));
+        assert_eq!(validate(4, |_| {}), Ok(()));
+
+        // The header must commit to the transactions
+        let mut block = genesis_block(Network::Bitcoin);
+        block.txdata.push(spend(0));
+        assert_eq!(block.validate(Network::Bitcoin), Err(ValidationError::BadMerkleRoot));
+
+        // Repeating the last transactions of an odd sized level keeps the merkle root
+        let mut block = genesis_block(Network::Bitcoin);
+        block.txdata.extend((0..2).map(spend));
+        block.header.merkle_root = block.merkle_root();
+        block.txdata.push(spend(1));
+        assert_eq!(block.header.merkle_root, block.merkle_root());
+        assert_eq!(block.validate(Network::Bitcoin), Err(ValidationError::MutatedMerkleTree));
+        let mut block = genesis_block(Network::Bitcoin);
+        block.txdata.extend((0..5).map(spend));
+        block.header.merkle_root = block.merkle_root();
+        block.txdata.extend((3..5).map(spend));
+        assert_eq!(block.header.merkle_root, block.merkle_root());
+        assert_eq!(block.validate(Network::Bitcoin), Err(ValidationError::MutatedMerkleTree));
+
+        assert_eq!(validate(0, |b| b.txdata.clear()), Err(ValidationError::NoTransactions));
+        assert_eq!(
+            validate(1, |b| b.txdata[1].output[0].script_pubkey = Script::from(vec![0; 1_000_000])),
+            Err(ValidationError::BlockTooLarge)
+        );
+        assert_eq!(
+            validate(1, |b| b.txdata[1].input[0].witness = vec![vec![0; 4_000_000]]),
+            Err(ValidationError::BlockTooLarge)
+        );
+
+        assert_eq!(validate(2, |b| { b.txdata.remove(0); }), Err(ValidationError::FirstNotCoinbase));
+        assert_eq!(
+            validate(2, |b| { let coinbase = b.txdata[0].clone(); b.txdata.push(coinbase); }),
+            Err(ValidationError::ExtraCoinbase(3))
+        );
+        assert_eq!(
+            validate(0, |b| b.txdata[0].input[0].script_sig = Script::from(vec![0])),
+            Err(ValidationError::BadCoinbaseLength)
+        );
+        assert_eq!(
+            validate(0, |b| b.txdata[0].input[0].script_sig = Script::from(vec![0; 101])),
+            Err(ValidationError::BadCoinbaseLength)
+        );
+
+        assert_eq!(validate(2, |b| b.txdata[2].input.clear()), Err(ValidationError::NoInputs(2)));
+        assert_eq!(validate(2, |b| b.txdata[1].output.clear()), Err(ValidationError::NoOutputs(1)));
+        assert_eq!(
+            validate(1, |b| b.txdata[1].output[0].value = max_money(Network::Bitcoin) + 1),
+            Err(ValidationError::OutputValueTooLarge(1))
+        );
+        assert_eq!(
+            validate(1, |b| {
+                let mut txout = b.txdata[1].output[0].clone();
+                txout.value = max_money(Network::Bitcoin);
+                b.txdata[1].output.push(txout);
+            }),
+            Err(ValidationError::TotalOutputValueTooLarge(1))
+        );
+        assert_eq!(
+            validate(1, |b| { let txin = b.txdata[1].input[0].clone(); b.txdata[1].input.push(txin); }),
+            Err(ValidationError::DuplicateInput(1))
+        );
+        assert_eq!(
+            validate(1, |b| { let txin = b.txdata[0].input[0].clone(); b.txdata[1].input.push(txin); }),
+            Err(ValidationError::NullPrevout(1))
+        );
+
+        // 20000 legacy sigops are allowed, counting both inputs and outputs
+        assert_eq!(
+            validate(2, |b| b.txdata[1].output[0].script_pubkey = Script::from(vec![0xac; 19_999])),
+            Ok(())
+        );
+        assert_eq!(
+            validate(2, |b| {
+                b.txdata[1].output[0].script_pubkey = Script::from(vec![0xac; 19_999]);
+                b.txdata[2].input[0].script_sig = Script::from(vec![0xac; 2]);
+            }),
+            Err(ValidationError::TooManySigops)
+        );
+
+        assert_eq!(
+            validate(1, |b| b.txdata[1].input[0].witness = vec![vec![1]]),
+            Err(ValidationError::BadWitnessCommitment)
+        );
+    }
+}
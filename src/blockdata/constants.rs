@@ -43,6 +43,12 @@ pub const DIFFCHANGE_TIMESPAN: u32 = 14 * 24 * 3600;
 pub const MAX_BLOCK_WEIGHT: u32 = 4_000_000;
 /// The minimum transaction weight for a valid serialized transaction
 pub const MIN_TRANSACTION_WEIGHT: u32 = 4 * 60;
+/// The factor by which non-witness data counts more than witness data towards
+/// the block weight, see BIP 141
+pub const WITNESS_SCALE_FACTOR: u32 = 4;
+/// The maximum allowed signature operation cost for a block, see BIP 141
+/// (network rule)
+pub const MAX_BLOCK_SIGOPS_COST: u32 = 80_000;
 
 
 /// In Bitcoind this is insanely described as ~((u256)0 >> 32)
@@ -124,6 +130,20 @@ pub fn genesis_block(network: Network) -> Block {
                 txdata: txdata
             }
         }
+        Network::Signet => {
+            let txdata = vec![bitcoin_genesis_tx()];
+            Block {
+                header: BlockHeader {
+                    version: 1,
+                    prev_blockhash: Default::default(),
+                    merkle_root: txdata[0].txid(),
+                    time: 1598918400,
+                    bits: 0x1e0377ae,
+                    nonce: 52613770
+                },
+                txdata: txdata
+            }
+        }
         Network::Regtest => {
             let txdata = vec![bitcoin_genesis_tx()];
             Block {
@@ -202,5 +222,18 @@ mod test {
         assert_eq!(format!("{:x}", gen.header.bitcoin_hash()),
                    "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943".to_string());
     }
-}
 
+    #[test]
+    fn signet_genesis_full_block() {
+        let gen = genesis_block(Network::Signet);
+        assert_eq!(gen.header.version, 1);
+        assert_eq!(gen.header.prev_blockhash, Default::default());
+        assert_eq!(format!("{:x}", gen.header.merkle_root),
+                  "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b".to_string());
+        assert_eq!(gen.header.time, 1598918400);
+        assert_eq!(gen.header.bits, 0x1e0377ae);
+        assert_eq!(gen.header.nonce, 52613770);
+        assert_eq!(format!("{:x}", gen.header.bitcoin_hash()),
+                   "00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6".to_string());
+    }
+}
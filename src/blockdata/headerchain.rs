@@ -0,0 +1,654 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Header Chain
+//!
+//! Tracks a tree of block headers rooted at the genesis block, checking the
+//! proof of work and timestamp of each header against the consensus rules,
+//! and follows the chain with the most cumulative work. Storage is pluggable
+//! through the `HeaderStore` trait; `MemoryHeaderStore` keeps everything in
+//! memory.
+//!
+
+use std::{error, fmt};
+use std::collections::HashMap;
+
+use hashes::sha256d;
+
+use blockdata::block::BlockHeader;
+use blockdata::constants::genesis_block;
+use consensus::params::Params;
+use network::chainparams::ChainParams;
+use util::hash::BitcoinHash;
+use util::uint::Uint256;
+
+/// How far in the future of the current time a header's timestamp may be
+pub const MAX_FUTURE_BLOCK_TIME: u32 = 2 * 60 * 60;
+
+/// Number of previous headers whose median timestamp a new header must exceed
+pub const MEDIAN_TIME_SPAN: usize = 11;
+
+/// A header accepted into the chain
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct StoredHeader {
+    /// The header
+    pub header: BlockHeader,
+    /// Its height, the genesis block being at height 0
+    pub height: u32,
+    /// Total work of the chain up to and including this header
+    pub chain_work: Uint256,
+}
+
+/// Storage for the header tree and the chain with the most work
+pub trait HeaderStore {
+    /// Look up a header by its block hash.
+    fn get(&self, hash: &sha256d::Hash) -> Option<StoredHeader>;
+
+    /// Store a header under its block hash.
+    fn insert(&mut self, hash: sha256d::Hash, header: StoredHeader);
+
+    /// The hash of the header at `height` in the best chain.
+    fn best_hash_at(&self, height: u32) -> Option<sha256d::Hash>;
+
+    /// The height of the tip of the best chain, or `None` if the store is
+    /// empty.
+    fn best_height(&self) -> Option<u32>;
+
+    /// Replace the best chain from `height` upwards by `hashes`, dropping
+    /// any higher entries.
+    fn set_best_chain(&mut self, height: u32, hashes: &[sha256d::Hash]);
+}
+
+/// A `HeaderStore` keeping everything in memory
+#[derive(Clone, Default, Debug)]
+pub struct MemoryHeaderStore {
+    headers: HashMap<sha256d::Hash, StoredHeader>,
+    best_chain: Vec<sha256d::Hash>,
+}
+
+impl MemoryHeaderStore {
+    /// Create an empty store
+    pub fn new() -> MemoryHeaderStore {
+        Default::default()
+    }
+}
+
+impl HeaderStore for MemoryHeaderStore {
+    fn get(&self, hash: &sha256d::Hash) -> Option<StoredHeader> {
+        self.headers.get(hash).cloned()
+    }
+
+    fn insert(&mut self, hash: sha256d::Hash, header: StoredHeader) {
+        self.headers.insert(hash, header);
+    }
+
+    fn best_hash_at(&self, height: u32) -> Option<sha256d::Hash> {
+        self.best_chain.get(height as usize).cloned()
+    }
+
+    fn best_height(&self) -> Option<u32> {
+        if self.best_chain.is_empty() {
+            None
+        } else {
+            Some(self.best_chain.len() as u32 - 1)
+        }
+    }
+
+    fn set_best_chain(&mut self, height: u32, hashes: &[sha256d::Hash]) {
+        self.best_chain.truncate(height as usize);
+        self.best_chain.extend_from_slice(hashes);
+    }
+}
+
+/// How the chain changed after adding a header
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ChainUpdate {
+    /// The header was already known
+    AlreadyKnown,
+    /// The header was stored on a chain with no more work than the best chain
+    SideChain,
+    /// The header extended the best chain
+    Extended,
+    /// The header made a different chain the best chain
+    Reorg {
+        /// Hashes of the headers leaving the best chain, from the old tip down
+        disconnected: Vec<sha256d::Hash>,
+        /// Hashes of the headers joining the best chain, from the fork point up
+        connected: Vec<sha256d::Hash>,
+    },
+}
+
+/// Reasons for a header to be rejected
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The header's previous block is not in the chain
+    UnknownPrevious(sha256d::Hash),
+    /// The header's `bits` are not the required difficulty
+    BadTarget {
+        /// The difficulty required at the header's height
+        required: u32,
+        /// The header's difficulty
+        actual: u32,
+    },
+    /// The header's hash is above its target
+    BadProofOfWork,
+    /// The header's timestamp is not after the median time past
+    TimeTooOld,
+    /// The header's timestamp is too far in the future
+    TimeTooNew,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnknownPrevious(ref hash) => write!(f, "{}: {}", error::Error::description(self), hash),
+            Error::BadTarget { required, actual } => write!(f, "{}: required {:#010x}, got {:#010x}", error::Error::description(self), required, actual),
+            Error::BadProofOfWork | Error::TimeTooOld | Error::TimeTooNew => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::UnknownPrevious(..) => "previous block unknown",
+            Error::BadTarget { .. } => "incorrect difficulty bits",
+            Error::BadProofOfWork => "proof of work below target",
+            Error::TimeTooOld => "timestamp not after median time past",
+            Error::TimeTooNew => "timestamp too far in the future",
+        }
+    }
+}
+
+/// A tree of validated headers with a best chain, kept in a `HeaderStore`
+#[derive(Debug)]
+pub struct HeaderChain<S: HeaderStore> {
+    params: Params,
+    store: S,
+}
+
+impl<S: HeaderStore> HeaderChain<S> {
+    /// Create a header chain for the network of `params`. An empty store is
+    /// initialized with the network's genesis header, otherwise the store
+    /// must have been used by a chain with the same parameters before.
+    pub fn new(params: Params, store: S) -> HeaderChain<S> {
+        let genesis = genesis_block(params.network).header;
+        HeaderChain::with_genesis(params, genesis, store)
+    }
+
+    /// Create a header chain for the chain described by `params`, which may
+    /// have its own genesis block.
+    pub fn with_chain_params(params: &ChainParams, store: S) -> HeaderChain<S> {
+        HeaderChain::with_genesis(params.consensus.clone(), params.genesis_block.header, store)
+    }
+
+    fn with_genesis(params: Params, genesis: BlockHeader, mut store: S) -> HeaderChain<S> {
+        if store.best_height().is_none() {
+            let hash = genesis.bitcoin_hash();
+            store.insert(hash, StoredHeader {
+                header: genesis,
+                height: 0,
+                chain_work: genesis.work(),
+            });
+            store.set_best_chain(0, &[hash]);
+        }
+        HeaderChain {
+            params: params,
+            store: store,
+        }
+    }
+
+    /// The consensus parameters of the chain.
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    /// The underlying store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Unwrap the underlying store.
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Look up a header in the tree by its block hash.
+    pub fn get(&self, hash: &sha256d::Hash) -> Option<StoredHeader> {
+        self.store.get(hash)
+    }
+
+    /// The hash of the tip of the best chain.
+    pub fn tip_hash(&self) -> sha256d::Hash {
+        let height = self.store.best_height().expect("store has genesis");
+        self.store.best_hash_at(height).expect("best chain has tip")
+    }
+
+    /// The tip of the best chain.
+    pub fn tip(&self) -> StoredHeader {
+        self.store.get(&self.tip_hash()).expect("tip is stored")
+    }
+
+    /// The header at `height` in the best chain.
+    pub fn header_at(&self, height: u32) -> Option<StoredHeader> {
+        self.store.best_hash_at(height).and_then(|hash| self.store.get(&hash))
+    }
+
+    /// Whether the header with the given hash is in the best chain.
+    pub fn is_in_best_chain(&self, hash: &sha256d::Hash) -> bool {
+        match self.store.get(hash) {
+            Some(stored) => self.store.best_hash_at(stored.height) == Some(*hash),
+            None => false,
+        }
+    }
+
+    /// The ancestor at `height` of the header with hash `hash`, which must
+    /// be in the tree.
+    fn ancestor(&self, hash: sha256d::Hash, height: u32) -> StoredHeader {
+        let mut hash = hash;
+        let mut stored = self.store.get(&hash).expect("header is stored");
+        assert!(height <= stored.height);
+        while stored.height > height {
+            // Once on the best chain, jump straight to the ancestor
+            if self.store.best_hash_at(stored.height) == Some(hash) {
+                return self.header_at(height).expect("best chain is stored");
+            }
+            hash = stored.header.prev_blockhash;
+            stored = self.store.get(&hash).expect("ancestors are stored");
+        }
+        stored
+    }
+
+    /// The median timestamp of the header with hash `hash` and the ten
+    /// headers before it.
+    pub fn median_time_past(&self, hash: &sha256d::Hash) -> Option<u32> {
+        let mut stored = self.store.get(hash)?;
+        let mut times = Vec::with_capacity(MEDIAN_TIME_SPAN);
+        loop {
+            times.push(stored.header.time);
+            if times.len() == MEDIAN_TIME_SPAN || stored.height == 0 {
+                break;
+            }
+            stored = self.store.get(&stored.header.prev_blockhash).expect("ancestors are stored");
+        }
+        times.sort();
+        Some(times[times.len() / 2])
+    }
+
+    /// The `bits` a header following the header with hash `prev` and
+    /// having timestamp `time` must have, mirroring Bitcoin Core's
+    /// `GetNextWorkRequired`.
+    pub fn required_bits(&self, prev: &sha256d::Hash, time: u32) -> Option<u32> {
+        let last = self.store.get(prev)?;
+        let pow_limit_bits = BlockHeader::compact_target_from_u256(&self.params.pow_limit);
+        let interval = self.params.difficulty_adjustment_interval() as u32;
+
+        if (last.height + 1) % interval != 0 {
+            if self.params.allow_min_difficulty_blocks {
+                // A block more than twice the target spacing after the
+                // previous one may have minimum difficulty
+                if time as u64 > last.header.time as u64 + 2 * self.params.pow_target_spacing {
+                    return Some(pow_limit_bits);
+                }
+                // Otherwise it has the difficulty of the last block which
+                // did not use that rule
+                let mut stored = last;
+                while stored.height % interval != 0 && stored.header.bits == pow_limit_bits {
+                    stored = self.store.get(&stored.header.prev_blockhash).expect("ancestors are stored");
+                }
+                return Some(stored.header.bits);
+            }
+            return Some(last.header.bits);
+        }
+
+        if self.params.no_pow_retargeting {
+            return Some(last.header.bits);
+        }
+        let first = self.ancestor(*prev, last.height + 1 - interval);
+        Some(retarget(&self.params, &last.header, first.header.time))
+    }
+
+    /// Check a header following `prev` against the consensus rules, with
+    /// `now` the current network-adjusted time.
+    fn check_header(&self, header: &BlockHeader, prev: &sha256d::Hash, now: u32) -> Result<(), Error> {
+        let required = self.required_bits(prev, header.time).expect("previous header is stored");
+        if header.bits != required {
+            return Err(Error::BadTarget {
+                required: required,
+                actual: header.bits,
+            });
+        }
+        if header.validate_pow(&header.target()).is_err() {
+            return Err(Error::BadProofOfWork);
+        }
+        if header.time <= self.median_time_past(prev).expect("previous header is stored") {
+            return Err(Error::TimeTooOld);
+        }
+        if header.time as u64 > now as u64 + MAX_FUTURE_BLOCK_TIME as u64 {
+            return Err(Error::TimeTooNew);
+        }
+        Ok(())
+    }
+
+    /// Validate a header and add it to the tree, switching to its chain if it
+    /// has more work than the best chain. `now` is the current
+    /// network-adjusted time, as a UNIX timestamp.
+    pub fn add_header(&mut self, header: BlockHeader, now: u32) -> Result<ChainUpdate, Error> {
+        let hash = header.bitcoin_hash();
+        if self.store.get(&hash).is_some() {
+            return Ok(ChainUpdate::AlreadyKnown);
+        }
+        let prev = match self.store.get(&header.prev_blockhash) {
+            Some(prev) => prev,
+            None => return Err(Error::UnknownPrevious(header.prev_blockhash)),
+        };
+        self.check_header(&header, &header.prev_blockhash, now)?;
+
+        let stored = StoredHeader {
+            header: header,
+            height: prev.height + 1,
+            chain_work: prev.chain_work + header.work(),
+        };
+        self.store.insert(hash, stored);
+
+        let tip_hash = self.tip_hash();
+        if stored.chain_work <= self.tip().chain_work {
+            return Ok(ChainUpdate::SideChain);
+        }
+        if header.prev_blockhash == tip_hash {
+            self.store.set_best_chain(stored.height, &[hash]);
+            return Ok(ChainUpdate::Extended);
+        }
+
+        // Walk back from the new tip to the best chain
+        let mut connected = vec![hash];
+        let mut fork = prev;
+        let mut fork_hash = header.prev_blockhash;
+        while self.store.best_hash_at(fork.height) != Some(fork_hash) {
+            connected.push(fork_hash);
+            fork_hash = fork.header.prev_blockhash;
+            fork = self.store.get(&fork_hash).expect("ancestors are stored");
+        }
+        connected.reverse();
+
+        let old_height = self.store.best_height().expect("store has genesis");
+        let disconnected = (fork.height + 1..old_height + 1).rev().map(|height| {
+            self.store.best_hash_at(height).expect("best chain is stored")
+        }).collect();
+        self.store.set_best_chain(fork.height + 1, &connected);
+        Ok(ChainUpdate::Reorg {
+            disconnected: disconnected,
+            connected: connected,
+        })
+    }
+}
+
+/// The difficulty after a retarget, given the last header of the period and
+/// the timestamp of its first header, mirroring Bitcoin Core's
+/// `CalculateNextWorkRequired`.
+fn retarget(params: &Params, last: &BlockHeader, first_time: u32) -> u32 {
+    if params.no_pow_retargeting {
+        return last.bits;
+    }
+    let timespan = params.pow_target_timespan;
+    let mut actual = last.time as i64 - first_time as i64;
+    if actual < (timespan / 4) as i64 {
+        actual = (timespan / 4) as i64;
+    }
+    if actual > (timespan * 4) as i64 {
+        actual = (timespan * 4) as i64;
+    }
+
+    let target = last.target();
+    let timespan = Uint256::from_u64(timespan).unwrap();
+    // The product cannot overflow on any real network; should it with
+    // custom parameters, divide first
+    let mut new_target = if target.bits() + 32 < 256 {
+        target.mul_u32(actual as u32) / timespan
+    } else {
+        (target / timespan).mul_u32(actual as u32)
+    };
+    if new_target > params.pow_limit {
+        new_target = params.pow_limit;
+    }
+    BlockHeader::compact_target_from_u256(&new_target)
+}
+
+#[cfg(test)]
+mod tests {
+    use hashes::{sha256d, Hash};
+
+    use blockdata::block::BlockHeader;
+    use blockdata::constants::genesis_block;
+    use consensus::params::Params;
+    use network::constants::Network;
+    use util::hash::BitcoinHash;
+
+    use super::*;
+
+    fn header_with(bits: u32, time: u32) -> BlockHeader {
+        BlockHeader {
+            version: 1,
+            prev_blockhash: Default::default(),
+            merkle_root: Default::default(),
+            time: time,
+            bits: bits,
+            nonce: 0,
+        }
+    }
+
+    // Test cases from Bitcoin Core's pow_tests.cpp
+    #[test]
+    fn retarget_test() {
+        let params = Params::new(Network::Bitcoin);
+        assert_eq!(retarget(&params, &header_with(0x1d00ffff, 1262152739), 1261130161), 0x1d00d86a);
+        assert_eq!(retarget(&params, &header_with(0x1d00ffff, 1233061996), 1231006505), 0x1d00ffff);
+        assert_eq!(retarget(&params, &header_with(0x1c05a3f4, 1279297671), 1279008237), 0x1c0168fd);
+        assert_eq!(retarget(&params, &header_with(0x1c387f6f, 1269211443), 1263163443), 0x1d00e1fd);
+    }
+
+    /// Regtest parameters with retargeting every 8 blocks of two seconds
+    fn test_params(min_difficulty: bool) -> Params {
+        let mut params = Params::new(Network::Regtest);
+        params.pow_target_spacing = 2;
+        params.pow_target_timespan = 16;
+        params.no_pow_retargeting = false;
+        params.allow_min_difficulty_blocks = min_difficulty;
+        params
+    }
+
+    /// Build a valid header on top of `prev`, `delta` seconds later
+    fn mine<S: HeaderStore>(chain: &HeaderChain<S>, prev: sha256d::Hash, delta: u32, tag: u8) -> BlockHeader {
+        let time = chain.get(&prev).unwrap().header.time + delta;
+        let mut header = BlockHeader {
+            version: 1,
+            prev_blockhash: prev,
+            merkle_root: sha256d::Hash::hash(&[tag]),
+            time: time,
+            bits: chain.required_bits(&prev, time).unwrap(),
+            nonce: 0,
+        };
+        while header.validate_pow(&header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    /// Mine and add `n` headers on top of `prev`, returning their hashes
+    fn extend<S: HeaderStore>(chain: &mut HeaderChain<S>, prev: sha256d::Hash, n: usize, tag: u8) -> Vec<sha256d::Hash> {
+        let mut hashes = vec![];
+        let mut prev = prev;
+        for _ in 0..n {
+            let header = mine(chain, prev, 1, tag);
+            chain.add_header(header, header.time).unwrap();
+            prev = header.bitcoin_hash();
+            hashes.push(prev);
+        }
+        hashes
+    }
+
+    #[test]
+    fn genesis_test() {
+        let chain = HeaderChain::new(Params::new(Network::Bitcoin), MemoryHeaderStore::new());
+        let genesis = genesis_block(Network::Bitcoin).header;
+        assert_eq!(chain.tip_hash(), genesis.bitcoin_hash());
+        assert_eq!(chain.tip().height, 0);
+        assert_eq!(chain.tip().chain_work, genesis.work());
+        assert_eq!(chain.header_at(0).unwrap().header, genesis);
+        assert_eq!(chain.median_time_past(&genesis.bitcoin_hash()), Some(genesis.time));
+    }
+
+    #[test]
+    fn difficulty_test() {
+        let mut chain = HeaderChain::new(test_params(false), MemoryHeaderStore::new());
+        let genesis = chain.tip_hash();
+        let genesis_bits = chain.tip().header.bits;
+        assert_eq!(genesis_bits, BlockHeader::compact_target_from_u256(&chain.params().pow_limit));
+
+        // Slow blocks cannot lower the difficulty below the minimum
+        let mut prev = genesis;
+        for _ in 0..16 {
+            let header = mine(&chain, prev, 4, 0);
+            chain.add_header(header, header.time).unwrap();
+            prev = header.bitcoin_hash();
+            assert_eq!(header.bits, genesis_bits);
+        }
+
+        // Fast blocks raise it at the next retarget
+        prev = *extend(&mut chain, prev, 8, 0).last().unwrap();
+        assert_eq!(chain.tip().height, 24);
+        let bits = chain.tip().header.bits;
+        assert_eq!(bits, retarget(chain.params(), &chain.header_at(23).unwrap().header, chain.header_at(16).unwrap().header.time));
+        assert!(bits < genesis_bits);
+        assert_eq!(chain.required_bits(&prev, chain.tip().header.time + 100), Some(bits));
+
+        // Wrong bits and insufficient work are rejected
+        let mut header = mine(&chain, prev, 1, 0);
+        header.bits = genesis_bits;
+        assert_eq!(
+            chain.add_header(header, header.time),
+            Err(Error::BadTarget { required: bits, actual: genesis_bits })
+        );
+        let mut header = mine(&chain, prev, 1, 0);
+        while header.validate_pow(&header.target()).is_ok() {
+            header.nonce += 1;
+        }
+        assert_eq!(chain.add_header(header, header.time), Err(Error::BadProofOfWork));
+    }
+
+    #[test]
+    fn min_difficulty_test() {
+        let mut chain = HeaderChain::new(test_params(true), MemoryHeaderStore::new());
+        let genesis_bits = chain.tip().header.bits;
+        let prev = chain.tip_hash();
+        // Fast blocks to raise the difficulty
+        let retarget = *extend(&mut chain, prev, 8, 0).last().unwrap();
+        let bits = chain.get(&retarget).unwrap().header.bits;
+        assert!(bits < genesis_bits);
+
+        // A slow block may use the minimum difficulty
+        let time = chain.tip().header.time;
+        assert_eq!(chain.required_bits(&retarget, time + 4), Some(bits));
+        assert_eq!(chain.required_bits(&retarget, time + 5), Some(genesis_bits));
+        let header = mine(&chain, retarget, 5, 0);
+        assert_eq!(header.bits, genesis_bits);
+        chain.add_header(header, header.time).unwrap();
+
+        // The next one returns to the last real difficulty
+        let next = extend(&mut chain, header.bitcoin_hash(), 1, 0);
+        assert_eq!(chain.get(&next[0]).unwrap().header.bits, bits);
+    }
+
+    #[test]
+    fn time_test() {
+        let mut chain = HeaderChain::new(Params::new(Network::Regtest), MemoryHeaderStore::new());
+        let genesis = chain.tip_hash();
+        let mut prev = genesis;
+        // Timestamps 1, 2, .. 11 seconds after genesis
+        for _ in 0..11 {
+            let header = mine(&chain, prev, 1, 0);
+            chain.add_header(header, header.time).unwrap();
+            prev = header.bitcoin_hash();
+        }
+        let genesis_time = chain.get(&genesis).unwrap().header.time;
+        assert_eq!(chain.median_time_past(&prev), Some(genesis_time + 6));
+
+        // Timestamps may go back, but not to the median time past
+        let header = mine(&chain, prev, 0, 0);
+        let mut old = header;
+        old.time = genesis_time + 6;
+        while old.validate_pow(&old.target()).is_err() {
+            old.nonce += 1;
+        }
+        assert_eq!(chain.add_header(old, header.time), Err(Error::TimeTooOld));
+        old.time = genesis_time + 7;
+        while old.validate_pow(&old.target()).is_err() {
+            old.nonce += 1;
+        }
+        assert_eq!(chain.add_header(old, header.time), Ok(ChainUpdate::Extended));
+
+        let header = mine(&chain, old.bitcoin_hash(), MAX_FUTURE_BLOCK_TIME + 1, 0);
+        assert_eq!(chain.add_header(header, old.time), Err(Error::TimeTooNew));
+        assert_eq!(chain.add_header(header, old.time + 1), Ok(ChainUpdate::Extended));
+    }
+
+    #[test]
+    fn reorg_test() {
+        let mut chain = HeaderChain::new(Params::new(Network::Regtest), MemoryHeaderStore::new());
+        let genesis = chain.tip_hash();
+        let main = extend(&mut chain, genesis, 3, 0);
+        assert_eq!(chain.tip_hash(), main[2]);
+
+        let header = chain.get(&main[0]).unwrap().header;
+        assert_eq!(chain.add_header(header, header.time), Ok(ChainUpdate::AlreadyKnown));
+        let mut orphan = mine(&chain, main[2], 1, 0);
+        orphan.prev_blockhash = sha256d::Hash::hash(&[]);
+        assert_eq!(chain.add_header(orphan, orphan.time), Err(Error::UnknownPrevious(orphan.prev_blockhash)));
+
+        // A fork with as much work as the best chain does not replace it
+        let fork = extend(&mut chain, main[0], 2, 1);
+        assert_eq!(chain.tip_hash(), main[2]);
+        assert!(!chain.is_in_best_chain(&fork[1]));
+        assert_eq!(chain.get(&fork[1]).unwrap().chain_work, chain.tip().chain_work);
+
+        // One more header makes it the best chain
+        let header = mine(&chain, fork[1], 1, 1);
+        let update = chain.add_header(header, header.time).unwrap();
+        assert_eq!(update, ChainUpdate::Reorg {
+            disconnected: vec![main[2], main[1]],
+            connected: vec![fork[0], fork[1], header.bitcoin_hash()],
+        });
+        assert_eq!(chain.tip_hash(), header.bitcoin_hash());
+        assert_eq!(chain.tip().height, 4);
+        assert_eq!(chain.header_at(1).unwrap().header.bitcoin_hash(), main[0]);
+        assert_eq!(chain.header_at(2).unwrap().header.bitcoin_hash(), fork[0]);
+        assert!(chain.is_in_best_chain(&main[0]));
+        assert!(!chain.is_in_best_chain(&main[1]));
+
+        // The old chain grows on the side, then takes over again
+        let header = mine(&chain, main[2], 1, 0);
+        assert_eq!(chain.add_header(header, header.time), Ok(ChainUpdate::SideChain));
+        let more = extend(&mut chain, header.bitcoin_hash(), 1, 0);
+        assert_eq!(chain.tip_hash(), more[0]);
+        assert_eq!(chain.header_at(5).unwrap().header.bitcoin_hash(), more[0]);
+        assert_eq!(chain.header_at(2).unwrap().header.bitcoin_hash(), main[1]);
+
+        let store = chain.into_store();
+        assert_eq!(store.best_height(), Some(5));
+    }
+}
@@ -0,0 +1,2164 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Script interpreter
+//!
+//! A native implementation of the script evaluation rules, following the
+//! behaviour of Bitcoin Core's `EvalScript` and `VerifyScript`. This makes it
+//! possible to check that a transaction input correctly spends an output
+//! without linking against `libbitcoinconsensus`.
+//!
+//! The main entry point is `verify_script`, which runs a scriptSig, the
+//! scriptPubKey it spends and (if present) the witness against each other
+//! under a set of `VerifyFlags`. Signatures are checked through the
+//! `SignatureChecker` trait, which is implemented for transactions by
+//! `TransactionSignatureChecker`.
+//!
+
+use std::{error, fmt, ops};
+use std::cell::RefCell;
+
+use secp256k1::{self, Message, Secp256k1, Signature, Verification};
+
+use blockdata::opcodes;
+use blockdata::script::{self, Builder, Instruction, Script};
+use blockdata::transaction::Transaction;
+use hashes::{hash160, ripemd160, sha1, sha256, sha256d, Hash};
+use util::bip143::SigHashCache;
+
+/// Maximum number of bytes which may be pushed onto the stack at once
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+/// Maximum number of non-push operations per script
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+/// Maximum number of public keys per multisig
+pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+/// Maximum script length in bytes
+pub const MAX_SCRIPT_SIZE: usize = 10000;
+/// Maximum number of elements on the main and alt stacks combined
+pub const MAX_STACK_SIZE: usize = 1000;
+/// Lock times below this value are block heights, above it they are timestamps
+pub const LOCKTIME_THRESHOLD: u32 = 500000000;
+/// If this bit is set in an input's sequence number, BIP68 relative lock times
+/// are disabled for it
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+/// If this bit is set in a relative lock time, it is measured in units of 512
+/// seconds rather than in blocks
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+/// Mask applied to the sequence number to extract the relative lock time
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000ffff;
+
+/// A set of script verification flags, selecting which consensus and policy
+/// rules the interpreter enforces. The bit values are the same as those used
+/// by Bitcoin Core and `libbitcoinconsensus`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct VerifyFlags(u32);
+
+impl VerifyFlags {
+    /// No additional rules
+    pub const NONE: VerifyFlags = VerifyFlags(0);
+    /// Evaluate P2SH (BIP16) subscripts
+    pub const P2SH: VerifyFlags = VerifyFlags(1 << 0);
+    /// Require strict encoding of signatures and public keys
+    pub const STRICTENC: VerifyFlags = VerifyFlags(1 << 1);
+    /// Require strict DER encoding of signatures (BIP66)
+    pub const DERSIG: VerifyFlags = VerifyFlags(1 << 2);
+    /// Require signatures to have a low S value
+    pub const LOW_S: VerifyFlags = VerifyFlags(1 << 3);
+    /// Require the dummy element of CHECKMULTISIG to be empty (BIP147)
+    pub const NULLDUMMY: VerifyFlags = VerifyFlags(1 << 4);
+    /// Require the scriptSig to contain only push operations
+    pub const SIGPUSHONLY: VerifyFlags = VerifyFlags(1 << 5);
+    /// Require data pushes and numbers to be minimally encoded
+    pub const MINIMALDATA: VerifyFlags = VerifyFlags(1 << 6);
+    /// Fail on the NOP opcodes reserved for soft-forks
+    pub const DISCOURAGE_UPGRADABLE_NOPS: VerifyFlags = VerifyFlags(1 << 7);
+    /// Require exactly one element on the stack after evaluation
+    pub const CLEANSTACK: VerifyFlags = VerifyFlags(1 << 8);
+    /// Enable OP_CHECKLOCKTIMEVERIFY (BIP65)
+    pub const CHECKLOCKTIMEVERIFY: VerifyFlags = VerifyFlags(1 << 9);
+    /// Enable OP_CHECKSEQUENCEVERIFY (BIP112)
+    pub const CHECKSEQUENCEVERIFY: VerifyFlags = VerifyFlags(1 << 10);
+    /// Evaluate segregated witness programs (BIP141)
+    pub const WITNESS: VerifyFlags = VerifyFlags(1 << 11);
+    /// Fail on witness programs with versions reserved for soft-forks
+    pub const DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM: VerifyFlags = VerifyFlags(1 << 12);
+    /// Require the argument of OP_IF/OP_NOTIF in witness scripts to be minimal
+    pub const MINIMALIF: VerifyFlags = VerifyFlags(1 << 13);
+    /// Require failed signature checks to have empty signatures
+    pub const NULLFAIL: VerifyFlags = VerifyFlags(1 << 14);
+    /// Require public keys in witness scripts to be compressed
+    pub const WITNESS_PUBKEYTYPE: VerifyFlags = VerifyFlags(1 << 15);
+    /// Fail on OP_CODESEPARATOR and signatures found in the scriptCode of
+    /// non-witness scripts
+    pub const CONST_SCRIPTCODE: VerifyFlags = VerifyFlags(1 << 16);
+
+    /// The rules which every block must satisfy since segwit activation
+    pub const CONSENSUS: VerifyFlags = VerifyFlags(
+        (1 << 0) | (1 << 2) | (1 << 4) | (1 << 9) | (1 << 10) | (1 << 11)
+    );
+    /// The rules Bitcoin Core applies when deciding whether to relay a
+    /// transaction
+    pub const STANDARD: VerifyFlags = VerifyFlags(
+        (1 << 0) | (1 << 1) | (1 << 2) | (1 << 3) | (1 << 4) | (1 << 6) | (1 << 7) |
+        (1 << 8) | (1 << 9) | (1 << 10) | (1 << 11) | (1 << 12) | (1 << 13) |
+        (1 << 14) | (1 << 15) | (1 << 16)
+    );
+
+    /// Creates a set of flags from its bit representation, keeping any
+    /// unknown bits
+    pub fn from_bits(bits: u32) -> VerifyFlags {
+        VerifyFlags(bits)
+    }
+
+    /// The bit representation of these flags
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// Whether all the flags in `other` are set in `self`
+    pub fn contains(&self, other: VerifyFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl ops::BitOr for VerifyFlags {
+    type Output = VerifyFlags;
+
+    fn bitor(self, other: VerifyFlags) -> VerifyFlags {
+        VerifyFlags(self.0 | other.0)
+    }
+}
+
+impl ops::BitOrAssign for VerifyFlags {
+    fn bitor_assign(&mut self, other: VerifyFlags) {
+        self.0 |= other.0
+    }
+}
+
+/// The kind of a script verification failure. These correspond to the
+/// `ScriptError` codes of Bitcoin Core, except that failures to interpret a
+/// stack element as a number are reported separately instead of as an
+/// unknown error.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ErrorKind {
+    /// The script finished with a false or empty top stack element
+    EvalFalse,
+    /// OP_RETURN was executed
+    OpReturn,
+    /// The script is longer than `MAX_SCRIPT_SIZE`
+    ScriptSize,
+    /// A push is larger than `MAX_SCRIPT_ELEMENT_SIZE`
+    PushSize,
+    /// More than `MAX_OPS_PER_SCRIPT` operations were executed
+    OpCount,
+    /// The stacks hold more than `MAX_STACK_SIZE` elements
+    StackSize,
+    /// The signature count of a CHECKMULTISIG is out of range
+    SigCount,
+    /// The public key count of a CHECKMULTISIG is out of range
+    PubkeyCount,
+    /// OP_VERIFY failed
+    Verify,
+    /// OP_EQUALVERIFY failed
+    EqualVerify,
+    /// OP_CHECKMULTISIGVERIFY failed
+    CheckMultiSigVerify,
+    /// OP_CHECKSIGVERIFY failed
+    CheckSigVerify,
+    /// OP_NUMEQUALVERIFY failed
+    NumEqualVerify,
+    /// The script is truncated or contains an undefined opcode
+    BadOpcode,
+    /// The script contains a disabled opcode
+    DisabledOpcode,
+    /// There are too few elements on the stack for the operation
+    InvalidStackOperation,
+    /// There are too few elements on the alt stack for the operation
+    InvalidAltstackOperation,
+    /// OP_IF/OP_NOTIF/OP_ELSE/OP_ENDIF are not properly nested
+    UnbalancedConditional,
+    /// The lock time argument is negative
+    NegativeLockTime,
+    /// The lock time requirement is not satisfied by the transaction
+    UnsatisfiedLockTime,
+    /// The signature hash type is undefined
+    SigHashType,
+    /// The signature is not strictly DER encoded
+    SigDer,
+    /// A push or number is not minimally encoded
+    MinimalData,
+    /// The scriptSig contains non-push operations
+    SigPushOnly,
+    /// The signature has a high S value
+    SigHighS,
+    /// The dummy element of CHECKMULTISIG is not empty
+    SigNullDummy,
+    /// The public key is neither compressed nor uncompressed
+    PubkeyType,
+    /// The stack does not hold exactly one element after evaluation
+    CleanStack,
+    /// The argument of OP_IF/OP_NOTIF is not minimal
+    MinimalIf,
+    /// A failed signature check had a non-empty signature
+    SigNullFail,
+    /// A NOP reserved for soft-forks was executed
+    DiscourageUpgradableNops,
+    /// A witness program with a version reserved for soft-forks was spent
+    DiscourageUpgradableWitnessProgram,
+    /// A version 0 witness program has an invalid length
+    WitnessProgramWrongLength,
+    /// A witness program was spent with an empty witness
+    WitnessProgramWitnessEmpty,
+    /// The witness does not match the witness program
+    WitnessProgramMismatch,
+    /// A native witness program was spent with a non-empty scriptSig
+    WitnessMalleated,
+    /// A P2SH witness program was spent with a scriptSig other than a single
+    /// push of the redeem script
+    WitnessMalleatedP2SH,
+    /// A witness was provided for a non-witness output
+    WitnessUnexpected,
+    /// An uncompressed public key was used in a witness script
+    WitnessPubkeyType,
+    /// OP_CODESEPARATOR was used in a non-witness script
+    OpCodeSeparator,
+    /// A signature was found in the scriptCode of a non-witness script
+    SigFindAndDelete,
+    /// A stack element is too long to be interpreted as a number
+    NumericOverflow,
+    /// A stack element is not minimally encoded as a number
+    NonMinimalNumber,
+}
+
+impl ErrorKind {
+    fn description(&self) -> &'static str {
+        match *self {
+            ErrorKind::EvalFalse => "script evaluated without error but finished with a false/empty top stack element",
+            ErrorKind::OpReturn => "OP_RETURN was encountered",
+            ErrorKind::ScriptSize => "script is too big",
+            ErrorKind::PushSize => "push value size limit exceeded",
+            ErrorKind::OpCount => "operation limit exceeded",
+            ErrorKind::StackSize => "stack size limit exceeded",
+            ErrorKind::SigCount => "signature count negative or greater than pubkey count",
+            ErrorKind::PubkeyCount => "pubkey count negative or limit exceeded",
+            ErrorKind::Verify => "script failed an OP_VERIFY operation",
+            ErrorKind::EqualVerify => "script failed an OP_EQUALVERIFY operation",
+            ErrorKind::CheckMultiSigVerify => "script failed an OP_CHECKMULTISIGVERIFY operation",
+            ErrorKind::CheckSigVerify => "script failed an OP_CHECKSIGVERIFY operation",
+            ErrorKind::NumEqualVerify => "script failed an OP_NUMEQUALVERIFY operation",
+            ErrorKind::BadOpcode => "opcode missing or not understood",
+            ErrorKind::DisabledOpcode => "attempted to use a disabled opcode",
+            ErrorKind::InvalidStackOperation => "operation not valid with the current stack size",
+            ErrorKind::InvalidAltstackOperation => "operation not valid with the current altstack size",
+            ErrorKind::UnbalancedConditional => "invalid OP_IF construction",
+            ErrorKind::NegativeLockTime => "negative locktime",
+            ErrorKind::UnsatisfiedLockTime => "locktime requirement not satisfied",
+            ErrorKind::SigHashType => "signature hash type missing or not understood",
+            ErrorKind::SigDer => "non-canonical DER signature",
+            ErrorKind::MinimalData => "data push larger than necessary",
+            ErrorKind::SigPushOnly => "only push operators allowed in signatures",
+            ErrorKind::SigHighS => "non-canonical signature: S value is unnecessarily high",
+            ErrorKind::SigNullDummy => "dummy CHECKMULTISIG argument must be zero",
+            ErrorKind::PubkeyType => "public key is neither compressed or uncompressed",
+            ErrorKind::CleanStack => "extra items left on stack after execution",
+            ErrorKind::MinimalIf => "OP_IF/NOTIF argument must be minimal",
+            ErrorKind::SigNullFail => "signature must be zero for failed CHECK(MULTI)SIG operation",
+            ErrorKind::DiscourageUpgradableNops => "NOPx reserved for soft-fork upgrades",
+            ErrorKind::DiscourageUpgradableWitnessProgram => "witness version reserved for soft-fork upgrades",
+            ErrorKind::WitnessProgramWrongLength => "witness program has incorrect length",
+            ErrorKind::WitnessProgramWitnessEmpty => "witness program was passed an empty witness",
+            ErrorKind::WitnessProgramMismatch => "witness program hash mismatch",
+            ErrorKind::WitnessMalleated => "witness requires empty scriptSig",
+            ErrorKind::WitnessMalleatedP2SH => "witness requires only-redeemscript scriptSig",
+            ErrorKind::WitnessUnexpected => "witness provided for non-witness script",
+            ErrorKind::WitnessPubkeyType => "using non-compressed keys in segwit",
+            ErrorKind::OpCodeSeparator => "using OP_CODESEPARATOR in non-witness script",
+            ErrorKind::SigFindAndDelete => "signature is found in scriptCode",
+            ErrorKind::NumericOverflow => "script number overflow",
+            ErrorKind::NonMinimalNumber => "non-minimally encoded script number",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// A script verification failure
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Error {
+    /// What went wrong
+    pub kind: ErrorKind,
+    /// The opcode being executed when the failure happened together with its
+    /// byte offset in the script, if the failure is tied to a specific opcode
+    pub opcode: Option<(usize, opcodes::All)>,
+}
+
+impl Error {
+    fn new(kind: ErrorKind) -> Error {
+        Error {
+            kind: kind,
+            opcode: None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.opcode {
+            Some((pos, op)) => write!(f, "{} ({:?} at offset {})", self.kind, op, pos),
+            None => fmt::Display::fmt(&self.kind, f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        self.kind.description()
+    }
+}
+
+#[doc(hidden)]
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error::new(kind)
+    }
+}
+
+/// The signature scheme a script is being evaluated under
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum SigVersion {
+    /// Legacy scripts: scriptSig, scriptPubKey and P2SH redeem scripts
+    Base,
+    /// Version 0 witness scripts, signed using BIP143
+    WitnessV0,
+}
+
+/// Something which can check signatures and lock times on behalf of the
+/// interpreter. The default implementations reject everything, which is
+/// appropriate when evaluating scripts outside of any transaction.
+pub trait SignatureChecker {
+    /// Check a signature, with its sighash type byte appended, against a
+    /// serialized public key. `script_code` is the part of the script being
+    /// executed that is committed to by the signature.
+    fn check_sig(&self, _sig: &[u8], _pubkey: &[u8], _script_code: &Script, _sigversion: SigVersion) -> bool {
+        false
+    }
+
+    /// Check that the argument of OP_CHECKLOCKTIMEVERIFY is satisfied
+    fn check_lock_time(&self, _lock_time: i64) -> bool {
+        false
+    }
+
+    /// Check that the argument of OP_CHECKSEQUENCEVERIFY is satisfied
+    fn check_sequence(&self, _sequence: i64) -> bool {
+        false
+    }
+}
+
+/// A `SignatureChecker` which rejects every signature and lock time
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct NullSignatureChecker;
+
+impl SignatureChecker for NullSignatureChecker {}
+
+/// Checks signatures and lock times against an input of a transaction
+pub struct TransactionSignatureChecker<'a, C: Verification + 'a> {
+    secp: &'a Secp256k1<C>,
+    tx: &'a Transaction,
+    input_index: usize,
+    value: u64,
+    sighash_cache: RefCell<SigHashCache<'a>>,
+}
+
+impl<'a, C: Verification + 'a> TransactionSignatureChecker<'a, C> {
+    /// Creates a checker for input `input_index` of `tx`, which spends an
+    /// output worth `value` satoshis. Panics if the input does not exist.
+    pub fn new(secp: &'a Secp256k1<C>, tx: &'a Transaction, input_index: usize, value: u64) -> TransactionSignatureChecker<'a, C> {
+        assert!(input_index < tx.input.len());  // Panic on OOB
+        TransactionSignatureChecker {
+            secp: secp,
+            tx: tx,
+            input_index: input_index,
+            value: value,
+            sighash_cache: RefCell::new(SigHashCache::new(tx)),
+        }
+    }
+}
+
+impl<'a, C: Verification + 'a> SignatureChecker for TransactionSignatureChecker<'a, C> {
+    fn check_sig(&self, sig: &[u8], pubkey: &[u8], script_code: &Script, sigversion: SigVersion) -> bool {
+        let pubkey = match secp256k1::PublicKey::from_slice(pubkey) {
+            Ok(pk) => pk,
+            Err(_) => return false,
+        };
+        let (hash_type, der) = match sig.split_last() {
+            Some((hash_type, der)) => (*hash_type as u32, der),
+            None => return false,
+        };
+        let mut sig = match Signature::from_der_lax(der) {
+            Ok(sig) => sig,
+            Err(_) => return false,
+        };
+        // libsecp256k1 only accepts low-S signatures, but consensus allows both
+        sig.normalize_s();
+
+        let sighash = match sigversion {
+            SigVersion::Base => {
+                let script_code = remove_codeseparators(script_code);
+                self.tx.signature_hash(self.input_index, &script_code, hash_type)
+            }
+            SigVersion::WitnessV0 => {
+                self.sighash_cache.borrow_mut().raw_signature_hash(self.input_index, script_code, self.value, hash_type)
+            }
+        };
+        let msg = Message::from_slice(&sighash[..]).expect("32 bytes");
+        self.secp.verify(&msg, &sig, &pubkey).is_ok()
+    }
+
+    fn check_lock_time(&self, lock_time: i64) -> bool {
+        let tx_lock_time = self.tx.lock_time as i64;
+        let threshold = LOCKTIME_THRESHOLD as i64;
+        // Lock times must be of the same kind (height or timestamp) to be comparable
+        if (tx_lock_time < threshold) != (lock_time < threshold) {
+            return false;
+        }
+        if lock_time > tx_lock_time {
+            return false;
+        }
+        // A final input would allow the transaction to bypass its lock time
+        self.tx.input[self.input_index].sequence != 0xffffffff
+    }
+
+    fn check_sequence(&self, sequence: i64) -> bool {
+        let tx_sequence = self.tx.input[self.input_index].sequence as i64;
+        // Relative lock times are only enforced from version 2 on, comparing
+        // the version as an unsigned integer
+        if self.tx.version < 2 {
+            return false;
+        }
+        if tx_sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG as i64 != 0 {
+            return false;
+        }
+        let mask = (SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK) as i64;
+        let tx_sequence = tx_sequence & mask;
+        let sequence = sequence & mask;
+        let type_flag = SEQUENCE_LOCKTIME_TYPE_FLAG as i64;
+        if (tx_sequence < type_flag) != (sequence < type_flag) {
+            return false;
+        }
+        sequence <= tx_sequence
+    }
+}
+
+/// Decode a stack element as a number, as Bitcoin Core's `CScriptNum` does
+fn read_num(v: &[u8], require_minimal: bool, max_size: usize) -> Result<i64, ErrorKind> {
+    if v.len() > max_size {
+        return Err(ErrorKind::NumericOverflow);
+    }
+    let last = match v.last() {
+        Some(last) => *last,
+        None => return Ok(0),
+    };
+    // The most significant byte may only be zero (apart from the sign bit) if
+    // the next byte would otherwise be mistaken for the sign bit
+    if require_minimal && last & 0x7f == 0 && (v.len() == 1 || v[v.len() - 2] & 0x80 == 0) {
+        return Err(ErrorKind::NonMinimalNumber);
+    }
+    let mut ret = 0i64;
+    for (i, byte) in v.iter().enumerate() {
+        ret |= (*byte as i64) << (8 * i);
+    }
+    if last & 0x80 != 0 {
+        ret &= !(0x80i64 << (8 * (v.len() - 1)));
+        ret = -ret;
+    }
+    Ok(ret)
+}
+
+/// Whether a push of `data` using `opcode` is the smallest possible encoding
+fn is_minimal_push(opcode: opcodes::All, data: &[u8]) -> bool {
+    let opcode = opcode.into_u8();
+    if data.is_empty() {
+        opcode == opcodes::all::OP_PUSHBYTES_0.into_u8()
+    } else if data.len() == 1 && ((data[0] >= 1 && data[0] <= 16) || data[0] == 0x81) {
+        // Should have used OP_1 to OP_16 or OP_1NEGATE
+        false
+    } else if data.len() < opcodes::all::OP_PUSHDATA1.into_u8() as usize {
+        opcode as usize == data.len()
+    } else if data.len() <= 0xff {
+        opcode == opcodes::all::OP_PUSHDATA1.into_u8()
+    } else if data.len() <= 0xffff {
+        opcode == opcodes::all::OP_PUSHDATA2.into_u8()
+    } else {
+        true
+    }
+}
+
+/// Number of bytes a push opcode occupies before its data
+fn push_prefix_len(opcode: opcodes::All) -> usize {
+    match opcode {
+        opcodes::all::OP_PUSHDATA1 => 2,
+        opcodes::all::OP_PUSHDATA2 => 3,
+        opcodes::all::OP_PUSHDATA4 => 5,
+        _ => 1,
+    }
+}
+
+/// The offset just after the instruction starting at `pos`, or `None` if
+/// there is no instruction there or it runs past the end of the script
+fn instruction_end(bytes: &[u8], pos: usize) -> Option<usize> {
+    if pos >= bytes.len() {
+        return None;
+    }
+    let (header, size) = match opcodes::All::from(bytes[pos]) {
+        opcodes::all::OP_PUSHDATA1 => (2, script::read_uint(&bytes[pos + 1..], 1).ok()?),
+        opcodes::all::OP_PUSHDATA2 => (3, script::read_uint(&bytes[pos + 1..], 2).ok()?),
+        opcodes::all::OP_PUSHDATA4 => (5, script::read_uint(&bytes[pos + 1..], 4).ok()?),
+        op => match op.classify() {
+            opcodes::Class::PushBytes(n) => (1, n as usize),
+            _ => (1, 0),
+        },
+    };
+    let end = pos + header + size;
+    if end > bytes.len() {
+        None
+    } else {
+        Some(end)
+    }
+}
+
+/// Whether a script only consists of push operations (including
+/// OP_RESERVED, as Bitcoin Core does)
+fn is_push_only(script: &Script) -> bool {
+    for instruction in script.iter(false) {
+        match instruction {
+            Instruction::PushBytes(_) => {}
+            Instruction::Op(op) => if op.into_u8() > opcodes::all::OP_PUSHNUM_16.into_u8() { return false },
+            Instruction::Error(_) => return false,
+        }
+    }
+    true
+}
+
+/// Remove all OP_CODESEPARATORs from a script, as done when computing a
+/// legacy signature hash
+fn remove_codeseparators(script: &Script) -> Script {
+    let bytes = script.as_bytes();
+    let mut ret = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while let Some(end) = instruction_end(bytes, pos) {
+        if bytes[pos] != opcodes::all::OP_CODESEPARATOR.into_u8() {
+            ret.extend_from_slice(&bytes[pos..end]);
+        }
+        pos = end;
+    }
+    ret.extend_from_slice(&bytes[pos..]);
+    Script::from(ret)
+}
+
+/// Remove every occurrence of `needle` which starts at an instruction
+/// boundary, returning the number of occurrences found. This replicates
+/// Bitcoin Core's `FindAndDelete`, including its quirk of resuming parsing
+/// right after a match.
+fn find_and_delete(script: &mut Script, needle: &[u8]) -> usize {
+    if needle.is_empty() {
+        return 0;
+    }
+    let mut found = 0;
+    let mut ret = Vec::with_capacity(script.len());
+    {
+        let bytes = script.as_bytes();
+        let mut pos = 0;
+        let mut copied = 0;
+        loop {
+            ret.extend_from_slice(&bytes[copied..pos]);
+            while bytes.len() - pos >= needle.len() && &bytes[pos..pos + needle.len()] == needle {
+                pos += needle.len();
+                found += 1;
+            }
+            copied = pos;
+            match instruction_end(bytes, pos) {
+                Some(end) => pos = end,
+                None => break,
+            }
+        }
+        ret.extend_from_slice(&bytes[copied..]);
+    }
+    if found > 0 {
+        *script = Script::from(ret);
+    }
+    found
+}
+
+/// Whether a signature (with hash type byte) is strictly DER encoded, per BIP66
+fn is_valid_signature_encoding(sig: &[u8]) -> bool {
+    // Format: 0x30 [total-length] 0x02 [R-length] [R] 0x02 [S-length] [S] [sighash]
+    if sig.len() < 9 || sig.len() > 73 {
+        return false;
+    }
+    if sig[0] != 0x30 || sig[1] as usize != sig.len() - 3 {
+        return false;
+    }
+    let len_r = sig[3] as usize;
+    if 5 + len_r >= sig.len() {
+        return false;
+    }
+    let len_s = sig[5 + len_r] as usize;
+    if len_r + len_s + 7 != sig.len() {
+        return false;
+    }
+    // R must be a positive integer without excess padding
+    if sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 {
+        return false;
+    }
+    if len_r > 1 && sig[4] == 0x00 && sig[5] & 0x80 == 0 {
+        return false;
+    }
+    // Same for S
+    if sig[len_r + 4] != 0x02 || len_s == 0 || sig[len_r + 6] & 0x80 != 0 {
+        return false;
+    }
+    if len_s > 1 && sig[len_r + 6] == 0x00 && sig[len_r + 7] & 0x80 == 0 {
+        return false;
+    }
+    true
+}
+
+/// Whether a strictly encoded signature has a low S value
+fn is_low_s(sig: &[u8]) -> bool {
+    match Signature::from_der_lax(&sig[..sig.len() - 1]) {
+        Ok(sig) => {
+            let mut normalized = sig;
+            normalized.normalize_s();
+            normalized == sig
+        }
+        Err(_) => false,
+    }
+}
+
+fn check_signature_encoding(sig: &[u8], flags: VerifyFlags) -> Result<(), ErrorKind> {
+    // An empty signature is a compact way to provide an invalid signature
+    if sig.is_empty() {
+        return Ok(());
+    }
+    if (flags.contains(VerifyFlags::DERSIG) || flags.contains(VerifyFlags::LOW_S) ||
+        flags.contains(VerifyFlags::STRICTENC)) && !is_valid_signature_encoding(sig) {
+        Err(ErrorKind::SigDer)
+    } else if flags.contains(VerifyFlags::LOW_S) && !is_low_s(sig) {
+        Err(ErrorKind::SigHighS)
+    } else if flags.contains(VerifyFlags::STRICTENC) {
+        let base_type = sig[sig.len() - 1] & !0x80;
+        if base_type < 1 || base_type > 3 {
+            Err(ErrorKind::SigHashType)
+        } else {
+            Ok(())
+        }
+    } else {
+        Ok(())
+    }
+}
+
+fn check_pubkey_encoding(pubkey: &[u8], flags: VerifyFlags, sigversion: SigVersion) -> Result<(), ErrorKind> {
+    let compressed = pubkey.len() == 33 && (pubkey[0] == 0x02 || pubkey[0] == 0x03);
+    let uncompressed = pubkey.len() == 65 && pubkey[0] == 0x04;
+    if flags.contains(VerifyFlags::STRICTENC) && !compressed && !uncompressed {
+        return Err(ErrorKind::PubkeyType);
+    }
+    if flags.contains(VerifyFlags::WITNESS_PUBKEYTYPE) && sigversion == SigVersion::WitnessV0 && !compressed {
+        return Err(ErrorKind::WitnessPubkeyType);
+    }
+    Ok(())
+}
+
+/// The state of a script being executed
+struct Machine<'a, C: SignatureChecker + 'a> {
+    stack: &'a mut Vec<Vec<u8>>,
+    altstack: Vec<Vec<u8>>,
+    exec: Vec<bool>,
+    op_count: usize,
+    script: &'a Script,
+    codeseparator: usize,
+    flags: VerifyFlags,
+    checker: &'a C,
+    sigversion: SigVersion,
+}
+
+impl<'a, C: SignatureChecker + 'a> Machine<'a, C> {
+    fn require_minimal(&self) -> bool {
+        self.flags.contains(VerifyFlags::MINIMALDATA)
+    }
+
+    /// Fail unless the stack has at least `n` elements
+    fn need(&self, n: usize) -> Result<(), ErrorKind> {
+        if self.stack.len() < n {
+            Err(ErrorKind::InvalidStackOperation)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The `n`th element from the top of the stack, starting at 1
+    fn top(&self, n: usize) -> &[u8] {
+        &self.stack[self.stack.len() - n]
+    }
+
+    fn top_num(&self, n: usize) -> Result<i64, ErrorKind> {
+        read_num(self.top(n), self.require_minimal(), 4)
+    }
+
+    fn pop(&mut self) -> Vec<u8> {
+        self.stack.pop().expect("stack size checked")
+    }
+
+    fn push_num(&mut self, n: i64) {
+        self.stack.push(script::build_scriptint(n));
+    }
+
+    fn push_bool(&mut self, b: bool) {
+        self.stack.push(if b { vec![1] } else { vec![] });
+    }
+
+    fn upgradable_nop(&self) -> Result<(), ErrorKind> {
+        if self.flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS) {
+            Err(ErrorKind::DiscourageUpgradableNops)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The part of the script committed to by signatures: everything after
+    /// the last executed OP_CODESEPARATOR
+    fn script_code(&self) -> Script {
+        Script::from(self.script.as_bytes()[self.codeseparator..].to_vec())
+    }
+
+    /// Remove a signature from the script code of legacy scripts, which can't
+    /// sign themselves
+    fn delete_signature(&self, script_code: &mut Script, sig: &[u8]) -> Result<(), ErrorKind> {
+        if self.sigversion == SigVersion::Base {
+            let needle = Builder::new().push_slice(sig).into_script();
+            let found = find_and_delete(script_code, needle.as_bytes());
+            if found > 0 && self.flags.contains(VerifyFlags::CONST_SCRIPTCODE) {
+                return Err(ErrorKind::SigFindAndDelete);
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute a single non-push opcode. `pc` is the offset just after it.
+    fn step(&mut self, op: opcodes::All, exec: bool, pc: usize) -> Result<(), ErrorKind> {
+        if op.into_u8() > opcodes::all::OP_PUSHNUM_16.into_u8() {
+            self.op_count += 1;
+            if self.op_count > MAX_OPS_PER_SCRIPT {
+                return Err(ErrorKind::OpCount);
+            }
+        }
+
+        // Disabled opcodes fail even in unexecuted branches
+        match op {
+            opcodes::all::OP_CAT | opcodes::all::OP_SUBSTR | opcodes::all::OP_LEFT |
+            opcodes::all::OP_RIGHT | opcodes::all::OP_INVERT | opcodes::all::OP_AND |
+            opcodes::all::OP_OR | opcodes::all::OP_XOR | opcodes::all::OP_2MUL |
+            opcodes::all::OP_2DIV | opcodes::all::OP_MUL | opcodes::all::OP_DIV |
+            opcodes::all::OP_MOD | opcodes::all::OP_LSHIFT | opcodes::all::OP_RSHIFT => {
+                return Err(ErrorKind::DisabledOpcode);
+            }
+            opcodes::all::OP_CODESEPARATOR if self.sigversion == SigVersion::Base &&
+                self.flags.contains(VerifyFlags::CONST_SCRIPTCODE) => {
+                return Err(ErrorKind::OpCodeSeparator);
+            }
+            _ => {}
+        }
+
+        // Conditionals are the only thing processed in unexecuted branches
+        let is_conditional = op.into_u8() >= opcodes::all::OP_IF.into_u8() &&
+                             op.into_u8() <= opcodes::all::OP_ENDIF.into_u8();
+        if !exec && !is_conditional {
+            return Ok(());
+        }
+
+        if let opcodes::Class::PushNum(n) = op.classify() {
+            self.push_num(n as i64);
+            return Ok(());
+        }
+
+        match op {
+            opcodes::all::OP_NOP => {}
+            opcodes::all::OP_CLTV => {
+                if !self.flags.contains(VerifyFlags::CHECKLOCKTIMEVERIFY) {
+                    return self.upgradable_nop();
+                }
+                self.need(1)?;
+                // Lock times are 5 bytes long, as 4-byte numbers would overflow in 2038
+                let lock_time = read_num(self.top(1), self.require_minimal(), 5)?;
+                if lock_time < 0 {
+                    return Err(ErrorKind::NegativeLockTime);
+                }
+                if !self.checker.check_lock_time(lock_time) {
+                    return Err(ErrorKind::UnsatisfiedLockTime);
+                }
+            }
+            opcodes::all::OP_CSV => {
+                if !self.flags.contains(VerifyFlags::CHECKSEQUENCEVERIFY) {
+                    return self.upgradable_nop();
+                }
+                self.need(1)?;
+                let sequence = read_num(self.top(1), self.require_minimal(), 5)?;
+                if sequence < 0 {
+                    return Err(ErrorKind::NegativeLockTime);
+                }
+                // With the disable flag set, CSV behaves as a NOP
+                if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG as i64 == 0 &&
+                   !self.checker.check_sequence(sequence) {
+                    return Err(ErrorKind::UnsatisfiedLockTime);
+                }
+            }
+            opcodes::all::OP_NOP1 | opcodes::all::OP_NOP4 | opcodes::all::OP_NOP5 |
+            opcodes::all::OP_NOP6 | opcodes::all::OP_NOP7 | opcodes::all::OP_NOP8 |
+            opcodes::all::OP_NOP9 | opcodes::all::OP_NOP10 => {
+                return self.upgradable_nop();
+            }
+            opcodes::all::OP_IF | opcodes::all::OP_NOTIF => {
+                let mut value = false;
+                if exec {
+                    if self.stack.is_empty() {
+                        return Err(ErrorKind::UnbalancedConditional);
+                    }
+                    {
+                        let top = self.top(1);
+                        if self.sigversion == SigVersion::WitnessV0 && self.flags.contains(VerifyFlags::MINIMALIF) &&
+                           (top.len() > 1 || (top.len() == 1 && top[0] != 1)) {
+                            return Err(ErrorKind::MinimalIf);
+                        }
+                        value = script::read_scriptbool(top);
+                    }
+                    if op == opcodes::all::OP_NOTIF {
+                        value = !value;
+                    }
+                    self.pop();
+                }
+                self.exec.push(value);
+            }
+            opcodes::all::OP_ELSE => {
+                match self.exec.last_mut() {
+                    Some(value) => *value = !*value,
+                    None => return Err(ErrorKind::UnbalancedConditional),
+                }
+            }
+            opcodes::all::OP_ENDIF => {
+                if self.exec.pop().is_none() {
+                    return Err(ErrorKind::UnbalancedConditional);
+                }
+            }
+            opcodes::all::OP_VERIFY => {
+                self.need(1)?;
+                if !script::read_scriptbool(self.top(1)) {
+                    return Err(ErrorKind::Verify);
+                }
+                self.pop();
+            }
+            opcodes::all::OP_RETURN => return Err(ErrorKind::OpReturn),
+
+            // Stack operations
+            opcodes::all::OP_TOALTSTACK => {
+                self.need(1)?;
+                let item = self.pop();
+                self.altstack.push(item);
+            }
+            opcodes::all::OP_FROMALTSTACK => {
+                match self.altstack.pop() {
+                    Some(item) => self.stack.push(item),
+                    None => return Err(ErrorKind::InvalidAltstackOperation),
+                }
+            }
+            opcodes::all::OP_2DROP => {
+                self.need(2)?;
+                self.pop();
+                self.pop();
+            }
+            opcodes::all::OP_2DUP => {
+                self.need(2)?;
+                let (a, b) = (self.top(2).to_vec(), self.top(1).to_vec());
+                self.stack.push(a);
+                self.stack.push(b);
+            }
+            opcodes::all::OP_3DUP => {
+                self.need(3)?;
+                let (a, b, c) = (self.top(3).to_vec(), self.top(2).to_vec(), self.top(1).to_vec());
+                self.stack.push(a);
+                self.stack.push(b);
+                self.stack.push(c);
+            }
+            opcodes::all::OP_2OVER => {
+                self.need(4)?;
+                let (a, b) = (self.top(4).to_vec(), self.top(3).to_vec());
+                self.stack.push(a);
+                self.stack.push(b);
+            }
+            opcodes::all::OP_2ROT => {
+                self.need(6)?;
+                let len = self.stack.len();
+                let a = self.stack.remove(len - 6);
+                let b = self.stack.remove(len - 6);
+                self.stack.push(a);
+                self.stack.push(b);
+            }
+            opcodes::all::OP_2SWAP => {
+                self.need(4)?;
+                let len = self.stack.len();
+                self.stack.swap(len - 4, len - 2);
+                self.stack.swap(len - 3, len - 1);
+            }
+            opcodes::all::OP_IFDUP => {
+                self.need(1)?;
+                if script::read_scriptbool(self.top(1)) {
+                    let a = self.top(1).to_vec();
+                    self.stack.push(a);
+                }
+            }
+            opcodes::all::OP_DEPTH => {
+                let depth = self.stack.len() as i64;
+                self.push_num(depth);
+            }
+            opcodes::all::OP_DROP => {
+                self.need(1)?;
+                self.pop();
+            }
+            opcodes::all::OP_DUP => {
+                self.need(1)?;
+                let a = self.top(1).to_vec();
+                self.stack.push(a);
+            }
+            opcodes::all::OP_NIP => {
+                self.need(2)?;
+                let len = self.stack.len();
+                self.stack.remove(len - 2);
+            }
+            opcodes::all::OP_OVER => {
+                self.need(2)?;
+                let a = self.top(2).to_vec();
+                self.stack.push(a);
+            }
+            opcodes::all::OP_PICK | opcodes::all::OP_ROLL => {
+                self.need(2)?;
+                let n = self.top_num(1)?;
+                self.pop();
+                if n < 0 || n as usize >= self.stack.len() {
+                    return Err(ErrorKind::InvalidStackOperation);
+                }
+                let index = self.stack.len() - 1 - n as usize;
+                let item = if op == opcodes::all::OP_ROLL {
+                    self.stack.remove(index)
+                } else {
+                    self.stack[index].clone()
+                };
+                self.stack.push(item);
+            }
+            opcodes::all::OP_ROT => {
+                self.need(3)?;
+                let len = self.stack.len();
+                let a = self.stack.remove(len - 3);
+                self.stack.push(a);
+            }
+            opcodes::all::OP_SWAP => {
+                self.need(2)?;
+                let len = self.stack.len();
+                self.stack.swap(len - 2, len - 1);
+            }
+            opcodes::all::OP_TUCK => {
+                self.need(2)?;
+                let len = self.stack.len();
+                let a = self.top(1).to_vec();
+                self.stack.insert(len - 2, a);
+            }
+            opcodes::all::OP_SIZE => {
+                self.need(1)?;
+                let size = self.top(1).len() as i64;
+                self.push_num(size);
+            }
+
+            // Bitwise logic
+            opcodes::all::OP_EQUAL | opcodes::all::OP_EQUALVERIFY => {
+                self.need(2)?;
+                let equal = self.top(2) == self.top(1);
+                self.pop();
+                self.pop();
+                self.push_bool(equal);
+                if op == opcodes::all::OP_EQUALVERIFY {
+                    if !equal {
+                        return Err(ErrorKind::EqualVerify);
+                    }
+                    self.pop();
+                }
+            }
+
+            // Arithmetic
+            opcodes::all::OP_1ADD | opcodes::all::OP_1SUB | opcodes::all::OP_NEGATE |
+            opcodes::all::OP_ABS | opcodes::all::OP_NOT | opcodes::all::OP_0NOTEQUAL => {
+                self.need(1)?;
+                let n = self.top_num(1)?;
+                let result = match op {
+                    opcodes::all::OP_1ADD => n + 1,
+                    opcodes::all::OP_1SUB => n - 1,
+                    opcodes::all::OP_NEGATE => -n,
+                    opcodes::all::OP_ABS => n.abs(),
+                    opcodes::all::OP_NOT => (n == 0) as i64,
+                    _ => (n != 0) as i64,
+                };
+                self.pop();
+                self.push_num(result);
+            }
+            opcodes::all::OP_ADD | opcodes::all::OP_SUB | opcodes::all::OP_BOOLAND |
+            opcodes::all::OP_BOOLOR | opcodes::all::OP_NUMEQUAL | opcodes::all::OP_NUMEQUALVERIFY |
+            opcodes::all::OP_NUMNOTEQUAL | opcodes::all::OP_LESSTHAN | opcodes::all::OP_GREATERTHAN |
+            opcodes::all::OP_LESSTHANOREQUAL | opcodes::all::OP_GREATERTHANOREQUAL |
+            opcodes::all::OP_MIN | opcodes::all::OP_MAX => {
+                self.need(2)?;
+                let a = self.top_num(2)?;
+                let b = self.top_num(1)?;
+                let result = match op {
+                    opcodes::all::OP_ADD => a + b,
+                    opcodes::all::OP_SUB => a - b,
+                    opcodes::all::OP_BOOLAND => (a != 0 && b != 0) as i64,
+                    opcodes::all::OP_BOOLOR => (a != 0 || b != 0) as i64,
+                    opcodes::all::OP_NUMEQUAL | opcodes::all::OP_NUMEQUALVERIFY => (a == b) as i64,
+                    opcodes::all::OP_NUMNOTEQUAL => (a != b) as i64,
+                    opcodes::all::OP_LESSTHAN => (a < b) as i64,
+                    opcodes::all::OP_GREATERTHAN => (a > b) as i64,
+                    opcodes::all::OP_LESSTHANOREQUAL => (a <= b) as i64,
+                    opcodes::all::OP_GREATERTHANOREQUAL => (a >= b) as i64,
+                    opcodes::all::OP_MIN => if a < b { a } else { b },
+                    _ => if a > b { a } else { b },
+                };
+                self.pop();
+                self.pop();
+                self.push_num(result);
+                if op == opcodes::all::OP_NUMEQUALVERIFY {
+                    if result == 0 {
+                        return Err(ErrorKind::NumEqualVerify);
+                    }
+                    self.pop();
+                }
+            }
+            opcodes::all::OP_WITHIN => {
+                self.need(3)?;
+                let x = self.top_num(3)?;
+                let min = self.top_num(2)?;
+                let max = self.top_num(1)?;
+                self.pop();
+                self.pop();
+                self.pop();
+                self.push_bool(min <= x && x < max);
+            }
+
+            // Crypto
+            opcodes::all::OP_RIPEMD160 | opcodes::all::OP_SHA1 | opcodes::all::OP_SHA256 |
+            opcodes::all::OP_HASH160 | opcodes::all::OP_HASH256 => {
+                self.need(1)?;
+                let hash = {
+                    let data = self.top(1);
+                    match op {
+                        opcodes::all::OP_RIPEMD160 => ripemd160::Hash::hash(data)[..].to_vec(),
+                        opcodes::all::OP_SHA1 => sha1::Hash::hash(data)[..].to_vec(),
+                        opcodes::all::OP_SHA256 => sha256::Hash::hash(data)[..].to_vec(),
+                        opcodes::all::OP_HASH160 => hash160::Hash::hash(data)[..].to_vec(),
+                        _ => sha256d::Hash::hash(data)[..].to_vec(),
+                    }
+                };
+                self.pop();
+                self.stack.push(hash);
+            }
+            opcodes::all::OP_CODESEPARATOR => {
+                self.codeseparator = pc;
+            }
+            opcodes::all::OP_CHECKSIG | opcodes::all::OP_CHECKSIGVERIFY => {
+                self.need(2)?;
+                let sig = self.top(2).to_vec();
+                let pubkey = self.top(1).to_vec();
+
+                let mut script_code = self.script_code();
+                self.delete_signature(&mut script_code, &sig)?;
+
+                check_signature_encoding(&sig, self.flags)?;
+                check_pubkey_encoding(&pubkey, self.flags, self.sigversion)?;
+                let success = self.checker.check_sig(&sig, &pubkey, &script_code, self.sigversion);
+                if !success && self.flags.contains(VerifyFlags::NULLFAIL) && !sig.is_empty() {
+                    return Err(ErrorKind::SigNullFail);
+                }
+
+                self.pop();
+                self.pop();
+                self.push_bool(success);
+                if op == opcodes::all::OP_CHECKSIGVERIFY {
+                    if !success {
+                        return Err(ErrorKind::CheckSigVerify);
+                    }
+                    self.pop();
+                }
+            }
+            opcodes::all::OP_CHECKMULTISIG | opcodes::all::OP_CHECKMULTISIGVERIFY => {
+                // Stack layout, from the top: key count, keys, signature
+                // count, signatures, and a dummy element
+                let mut i = 1;
+                self.need(i)?;
+
+                let n_keys = self.top_num(i)?;
+                if n_keys < 0 || n_keys > MAX_PUBKEYS_PER_MULTISIG as i64 {
+                    return Err(ErrorKind::PubkeyCount);
+                }
+                let mut n_keys = n_keys as usize;
+                self.op_count += n_keys;
+                if self.op_count > MAX_OPS_PER_SCRIPT {
+                    return Err(ErrorKind::OpCount);
+                }
+                i += 1;
+                let mut ikey = i;
+                // Position of the last key, used to tell keys from
+                // signatures when applying NULLFAIL
+                let mut ikey2 = n_keys + 2;
+                i += n_keys;
+                self.need(i)?;
+
+                let n_sigs = self.top_num(i)?;
+                if n_sigs < 0 || n_sigs > n_keys as i64 {
+                    return Err(ErrorKind::SigCount);
+                }
+                let mut n_sigs = n_sigs as usize;
+                i += 1;
+                let mut isig = i;
+                i += n_sigs;
+                self.need(i)?;
+
+                let mut script_code = self.script_code();
+                for k in 0..n_sigs {
+                    let sig = self.top(isig + k).to_vec();
+                    self.delete_signature(&mut script_code, &sig)?;
+                }
+
+                let mut success = true;
+                while success && n_sigs > 0 {
+                    let sig = self.top(isig).to_vec();
+                    let pubkey = self.top(ikey).to_vec();
+                    check_signature_encoding(&sig, self.flags)?;
+                    check_pubkey_encoding(&pubkey, self.flags, self.sigversion)?;
+
+                    if self.checker.check_sig(&sig, &pubkey, &script_code, self.sigversion) {
+                        isig += 1;
+                        n_sigs -= 1;
+                    }
+                    ikey += 1;
+                    n_keys -= 1;
+
+                    // If there are more signatures left than keys left,
+                    // then too many signatures have failed
+                    if n_sigs > n_keys {
+                        success = false;
+                    }
+                }
+
+                // Clean up the stack, enforcing NULLFAIL on the signatures
+                while i > 1 {
+                    i -= 1;
+                    if !success && self.flags.contains(VerifyFlags::NULLFAIL) && ikey2 == 0 &&
+                       !self.top(1).is_empty() {
+                        return Err(ErrorKind::SigNullFail);
+                    }
+                    ikey2 = ikey2.saturating_sub(1);
+                    self.pop();
+                }
+
+                // A bug in the original implementation consumes an extra
+                // element, which is required to be empty by NULLDUMMY
+                self.need(1)?;
+                if self.flags.contains(VerifyFlags::NULLDUMMY) && !self.top(1).is_empty() {
+                    return Err(ErrorKind::SigNullDummy);
+                }
+                self.pop();
+
+                self.push_bool(success);
+                if op == opcodes::all::OP_CHECKMULTISIGVERIFY {
+                    if !success {
+                        return Err(ErrorKind::CheckMultiSigVerify);
+                    }
+                    self.pop();
+                }
+            }
+            _ => return Err(ErrorKind::BadOpcode),
+        }
+        Ok(())
+    }
+}
+
+/// Evaluate `script` on top of `stack`, which holds the result on success.
+/// This is the equivalent of Bitcoin Core's `EvalScript`.
+pub fn eval_script<C: SignatureChecker>(stack: &mut Vec<Vec<u8>>, script: &Script, flags: VerifyFlags, checker: &C, sigversion: SigVersion) -> Result<(), Error> {
+    if script.len() > MAX_SCRIPT_SIZE {
+        return Err(Error::new(ErrorKind::ScriptSize));
+    }
+
+    let bytes = script.as_bytes();
+    let mut machine = Machine {
+        stack: stack,
+        altstack: vec![],
+        exec: vec![],
+        op_count: 0,
+        script: script,
+        codeseparator: 0,
+        flags: flags,
+        checker: checker,
+        sigversion: sigversion,
+    };
+
+    let mut pos = 0;
+    for instruction in script.iter(false) {
+        let start = pos;
+        let opcode = opcodes::All::from(bytes[start]);
+        let exec = !machine.exec.contains(&false);
+
+        let result = match instruction {
+            Instruction::Error(_) => Err(ErrorKind::BadOpcode),
+            Instruction::PushBytes(data) => {
+                pos += push_prefix_len(opcode) + data.len();
+                if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    Err(ErrorKind::PushSize)
+                } else if !exec {
+                    Ok(())
+                } else if machine.require_minimal() && !is_minimal_push(opcode, data) {
+                    Err(ErrorKind::MinimalData)
+                } else {
+                    machine.stack.push(data.to_vec());
+                    Ok(())
+                }
+            }
+            Instruction::Op(op) => {
+                pos += 1;
+                machine.step(op, exec, pos)
+            }
+        };
+
+        let result = result.and_then(|_| {
+            if machine.stack.len() + machine.altstack.len() > MAX_STACK_SIZE {
+                Err(ErrorKind::StackSize)
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(kind) = result {
+            return Err(Error {
+                kind: kind,
+                opcode: Some((start, opcode)),
+            });
+        }
+    }
+
+    if !machine.exec.is_empty() {
+        return Err(Error::new(ErrorKind::UnbalancedConditional));
+    }
+    Ok(())
+}
+
+fn verify_witness_program<C: SignatureChecker>(witness: &[Vec<u8>], version: u8, program: &[u8], flags: VerifyFlags, checker: &C) -> Result<(), Error> {
+    if version != 0 {
+        // Higher versions are reserved for soft-forks and succeed unconditionally
+        if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM) {
+            return Err(Error::new(ErrorKind::DiscourageUpgradableWitnessProgram));
+        }
+        return Ok(());
+    }
+
+    let (mut stack, script) = match program.len() {
+        32 => {
+            let (witness_script, stack) = match witness.split_last() {
+                Some(split) => split,
+                None => return Err(Error::new(ErrorKind::WitnessProgramWitnessEmpty)),
+            };
+            if &sha256::Hash::hash(witness_script)[..] != program {
+                return Err(Error::new(ErrorKind::WitnessProgramMismatch));
+            }
+            (stack.to_vec(), Script::from(witness_script.clone()))
+        }
+        20 => {
+            if witness.len() != 2 {
+                return Err(Error::new(ErrorKind::WitnessProgramMismatch));
+            }
+            let script = Builder::new().push_opcode(opcodes::all::OP_DUP)
+                                       .push_opcode(opcodes::all::OP_HASH160)
+                                       .push_slice(program)
+                                       .push_opcode(opcodes::all::OP_EQUALVERIFY)
+                                       .push_opcode(opcodes::all::OP_CHECKSIG)
+                                       .into_script();
+            (witness.to_vec(), script)
+        }
+        _ => return Err(Error::new(ErrorKind::WitnessProgramWrongLength)),
+    };
+
+    if stack.iter().any(|item| item.len() > MAX_SCRIPT_ELEMENT_SIZE) {
+        return Err(Error::new(ErrorKind::PushSize));
+    }
+    eval_script(&mut stack, &script, flags, checker, SigVersion::WitnessV0)?;
+    // Witness scripts implicitly require clean stack behaviour
+    if stack.len() != 1 {
+        return Err(Error::new(ErrorKind::CleanStack));
+    }
+    if !script::read_scriptbool(&stack[0]) {
+        return Err(Error::new(ErrorKind::EvalFalse));
+    }
+    Ok(())
+}
+
+/// Split a witness program into its version and program
+fn witness_program(script: &Script) -> Option<(u8, &[u8])> {
+    if script.is_witness_program() {
+        let bytes = script.as_bytes();
+        let version = if bytes[0] == 0 { 0 } else { bytes[0] - 0x50 };
+        Some((version, &bytes[2..]))
+    } else {
+        None
+    }
+}
+
+/// Verify that `script_sig` and `witness` satisfy `script_pubkey`. This is
+/// the equivalent of Bitcoin Core's `VerifyScript`.
+///
+/// `CLEANSTACK` is only meaningful together with `P2SH` and `WITNESS`, as
+/// otherwise stack elements can be added which those soft-forks require to
+/// be ignored.
+pub fn verify_script<C: SignatureChecker>(script_sig: &Script, script_pubkey: &Script, witness: &[Vec<u8>], flags: VerifyFlags, checker: &C) -> Result<(), Error> {
+    if flags.contains(VerifyFlags::SIGPUSHONLY) && !is_push_only(script_sig) {
+        return Err(Error::new(ErrorKind::SigPushOnly));
+    }
+
+    let mut stack = vec![];
+    eval_script(&mut stack, script_sig, flags, checker, SigVersion::Base)?;
+    let stack_copy = if flags.contains(VerifyFlags::P2SH) { stack.clone() } else { vec![] };
+    eval_script(&mut stack, script_pubkey, flags, checker, SigVersion::Base)?;
+    match stack.last() {
+        Some(top) if script::read_scriptbool(top) => {}
+        _ => return Err(Error::new(ErrorKind::EvalFalse)),
+    }
+
+    let mut had_witness = false;
+    if flags.contains(VerifyFlags::WITNESS) {
+        if let Some((version, program)) = witness_program(script_pubkey) {
+            had_witness = true;
+            // The scriptSig must be empty, or it could be malleated
+            if !script_sig.is_empty() {
+                return Err(Error::new(ErrorKind::WitnessMalleated));
+            }
+            verify_witness_program(witness, version, program, flags, checker)?;
+            // Bypass the clean stack check
+            stack.truncate(1);
+        }
+    }
+
+    if flags.contains(VerifyFlags::P2SH) && script_pubkey.is_p2sh() {
+        // The scriptSig must be literals only, or the redeem script could be
+        // replaced by a computation producing the same hash
+        if !is_push_only(script_sig) {
+            return Err(Error::new(ErrorKind::SigPushOnly));
+        }
+
+        stack = stack_copy;
+        // The scriptSig can't be empty here, as the scriptPubKey succeeded
+        let redeem_script = Script::from(stack.pop().expect("non-empty stack"));
+        eval_script(&mut stack, &redeem_script, flags, checker, SigVersion::Base)?;
+        match stack.last() {
+            Some(top) if script::read_scriptbool(top) => {}
+            _ => return Err(Error::new(ErrorKind::EvalFalse)),
+        }
+
+        if flags.contains(VerifyFlags::WITNESS) {
+            if let Some((version, program)) = witness_program(&redeem_script) {
+                had_witness = true;
+                // The scriptSig must be exactly a push of the redeem script
+                let expected = Builder::new().push_slice(redeem_script.as_bytes()).into_script();
+                if *script_sig != expected {
+                    return Err(Error::new(ErrorKind::WitnessMalleatedP2SH));
+                }
+                verify_witness_program(witness, version, program, flags, checker)?;
+                stack.truncate(1);
+            }
+        }
+    }
+
+    if flags.contains(VerifyFlags::CLEANSTACK) && stack.len() != 1 {
+        return Err(Error::new(ErrorKind::CleanStack));
+    }
+
+    if flags.contains(VerifyFlags::WITNESS) && !had_witness && !witness.is_empty() {
+        return Err(Error::new(ErrorKind::WitnessUnexpected));
+    }
+
+    Ok(())
+}
+
+/// Verify that input `input_index` of `tx` correctly spends an output with
+/// the given scriptPubKey and value
+pub fn verify_input<C: Verification>(secp: &Secp256k1<C>, tx: &Transaction, input_index: usize, script_pubkey: &Script, value: u64, flags: VerifyFlags) -> Result<(), Error> {
+    let checker = TransactionSignatureChecker::new(secp, tx, input_index, value);
+    let input = &tx.input[input_index];
+    verify_script(&input.script_sig, script_pubkey, &input.witness, flags, &checker)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use secp256k1::{self, Secp256k1, Message};
+    use secp256k1::rand::thread_rng;
+
+    use blockdata::opcodes;
+    use blockdata::script::{Builder, Script};
+    use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+    use consensus::encode::deserialize;
+    use hashes::{hash160, Hash};
+    use util::bip143;
+    use util::misc::hex_bytes;
+
+    use super::*;
+
+    /// Parse a script in the notation of Bitcoin Core's `script_tests.json`
+    fn parse_script(asm: &str) -> Script {
+        let mut builder = Builder::new();
+        let mut raw = vec![];
+        for word in asm.split_whitespace() {
+            if word.starts_with("0x") {
+                // Raw bytes are inserted as-is, not pushed
+                raw.extend(hex_bytes(&word[2..]).unwrap());
+                continue;
+            }
+            if let Ok(n) = word.parse::<i64>() {
+                builder = builder.push_int(n);
+            } else if word.starts_with('\'') && word.ends_with('\'') && word.len() >= 2 {
+                builder = builder.push_slice(&word.as_bytes()[1..word.len() - 1]);
+            } else {
+                builder = builder.push_opcode(parse_opcode(word));
+            }
+            flush(&mut builder, &mut raw);
+        }
+        flush(&mut builder, &mut raw);
+        Script::from(raw)
+    }
+
+    fn flush(builder: &mut Builder, raw: &mut Vec<u8>) {
+        let script = ::std::mem::replace(builder, Builder::new()).into_script();
+        raw.extend_from_slice(script.as_bytes());
+    }
+
+    fn parse_opcode(word: &str) -> opcodes::All {
+        let name = if word.starts_with("OP_") { &word[3..] } else { word };
+        match name {
+            "CHECKLOCKTIMEVERIFY" | "NOP2" => return opcodes::all::OP_CLTV,
+            "CHECKSEQUENCEVERIFY" | "NOP3" => return opcodes::all::OP_CSV,
+            "1NEGATE" => return opcodes::all::OP_PUSHNUM_NEG1,
+            "FALSE" => return opcodes::all::OP_PUSHBYTES_0,
+            "TRUE" => return opcodes::all::OP_PUSHNUM_1,
+            _ => {}
+        }
+        for code in 0x50..0xba {
+            let op = opcodes::All::from(code as u8);
+            if format!("{:?}", op) == format!("OP_{}", name) {
+                return op;
+            }
+        }
+        panic!("unknown opcode {}", word)
+    }
+
+    /// Flags of Bitcoin Core's script tests which this interpreter does not
+    /// implement. Test cases using them are skipped; any other unknown flag
+    /// fails the test.
+    const UNIMPLEMENTED_FLAGS: &'static [&'static str] = &[
+        "TAPROOT",
+        "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION",
+        "DISCOURAGE_OP_SUCCESS",
+        "DISCOURAGE_UPGRADABLE_PUBKEYTYPE",
+    ];
+
+    /// Parse the flags of a test case, `None` if it uses an unimplemented one
+    fn parse_flags(flags: &str) -> Option<VerifyFlags> {
+        let mut ret = VerifyFlags::NONE;
+        for flag in flags.split(',').filter(|f| !f.is_empty()) {
+            if UNIMPLEMENTED_FLAGS.contains(&flag) {
+                return None;
+            }
+            ret |= match flag {
+                "NONE" => VerifyFlags::NONE,
+                "P2SH" => VerifyFlags::P2SH,
+                "STRICTENC" => VerifyFlags::STRICTENC,
+                "DERSIG" => VerifyFlags::DERSIG,
+                "LOW_S" => VerifyFlags::LOW_S,
+                "NULLDUMMY" => VerifyFlags::NULLDUMMY,
+                "SIGPUSHONLY" => VerifyFlags::SIGPUSHONLY,
+                "MINIMALDATA" => VerifyFlags::MINIMALDATA,
+                "DISCOURAGE_UPGRADABLE_NOPS" => VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS,
+                "CLEANSTACK" => VerifyFlags::CLEANSTACK,
+                "CHECKLOCKTIMEVERIFY" => VerifyFlags::CHECKLOCKTIMEVERIFY,
+                "CHECKSEQUENCEVERIFY" => VerifyFlags::CHECKSEQUENCEVERIFY,
+                "WITNESS" => VerifyFlags::WITNESS,
+                "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM" => VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM,
+                "MINIMALIF" => VerifyFlags::MINIMALIF,
+                "NULLFAIL" => VerifyFlags::NULLFAIL,
+                "WITNESS_PUBKEYTYPE" => VerifyFlags::WITNESS_PUBKEYTYPE,
+                "CONST_SCRIPTCODE" => VerifyFlags::CONST_SCRIPTCODE,
+                _ => panic!("unknown flag {}", flag),
+            };
+        }
+        Some(ret)
+    }
+
+    /// The name Bitcoin Core's `script_tests.json` uses for an error
+    fn core_name(result: &Result<(), Error>) -> &'static str {
+        let kind = match *result {
+            Ok(()) => return "OK",
+            Err(ref e) => e.kind,
+        };
+        match kind {
+            ErrorKind::EvalFalse => "EVAL_FALSE",
+            ErrorKind::OpReturn => "OP_RETURN",
+            ErrorKind::ScriptSize => "SCRIPT_SIZE",
+            ErrorKind::PushSize => "PUSH_SIZE",
+            ErrorKind::OpCount => "OP_COUNT",
+            ErrorKind::StackSize => "STACK_SIZE",
+            ErrorKind::SigCount => "SIG_COUNT",
+            ErrorKind::PubkeyCount => "PUBKEY_COUNT",
+            ErrorKind::Verify => "VERIFY",
+            ErrorKind::EqualVerify => "EQUALVERIFY",
+            ErrorKind::CheckMultiSigVerify => "CHECKMULTISIGVERIFY",
+            ErrorKind::CheckSigVerify => "CHECKSIGVERIFY",
+            ErrorKind::NumEqualVerify => "NUMEQUALVERIFY",
+            ErrorKind::BadOpcode => "BAD_OPCODE",
+            ErrorKind::DisabledOpcode => "DISABLED_OPCODE",
+            ErrorKind::InvalidStackOperation => "INVALID_STACK_OPERATION",
+            ErrorKind::InvalidAltstackOperation => "INVALID_ALTSTACK_OPERATION",
+            ErrorKind::UnbalancedConditional => "UNBALANCED_CONDITIONAL",
+            ErrorKind::NegativeLockTime => "NEGATIVE_LOCKTIME",
+            ErrorKind::UnsatisfiedLockTime => "UNSATISFIED_LOCKTIME",
+            ErrorKind::SigHashType => "SIG_HASHTYPE",
+            ErrorKind::SigDer => "SIG_DER",
+            ErrorKind::MinimalData => "MINIMALDATA",
+            ErrorKind::SigPushOnly => "SIG_PUSHONLY",
+            ErrorKind::SigHighS => "SIG_HIGH_S",
+            ErrorKind::SigNullDummy => "SIG_NULLDUMMY",
+            ErrorKind::PubkeyType => "PUBKEYTYPE",
+            ErrorKind::CleanStack => "CLEANSTACK",
+            ErrorKind::MinimalIf => "MINIMALIF",
+            ErrorKind::SigNullFail => "NULLFAIL",
+            ErrorKind::DiscourageUpgradableNops => "DISCOURAGE_UPGRADABLE_NOPS",
+            ErrorKind::DiscourageUpgradableWitnessProgram => "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM",
+            ErrorKind::WitnessProgramWrongLength => "WITNESS_PROGRAM_WRONG_LENGTH",
+            ErrorKind::WitnessProgramWitnessEmpty => "WITNESS_PROGRAM_WITNESS_EMPTY",
+            ErrorKind::WitnessProgramMismatch => "WITNESS_PROGRAM_MISMATCH",
+            ErrorKind::WitnessMalleated => "WITNESS_MALLEATED",
+            ErrorKind::WitnessMalleatedP2SH => "WITNESS_MALLEATED_P2SH",
+            ErrorKind::WitnessUnexpected => "WITNESS_UNEXPECTED",
+            ErrorKind::WitnessPubkeyType => "WITNESS_PUBKEYTYPE",
+            ErrorKind::OpCodeSeparator => "OP_CODESEPARATOR",
+            ErrorKind::SigFindAndDelete => "SIG_FINDANDDELETE",
+            ErrorKind::NumericOverflow | ErrorKind::NonMinimalNumber => "UNKNOWN_ERROR",
+        }
+    }
+
+    /// The transaction creating the output spent in Bitcoin Core's script tests
+    fn crediting_tx(script_pubkey: &Script, value: u64) -> Transaction {
+        Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Builder::new().push_int(0).push_int(0).into_script(),
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            output: vec![TxOut {
+                value: value,
+                script_pubkey: script_pubkey.clone(),
+            }],
+        }
+    }
+
+    /// The transaction spending `crediting_tx` in Bitcoin Core's script tests
+    fn spending_tx(credit: &Transaction, script_sig: &Script, witness: &[Vec<u8>]) -> Transaction {
+        Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: credit.txid(), vout: 0 },
+                script_sig: script_sig.clone(),
+                sequence: 0xffffffff,
+                witness: witness.to_vec(),
+            }],
+            output: vec![TxOut {
+                value: credit.output[0].value,
+                script_pubkey: Script::new(),
+            }],
+        }
+    }
+
+    fn run(script_sig: &Script, script_pubkey: &Script, witness: &[Vec<u8>], value: u64, flags: VerifyFlags) -> Result<(), Error> {
+        let secp = Secp256k1::verification_only();
+        let credit = crediting_tx(script_pubkey, value);
+        let spend = spending_tx(&credit, script_sig, witness);
+        verify_input(&secp, &spend, 0, script_pubkey, value, flags)
+    }
+
+    fn check(script_sig: &Script, script_pubkey: &Script, witness: &[Vec<u8>], value: u64, flags: &str, expected: &str) {
+        let result = run(script_sig, script_pubkey, witness, value, parse_flags(flags).expect("implemented flags"));
+        assert_eq!(core_name(&result), expected,
+                   "scriptSig {:?}, scriptPubKey {:?}, flags {}: {:?}", script_sig, script_pubkey, flags, result);
+    }
+
+    /// Run every test case of `test_data/script_tests.json`, which is in the
+    /// format of Bitcoin Core's `src/test/data/script_tests.json`. Only cases
+    /// with a flag in `UNIMPLEMENTED_FLAGS` are skipped.
+    #[test]
+    fn script_tests() {
+        let tests: Vec<serde_json::Value> = serde_json::from_str(include_str!("../../test_data/script_tests.json")).unwrap();
+        let mut count = 0;
+        let mut skipped = 0;
+        for test in &tests {
+            let test = test.as_array().unwrap();
+            // Comments are single strings, and follow the expected result
+            if test.len() < 4 {
+                continue;
+            }
+            let (witness, value, rest) = match test[0].as_array() {
+                Some(wit) => {
+                    let (amount, items) = wit.split_last().unwrap();
+                    let witness: Vec<Vec<u8>> = items.iter().map(|item| {
+                        let item = item.as_str().unwrap();
+                        if item.starts_with("#SCRIPT#") {
+                            parse_script(item["#SCRIPT#".len()..].trim()).into_bytes()
+                        } else {
+                            hex_bytes(item).unwrap()
+                        }
+                    }).collect();
+                    let value = (amount.as_f64().unwrap() * 100_000_000.0).round() as u64;
+                    (witness, value, &test[1..])
+                }
+                None => (vec![], 0, &test[..]),
+            };
+            let field = |i: usize| rest[i].as_str().unwrap();
+            if parse_flags(field(2)).is_none() {
+                skipped += 1;
+                continue;
+            }
+            check(&parse_script(field(0)), &parse_script(field(1)), &witness, value, field(2), field(3));
+            count += 1;
+        }
+        assert!(count > 0, "all {} cases skipped", skipped);
+    }
+
+    #[test]
+    fn limits() {
+        let nops = |n| {
+            let mut builder = Builder::new();
+            for _ in 0..n {
+                builder = builder.push_opcode(opcodes::all::OP_NOP);
+            }
+            builder.push_int(1).into_script()
+        };
+        check(&Script::new(), &nops(201), &[], 0, "", "OK");
+        check(&Script::new(), &nops(202), &[], 0, "", "OP_COUNT");
+
+        // Public keys of executed CHECKMULTISIGs count towards the op limit
+        let multisigs = |n| {
+            let mut builder = Builder::new();
+            for _ in 0..n {
+                builder = builder.push_int(0).push_int(0);
+                for _ in 0..20 {
+                    builder = builder.push_int(0);
+                }
+                builder = builder.push_int(20)
+                                 .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+                                 .push_opcode(opcodes::all::OP_DROP);
+            }
+            builder.push_int(1).into_script()
+        };
+        check(&Script::new(), &multisigs(9), &[], 0, "", "OK");
+        check(&Script::new(), &multisigs(10), &[], 0, "", "OP_COUNT");
+
+        let pushes = |n| {
+            let mut builder = Builder::new();
+            for _ in 0..n {
+                builder = builder.push_int(1);
+            }
+            builder.into_script()
+        };
+        check(&pushes(1000), &Script::new(), &[], 0, "", "OK");
+        check(&pushes(1001), &Script::new(), &[], 0, "", "STACK_SIZE");
+        check(&pushes(999), &parse_script("TOALTSTACK 1 1"), &[], 0, "", "STACK_SIZE");
+
+        let push_size = |n| Builder::new().push_slice(&vec![1u8; n]).into_script();
+        check(&push_size(520), &parse_script("SIZE 520 EQUAL"), &[], 0, "", "OK");
+        check(&push_size(521), &parse_script("SIZE 521 EQUAL"), &[], 0, "", "PUSH_SIZE");
+        let unexecuted = Builder::new().push_int(0)
+                                       .push_opcode(opcodes::all::OP_IF)
+                                       .push_slice(&[0; 521])
+                                       .push_opcode(opcodes::all::OP_ENDIF)
+                                       .push_int(1)
+                                       .into_script();
+        check(&Script::new(), &unexecuted, &[], 0, "", "PUSH_SIZE");
+
+        let mut long = vec![opcodes::all::OP_PUSHNUM_1.into_u8()];
+        long.extend(vec![opcodes::all::OP_NOP.into_u8(); MAX_SCRIPT_SIZE - 1]);
+        check(&Script::new(), &Script::from(long.clone()), &[], 0, "", "OP_COUNT");
+        long.push(opcodes::all::OP_NOP.into_u8());
+        check(&Script::new(), &Script::from(long), &[], 0, "", "SCRIPT_SIZE");
+    }
+
+    #[test]
+    fn error_location() {
+        let err = run(&Script::new(), &parse_script("1 2 3 EQUALVERIFY"), &[], 0, VerifyFlags::NONE).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::EqualVerify);
+        assert_eq!(err.opcode, Some((3, opcodes::all::OP_EQUALVERIFY)));
+        assert_eq!(err.to_string(), "script failed an OP_EQUALVERIFY operation (OP_EQUALVERIFY at offset 3)");
+
+        let err = run(&Script::new(), &Script::new(), &[], 0, VerifyFlags::NONE).unwrap_err();
+        assert_eq!(err, Error { kind: ErrorKind::EvalFalse, opcode: None });
+    }
+
+    #[test]
+    fn find_and_delete_test() {
+        // Test cases from Bitcoin Core's script_FindAndDelete
+        let cases = [
+            ("0302ff03", "0302ff03", "", 1),
+            ("0302ff030302ff03", "0302ff03", "", 2),
+            ("0302ff030302ff03", "02", "0302ff030302ff03", 0),
+            ("0302ff030302ff03", "ff", "0302ff030302ff03", 0),
+            // Strips the push-three-bytes prefix, leaving push-two-bytes
+            ("0302ff030302ff03", "03", "02ff0302ff03", 2),
+            ("0003feed", "03feed", "00", 1),
+            ("0003feed", "00", "03feed", 1),
+            ("51525152", "52", "5151", 2),
+            ("5152", "", "5152", 0),
+            // Matching resumes right after a match, not at the next original boundary
+            ("0302ff030302ff03", "0302", "ff030302ff03", 1),
+        ];
+        for &(script, needle, expected, count) in cases.iter() {
+            let mut script = Script::from(hex_bytes(script).unwrap());
+            assert_eq!(find_and_delete(&mut script, &hex_bytes(needle).unwrap()), count);
+            assert_eq!(script, Script::from(hex_bytes(expected).unwrap()));
+        }
+
+        let script = Script::from(hex_bytes("51ab52ab4c01ab").unwrap());
+        assert_eq!(remove_codeseparators(&script), Script::from(hex_bytes("51524c01ab").unwrap()));
+    }
+
+    #[test]
+    fn p2sh() {
+        let redeem = parse_script("2 EQUAL");
+        let p2sh = redeem.to_p2sh();
+        let script_sig = Builder::new().push_int(2).push_slice(redeem.as_bytes()).into_script();
+        check(&script_sig, &p2sh, &[], 0, "P2SH", "OK");
+        let wrong = Builder::new().push_int(3).push_slice(redeem.as_bytes()).into_script();
+        check(&wrong, &p2sh, &[], 0, "", "OK");
+        check(&wrong, &p2sh, &[], 0, "P2SH", "EVAL_FALSE");
+
+        // The scriptSig must be push-only
+        let mut not_push_only = Builder::new().push_int(2).into_script().into_bytes();
+        not_push_only.push(opcodes::all::OP_NOP.into_u8());
+        not_push_only.extend(Builder::new().push_slice(redeem.as_bytes()).into_script().into_bytes());
+        check(&Script::from(not_push_only), &p2sh, &[], 0, "P2SH", "SIG_PUSHONLY");
+
+        // Leftover stack items
+        let extra = Builder::new().push_int(1).push_int(2).push_slice(redeem.as_bytes()).into_script();
+        check(&extra, &p2sh, &[], 0, "P2SH", "OK");
+        check(&extra, &p2sh, &[], 0, "P2SH,WITNESS,CLEANSTACK", "CLEANSTACK");
+    }
+
+    #[test]
+    fn witness_programs() {
+        let witness_script = parse_script("1");
+        let p2wsh = witness_script.to_v0_p2wsh();
+        let witness = vec![witness_script.to_bytes()];
+        check(&Script::new(), &p2wsh, &witness, 0, "P2SH,WITNESS", "OK");
+        // Without the flag the witness is ignored
+        check(&Script::new(), &p2wsh, &witness, 0, "P2SH", "OK");
+        check(&Script::new(), &p2wsh, &[], 0, "P2SH,WITNESS", "WITNESS_PROGRAM_WITNESS_EMPTY");
+        check(&Script::new(), &p2wsh, &[vec![0x52]], 0, "P2SH,WITNESS", "WITNESS_PROGRAM_MISMATCH");
+        check(&parse_script("1"), &p2wsh, &witness, 0, "P2SH,WITNESS", "WITNESS_MALLEATED");
+        check(&Script::new(), &p2wsh, &[vec![1], witness_script.to_bytes()], 0, "P2SH,WITNESS", "CLEANSTACK");
+        check(&Script::new(), &p2wsh, &[vec![0x00]], 0, "P2SH,WITNESS", "WITNESS_PROGRAM_MISMATCH");
+        let falsy = parse_script("0");
+        check(&Script::new(), &falsy.to_v0_p2wsh(), &[falsy.to_bytes()], 0, "P2SH,WITNESS", "EVAL_FALSE");
+        check(&Script::new(), &p2wsh, &[vec![0; 521], witness_script.to_bytes()], 0, "P2SH,WITNESS", "PUSH_SIZE");
+
+        // MINIMALIF only applies to witness scripts
+        let if_script = parse_script("IF 1 ELSE 1 ENDIF");
+        let witness = vec![vec![2], if_script.to_bytes()];
+        check(&Script::new(), &if_script.to_v0_p2wsh(), &witness, 0, "P2SH,WITNESS", "OK");
+        check(&Script::new(), &if_script.to_v0_p2wsh(), &witness, 0, "P2SH,WITNESS,MINIMALIF", "MINIMALIF");
+        check(&parse_script("2"), &if_script, &[], 0, "P2SH,WITNESS,MINIMALIF", "OK");
+        let witness = vec![vec![1, 0], if_script.to_bytes()];
+        check(&Script::new(), &if_script.to_v0_p2wsh(), &witness, 0, "P2SH,WITNESS,MINIMALIF", "MINIMALIF");
+
+        // Nested in P2SH
+        let p2sh = p2wsh.to_p2sh();
+        let script_sig = Builder::new().push_slice(p2wsh.as_bytes()).into_script();
+        let witness = vec![witness_script.to_bytes()];
+        check(&script_sig, &p2sh, &witness, 0, "P2SH,WITNESS", "OK");
+        let script_sig = Builder::new().push_int(1).push_slice(p2wsh.as_bytes()).into_script();
+        check(&script_sig, &p2sh, &witness, 0, "P2SH,WITNESS", "WITNESS_MALLEATED_P2SH");
+
+        // Program lengths and versions
+        let wrong_length = Builder::new().push_int(0).push_slice(&[1; 25]).into_script();
+        check(&Script::new(), &wrong_length, &witness, 0, "P2SH,WITNESS", "WITNESS_PROGRAM_WRONG_LENGTH");
+        let p2wpkh = Builder::new().push_int(0).push_slice(&[1; 20]).into_script();
+        check(&Script::new(), &p2wpkh, &witness, 0, "P2SH,WITNESS", "WITNESS_PROGRAM_MISMATCH");
+        let v1 = Builder::new().push_int(1).push_slice(&[1; 32]).into_script();
+        check(&Script::new(), &v1, &witness, 0, "P2SH,WITNESS", "OK");
+        check(&Script::new(), &v1, &witness, 0, "P2SH,WITNESS,DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM", "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM");
+
+        // Witnesses for non-witness outputs
+        check(&Script::new(), &parse_script("1"), &witness, 0, "P2SH,WITNESS", "WITNESS_UNEXPECTED");
+        check(&Script::new(), &parse_script("1"), &witness, 0, "P2SH", "OK");
+        let p2sh = witness_script.to_p2sh();
+        let script_sig = Builder::new().push_slice(witness_script.as_bytes()).into_script();
+        check(&script_sig, &p2sh, &witness, 0, "P2SH,WITNESS", "WITNESS_UNEXPECTED");
+    }
+
+    /// Replace the S value of a DER signature by its negation, giving the
+    /// high-S form of a low-S signature
+    fn negate_s(der: &[u8]) -> Vec<u8> {
+        const ORDER: [u8; 32] = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+            0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+        ];
+        let compact = secp256k1::Signature::from_der(der).unwrap().serialize_compact();
+        let mut s = [0u8; 32];
+        let mut borrow = 0i16;
+        for i in (0..32).rev() {
+            let mut digit = ORDER[i] as i16 - compact[32 + i] as i16 - borrow;
+            borrow = if digit < 0 { digit += 256; 1 } else { 0 };
+            s[i] = digit as u8;
+        }
+
+        let der_int = |bytes: &[u8]| {
+            let mut ret: Vec<u8> = bytes.iter().cloned().skip_while(|b| *b == 0).collect();
+            if ret[0] & 0x80 != 0 {
+                ret.insert(0, 0);
+            }
+            ret
+        };
+        let r = der_int(&compact[..32]);
+        let s = der_int(&s);
+        let mut ret = vec![0x30, (r.len() + s.len() + 4) as u8, 0x02, r.len() as u8];
+        ret.extend(r);
+        ret.push(0x02);
+        ret.push(s.len() as u8);
+        ret.extend(s);
+        ret
+    }
+
+    struct Signer {
+        secp: Secp256k1<secp256k1::All>,
+        keys: Vec<(secp256k1::SecretKey, secp256k1::PublicKey)>,
+    }
+
+    impl Signer {
+        fn new(n: usize) -> Signer {
+            let secp = Secp256k1::new();
+            let keys = (0..n).map(|_| secp.generate_keypair(&mut thread_rng())).collect();
+            Signer { secp: secp, keys: keys }
+        }
+
+        fn pubkey(&self, i: usize) -> Vec<u8> {
+            self.keys[i].1.serialize().to_vec()
+        }
+
+        fn uncompressed_pubkey(&self, i: usize) -> Vec<u8> {
+            self.keys[i].1.serialize_uncompressed().to_vec()
+        }
+
+        fn sign_hash(&self, i: usize, hash: &[u8], hash_type: u32) -> Vec<u8> {
+            let msg = Message::from_slice(hash).unwrap();
+            let mut sig = self.secp.sign(&msg, &self.keys[i].0).serialize_der().to_vec();
+            sig.push(hash_type as u8);
+            sig
+        }
+
+        /// Sign the spending transaction of the script test setup, returning
+        /// the signature and the transaction it is valid for
+        fn sign(&self, i: usize, script_code: &Script, script_pubkey: &Script, value: u64, hash_type: u32, sigversion: SigVersion) -> Vec<u8> {
+            let credit = crediting_tx(script_pubkey, value);
+            let spend = spending_tx(&credit, &Script::new(), &[]);
+            let hash = match sigversion {
+                SigVersion::Base => spend.signature_hash(0, script_code, hash_type),
+                SigVersion::WitnessV0 => {
+                    bip143::SigHashCache::new(&spend).raw_signature_hash(0, script_code, value, hash_type)
+                }
+            };
+            self.sign_hash(i, &hash[..], hash_type)
+        }
+    }
+
+    #[test]
+    fn legacy_signatures() {
+        let signer = Signer::new(3);
+
+        // Pay to public key
+        let p2pk = Builder::new().push_slice(&signer.pubkey(0)).push_opcode(opcodes::all::OP_CHECKSIG).into_script();
+        let sig = signer.sign(0, &p2pk, &p2pk, 0, 1, SigVersion::Base);
+        let script_sig = Builder::new().push_slice(&sig).into_script();
+        check(&script_sig, &p2pk, &[], 0, "", "OK");
+        check(&script_sig, &p2pk, &[], 0, "P2SH,STRICTENC,DERSIG,LOW_S,NULLFAIL", "OK");
+        let other = signer.sign(1, &p2pk, &p2pk, 0, 1, SigVersion::Base);
+        let script_sig = Builder::new().push_slice(&other).into_script();
+        check(&script_sig, &p2pk, &[], 0, "", "EVAL_FALSE");
+        check(&script_sig, &p2pk, &[], 0, "NULLFAIL", "NULLFAIL");
+
+        // Pay to public key hash, with an uncompressed key
+        let pubkey = signer.uncompressed_pubkey(0);
+        let p2pkh = Builder::new().push_opcode(opcodes::all::OP_DUP)
+                                  .push_opcode(opcodes::all::OP_HASH160)
+                                  .push_slice(&hash160::Hash::hash(&pubkey)[..])
+                                  .push_opcode(opcodes::all::OP_EQUALVERIFY)
+                                  .push_opcode(opcodes::all::OP_CHECKSIG)
+                                  .into_script();
+        let sig = signer.sign(0, &p2pkh, &p2pkh, 0, 1, SigVersion::Base);
+        let script_sig = Builder::new().push_slice(&sig).push_slice(&pubkey).into_script();
+        check(&script_sig, &p2pkh, &[], 0, "P2SH,STRICTENC", "OK");
+        let script_sig = Builder::new().push_slice(&sig).push_slice(&signer.pubkey(0)).into_script();
+        check(&script_sig, &p2pkh, &[], 0, "", "EQUALVERIFY");
+
+        // Signature hash types
+        for &hash_type in [2u32, 3, 0x81, 0x82, 0x83].iter() {
+            let sig = signer.sign(0, &p2pk, &p2pk, 0, hash_type, SigVersion::Base);
+            let script_sig = Builder::new().push_slice(&sig).into_script();
+            check(&script_sig, &p2pk, &[], 0, "STRICTENC", "OK");
+        }
+        let sig = signer.sign(0, &p2pk, &p2pk, 0, 0x21, SigVersion::Base);
+        let script_sig = Builder::new().push_slice(&sig).into_script();
+        check(&script_sig, &p2pk, &[], 0, "", "OK");
+        check(&script_sig, &p2pk, &[], 0, "STRICTENC", "SIG_HASHTYPE");
+
+        // High S signatures are valid unless LOW_S is set
+        let sig = signer.sign(0, &p2pk, &p2pk, 0, 1, SigVersion::Base);
+        let mut high_s = negate_s(&sig[..sig.len() - 1]);
+        high_s.push(1);
+        let script_sig = Builder::new().push_slice(&high_s).into_script();
+        check(&script_sig, &p2pk, &[], 0, "DERSIG", "OK");
+        check(&script_sig, &p2pk, &[], 0, "LOW_S", "SIG_HIGH_S");
+
+        // Non-DER encodings are only valid without DERSIG
+        let mut padded = sig.clone();
+        padded[1] += 1;
+        padded.insert(sig.len() - 1, 0);
+        let script_sig = Builder::new().push_slice(&padded).into_script();
+        check(&script_sig, &p2pk, &[], 0, "", "OK");
+        check(&script_sig, &p2pk, &[], 0, "DERSIG", "SIG_DER");
+
+        // 2-of-3 multisig, with signatures in key order
+        let multisig = Builder::new().push_int(2)
+                                     .push_slice(&signer.pubkey(0))
+                                     .push_slice(&signer.pubkey(1))
+                                     .push_slice(&signer.pubkey(2))
+                                     .push_int(3)
+                                     .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+                                     .into_script();
+        let sig0 = signer.sign(0, &multisig, &multisig, 0, 1, SigVersion::Base);
+        let sig2 = signer.sign(2, &multisig, &multisig, 0, 1, SigVersion::Base);
+        let script_sig = Builder::new().push_int(0).push_slice(&sig0).push_slice(&sig2).into_script();
+        check(&script_sig, &multisig, &[], 0, "NULLDUMMY,NULLFAIL", "OK");
+        let script_sig = Builder::new().push_int(1).push_slice(&sig0).push_slice(&sig2).into_script();
+        check(&script_sig, &multisig, &[], 0, "", "OK");
+        check(&script_sig, &multisig, &[], 0, "NULLDUMMY", "SIG_NULLDUMMY");
+        let script_sig = Builder::new().push_int(0).push_slice(&sig2).push_slice(&sig0).into_script();
+        check(&script_sig, &multisig, &[], 0, "", "EVAL_FALSE");
+        check(&script_sig, &multisig, &[], 0, "NULLFAIL", "NULLFAIL");
+
+        // OP_CODESEPARATOR changes the signed script code
+        let codesep = Builder::new().push_opcode(opcodes::all::OP_CODESEPARATOR)
+                                    .push_slice(&signer.pubkey(0))
+                                    .push_opcode(opcodes::all::OP_CHECKSIG)
+                                    .into_script();
+        let sig = signer.sign(0, &p2pk, &codesep, 0, 1, SigVersion::Base);
+        let script_sig = Builder::new().push_slice(&sig).into_script();
+        check(&script_sig, &codesep, &[], 0, "", "OK");
+        check(&script_sig, &codesep, &[], 0, "CONST_SCRIPTCODE", "OP_CODESEPARATOR");
+        let sig = signer.sign(0, &codesep, &codesep, 0, 1, SigVersion::Base);
+        let script_sig = Builder::new().push_slice(&sig).into_script();
+        check(&script_sig, &codesep, &[], 0, "", "EVAL_FALSE");
+
+        // P2SH multisig
+        let p2sh = multisig.to_p2sh();
+        let sig0 = signer.sign(0, &multisig, &p2sh, 0, 1, SigVersion::Base);
+        let sig1 = signer.sign(1, &multisig, &p2sh, 0, 1, SigVersion::Base);
+        let script_sig = Builder::new().push_int(0)
+                                       .push_slice(&sig0)
+                                       .push_slice(&sig1)
+                                       .push_slice(multisig.as_bytes())
+                                       .into_script();
+        check(&script_sig, &p2sh, &[], 0, "P2SH,STRICTENC,NULLDUMMY,CLEANSTACK,WITNESS", "OK");
+    }
+
+    #[test]
+    fn segwit_signatures() {
+        let signer = Signer::new(2);
+        let value = 100000;
+
+        // Pay to witness public key hash
+        let pubkey = signer.pubkey(0);
+        let pubkey_hash = hash160::Hash::hash(&pubkey);
+        let p2wpkh = Builder::new().push_int(0).push_slice(&pubkey_hash[..]).into_script();
+        let script_code = Builder::new().push_opcode(opcodes::all::OP_DUP)
+                                        .push_opcode(opcodes::all::OP_HASH160)
+                                        .push_slice(&pubkey_hash[..])
+                                        .push_opcode(opcodes::all::OP_EQUALVERIFY)
+                                        .push_opcode(opcodes::all::OP_CHECKSIG)
+                                        .into_script();
+        let sig = signer.sign(0, &script_code, &p2wpkh, value, 1, SigVersion::WitnessV0);
+        let witness = vec![sig.clone(), pubkey.clone()];
+        check(&Script::new(), &p2wpkh, &witness, value, "P2SH,WITNESS", "OK");
+        check(&Script::new(), &p2wpkh, &witness, value + 1, "P2SH,WITNESS", "EVAL_FALSE");
+        check(&Script::new(), &p2wpkh, &witness[..1], value, "P2SH,WITNESS", "WITNESS_PROGRAM_MISMATCH");
+        for &hash_type in [2u32, 3, 0x81, 0x82, 0x83].iter() {
+            let sig = signer.sign(0, &script_code, &p2wpkh, value, hash_type, SigVersion::WitnessV0);
+            check(&Script::new(), &p2wpkh, &[sig, pubkey.clone()], value, "P2SH,WITNESS,STRICTENC", "OK");
+        }
+
+        // Nested in P2SH
+        let p2sh = p2wpkh.to_p2sh();
+        let sig = signer.sign(0, &script_code, &p2sh, value, 1, SigVersion::WitnessV0);
+        let script_sig = Builder::new().push_slice(p2wpkh.as_bytes()).into_script();
+        check(&script_sig, &p2sh, &[sig, pubkey.clone()], value, "P2SH,WITNESS", "OK");
+
+        // Uncompressed keys are only allowed without WITNESS_PUBKEYTYPE
+        let pubkey = signer.uncompressed_pubkey(1);
+        let pubkey_hash = hash160::Hash::hash(&pubkey);
+        let p2wpkh = Builder::new().push_int(0).push_slice(&pubkey_hash[..]).into_script();
+        let script_code = Builder::new().push_opcode(opcodes::all::OP_DUP)
+                                        .push_opcode(opcodes::all::OP_HASH160)
+                                        .push_slice(&pubkey_hash[..])
+                                        .push_opcode(opcodes::all::OP_EQUALVERIFY)
+                                        .push_opcode(opcodes::all::OP_CHECKSIG)
+                                        .into_script();
+        let sig = signer.sign(1, &script_code, &p2wpkh, value, 1, SigVersion::WitnessV0);
+        let witness = vec![sig, pubkey];
+        check(&Script::new(), &p2wpkh, &witness, value, "P2SH,WITNESS", "OK");
+        check(&Script::new(), &p2wpkh, &witness, value, "P2SH,WITNESS,WITNESS_PUBKEYTYPE", "WITNESS_PUBKEYTYPE");
+
+        // OP_CODESEPARATOR is allowed in witness scripts
+        let witness_script = Builder::new().push_opcode(opcodes::all::OP_CODESEPARATOR)
+                                           .push_slice(&signer.pubkey(0))
+                                           .push_opcode(opcodes::all::OP_CHECKSIG)
+                                           .into_script();
+        let p2wsh = witness_script.to_v0_p2wsh();
+        let script_code = Script::from(witness_script.as_bytes()[1..].to_vec());
+        let sig = signer.sign(0, &script_code, &p2wsh, value, 1, SigVersion::WitnessV0);
+        let witness = vec![sig, witness_script.to_bytes()];
+        check(&Script::new(), &p2wsh, &witness, value, "P2SH,WITNESS,CONST_SCRIPTCODE", "OK");
+    }
+
+    #[test]
+    fn lock_times() {
+        let secp = Secp256k1::verification_only();
+        let cltv = parse_script("500 CHECKLOCKTIMEVERIFY");
+        let csv = parse_script("10 CHECKSEQUENCEVERIFY");
+        let credit = crediting_tx(&cltv, 0);
+        let mut spend = spending_tx(&credit, &Script::new(), &[]);
+        let flags = VerifyFlags::CHECKLOCKTIMEVERIFY | VerifyFlags::CHECKSEQUENCEVERIFY;
+
+        spend.lock_time = 500;
+        spend.input[0].sequence = 0xfffffffe;
+        assert!(verify_input(&secp, &spend, 0, &cltv, 0, flags).is_ok());
+        spend.lock_time = 499;
+        assert_eq!(verify_input(&secp, &spend, 0, &cltv, 0, flags).unwrap_err().kind, ErrorKind::UnsatisfiedLockTime);
+        // Timestamps and heights are not comparable
+        spend.lock_time = 500000000;
+        assert_eq!(verify_input(&secp, &spend, 0, &cltv, 0, flags).unwrap_err().kind, ErrorKind::UnsatisfiedLockTime);
+
+        spend.input[0].sequence = 10;
+        assert_eq!(verify_input(&secp, &spend, 0, &csv, 0, flags).unwrap_err().kind, ErrorKind::UnsatisfiedLockTime);
+        spend.version = 2;
+        assert!(verify_input(&secp, &spend, 0, &csv, 0, flags).is_ok());
+        // Versions with the top bit set are at least 2
+        spend.version = 0xffffffff;
+        assert!(verify_input(&secp, &spend, 0, &csv, 0, flags).is_ok());
+        spend.version = 0x80000000;
+        assert!(verify_input(&secp, &spend, 0, &csv, 0, flags).is_ok());
+        spend.version = 2;
+        spend.input[0].sequence = 9;
+        assert_eq!(verify_input(&secp, &spend, 0, &csv, 0, flags).unwrap_err().kind, ErrorKind::UnsatisfiedLockTime);
+        spend.input[0].sequence = SEQUENCE_LOCKTIME_TYPE_FLAG | 10;
+        assert_eq!(verify_input(&secp, &spend, 0, &csv, 0, flags).unwrap_err().kind, ErrorKind::UnsatisfiedLockTime);
+        spend.input[0].sequence = SEQUENCE_LOCKTIME_DISABLE_FLAG | 10;
+        assert_eq!(verify_input(&secp, &spend, 0, &csv, 0, flags).unwrap_err().kind, ErrorKind::UnsatisfiedLockTime);
+        // Without the flags both are NOPs
+        assert!(verify_input(&secp, &spend, 0, &csv, 0, VerifyFlags::NONE).is_ok());
+    }
+
+    #[test]
+    fn null_checker() {
+        let mut stack = vec![vec![1], vec![2]];
+        eval_script(&mut stack, &parse_script("ADD 3 EQUAL"), VerifyFlags::NONE, &NullSignatureChecker, SigVersion::Base).unwrap();
+        assert_eq!(stack, vec![vec![1]]);
+        let mut stack = vec![];
+        eval_script(&mut stack, &parse_script("0 0 CHECKSIG"), VerifyFlags::NONE, &NullSignatureChecker, SigVersion::Base).unwrap();
+        assert_eq!(stack, vec![Vec::<u8>::new()]);
+    }
+
+    #[test]
+    fn mainnet_transactions() {
+        let secp = Secp256k1::verification_only();
+
+        // A native P2WSH 2-of-3 multisig spend
+        let spent = Script::from(hex_bytes("0020701a8d401c84fb13e6baf169d59684e17abd9fa216c8cc5b9fc63d622ff8c58d").unwrap());
+        let spending: Transaction = deserialize(&hex_bytes("010000000001011f97548fbbe7a0db7588a66e18d803d0089315aa7d4cc28360b6ec50ef36718a0100000000ffffffff02df1776000000000017a9146c002a686959067f4866b8fb493ad7970290ab728757d29f0000000000220020701a8d401c84fb13e6baf169d59684e17abd9fa216c8cc5b9fc63d622ff8c58d04004730440220565d170eed95ff95027a69b313758450ba84a01224e1f7f130dda46e94d13f8602207bdd20e307f062594022f12ed5017bbf4a055a06aea91c10110a0e3bb23117fc014730440220647d2dc5b15f60bc37dc42618a370b2a1490293f9e5c8464f53ec4fe1dfe067302203598773895b4b16d37485cbe21b337f4e4b650739880098c592553add7dd4355016952210375e00eb72e29da82b89367947f29ef34afb75e8654f6ea368e0acdfd92976b7c2103a1b26313f430c4b15bb1fdce663207659d8cac749a0e53d70eff01874496feff2103c96d495bfdd5ba4145e3e046fee45e84a8a48ad05bd8dbb395c011a32cf9f88053ae00000000").unwrap()).unwrap();
+        verify_input(&secp, &spending, 0, &spent, 18393430, VerifyFlags::STANDARD).unwrap();
+        assert_eq!(verify_input(&secp, &spending, 0, &spent, 18393431, VerifyFlags::CONSENSUS).unwrap_err().kind,
+                   ErrorKind::EvalFalse);
+        assert_eq!(verify_input(&secp, &spending, 0, &spent, 18393431, VerifyFlags::STANDARD).unwrap_err().kind,
+                   ErrorKind::SigNullFail);
+
+        // A transaction spending a P2PKH and two P2SH-P2WPKH outputs
+        let spending: Transaction = deserialize(&hex_bytes("020000000001031cfbc8f54fbfa4a33a30068841371f80dbfe166211242213188428f437445c91000000006a47304402206fbcec8d2d2e740d824d3d36cc345b37d9f65d665a99f5bd5c9e8d42270a03a8022013959632492332200c2908459547bf8dbf97c65ab1a28dec377d6f1d41d3d63e012103d7279dfb90ce17fe139ba60a7c41ddf605b25e1c07a4ddcb9dfef4e7d6710f48feffffff476222484f5e35b3f0e43f65fc76e21d8be7818dd6a989c160b1e5039b7835fc00000000171600140914414d3c94af70ac7e25407b0689e0baa10c77feffffffa83d954a62568bbc99cc644c62eb7383d7c2a2563041a0aeb891a6a4055895570000000017160014795d04cc2d4f31480d9a3710993fbd80d04301dffeffffff06fef72f000000000017a91476fd7035cd26f1a32a5ab979e056713aac25796887a5000f00000000001976a914b8332d502a529571c6af4be66399cd33379071c588ac3fda0500000000001976a914fc1d692f8de10ae33295f090bea5fe49527d975c88ac522e1b00000000001976a914808406b54d1044c429ac54c0e189b0d8061667e088ac6eb68501000000001976a914dfab6085f3a8fb3e6710206a5a959313c5618f4d88acbba20000000000001976a914eb3026552d7e3f3073457d0bee5d4757de48160d88ac0002483045022100bee24b63212939d33d513e767bc79300051f7a0d433c3fcf1e0e3bf03b9eb1d70220588dc45a9ce3a939103b4459ce47500b64e23ab118dfc03c9caa7d6bfc32b9c601210354fd80328da0f9ae6eef2b3a81f74f9a6f66761fadf96f1d1d22b1fd6845876402483045022100e29c7e3a5efc10da6269e5fc20b6a1cb8beb92130cc52c67e46ef40aaa5cac5f0220644dd1b049727d991aece98a105563416e10a5ac4221abac7d16931842d5c322012103960b87412d6e169f30e12106bdf70122aabb9eb61f455518322a18b920a4dfa887d30700").unwrap()).unwrap();
+        let spent: Vec<Transaction> = [
+            "020000000001040aacd2c49f5f3c0968cfa8caf9d5761436d95385252e3abb4de8f5dcf8a582f20000000017160014bcadb2baea98af0d9a902e53a7e9adff43b191e9feffffff96cd3c93cac3db114aafe753122bd7d1afa5aa4155ae04b3256344ecca69d72001000000171600141d9984579ceb5c67ebfbfb47124f056662fe7adbfeffffffc878dd74d3a44072eae6178bb94b9253177db1a5aaa6d068eb0e4db7631762e20000000017160014df2a48cdc53dae1aba7aa71cb1f9de089d75aac3feffffffe49f99275bc8363f5f593f4eec371c51f62c34ff11cc6d8d778787d340d6896c0100000017160014229b3b297a0587e03375ab4174ef56eeb0968735feffffff03360d0f00000000001976a9149f44b06f6ee92ddbc4686f71afe528c09727a5c788ac24281b00000000001976a9140277b4f68ff20307a2a9f9b4487a38b501eb955888ac227c0000000000001976a9148020cd422f55eef8747a9d418f5441030f7c9c7788ac0247304402204aa3bd9682f9a8e101505f6358aacd1749ecf53a62b8370b97d59243b3d6984f02200384ad449870b0e6e89c92505880411285ecd41cf11e7439b973f13bad97e53901210205b392ffcb83124b1c7ce6dd594688198ef600d34500a7f3552d67947bbe392802473044022033dfd8d190a4ae36b9f60999b217c775b96eb10dee3a1ff50fb6a75325719106022005872e4e36d194e49ced2ebcf8bb9d843d842e7b7e0eb042f4028396088d292f012103c9d7cbf369410b090480de2aa15c6c73d91b9ffa7d88b90724614b70be41e98e0247304402207d952de9e59e4684efed069797e3e2d993e9f98ec8a9ccd599de43005fe3f713022076d190cc93d9513fc061b1ba565afac574e02027c9efbfa1d7b71ab8dbb21e0501210313ad44bc030cc6cb111798c2bf3d2139418d751c1e79ec4e837ce360cc03b97a024730440220029e75edb5e9413eb98d684d62a077b17fa5b7cc19349c1e8cc6c4733b7b7452022048d4b9cae594f03741029ff841e35996ef233701c1ea9aa55c301362ea2e2f68012103590657108a72feb8dc1dec022cf6a230bb23dc7aaa52f4032384853b9f8388baf9d20700",
+            "0200000000010166c3d39490dc827a2594c7b17b7d37445e1f4b372179649cd2ce4475e3641bbb0100000017160014e69aa750e9bff1aca1e32e57328b641b611fc817fdffffff01e87c5d010000000017a914f3890da1b99e44cd3d52f7bcea6a1351658ea7be87024830450221009eb97597953dc288de30060ba02d4e91b2bde1af2ecf679c7f5ab5989549aa8002202a98f8c3bd1a5a31c0d72950dd6e2e3870c6c5819a6c3db740e91ebbbc5ef4800121023f3d3b8e74b807e32217dea2c75c8d0bd46b8665b3a2d9b3cb310959de52a09bc9d20700",
+            "01000000027a1120a30cef95422638e8dab9dedf720ec614b1b21e451a4957a5969afb869d000000006a47304402200ecc318a829a6cad4aa9db152adbf09b0cd2de36f47b53f5dade3bc7ef086ca702205722cda7404edd6012eedd79b2d6f24c0a0c657df1a442d0a2166614fb164a4701210372f4b97b34e9c408741cd1fc97bcc7ffdda6941213ccfde1cb4075c0f17aab06ffffffffc23b43e5a18e5a66087c0d5e64d58e8e21fcf83ce3f5e4f7ecb902b0e80a7fb6010000006b483045022100f10076a0ea4b4cf8816ed27a1065883efca230933bf2ff81d5db6258691ff75202206b001ef87624e76244377f57f0c84bc5127d0dd3f6e0ef28b276f176badb223a01210309a3a61776afd39de4ed29b622cd399d99ecd942909c36a8696cfd22fc5b5a1affffffff0200127a000000000017a914f895e1dd9b29cb228e9b06a15204e3b57feaf7cc8769311d09000000001976a9144d00da12aaa51849d2583ae64525d4a06cd70fde88ac00000000",
+        ].iter().map(|hex| deserialize(&hex_bytes(hex).unwrap()).unwrap()).collect();
+
+        for (index, input) in spending.input.iter().enumerate() {
+            let prev = spent.iter().find(|tx| tx.txid() == input.previous_output.txid).unwrap();
+            let output = &prev.output[input.previous_output.vout as usize];
+            verify_input(&secp, &spending, index, &output.script_pubkey, output.value, VerifyFlags::STANDARD).unwrap();
+        }
+
+        // Tampering with an output invalidates all signatures
+        let mut tampered = spending.clone();
+        tampered.output[0].value += 1;
+        for (index, input) in tampered.input.iter().enumerate() {
+            let prev = spent.iter().find(|tx| tx.txid() == input.previous_output.txid).unwrap();
+            let output = &prev.output[input.previous_output.vout as usize];
+            assert!(verify_input(&secp, &tampered, index, &output.script_pubkey, output.value, VerifyFlags::CONSENSUS).is_err());
+        }
+    }
+
+    #[test]
+    fn flags() {
+        let flags = VerifyFlags::P2SH | VerifyFlags::WITNESS;
+        assert_eq!(flags.bits(), 0x801);
+        assert!(flags.contains(VerifyFlags::P2SH));
+        assert!(!flags.contains(VerifyFlags::P2SH | VerifyFlags::DERSIG));
+        assert!(VerifyFlags::STANDARD.contains(VerifyFlags::CONSENSUS));
+        assert_eq!(VerifyFlags::from_bits(1 << 31).bits(), 1 << 31);
+    }
+}
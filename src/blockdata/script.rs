@@ -136,7 +136,7 @@ impl convert::From<bitcoinconsensus::Error> for Error {
     }
 }
 /// Helper to encode an integer in script format
-fn build_scriptint(n: i64) -> Vec<u8> {
+pub fn build_scriptint(n: i64) -> Vec<u8> {
     if n == 0 { return vec![] }
 
     let neg = n < 0;
@@ -324,6 +324,25 @@ impl Script {
                                opcodes::All::from(self.0[0]).classify() == opcodes::Class::IllegalOp)
     }
 
+    /// Count the signature operations in the script the way pre-segwit
+    /// consensus rules do: each CHECKSIG(VERIFY) counts as one and each
+    /// CHECKMULTISIG(VERIFY) as 20, regardless of its key count. Counting
+    /// stops at the first unparseable opcode.
+    pub fn legacy_sigop_count(&self) -> usize {
+        let mut count = 0;
+        for instruction in self.iter(false) {
+            match instruction {
+                Instruction::Op(opcodes::all::OP_CHECKSIG) |
+                Instruction::Op(opcodes::all::OP_CHECKSIGVERIFY) => count += 1,
+                Instruction::Op(opcodes::all::OP_CHECKMULTISIG) |
+                Instruction::Op(opcodes::all::OP_CHECKMULTISIGVERIFY) => count += 20,
+                Instruction::Error(_) => break,
+                _ => {}
+            }
+        }
+        count
+    }
+
     /// Iterate over the script in the form of `Instruction`s, which are an enum covering
     /// opcodes, datapushes and errors. At most one error will be returned and then the
     /// iterator will end. To instead iterate over the script as sequence of bytes, treat
@@ -1077,6 +1096,32 @@ mod test {
         assert!(script_2 > script_1);
     }
 
+    #[test]
+    fn legacy_sigop_count() {
+        let p2pkh = Builder::new().push_opcode(opcodes::all::OP_DUP)
+                                  .push_opcode(opcodes::all::OP_HASH160)
+                                  .push_slice(&[0; 20])
+                                  .push_opcode(opcodes::all::OP_EQUALVERIFY)
+                                  .push_opcode(opcodes::all::OP_CHECKSIG)
+                                  .into_script();
+        assert_eq!(p2pkh.legacy_sigop_count(), 1);
+
+        // Multisig counts as 20 whatever its key count
+        let multi = Builder::new().push_int(1)
+                                  .push_slice(&[2; 33])
+                                  .push_int(1)
+                                  .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+                                  .push_opcode(opcodes::all::OP_CHECKSIGVERIFY)
+                                  .into_script();
+        assert_eq!(multi.legacy_sigop_count(), 21);
+
+        // Sigops inside pushes, complete or truncated, are not counted
+        let mut bytes = vec![0x01, 0xac, 0xac, 0x4c, 0x02, 0xac];
+        assert_eq!(Script::from(bytes.clone()).legacy_sigop_count(), 1);
+        bytes.push(0xac);
+        assert_eq!(Script::from(bytes).legacy_sigop_count(), 1);
+    }
+
 	#[test]
 	#[cfg(feature="bitcoinconsensus")]
 	fn test_bitcoinconsensus () {
@@ -38,15 +38,17 @@ use std::io::{Cursor, Read, Write};
 use byteorder::{LittleEndian, WriteBytesExt, ReadBytesExt};
 use hex::encode as hex_encode;
 
+use base64;
 use hashes::{sha256d, Hash as HashTrait};
 use secp256k1;
 
 use util::base58;
+use util::bip152::ShortId;
 use util::psbt;
 
 use blockdata::transaction::{TxOut, Transaction, TxIn};
 use network::message_blockdata::Inventory;
-use network::address::Address;
+use network::address::{Address, AddrV2Message};
 
 /// Encoding error
 #[derive(Debug)]
@@ -55,6 +57,8 @@ pub enum Error {
     Io(io::Error),
     /// Base58 encoding error
     Base58(base58::Error),
+    /// Base64 encoding error
+    Base64(base64::DecodeError),
     /// Error from the `byteorder` crate
     ByteOrder(io::Error),
     /// secp-related error
@@ -75,6 +79,13 @@ pub enum Error {
         /// The maximum capacity
         max: usize,
     },
+    /// Network message header announced a payload above the accepted size
+    OversizedMessage {
+        /// The announced payload size
+        size: usize,
+        /// The maximum accepted payload size
+        max: usize,
+    },
     /// Checksum was invalid
     InvalidChecksum {
         /// The expected checksum
@@ -99,11 +110,13 @@ impl fmt::Display for Error {
         match *self {
             Error::Io(ref e) => fmt::Display::fmt(e, f),
             Error::Base58(ref e) => fmt::Display::fmt(e, f),
+            Error::Base64(ref e) => fmt::Display::fmt(e, f),
             Error::ByteOrder(ref e) => fmt::Display::fmt(e, f),
             Error::Secp256k1(ref e) => fmt::Display::fmt(e, f),
             Error::Psbt(ref e) => fmt::Display::fmt(e, f),
             Error::UnexpectedNetworkMagic { expected: ref e, actual: ref a } => write!(f, "{}: expected {}, actual {}", error::Error::description(self), e, a),
             Error::OversizedVectorAllocation { requested: ref r, max: ref m } => write!(f, "{}: requested {}, maximum {}", error::Error::description(self), r, m),
+            Error::OversizedMessage { size: ref s, max: ref m } => write!(f, "{}: size {}, maximum {}", error::Error::description(self), s, m),
             Error::InvalidChecksum { expected: ref e, actual: ref a } => write!(f, "{}: expected {}, actual {}", error::Error::description(self), hex_encode(e), hex_encode(a)),
             Error::UnknownNetworkMagic(ref m) => write!(f, "{}: {}", error::Error::description(self), m),
             Error::ParseFailed(ref e) => write!(f, "{}: {}", error::Error::description(self), e),
@@ -119,11 +132,13 @@ impl error::Error for Error {
         match *self {
             Error::Io(ref e) => Some(e),
             Error::Base58(ref e) => Some(e),
+            Error::Base64(ref e) => Some(e),
             Error::ByteOrder(ref e) => Some(e),
             Error::Secp256k1(ref e) => Some(e),
             Error::Psbt(ref e) => Some(e),
             Error::UnexpectedNetworkMagic { .. }
             | Error::OversizedVectorAllocation { .. }
+            | Error::OversizedMessage { .. }
             | Error::InvalidChecksum { .. }
             | Error::UnknownNetworkMagic(..)
             | Error::ParseFailed(..)
@@ -137,11 +152,13 @@ impl error::Error for Error {
         match *self {
             Error::Io(ref e) => e.description(),
             Error::Base58(ref e) => e.description(),
+            Error::Base64(ref e) => e.description(),
             Error::ByteOrder(ref e) => e.description(),
             Error::Secp256k1(ref e) => e.description(),
             Error::Psbt(ref e) => e.description(),
             Error::UnexpectedNetworkMagic { .. } => "unexpected network magic",
             Error::OversizedVectorAllocation { .. } => "allocation of oversized vector requested",
+            Error::OversizedMessage { .. } => "oversized network message",
             Error::InvalidChecksum { .. } => "invalid checksum",
             Error::UnknownNetworkMagic(..) => "unknown network magic",
             Error::ParseFailed(..) => "parse failed",
@@ -159,6 +176,13 @@ impl From<base58::Error> for Error {
     }
 }
 
+#[doc(hidden)]
+impl From<base64::DecodeError> for Error {
+    fn from(e: base64::DecodeError) -> Error {
+        Error::Base64(e)
+    }
+}
+
 #[doc(hidden)]
 impl From<secp256k1::Error> for Error {
     fn from(e: secp256k1::Error) -> Error {
@@ -539,6 +563,7 @@ macro_rules! impl_array {
 
 impl_array!(2);
 impl_array!(4);
+impl_array!(6);
 impl_array!(8);
 impl_array!(12);
 impl_array!(16);
@@ -609,6 +634,8 @@ impl_vec!(Inventory);
 impl_vec!(Vec<u8>);
 impl_vec!((u32, Address));
 impl_vec!(u64);
+impl_vec!(ShortId);
+impl_vec!(AddrV2Message);
 
 impl Encodable for Vec<u8> {
     #[inline]
@@ -20,6 +20,7 @@
 
 pub mod encode;
 pub mod params;
+pub mod versionbits;
 
 pub use self::encode::{Encodable, Decodable, WriteExt, ReadExt};
 pub use self::encode::{serialize, deserialize, deserialize_partial};
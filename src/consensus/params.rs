@@ -34,6 +34,13 @@ const MAX_BITS_TESTNET: Uint256 = Uint256([
     0xffffffffffffffffu64,
     0x00000000ffffffffu64,
 ]);
+/// Lowest possible difficulty for Signet.
+const MAX_BITS_SIGNET: Uint256 = Uint256([
+    0x0000000000000000u64,
+    0x0000000000000000u64,
+    0x0000000000000000u64,
+    0x00000377ae000000u64,
+]);
 /// Lowest possible difficulty for Regtest.
 const MAX_BITS_REGTEST: Uint256 = Uint256([
     0xffffffffffffffffu64,
@@ -105,6 +112,20 @@ impl Params {
                 allow_min_difficulty_blocks: true,
                 no_pow_retargeting: false,
             },
+            Network::Signet => Params {
+                network: Network::Signet,
+                bip16_time: 1333238400, // Apr 1 2012
+                bip34_height: 1,
+                bip65_height: 1,
+                bip66_height: 1,
+                rule_change_activation_threshold: 1815, // 90%
+                miner_confirmation_window: 2016,
+                pow_limit: MAX_BITS_SIGNET,
+                pow_target_spacing: 10 * 60,            // 10 minutes.
+                pow_target_timespan: 14 * 24 * 60 * 60, // 2 weeks.
+                allow_min_difficulty_blocks: false,
+                no_pow_retargeting: false,
+            },
             Network::Regtest => Params {
                 network: Network::Regtest,
                 bip16_time: 1333238400,  // Apr 1 2012
@@ -0,0 +1,311 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! BIP9 Version Bits
+//!
+//! Tracking of soft fork deployments signalled by miners in block versions,
+//! as described at https://github.com/bitcoin/bips/blob/master/bip-0009.mediawiki
+//! together with the minimum activation height of BIP341.
+//!
+//! The state of a deployment changes only at the boundaries of the
+//! `miner_confirmation_window` periods of `Params`: it is `Started` once the
+//! median time past reaches the start time, `LockedIn` after a period in
+//! which at least `rule_change_activation_threshold` blocks signalled, and
+//! `Active` from the first period at or above the minimum activation height
+//! after that. A deployment which times out before locking in is `Failed`.
+//!
+//! The functions here take the headers of a chain from its genesis block,
+//! so that `headers[h]` is the header at height `h`.
+//!
+
+use std::i64;
+
+use blockdata::block::BlockHeader;
+use consensus::params::Params;
+use network::constants::Network;
+
+/// The top bits a block version must have to signal with version bits
+pub const VERSIONBITS_TOP_BITS: u32 = 0x20000000;
+/// Mask of the top bits of a block version
+pub const VERSIONBITS_TOP_MASK: u32 = 0xE0000000;
+/// Number of bits available for deployments
+pub const VERSIONBITS_NUM_BITS: u8 = 29;
+
+/// Start time of a deployment which is active from the genesis block
+pub const ALWAYS_ACTIVE: i64 = -1;
+/// Start time of a deployment which is never activated
+pub const NEVER_ACTIVE: i64 = -2;
+/// Timeout of a deployment which never times out
+pub const NO_TIMEOUT: i64 = i64::MAX;
+
+/// Number of previous headers whose median timestamp is compared with the
+/// start time and timeout
+const MEDIAN_TIME_SPAN: usize = 11;
+
+/// The states of a deployment
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ThresholdState {
+    /// The start time has not been reached
+    Defined,
+    /// Miners signal for the deployment
+    Started,
+    /// The deployment will activate
+    LockedIn,
+    /// The rules of the deployment are enforced
+    Active,
+    /// The deployment timed out without locking in
+    Failed,
+}
+
+/// A soft fork deployment
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Deployment {
+    /// Name of the deployment
+    pub name: &'static str,
+    /// The version bit miners signal with, below `VERSIONBITS_NUM_BITS`
+    pub bit: u8,
+    /// Median time past from which the deployment is `Started`, or
+    /// `ALWAYS_ACTIVE` or `NEVER_ACTIVE`
+    pub start_time: i64,
+    /// Median time past from which a deployment which did not lock in is
+    /// `Failed`, or `NO_TIMEOUT`
+    pub timeout: i64,
+    /// Lowest height at which a locked in deployment becomes `Active`
+    pub min_activation_height: u32,
+}
+
+/// Signalling in the current period of a `Started` deployment
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct SignallingStats {
+    /// Length of a period
+    pub period: u32,
+    /// Number of signalling blocks needed to lock in
+    pub threshold: u32,
+    /// Number of blocks of the current period
+    pub elapsed: u32,
+    /// Number of blocks of the current period which signalled
+    pub count: u32,
+    /// Whether the threshold can still be reached in this period
+    pub possible: bool,
+}
+
+/// The deployments of Bitcoin Core which are not buried yet on `network`
+pub fn deployments(network: Network) -> Vec<Deployment> {
+    let (start_time, timeout, min_activation_height) = match network {
+        // 24 April 2021 to 11 August 2021
+        Network::Bitcoin => (1619222400, 1628640000, 709632),
+        Network::Testnet => (1619222400, 1628640000, 0),
+        Network::Signet | Network::Regtest => (ALWAYS_ACTIVE, NO_TIMEOUT, 0),
+    };
+    vec![Deployment {
+        name: "taproot",
+        bit: 2,
+        start_time: start_time,
+        timeout: timeout,
+        min_activation_height: min_activation_height,
+    }]
+}
+
+impl Deployment {
+    /// The version bits mask of this deployment
+    pub fn mask(&self) -> u32 {
+        1 << self.bit
+    }
+
+    /// Whether a block with `version` signals for this deployment
+    pub fn signals(&self, version: u32) -> bool {
+        version & VERSIONBITS_TOP_MASK == VERSIONBITS_TOP_BITS && version & self.mask() != 0
+    }
+
+    /// The state of the deployment in each period of the chain, up to and
+    /// including the period of the block following the last header.
+    pub fn states(&self, params: &Params, headers: &[BlockHeader]) -> Vec<ThresholdState> {
+        let period = params.miner_confirmation_window as usize;
+        let next_height = headers.len();
+        let mut states = Vec::with_capacity(next_height / period + 1);
+
+        let mut state = match self.start_time {
+            ALWAYS_ACTIVE => ThresholdState::Active,
+            NEVER_ACTIVE => ThresholdState::Failed,
+            _ => ThresholdState::Defined,
+        };
+        states.push(state);
+        for end in (1..next_height / period + 1).map(|p| p * period) {
+            // the last block of the previous period decides the transition
+            let last = end - 1;
+            let time = median_time_past(headers, last) as i64;
+            state = match state {
+                ThresholdState::Defined if time >= self.start_time => ThresholdState::Started,
+                ThresholdState::Started => {
+                    let count = headers[end - period..end].iter()
+                        .filter(|h| self.signals(h.version))
+                        .count();
+                    if count as u32 >= params.rule_change_activation_threshold {
+                        ThresholdState::LockedIn
+                    } else if time >= self.timeout {
+                        ThresholdState::Failed
+                    } else {
+                        ThresholdState::Started
+                    }
+                }
+                ThresholdState::LockedIn if end as u32 >= self.min_activation_height => ThresholdState::Active,
+                s => s,
+            };
+            states.push(state);
+        }
+        states
+    }
+
+    /// The state of the deployment for the block following the last header
+    pub fn state(&self, params: &Params, headers: &[BlockHeader]) -> ThresholdState {
+        *self.states(params, headers).last().expect("at least one period")
+    }
+
+    /// The height from which the deployment has its current state for the
+    /// block following the last header
+    pub fn state_since_height(&self, params: &Params, headers: &[BlockHeader]) -> u32 {
+        let states = self.states(params, headers);
+        let current = states[states.len() - 1];
+        let since = states.iter().rposition(|s| *s != current).map_or(0, |p| p + 1);
+        since as u32 * params.miner_confirmation_window
+    }
+
+    /// Signalling statistics of the period of the block following the last
+    /// header, counting the blocks of that period among `headers`. This is
+    /// only meaningful while the deployment is `Started`.
+    pub fn statistics(&self, params: &Params, headers: &[BlockHeader]) -> SignallingStats {
+        let period = params.miner_confirmation_window;
+        let threshold = params.rule_change_activation_threshold;
+        let start = headers.len() - headers.len() % period as usize;
+        let elapsed = (headers.len() - start) as u32;
+        let count = headers[start..].iter().filter(|h| self.signals(h.version)).count() as u32;
+        SignallingStats {
+            period: period,
+            threshold: threshold,
+            elapsed: elapsed,
+            count: count,
+            // elapsed is always below period, so this can not underflow even
+            // for parameters with a threshold above the period
+            possible: count + (period - elapsed) >= threshold,
+        }
+    }
+}
+
+/// The median timestamp of the header at `height` and the ones before it
+fn median_time_past(headers: &[BlockHeader], height: usize) -> u32 {
+    let first = (height + 1).saturating_sub(MEDIAN_TIME_SPAN);
+    let mut times: Vec<u32> = headers[first..height + 1].iter().map(|h| h.time).collect();
+    times.sort();
+    times[times.len() / 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use blockdata::block::BlockHeader;
+    use consensus::params::Params;
+    use network::constants::Network;
+
+    use super::{deployments, Deployment, ThresholdState, ALWAYS_ACTIVE, NEVER_ACTIVE, NO_TIMEOUT};
+
+    const START: i64 = 1_000_000;
+
+    /// A chain of `len` headers ten minutes apart, from one hour before
+    /// `START`, where the headers in `signal` signal for bit 1
+    fn chain<F: Fn(usize) -> bool>(len: usize, signal: F) -> Vec<BlockHeader> {
+        (0..len).map(|h| BlockHeader {
+            version: if signal(h) { 0x20000002 } else { 0x20000000 },
+            prev_blockhash: Default::default(),
+            merkle_root: Default::default(),
+            time: (START - 3600 + h as i64 * 600) as u32,
+            bits: 0x207fffff,
+            nonce: 0,
+        }).collect()
+    }
+
+    fn deployment(timeout: i64, min_activation_height: u32) -> Deployment {
+        Deployment {
+            name: "test",
+            bit: 1,
+            start_time: START,
+            timeout: timeout,
+            min_activation_height: min_activation_height,
+        }
+    }
+
+    #[test]
+    fn activation_test() {
+        use super::ThresholdState::*;
+
+        // regtest: periods of 144 blocks, threshold 108
+        let params = Params::new(Network::Regtest);
+        let d = deployment(NO_TIMEOUT, 0);
+
+        assert_eq!(d.states(&params, &chain(143, |_| true)), vec![Defined]);
+        assert_eq!(d.states(&params, &chain(144, |_| true)), vec![Defined, Started]);
+        assert_eq!(d.state_since_height(&params, &chain(144, |_| true)), 144);
+        // not enough signalling in the second period
+        assert_eq!(d.state(&params, &chain(288, |h| h % 144 < 107)), Started);
+        assert_eq!(d.states(&params, &chain(432, |h| h % 144 < 108)),
+                   vec![Defined, Started, LockedIn, Active]);
+        assert_eq!(d.state_since_height(&params, &chain(500, |h| h % 144 < 108)), 432);
+
+        // activation waits for the minimum height
+        let d = deployment(NO_TIMEOUT, 600);
+        assert_eq!(d.states(&params, &chain(720, |_| true)),
+                   vec![Defined, Started, LockedIn, LockedIn, LockedIn, Active]);
+    }
+
+    #[test]
+    fn timeout_test() {
+        let params = Params::new(Network::Regtest);
+        // times out at the end of the second period
+        let d = deployment(START + 250 * 600, 0);
+        assert_eq!(d.states(&params, &chain(432, |h| h >= 288)),
+                   vec![ThresholdState::Defined, ThresholdState::Started,
+                        ThresholdState::Failed, ThresholdState::Failed]);
+        // locking in wins over timing out in the same period
+        assert_eq!(d.state(&params, &chain(288, |_| true)), ThresholdState::LockedIn);
+
+        let mut d = deployment(NO_TIMEOUT, 0);
+        d.start_time = ALWAYS_ACTIVE;
+        assert_eq!(d.state(&params, &chain(10, |_| false)), ThresholdState::Active);
+        d.start_time = NEVER_ACTIVE;
+        assert_eq!(d.state(&params, &chain(500, |_| true)), ThresholdState::Failed);
+    }
+
+    #[test]
+    fn statistics_test() {
+        let params = Params::new(Network::Regtest);
+        let d = deployment(NO_TIMEOUT, 0);
+        assert!(d.signals(0x20000002));
+        assert!(!d.signals(0x20000001));
+        assert!(!d.signals(0x00000002));
+
+        let stats = d.statistics(&params, &chain(144 + 40, |h| h % 2 == 0));
+        assert_eq!((stats.period, stats.threshold, stats.elapsed, stats.count), (144, 108, 40, 20));
+        assert!(stats.possible);
+        let stats = d.statistics(&params, &chain(144 + 40, |h| h < 144));
+        assert_eq!(stats.count, 0);
+        assert!(!stats.possible);
+
+        let mut unreachable = params.clone();
+        unreachable.rule_change_activation_threshold = 145;
+        let stats = d.statistics(&unreachable, &chain(144 + 40, |_| true));
+        assert_eq!(stats.count, 40);
+        assert!(!stats.possible);
+
+        assert_eq!(deployments(Network::Bitcoin)[0].min_activation_height, 709632);
+        assert_eq!(deployments(Network::Regtest)[0].state(&params, &[]), ThresholdState::Active);
+    }
+}
@@ -18,16 +18,19 @@
 //! network addresses in Bitcoin messages.
 //!
 
-use std::io;
+use std::{error, io};
 use std::fmt;
-use std::net::{SocketAddr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+use std::net::{SocketAddr, Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+use std::str::FromStr;
 
-use consensus::encode::{self, Decodable, Encodable};
+use consensus::encode::{self, Decodable, Encodable, VarInt};
+use network::constants::ServiceFlags;
+use util::sha3::sha3_256;
 
 /// A message which can be sent on the Bitcoin network
 pub struct Address {
     /// Services provided by the peer whose address this is
-    pub services: u64,
+    pub services: ServiceFlags,
     /// Network byte-order ipv6 address, or ipv4-mapped ipv6 address
     pub address: [u16; 8],
     /// Network port
@@ -38,7 +41,7 @@ const ONION : [u16; 3] = [0xFD87, 0xD87E, 0xEB43];
 
 impl Address {
     /// Create an address message for a socket
-    pub fn new (socket :&SocketAddr, services: u64) -> Address {
+    pub fn new (socket :&SocketAddr, services: ServiceFlags) -> Address {
         let (address, port) = match socket {
             &SocketAddr::V4(ref addr) => (addr.ip().to_ipv6_mapped().segments(), addr.port()),
             &SocketAddr::V6(ref addr) => (addr.ip().segments(), addr.port())
@@ -123,18 +126,364 @@ impl PartialEq for Address {
 
 impl Eq for Address {}
 
+/// The longest address a BIP155 `addrv2` entry may have
+pub const MAX_ADDRV2_SIZE: u64 = 512;
+
+/// Prefix of IPv4-mapped IPv6 addresses, ::ffff:0:0/96
+const IPV4_IN_IPV6_PREFIX: [u8; 12] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff];
+/// Prefix of Tor v2 addresses in IPv6 notation (OnionCat), fd87:d87e:eb43::/48
+const TORV2_IN_IPV6_PREFIX: [u8; 6] = [0xfd, 0x87, 0xd8, 0x7e, 0xeb, 0x43];
+/// First byte of all CJDNS addresses
+const CJDNS_PREFIX: u8 = 0xfc;
+
+/// A network address of any of the networks of BIP155
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum AddrV2 {
+    /// IPv4 address
+    Ipv4(Ipv4Addr),
+    /// IPv6 address
+    Ipv6(Ipv6Addr),
+    /// Tor v2 onion service, the hash of its key
+    TorV2([u8; 10]),
+    /// Tor v3 onion service, its ed25519 public key
+    TorV3([u8; 32]),
+    /// I2P service, the hash of its destination
+    I2p([u8; 32]),
+    /// CJDNS address, in fc00::/8
+    Cjdns(Ipv6Addr),
+    /// Address of a network unknown to us, with its network id
+    Unknown(u8, Vec<u8>),
+}
+
+impl AddrV2 {
+    /// The BIP155 network id
+    pub fn network_id(&self) -> u8 {
+        match *self {
+            AddrV2::Ipv4(_) => 1,
+            AddrV2::Ipv6(_) => 2,
+            AddrV2::TorV2(_) => 3,
+            AddrV2::TorV3(_) => 4,
+            AddrV2::I2p(_) => 5,
+            AddrV2::Cjdns(_) => 6,
+            AddrV2::Unknown(id, _) => id,
+        }
+    }
+
+    /// The raw address
+    fn to_bytes(&self) -> Vec<u8> {
+        match *self {
+            AddrV2::Ipv4(ref addr) => addr.octets().to_vec(),
+            AddrV2::Ipv6(ref addr) | AddrV2::Cjdns(ref addr) => addr.octets().to_vec(),
+            AddrV2::TorV2(ref addr) => addr.to_vec(),
+            AddrV2::TorV3(ref addr) | AddrV2::I2p(ref addr) => addr.to_vec(),
+            AddrV2::Unknown(_, ref addr) => addr.clone(),
+        }
+    }
+
+    /// Build an address from its network id and raw bytes, checking the
+    /// length for the known networks, and like Bitcoin Core that IPv6
+    /// addresses do not embed an IPv4 or Tor address and CJDNS addresses
+    /// are in fc00::/8
+    fn from_bytes(network_id: u8, bytes: Vec<u8>) -> Result<AddrV2, encode::Error> {
+        let expected = match network_id {
+            1 => 4,
+            2 | 6 => 16,
+            3 => 10,
+            4 | 5 => 32,
+            _ => return Ok(AddrV2::Unknown(network_id, bytes)),
+        };
+        if bytes.len() != expected {
+            return Err(encode::Error::ParseFailed("invalid addrv2 address length"));
+        }
+        Ok(match network_id {
+            1 => AddrV2::Ipv4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3])),
+            2 => {
+                if bytes[..12] == IPV4_IN_IPV6_PREFIX {
+                    return Err(encode::Error::ParseFailed("IPv6 addrv2 address embeds an IPv4 address"));
+                }
+                if bytes[..6] == TORV2_IN_IPV6_PREFIX {
+                    return Err(encode::Error::ParseFailed("IPv6 addrv2 address embeds a Tor address"));
+                }
+                AddrV2::Ipv6(ipv6_from_slice(&bytes))
+            }
+            3 => {
+                let mut addr = [0; 10];
+                addr.copy_from_slice(&bytes);
+                AddrV2::TorV2(addr)
+            }
+            4 | 5 => {
+                let mut addr = [0; 32];
+                addr.copy_from_slice(&bytes);
+                if network_id == 4 { AddrV2::TorV3(addr) } else { AddrV2::I2p(addr) }
+            }
+            _ => {
+                if bytes[0] != CJDNS_PREFIX {
+                    return Err(encode::Error::ParseFailed("CJDNS addrv2 address is not in fc00::/8"));
+                }
+                AddrV2::Cjdns(ipv6_from_slice(&bytes))
+            }
+        })
+    }
+}
+
+fn ipv6_from_slice(bytes: &[u8]) -> Ipv6Addr {
+    let mut octets = [0; 16];
+    octets.copy_from_slice(bytes);
+    Ipv6Addr::from(octets)
+}
+
+impl Encodable for AddrV2 {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let len = self.network_id().consensus_encode(&mut s)?
+            + self.to_bytes().consensus_encode(&mut s)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for AddrV2 {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+        let network_id: u8 = Decodable::consensus_decode(&mut d)?;
+        let len = VarInt::consensus_decode(&mut d)?.0;
+        if len > MAX_ADDRV2_SIZE {
+            return Err(encode::Error::ParseFailed("addrv2 address too long"));
+        }
+        let mut bytes = vec![0; len as usize];
+        d.read_exact(&mut bytes)?;
+        AddrV2::from_bytes(network_id, bytes)
+    }
+}
+
+/// Error parsing an `AddrV2` from a string
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AddrV2ParseError {
+    /// Not an IP, onion or I2P address
+    Unrecognized,
+    /// Invalid base32 in an onion or I2P address
+    InvalidBase32,
+    /// Onion or I2P address of the wrong length
+    InvalidLength,
+    /// Tor v3 address with a wrong checksum
+    BadChecksum,
+    /// Onion address of an unknown version
+    UnknownVersion(u8),
+}
+
+impl fmt::Display for AddrV2ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AddrV2ParseError::UnknownVersion(v) => write!(f, "{}: {}", error::Error::description(self), v),
+            _ => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for AddrV2ParseError {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            AddrV2ParseError::Unrecognized => "unrecognized network address",
+            AddrV2ParseError::InvalidBase32 => "invalid base32 in network address",
+            AddrV2ParseError::InvalidLength => "invalid network address length",
+            AddrV2ParseError::BadChecksum => "bad onion address checksum",
+            AddrV2ParseError::UnknownVersion(_) => "unknown onion address version",
+        }
+    }
+}
+
+const BASE32_CHARS: &'static [u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// RFC4648 base32 in lower case, without padding
+fn base32_encode(data: &[u8]) -> String {
+    let mut ret = String::with_capacity((data.len() * 8 + 4) / 5);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for byte in data {
+        acc = acc << 8 | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            ret.push(BASE32_CHARS[(acc >> bits) as usize & 31] as char);
+        }
+    }
+    if bits > 0 {
+        ret.push(BASE32_CHARS[(acc << (5 - bits)) as usize & 31] as char);
+    }
+    ret
+}
+
+fn base32_decode(s: &str) -> Result<Vec<u8>, AddrV2ParseError> {
+    let mut ret = Vec::with_capacity(s.len() * 5 / 8);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in s.bytes() {
+        let c = c.to_ascii_lowercase();
+        let value = match BASE32_CHARS.iter().position(|x| *x == c) {
+            Some(v) => v as u32,
+            None => return Err(AddrV2ParseError::InvalidBase32),
+        };
+        acc = (acc << 5 | value) & 0xfff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            ret.push((acc >> bits) as u8);
+        }
+    }
+    // the padding bits must be zero
+    if acc & ((1 << bits) - 1) != 0 {
+        return Err(AddrV2ParseError::InvalidBase32);
+    }
+    Ok(ret)
+}
+
+const TORV3_VERSION: u8 = 3;
+
+/// The checksum of a Tor v3 onion address, the first two bytes of
+/// SHA3-256(".onion checksum" || pubkey || version)
+fn torv3_checksum(pubkey: &[u8; 32]) -> [u8; 2] {
+    let mut data = b".onion checksum".to_vec();
+    data.extend_from_slice(pubkey);
+    data.push(TORV3_VERSION);
+    let hash = sha3_256(&data);
+    [hash[0], hash[1]]
+}
+
+impl fmt::Display for AddrV2 {
+    /// IP addresses are shown as usual, Tor addresses as `<base32>.onion`
+    /// and I2P addresses as `<base32>.b32.i2p`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AddrV2::Ipv4(ref addr) => fmt::Display::fmt(addr, f),
+            AddrV2::Ipv6(ref addr) | AddrV2::Cjdns(ref addr) => fmt::Display::fmt(addr, f),
+            AddrV2::TorV2(ref addr) => write!(f, "{}.onion", base32_encode(addr)),
+            AddrV2::TorV3(ref pubkey) => {
+                let mut data = pubkey.to_vec();
+                data.extend_from_slice(&torv3_checksum(pubkey));
+                data.push(TORV3_VERSION);
+                write!(f, "{}.onion", base32_encode(&data))
+            }
+            AddrV2::I2p(ref addr) => write!(f, "{}.b32.i2p", base32_encode(addr)),
+            AddrV2::Unknown(id, ref addr) => {
+                write!(f, "unknown network {}:", id)?;
+                for byte in addr {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for AddrV2 {
+    type Err = AddrV2ParseError;
+
+    /// Parse an IP, onion or I2P address. CJDNS addresses are parsed as
+    /// IPv6, since they are only told apart by the network they are used on.
+    fn from_str(s: &str) -> Result<AddrV2, AddrV2ParseError> {
+        if s.ends_with(".onion") {
+            let encoded = &s[..s.len() - 6];
+            match encoded.len() {
+                16 => {
+                    let mut addr = [0; 10];
+                    addr.copy_from_slice(&base32_decode(encoded)?);
+                    Ok(AddrV2::TorV2(addr))
+                }
+                56 => {
+                    let data = base32_decode(encoded)?;
+                    if data[34] != TORV3_VERSION {
+                        return Err(AddrV2ParseError::UnknownVersion(data[34]));
+                    }
+                    let mut pubkey = [0; 32];
+                    pubkey.copy_from_slice(&data[..32]);
+                    if torv3_checksum(&pubkey)[..] != data[32..34] {
+                        return Err(AddrV2ParseError::BadChecksum);
+                    }
+                    Ok(AddrV2::TorV3(pubkey))
+                }
+                _ => Err(AddrV2ParseError::InvalidLength),
+            }
+        } else if s.ends_with(".b32.i2p") {
+            let encoded = &s[..s.len() - 8];
+            if encoded.len() != 52 {
+                return Err(AddrV2ParseError::InvalidLength);
+            }
+            let mut addr = [0; 32];
+            addr.copy_from_slice(&base32_decode(encoded)?);
+            Ok(AddrV2::I2p(addr))
+        } else if let Ok(addr) = Ipv4Addr::from_str(s) {
+            Ok(AddrV2::Ipv4(addr))
+        } else if let Ok(addr) = Ipv6Addr::from_str(s) {
+            Ok(AddrV2::Ipv6(addr))
+        } else {
+            Err(AddrV2ParseError::Unrecognized)
+        }
+    }
+}
+
+/// An entry of a BIP155 `addrv2` message
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct AddrV2Message {
+    /// Time the address was last seen
+    pub time: u32,
+    /// Services provided by the peer at the address
+    pub services: ServiceFlags,
+    /// The network address
+    pub addr: AddrV2,
+    /// Network port
+    pub port: u16,
+}
+
+impl AddrV2Message {
+    /// The socket address of an IPv4 or IPv6 entry.
+    /// This will return io::Error ErrorKind::AddrNotAvailable for the other networks.
+    pub fn socket_addr(&self) -> Result<SocketAddr, io::Error> {
+        match self.addr {
+            AddrV2::Ipv4(addr) => Ok(SocketAddr::V4(SocketAddrV4::new(addr, self.port))),
+            AddrV2::Ipv6(addr) => Ok(SocketAddr::V6(SocketAddrV6::new(addr, self.port, 0, 0))),
+            _ => Err(io::Error::from(io::ErrorKind::AddrNotAvailable)),
+        }
+    }
+}
+
+impl Encodable for AddrV2Message {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        // services are a compact size here, unlike in `addr`
+        let len = self.time.consensus_encode(&mut s)?
+            + VarInt(self.services.as_u64()).consensus_encode(&mut s)?
+            + self.addr.consensus_encode(&mut s)?
+            + self.port.to_be().consensus_encode(s)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for AddrV2Message {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+        Ok(AddrV2Message {
+            time: Decodable::consensus_decode(&mut d)?,
+            services: ServiceFlags::from(VarInt::consensus_decode(&mut d)?.0),
+            addr: Decodable::consensus_decode(&mut d)?,
+            port: u16::from_be(Decodable::consensus_decode(d)?),
+        })
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
-    use super::Address;
+    use super::{Address, AddrV2, AddrV2Message, AddrV2ParseError};
+    use network::constants::ServiceFlags;
     use std::net::{SocketAddr, IpAddr, Ipv4Addr, Ipv6Addr};
 
+    use hex::{decode as hex_decode, encode as hex_encode};
+
     use consensus::encode::{deserialize, serialize};
 
     #[test]
     fn serialize_address_test() {
         assert_eq!(serialize(&Address {
-            services: 1,
+            services: ServiceFlags::NETWORK,
             address: [0, 0, 0, 0, 0, 0xffff, 0x0a00, 0x0001],
             port: 8333
         }),
@@ -154,7 +503,7 @@ mod test {
                     _ => false
                 }
             );
-        assert_eq!(full.services, 1);
+        assert_eq!(full.services, ServiceFlags::NETWORK);
         assert_eq!(full.address, [0, 0, 0, 0, 0, 0xffff, 0x0a00, 0x0001]);
         assert_eq!(full.port, 8333);
 
@@ -166,11 +515,11 @@ mod test {
     #[test]
     fn test_socket_addr () {
         let s4 = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(111,222,123,4)), 5555);
-        let a4 = Address::new(&s4, 9);
+        let a4 = Address::new(&s4, ServiceFlags::NETWORK | ServiceFlags::WITNESS);
         assert_eq!(a4.socket_addr().unwrap(), s4);
         let s6 = SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0x1111, 0x2222, 0x3333, 0x4444,
         0x5555, 0x6666, 0x7777, 0x8888)), 9999);
-        let a6 = Address::new(&s6, 9);
+        let a6 = Address::new(&s6, ServiceFlags::NETWORK | ServiceFlags::WITNESS);
         assert_eq!(a6.socket_addr().unwrap(), s6);
     }
 
@@ -179,8 +528,84 @@ mod test {
         let onionaddr = SocketAddr::new(
             IpAddr::V6(
             Ipv6Addr::from_str("FD87:D87E:EB43:edb1:8e4:3588:e546:35ca").unwrap()), 1111);
-        let addr = Address::new(&onionaddr, 0);
+        let addr = Address::new(&onionaddr, ServiceFlags::NONE);
         assert!(addr.socket_addr().is_err());
     }
-}
 
+    #[test]
+    fn addrv2_string_test() {
+        let torv3 = "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion";
+        let addr = AddrV2::from_str(torv3).unwrap();
+        match addr {
+            AddrV2::TorV3(pubkey) => assert_eq!(hex_encode(&pubkey[..4]), "79bcc625"),
+            _ => panic!("expected a tor v3 address"),
+        }
+        assert_eq!(addr.to_string(), torv3);
+        assert_eq!(AddrV2::from_str(&torv3.to_uppercase().replace(".ONION", ".onion")).unwrap(), addr);
+
+        // a changed character breaks the checksum
+        let bad = "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscsyd.onion";
+        assert_eq!(AddrV2::from_str(bad), Err(AddrV2ParseError::BadChecksum));
+        let bad = "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscrya.onion";
+        assert_eq!(AddrV2::from_str(bad), Err(AddrV2ParseError::UnknownVersion(0)));
+        assert_eq!(AddrV2::from_str("pg6mmjiyjmcrsslv.onion"), Ok(AddrV2::TorV2([0x79, 0xbc, 0xc6, 0x25, 0x18, 0x4b, 0x05, 0x19, 0x49, 0x75])));
+        assert_eq!(AddrV2::from_str("pg6mmjiyjmcrsslv.onion").unwrap().to_string(), "pg6mmjiyjmcrsslv.onion");
+        assert_eq!(AddrV2::from_str("pg6mmjiyjmcrssl1.onion"), Err(AddrV2ParseError::InvalidBase32));
+        assert_eq!(AddrV2::from_str("pg6mmjiyjmcrssl.onion"), Err(AddrV2ParseError::InvalidLength));
+
+        let i2p = "ukeu3k5oycgaauneqgtnvselmt4yemvoilkln7jpvamvfx7dnkdq.b32.i2p";
+        let addr = AddrV2::from_str(i2p).unwrap();
+        assert_eq!(addr.network_id(), 5);
+        assert_eq!(addr.to_string(), i2p);
+        assert_eq!(AddrV2::from_str("ukeu3k5oycgaauneqgtnvselmt4yemvoilkln7jpvamvfx7dnkdr.b32.i2p"), Err(AddrV2ParseError::InvalidBase32));
+
+        assert_eq!(AddrV2::from_str("1.2.3.4"), Ok(AddrV2::Ipv4(Ipv4Addr::new(1, 2, 3, 4))));
+        assert_eq!(AddrV2::from_str("1:2:3:4:5:6:7:8").unwrap().to_string(), "1:2:3:4:5:6:7:8");
+        assert_eq!(AddrV2::from_str("example.com"), Err(AddrV2ParseError::Unrecognized));
+    }
+
+    #[test]
+    fn addrv2_encoding_test() {
+        // vectors from Bitcoin Core's net_tests
+        let vectors: Vec<(AddrV2, &str)> = vec![
+            (AddrV2::Ipv4(Ipv4Addr::new(1, 2, 3, 4)), "010401020304"),
+            (AddrV2::Ipv6(Ipv6Addr::from_str("1a1b:2a2b:3a3b:4a4b:5a5b:6a6b:7a7b:8a8b").unwrap()),
+             "02101a1b2a2b3a3b4a4b5a5b6a6b7a7b8a8b"),
+            (AddrV2::TorV2([0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa]), "030af1f2f3f4f5f6f7f8f9fa"),
+            (AddrV2::from_str("pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion").unwrap(),
+             "042079bcc625184b05194975c28b66b66b0469f7f6556fb1ac3189a79b40dda32f1f"),
+            (AddrV2::from_str("ukeu3k5oycgaauneqgtnvselmt4yemvoilkln7jpvamvfx7dnkdq.b32.i2p").unwrap(),
+             "0520a2894dabaec08c0051a481a6dac88b64f98232ae42d4b6fd2fa81952dfe36a87"),
+            (AddrV2::Cjdns(Ipv6Addr::from_str("fc00:1:2:3:4:5:6:7").unwrap()), "0610fc000001000200030004000500060007"),
+            (AddrV2::Unknown(0xaa, vec![0x01, 0x02]), "aa020102"),
+        ];
+        for (addr, hex) in vectors {
+            assert_eq!(hex_encode(&serialize(&addr)), hex);
+            assert_eq!(deserialize::<AddrV2>(&hex_decode(hex).unwrap()).unwrap(), addr);
+        }
+
+        // wrong length for the network
+        assert!(deserialize::<AddrV2>(&hex_decode("01050102030405").unwrap()).is_err());
+        assert!(deserialize::<AddrV2>(&hex_decode("0203010203").unwrap()).is_err());
+        // IPv4 and Tor addresses must use their own network id
+        assert!(deserialize::<AddrV2>(&hex_decode("021000000000000000000000ffff01020304").unwrap()).is_err());
+        assert!(deserialize::<AddrV2>(&hex_decode("0210fd87d87eeb43f1f2f3f4f5f6f7f8f9fa").unwrap()).is_err());
+        // CJDNS addresses must be in fc00::/8
+        assert!(deserialize::<AddrV2>(&hex_decode("0610aa000001000200030004000500060007").unwrap()).is_err());
+        // too long
+        assert!(deserialize::<AddrV2>(&hex_decode("aafd0102").unwrap()).is_err());
+
+        let msg = AddrV2Message {
+            time: 0x4966bc61,
+            services: ServiceFlags::NETWORK_LIMITED | ServiceFlags::WITNESS | ServiceFlags::NETWORK,
+            addr: AddrV2::Ipv4(Ipv4Addr::new(1, 2, 3, 4)),
+            port: 8333,
+        };
+        let bytes = serialize(&msg);
+        assert_eq!(hex_encode(&bytes), "61bc6649fd0904010401020304208d");
+        assert_eq!(deserialize::<AddrV2Message>(&bytes).unwrap(), msg);
+        assert_eq!(msg.socket_addr().unwrap(), SocketAddr::from_str("1.2.3.4:8333").unwrap());
+        let onion = AddrV2Message { addr: AddrV2::TorV2([0; 10]), ..msg };
+        assert!(onion.socket_addr().is_err());
+    }
+}
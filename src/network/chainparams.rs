@@ -0,0 +1,223 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Chain parameters
+//!
+//! This module defines `ChainParams`, which bundles everything that tells
+//! chains apart: the network magic, the genesis block, address and key
+//! prefixes, the default port, DNS seeds and the consensus parameters.
+//!
+//! `ChainParams::new` gives the parameters of the built-in networks. A
+//! private chain starts from the preset it was derived from and changes what
+//! differs, and is then used with the APIs taking `ChainParams`, like
+//! `Address::from_str_with_params` or `HeaderChain::with_chain_params`.
+//!
+//! # Example: a private regtest-like chain
+//!
+//! ```rust
+//! use bitcoin::network::chainparams::ChainParams;
+//! use bitcoin::network::constants::Network;
+//!
+//! let mut params = ChainParams::new(Network::Regtest);
+//! params.magic = 0x0B0B0B0B;
+//! params.bech32_hrp = "pt".to_owned();
+//! params.default_port = 19444;
+//! ```
+//!
+
+use blockdata::block::Block;
+use blockdata::constants::genesis_block;
+use consensus::params::Params;
+use network::constants::Network;
+
+/// Version bytes of base58 encoded addresses and keys
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Base58Prefixes {
+    /// Version byte of pay-to-pubkey-hash addresses
+    pub pubkey_address: u8,
+    /// Version byte of pay-to-script-hash addresses
+    pub script_address: u8,
+    /// Version byte of WIF private keys
+    pub secret_key: u8,
+    /// Version bytes of BIP32 extended public keys
+    pub ext_public_key: [u8; 4],
+    /// Version bytes of BIP32 extended private keys
+    pub ext_secret_key: [u8; 4],
+}
+
+/// The parameters of a chain
+#[derive(Clone, Debug)]
+pub struct ChainParams {
+    /// The built-in network these parameters derive from. Types which only
+    /// carry a `Network`, like `Address`, get this one when parsed with
+    /// these parameters.
+    pub network: Network,
+    /// Network magic, encoded little-endian at the start of every message
+    pub magic: u32,
+    /// The first block of the chain
+    pub genesis_block: Block,
+    /// Human readable part of bech32 addresses
+    pub bech32_hrp: String,
+    /// Version bytes of base58 encoded addresses and keys
+    pub base58_prefixes: Base58Prefixes,
+    /// Port nodes listen on by default
+    pub default_port: u16,
+    /// Host names resolving to addresses of nodes
+    pub dns_seeds: Vec<String>,
+    /// Consensus parameters
+    pub consensus: Params,
+}
+
+/// Base58 prefixes shared by all test networks
+const TEST_PREFIXES: Base58Prefixes = Base58Prefixes {
+    pubkey_address: 111,
+    script_address: 196,
+    secret_key: 239,
+    ext_public_key: [0x04, 0x35, 0x87, 0xCF],
+    ext_secret_key: [0x04, 0x35, 0x83, 0x94],
+};
+
+impl ChainParams {
+    /// The parameters of a built-in network
+    pub fn new(network: Network) -> ChainParams {
+        let (hrp, prefixes, port, seeds): (_, _, _, &[&str]) = match network {
+            Network::Bitcoin => ("bc", Base58Prefixes {
+                pubkey_address: 0,
+                script_address: 5,
+                secret_key: 128,
+                ext_public_key: [0x04, 0x88, 0xB2, 0x1E],
+                ext_secret_key: [0x04, 0x88, 0xAD, 0xE4],
+            }, 8333, &[
+                "seed.bitcoin.sipa.be",
+                "dnsseed.bluematt.me",
+                "dnsseed.bitcoin.dashjr.org",
+                "seed.bitcoinstats.com",
+                "seed.bitcoin.jonasschnelli.ch",
+                "seed.btc.petertodd.org",
+                "seed.bitcoin.sprovoost.nl",
+                "dnsseed.emzy.de",
+                "seed.bitcoin.wiz.biz",
+            ]),
+            Network::Testnet => ("tb", TEST_PREFIXES, 18333, &[
+                "testnet-seed.bitcoin.jonasschnelli.ch",
+                "seed.tbtc.petertodd.org",
+                "seed.testnet.bitcoin.sprovoost.nl",
+                "testnet-seed.bluematt.me",
+            ]),
+            Network::Signet => ("tb", TEST_PREFIXES, 38333, &[
+                "seed.signet.bitcoin.sprovoost.nl",
+            ]),
+            Network::Regtest => ("bcrt", TEST_PREFIXES, 18444, &[]),
+        };
+        ChainParams {
+            network: network,
+            magic: network.magic(),
+            genesis_block: genesis_block(network),
+            bech32_hrp: hrp.to_owned(),
+            base58_prefixes: prefixes,
+            default_port: port,
+            dns_seeds: seeds.iter().map(|s| s.to_string()).collect(),
+            consensus: Params::new(network),
+        }
+    }
+}
+
+impl From<Network> for ChainParams {
+    fn from(network: Network) -> ChainParams {
+        ChainParams::new(network)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use secp256k1::Secp256k1;
+
+    use blockdata::headerchain::{HeaderChain, MemoryHeaderStore};
+    use network::constants::Network;
+    use util::address::Address;
+    use util::bip32::{ExtendedPrivKey, ExtendedPubKey};
+    use util::hash::BitcoinHash;
+    use util::key::PrivateKey;
+
+    use super::ChainParams;
+
+    #[test]
+    fn presets_test() {
+        for &network in [Network::Bitcoin, Network::Testnet, Network::Signet, Network::Regtest].iter() {
+            let params = ChainParams::new(network);
+            assert_eq!(params.network, network);
+            assert_eq!(params.magic, network.magic());
+            assert_eq!(params.consensus.network, network);
+        }
+
+        let params = ChainParams::new(Network::Bitcoin);
+        assert_eq!(format!("{:x}", params.genesis_block.bitcoin_hash()),
+                   "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f");
+        assert_eq!(params.default_port, 8333);
+        assert_eq!(params.bech32_hrp, "bc");
+        assert_eq!(ChainParams::new(Network::Regtest).bech32_hrp, "bcrt");
+        assert!(ChainParams::new(Network::Regtest).dns_seeds.is_empty());
+        assert_eq!(ChainParams::new(Network::Signet).default_port, 38333);
+    }
+
+    #[test]
+    fn private_chain_test() {
+        let secp = Secp256k1::new();
+        let mut params = ChainParams::new(Network::Regtest);
+        params.bech32_hrp = "pt".to_owned();
+        params.base58_prefixes.pubkey_address = 55;
+        params.base58_prefixes.script_address = 56;
+        params.base58_prefixes.secret_key = 57;
+        params.base58_prefixes.ext_public_key = [0x01, 0x02, 0x03, 0x04];
+        params.base58_prefixes.ext_secret_key = [0x05, 0x06, 0x07, 0x08];
+        params.genesis_block.header.time += 1;
+
+        let xpriv = ExtendedPrivKey::new_master(Network::Regtest, &[7; 32]).unwrap();
+        let xpub = ExtendedPubKey::from_private(&secp, &xpriv);
+        let pk = xpub.public_key;
+
+        for addr in [Address::p2pkh(&pk, Network::Regtest), Address::p2shwpkh(&pk, Network::Regtest),
+                     Address::p2wpkh(&pk, Network::Regtest)].iter() {
+            let s = addr.to_string_with_params(&params);
+            assert!(s != addr.to_string());
+            assert_eq!(&Address::from_str_with_params(&s, &params).unwrap(), addr);
+            assert!(Address::from_str_with_params(&addr.to_string(), &params).is_err());
+        }
+        assert!(Address::p2wpkh(&pk, Network::Regtest).to_string_with_params(&params).starts_with("pt1"));
+        // the presets give the usual encodings
+        let addr = Address::p2pkh(&pk, Network::Bitcoin);
+        assert_eq!(addr.to_string_with_params(&ChainParams::new(Network::Bitcoin)), addr.to_string());
+
+        let wif = {
+            let mut s = String::new();
+            xpriv.private_key.fmt_wif_with_params(&mut s, &params).unwrap();
+            s
+        };
+        let key = PrivateKey::from_wif_with_params(&wif, &params).unwrap();
+        assert_eq!(key.key, xpriv.private_key.key);
+        assert!(PrivateKey::from_wif_with_params(&xpriv.private_key.to_wif(), &params).is_err());
+
+        let s = xpriv.to_string_with_params(&params);
+        assert_eq!(ExtendedPrivKey::from_str_with_params(&s, &params).unwrap(), xpriv);
+        assert!(ExtendedPrivKey::from_str(&s).is_err());
+        let s = xpub.to_string_with_params(&params);
+        assert_eq!(ExtendedPubKey::from_str_with_params(&s, &params).unwrap(), xpub);
+        assert!(ExtendedPubKey::from_str_with_params(&xpub.to_string(), &params).is_err());
+
+        let chain = HeaderChain::with_chain_params(&params, MemoryHeaderStore::new());
+        assert_eq!(chain.tip_hash(), params.genesis_block.bitcoin_hash());
+    }
+}
@@ -0,0 +1,242 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Async codec
+//!
+//! This module defines `RawNetworkMessageCodec`, a `tokio-util` codec which
+//! turns any `AsyncRead` into a stream of `RawNetworkMessage`s with
+//! `FramedRead`, and any `AsyncWrite` into a sink of them with `FramedWrite`.
+//! Unlike `StreamReader` it parses the 24-byte message header first, so it
+//! rejects messages for another network or with an oversized payload before
+//! buffering them, and it only decodes a message once all of it arrived.
+//!
+//! It is available with the `async-codec` feature.
+//!
+
+use std::cmp;
+
+use byteorder::{ByteOrder, LittleEndian};
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+use consensus::encode::{self, deserialize, serialize, MAX_VEC_SIZE};
+use network::chainparams::ChainParams;
+use network::constants::Network;
+use network::message::RawNetworkMessage;
+
+/// Size of the message header: magic, command, payload length and checksum
+const HEADER_SIZE: usize = 24;
+
+/// Most buffer space reserved at once for a partially received message, so
+/// a header announcing a large payload does not make us allocate it up front
+const MAX_RESERVE: usize = 64 * 1024;
+
+/// Codec for the messages of one network
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RawNetworkMessageCodec {
+    magic: u32,
+    max_payload_size: usize,
+}
+
+impl RawNetworkMessageCodec {
+    /// Constructs a codec for messages on `network`, accepting payloads up
+    /// to `MAX_VEC_SIZE`
+    pub fn new(network: Network) -> RawNetworkMessageCodec {
+        RawNetworkMessageCodec::with_max_payload_size(network, MAX_VEC_SIZE)
+    }
+
+    /// Constructs a codec for messages on `network`, accepting payloads up
+    /// to `max_payload_size` bytes
+    pub fn with_max_payload_size(network: Network, max_payload_size: usize) -> RawNetworkMessageCodec {
+        RawNetworkMessageCodec::with_magic(network.magic(), max_payload_size)
+    }
+
+    /// Constructs a codec for messages of the chain described by `params`,
+    /// accepting payloads up to `MAX_VEC_SIZE`
+    pub fn with_chain_params(params: &ChainParams) -> RawNetworkMessageCodec {
+        RawNetworkMessageCodec::with_magic(params.magic, MAX_VEC_SIZE)
+    }
+
+    /// Constructs a codec for messages with `magic`, accepting payloads up
+    /// to `max_payload_size` bytes
+    pub fn with_magic(magic: u32, max_payload_size: usize) -> RawNetworkMessageCodec {
+        RawNetworkMessageCodec {
+            magic: magic,
+            max_payload_size: max_payload_size,
+        }
+    }
+}
+
+impl Decoder for RawNetworkMessageCodec {
+    type Item = RawNetworkMessage;
+    type Error = encode::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<RawNetworkMessage>, encode::Error> {
+        if src.len() < HEADER_SIZE {
+            src.reserve(HEADER_SIZE - src.len());
+            return Ok(None);
+        }
+
+        let magic = LittleEndian::read_u32(&src[0..4]);
+        if magic != self.magic {
+            return Err(encode::Error::UnexpectedNetworkMagic {
+                expected: self.magic,
+                actual: magic,
+            });
+        }
+        let payload_size = LittleEndian::read_u32(&src[16..20]) as usize;
+        if payload_size > self.max_payload_size {
+            return Err(encode::Error::OversizedMessage {
+                size: payload_size,
+                max: self.max_payload_size,
+            });
+        }
+
+        let size = HEADER_SIZE + payload_size;
+        if src.len() < size {
+            src.reserve(cmp::min(size - src.len(), MAX_RESERVE));
+            return Ok(None);
+        }
+        // the checksum is verified while decoding
+        let message = src.split_to(size);
+        Ok(Some(deserialize(&message)?))
+    }
+}
+
+impl Encoder<RawNetworkMessage> for RawNetworkMessageCodec {
+    type Error = encode::Error;
+
+    fn encode(&mut self, message: RawNetworkMessage, dst: &mut BytesMut) -> Result<(), encode::Error> {
+        dst.extend_from_slice(&serialize(&message));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use futures::executor::block_on;
+    use futures::stream::StreamExt;
+    use tokio_util::codec::{Decoder, Encoder, FramedRead};
+
+    use consensus::encode::{self, serialize};
+    use network::chainparams::ChainParams;
+    use network::constants::Network;
+    use network::message::{NetworkMessage, RawNetworkMessage};
+
+    use super::RawNetworkMessageCodec;
+
+    fn message(payload: NetworkMessage) -> RawNetworkMessage {
+        RawNetworkMessage { magic: Network::Bitcoin.magic(), payload: payload }
+    }
+
+    #[test]
+    fn decode_partial_test() {
+        let mut codec = RawNetworkMessageCodec::new(Network::Bitcoin);
+        let bytes = serialize(&message(NetworkMessage::Ping(100)));
+        let mut buf = BytesMut::new();
+        for byte in &bytes[..bytes.len() - 1] {
+            buf.extend_from_slice(&[*byte]);
+            assert!(codec.decode(&mut buf).unwrap().is_none());
+        }
+        buf.extend_from_slice(&bytes[bytes.len() - 1..]);
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap().payload, NetworkMessage::Ping(100));
+        assert!(buf.is_empty());
+
+        // several messages at once
+        let mut buf = BytesMut::new();
+        codec.encode(message(NetworkMessage::Verack), &mut buf).unwrap();
+        codec.encode(message(NetworkMessage::Pong(7)), &mut buf).unwrap();
+        buf.extend_from_slice(&bytes[..10]);
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap().payload, NetworkMessage::Verack);
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap().payload, NetworkMessage::Pong(7));
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert_eq!(buf.len(), 10);
+
+        // the buffer is not grown to a large announced payload at once
+        let mut header = bytes[..24].to_vec();
+        header[16..20].copy_from_slice(&[0x00, 0x00, 0x10, 0x00]);
+        let mut buf = BytesMut::from(&header[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert!(buf.capacity() < 0x100000);
+    }
+
+    #[test]
+    fn decode_error_test() {
+        let mut codec = RawNetworkMessageCodec::with_max_payload_size(Network::Bitcoin, 4);
+        let bytes = serialize(&message(NetworkMessage::Ping(100)));
+
+        // rejected from the header alone
+        match codec.decode(&mut BytesMut::from(&bytes[..24])) {
+            Err(encode::Error::OversizedMessage { size: 8, max: 4 }) => {}
+            x => panic!("unexpected result {:?}", x),
+        }
+        let mut testnet = RawNetworkMessageCodec::new(Network::Testnet);
+        match testnet.decode(&mut BytesMut::from(&bytes[..24])) {
+            Err(encode::Error::UnexpectedNetworkMagic { .. }) => {}
+            x => panic!("unexpected result {:?}", x),
+        }
+
+        let mut codec = RawNetworkMessageCodec::new(Network::Bitcoin);
+        let mut bad = bytes.clone();
+        bad[30] ^= 1;
+        match codec.decode(&mut BytesMut::from(&bad[..])) {
+            Err(encode::Error::InvalidChecksum { .. }) => {}
+            x => panic!("unexpected result {:?}", x),
+        }
+    }
+
+    #[test]
+    fn custom_magic_test() {
+        let mut params = ChainParams::new(Network::Regtest);
+        params.magic = 0x0B0B0B0B;
+        let mut codec = RawNetworkMessageCodec::with_chain_params(&params);
+        let msg = RawNetworkMessage { magic: params.magic, payload: NetworkMessage::Ping(5) };
+        let mut buf = BytesMut::from(&serialize(&msg)[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap().payload, NetworkMessage::Ping(5));
+
+        let mut regtest = RawNetworkMessageCodec::new(Network::Regtest);
+        match regtest.decode(&mut BytesMut::from(&serialize(&msg)[..])) {
+            Err(encode::Error::UnexpectedNetworkMagic { expected, actual: 0x0B0B0B0B }) => {
+                assert_eq!(expected, Network::Regtest.magic());
+            }
+            x => panic!("unexpected result {:?}", x),
+        }
+
+        let mut small = RawNetworkMessageCodec::with_magic(params.magic, 4);
+        match small.decode(&mut BytesMut::from(&serialize(&msg)[..])) {
+            Err(encode::Error::OversizedMessage { size: 8, max: 4 }) => {}
+            x => panic!("unexpected result {:?}", x),
+        }
+    }
+
+    #[test]
+    fn framed_read_test() {
+        let messages = vec![
+            message(NetworkMessage::Verack),
+            message(NetworkMessage::Ping(1)),
+            message(NetworkMessage::GetAddr),
+        ];
+        let mut bytes = vec![];
+        for msg in &messages {
+            bytes.extend_from_slice(&serialize(msg));
+        }
+
+        let stream = FramedRead::new(&bytes[..], RawNetworkMessageCodec::new(Network::Bitcoin));
+        let received: Vec<_> = block_on(stream.collect());
+        let received: Vec<_> = received.into_iter().map(|msg| msg.unwrap().payload).collect();
+        let expected: Vec<_> = messages.into_iter().map(|msg| msg.payload).collect();
+        assert_eq!(received, expected);
+    }
+}
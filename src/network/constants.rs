@@ -37,10 +37,14 @@
 //! assert_eq!(&bytes[..], &[0xF9, 0xBE, 0xB4, 0xD9]);
 //! ```
 
+use std::{fmt, io, ops};
+
+use consensus::encode::{self, Decodable, Encodable};
+
 /// Version of the protocol as appearing in network message headers
 pub const PROTOCOL_VERSION: u32 = 70001;
 /// Bitfield of services provided by this node
-pub const SERVICES: u64 = 0;
+pub const SERVICES: ServiceFlags = ServiceFlags::NONE;
 /// User agent as it appears in the version message
 pub const USER_AGENT: &'static str = "bitcoin-rust v0.1";
 
@@ -52,6 +56,8 @@ user_enum! {
         Bitcoin <-> "bitcoin",
         /// Bitcoin's testnet
         Testnet <-> "testnet",
+        /// Bitcoin's default signet (BIP325)
+        Signet <-> "signet",
         /// Bitcoin's regtest
         Regtest <-> "regtest"
     }
@@ -73,6 +79,7 @@ impl Network {
         match magic {
             0xD9B4BEF9 => Some(Network::Bitcoin),
             0x0709110B => Some(Network::Testnet),
+            0x40CF030A => Some(Network::Signet),
             0xDAB5BFFA => Some(Network::Regtest),
             _ => None
         }
@@ -94,14 +101,159 @@ impl Network {
         match *self {
             Network::Bitcoin => 0xD9B4BEF9,
             Network::Testnet => 0x0709110B,
+            Network::Signet => 0x40CF030A,
             Network::Regtest => 0xDAB5BFFA,
         }
     }
 }
 
+/// Flags for the services offered by a node, as announced in `version`
+/// and `addr` messages. Bits without a name are kept as they are.
+#[derive(Copy, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Debug, Default)]
+pub struct ServiceFlags(u64);
+
+impl ServiceFlags {
+    /// No services
+    pub const NONE: ServiceFlags = ServiceFlags(0);
+    /// NODE_NETWORK: serves the full block chain
+    pub const NETWORK: ServiceFlags = ServiceFlags(1 << 0);
+    /// NODE_GETUTXO: answers `getutxo` (BIP64)
+    pub const GETUTXO: ServiceFlags = ServiceFlags(1 << 1);
+    /// NODE_BLOOM: handles bloom filtered connections (BIP111)
+    pub const BLOOM: ServiceFlags = ServiceFlags(1 << 2);
+    /// NODE_WITNESS: serves blocks and transactions with witnesses (BIP144)
+    pub const WITNESS: ServiceFlags = ServiceFlags(1 << 3);
+    /// NODE_COMPACT_FILTERS: serves compact block filters (BIP157)
+    pub const COMPACT_FILTERS: ServiceFlags = ServiceFlags(1 << 6);
+    /// NODE_NETWORK_LIMITED: serves the last 288 blocks (BIP159)
+    pub const NETWORK_LIMITED: ServiceFlags = ServiceFlags(1 << 10);
+
+    /// The named flags, in bit order
+    const NAMED: [(ServiceFlags, &'static str); 6] = [
+        (ServiceFlags::NETWORK, "NETWORK"),
+        (ServiceFlags::GETUTXO, "GETUTXO"),
+        (ServiceFlags::BLOOM, "BLOOM"),
+        (ServiceFlags::WITNESS, "WITNESS"),
+        (ServiceFlags::COMPACT_FILTERS, "COMPACT_FILTERS"),
+        (ServiceFlags::NETWORK_LIMITED, "NETWORK_LIMITED"),
+    ];
+
+    /// Add the flags of `other` to these and return the result.
+    pub fn add(&mut self, other: ServiceFlags) -> ServiceFlags {
+        self.0 |= other.0;
+        *self
+    }
+
+    /// Remove the flags of `other` from these and return the result.
+    pub fn remove(&mut self, other: ServiceFlags) -> ServiceFlags {
+        self.0 &= !other.0;
+        *self
+    }
+
+    /// Whether all flags of `flags` are set.
+    pub fn has(&self, flags: ServiceFlags) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+
+    /// The flags as a bitfield
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for ServiceFlags {
+    fn from(f: u64) -> Self {
+        ServiceFlags(f)
+    }
+}
+
+impl From<ServiceFlags> for u64 {
+    fn from(f: ServiceFlags) -> Self {
+        f.0
+    }
+}
+
+impl fmt::LowerHex for ServiceFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for ServiceFlags {
+    /// Lists the names of the flags, and unknown bits in hex, as
+    /// `ServiceFlags(NETWORK|WITNESS|0x1000)`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut flags = *self;
+        if flags == ServiceFlags::NONE {
+            return f.write_str("ServiceFlags(NONE)");
+        }
+        f.write_str("ServiceFlags(")?;
+        let mut first = true;
+        for &(flag, name) in ServiceFlags::NAMED.iter() {
+            if flags.has(flag) {
+                if !first {
+                    f.write_str("|")?;
+                }
+                first = false;
+                f.write_str(name)?;
+                flags.remove(flag);
+            }
+        }
+        if flags != ServiceFlags::NONE {
+            if !first {
+                f.write_str("|")?;
+            }
+            write!(f, "0x{:x}", flags)?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl ops::BitOr for ServiceFlags {
+    type Output = Self;
+
+    fn bitor(mut self, rhs: Self) -> Self {
+        self.add(rhs)
+    }
+}
+
+impl ops::BitOrAssign for ServiceFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.add(rhs);
+    }
+}
+
+impl ops::BitXor for ServiceFlags {
+    type Output = Self;
+
+    fn bitxor(self, rhs: Self) -> Self {
+        ServiceFlags(self.0 ^ rhs.0)
+    }
+}
+
+impl ops::BitXorAssign for ServiceFlags {
+    fn bitxor_assign(&mut self, rhs: Self) {
+        self.0 ^= rhs.0;
+    }
+}
+
+impl Encodable for ServiceFlags {
+    #[inline]
+    fn consensus_encode<S: io::Write>(&self, s: S) -> Result<usize, encode::Error> {
+        self.0.consensus_encode(s)
+    }
+}
+
+impl Decodable for ServiceFlags {
+    #[inline]
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Ok(ServiceFlags(Decodable::consensus_decode(d)?))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Network;
+    use super::{Network, ServiceFlags};
     use consensus::encode::{deserialize, serialize};
 
     #[test]
@@ -114,6 +266,10 @@ mod tests {
             serialize(&Network::Testnet.magic()),
             &[0x0b, 0x11, 0x09, 0x07]
         );
+        assert_eq!(
+            serialize(&Network::Signet.magic()),
+            &[0x0a, 0x03, 0xcf, 0x40]
+        );
         assert_eq!(
             serialize(&Network::Regtest.magic()),
             &[0xfa, 0xbf, 0xb5, 0xda]
@@ -127,20 +283,70 @@ mod tests {
             deserialize(&[0x0b, 0x11, 0x09, 0x07]).ok(),
             Some(Network::Testnet.magic())
         );
+        assert_eq!(
+            deserialize(&[0x0a, 0x03, 0xcf, 0x40]).ok(),
+            Some(Network::Signet.magic())
+        );
         assert_eq!(
             deserialize(&[0xfa, 0xbf, 0xb5, 0xda]).ok(),
             Some(Network::Regtest.magic())
         );
+        assert_eq!(Network::from_magic(0x40CF030A), Some(Network::Signet));
+    }
+
+    #[test]
+    fn service_flags_test() {
+        let all = [
+            ServiceFlags::NETWORK,
+            ServiceFlags::GETUTXO,
+            ServiceFlags::BLOOM,
+            ServiceFlags::WITNESS,
+            ServiceFlags::COMPACT_FILTERS,
+            ServiceFlags::NETWORK_LIMITED,
+        ];
+
+        let mut flags = ServiceFlags::NONE;
+        for f in all.iter() {
+            assert!(!flags.has(*f));
+        }
+        flags |= ServiceFlags::WITNESS;
+        assert_eq!(flags, ServiceFlags::WITNESS);
+
+        let mut flags2 = flags | ServiceFlags::GETUTXO;
+        for f in all.iter() {
+            assert_eq!(flags2.has(*f), *f == ServiceFlags::WITNESS || *f == ServiceFlags::GETUTXO);
+        }
+        flags2 ^= ServiceFlags::WITNESS;
+        assert_eq!(flags2, ServiceFlags::GETUTXO);
+        flags2.remove(ServiceFlags::GETUTXO);
+        assert_eq!(flags2, ServiceFlags::NONE);
+        assert!(flags2.has(ServiceFlags::NONE));
+
+        flags2.add(ServiceFlags::WITNESS);
+        assert_eq!(flags2, flags);
+
+        // unknown bits survive encoding and are shown in hex
+        let flag = ServiceFlags::from(0b11 << 14) | ServiceFlags::WITNESS | ServiceFlags::NETWORK;
+        assert_eq!(flag.as_u64(), 0xc009);
+        assert_eq!(deserialize::<ServiceFlags>(&serialize(&flag)).unwrap(), flag);
+        assert_eq!(serialize(&flag), serialize(&0xc009u64));
+        assert_eq!("ServiceFlags(NETWORK|WITNESS|0xc000)", flag.to_string());
+        assert_eq!("ServiceFlags(0x800000)", ServiceFlags::from(0x800000).to_string());
+        assert_eq!("ServiceFlags(NONE)", ServiceFlags::NONE.to_string());
+        assert_eq!("ServiceFlags(COMPACT_FILTERS|NETWORK_LIMITED)",
+                   (ServiceFlags::COMPACT_FILTERS | ServiceFlags::NETWORK_LIMITED).to_string());
     }
 
   #[test]
   fn string_test() {
       assert_eq!(Network::Bitcoin.to_string(), "bitcoin");
       assert_eq!(Network::Testnet.to_string(), "testnet");
+      assert_eq!(Network::Signet.to_string(), "signet");
       assert_eq!(Network::Regtest.to_string(), "regtest");
 
       assert_eq!("bitcoin".parse::<Network>().unwrap(), Network::Bitcoin);
       assert_eq!("testnet".parse::<Network>().unwrap(), Network::Testnet);
+      assert_eq!("signet".parse::<Network>().unwrap(), Network::Signet);
       assert_eq!("regtest".parse::<Network>().unwrap(), Network::Regtest);
       assert!("fakenet".parse::<Network>().is_err());
   }
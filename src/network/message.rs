@@ -24,13 +24,16 @@ use std::io::Cursor;
 
 use blockdata::block;
 use blockdata::transaction;
-use network::address::Address;
+use network::address::{Address, AddrV2Message};
 use network::message_network;
 use network::message_blockdata;
 use network::message_filter;
 use consensus::encode::{CheckedData, Decodable, Encodable, VarInt};
 use consensus::{encode, serialize};
 use consensus::encode::MAX_VEC_SIZE;
+use util::bip152;
+use util::bloom;
+use util::merkleblock;
 
 /// Serializer for command string
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -87,6 +90,8 @@ pub enum NetworkMessage {
     Verack,
     /// `addr`
     Addr(Vec<(u32, Address)>),
+    /// BIP155 `addrv2`
+    AddrV2(Vec<AddrV2Message>),
     /// `inv`
     Inv(Vec<message_blockdata::Inventory>),
     /// `getdata`
@@ -107,6 +112,10 @@ pub enum NetworkMessage {
     Headers(Vec<block::BlockHeader>),
     /// `sendheaders`
     SendHeaders,
+    /// BIP339 `wtxidrelay`
+    WtxidRelay,
+    /// BIP155 `sendaddrv2`
+    SendAddrV2,
     /// `getaddr`
     GetAddr,
     // TODO: checkorder,
@@ -117,7 +126,22 @@ pub enum NetworkMessage {
     /// `pong`
     Pong(u64),
     // TODO: reject,
-    // TODO: bloom filtering
+    /// BIP37 `filterload`
+    FilterLoad(bloom::BloomFilter),
+    /// BIP37 `filteradd`
+    FilterAdd(Vec<u8>),
+    /// BIP37 `filterclear`
+    FilterClear,
+    /// BIP37 `merkleblock`
+    MerkleBlock(merkleblock::MerkleBlock),
+    /// BIP152 `sendcmpct`
+    SendCmpct(message_network::SendCmpct),
+    /// BIP152 `cmpctblock`
+    CmpctBlock(bip152::HeaderAndShortIds),
+    /// BIP152 `getblocktxn`
+    GetBlockTxn(bip152::BlockTransactionsRequest),
+    /// BIP152 `blocktxn`
+    BlockTxn(bip152::BlockTransactions),
     /// BIP157 getcfilters
     GetCFilters(message_filter::GetCFilters),
     /// BIP157 cfilter
@@ -143,6 +167,7 @@ impl RawNetworkMessage {
             NetworkMessage::Version(_) => "version",
             NetworkMessage::Verack     => "verack",
             NetworkMessage::Addr(_)    => "addr",
+            NetworkMessage::AddrV2(_)  => "addrv2",
             NetworkMessage::Inv(_)     => "inv",
             NetworkMessage::GetData(_) => "getdata",
             NetworkMessage::NotFound(_) => "notfound",
@@ -153,9 +178,19 @@ impl RawNetworkMessage {
             NetworkMessage::Block(_)   => "block",
             NetworkMessage::Headers(_) => "headers",
             NetworkMessage::SendHeaders => "sendheaders",
+            NetworkMessage::WtxidRelay => "wtxidrelay",
+            NetworkMessage::SendAddrV2 => "sendaddrv2",
             NetworkMessage::GetAddr    => "getaddr",
             NetworkMessage::Ping(_)    => "ping",
             NetworkMessage::Pong(_)    => "pong",
+            NetworkMessage::FilterLoad(_) => "filterload",
+            NetworkMessage::FilterAdd(_) => "filteradd",
+            NetworkMessage::FilterClear => "filterclear",
+            NetworkMessage::MerkleBlock(_) => "merkleblock",
+            NetworkMessage::SendCmpct(_) => "sendcmpct",
+            NetworkMessage::CmpctBlock(_) => "cmpctblock",
+            NetworkMessage::GetBlockTxn(_) => "getblocktxn",
+            NetworkMessage::BlockTxn(_) => "blocktxn",
             NetworkMessage::GetCFilters(_) => "getcfilters",
             NetworkMessage::CFilter(_) => "cfilter",
             NetworkMessage::GetCFHeaders(_) => "getcfheaders",
@@ -197,6 +232,7 @@ impl Encodable for RawNetworkMessage {
         len += CheckedData(match self.payload {
             NetworkMessage::Version(ref dat) => serialize(dat),
             NetworkMessage::Addr(ref dat)    => serialize(dat),
+            NetworkMessage::AddrV2(ref dat)  => serialize(dat),
             NetworkMessage::Inv(ref dat)     => serialize(dat),
             NetworkMessage::GetData(ref dat) => serialize(dat),
             NetworkMessage::NotFound(ref dat) => serialize(dat),
@@ -207,6 +243,13 @@ impl Encodable for RawNetworkMessage {
             NetworkMessage::Headers(ref dat) => serialize(&HeaderSerializationWrapper(dat)),
             NetworkMessage::Ping(ref dat)    => serialize(dat),
             NetworkMessage::Pong(ref dat)    => serialize(dat),
+            NetworkMessage::FilterLoad(ref dat) => serialize(dat),
+            NetworkMessage::FilterAdd(ref dat) => serialize(dat),
+            NetworkMessage::MerkleBlock(ref dat) => serialize(dat),
+            NetworkMessage::SendCmpct(ref dat) => serialize(dat),
+            NetworkMessage::CmpctBlock(ref dat) => serialize(dat),
+            NetworkMessage::GetBlockTxn(ref dat) => serialize(dat),
+            NetworkMessage::BlockTxn(ref dat) => serialize(dat),
             NetworkMessage::GetCFilters(ref dat) => serialize(dat),
             NetworkMessage::CFilter(ref dat) => serialize(dat),
             NetworkMessage::GetCFHeaders(ref dat) => serialize(dat),
@@ -217,8 +260,11 @@ impl Encodable for RawNetworkMessage {
             NetworkMessage::Reject(ref dat) => serialize(dat),
             NetworkMessage::Verack
             | NetworkMessage::SendHeaders
+            | NetworkMessage::WtxidRelay
+            | NetworkMessage::SendAddrV2
             | NetworkMessage::MemPool
-            | NetworkMessage::GetAddr => vec![],
+            | NetworkMessage::GetAddr
+            | NetworkMessage::FilterClear => vec![],
         }).consensus_encode(&mut s)?;
         Ok(len)
     }
@@ -258,6 +304,7 @@ impl Decodable for RawNetworkMessage {
             "version" => NetworkMessage::Version(Decodable::consensus_decode(&mut mem_d)?),
             "verack"  => NetworkMessage::Verack,
             "addr"    => NetworkMessage::Addr(Decodable::consensus_decode(&mut mem_d)?),
+            "addrv2"  => NetworkMessage::AddrV2(Decodable::consensus_decode(&mut mem_d)?),
             "inv"     => NetworkMessage::Inv(Decodable::consensus_decode(&mut mem_d)?),
             "getdata" => NetworkMessage::GetData(Decodable::consensus_decode(&mut mem_d)?),
             "notfound" => NetworkMessage::NotFound(Decodable::consensus_decode(&mut mem_d)?),
@@ -269,10 +316,20 @@ impl Decodable for RawNetworkMessage {
                 HeaderDeserializationWrapper::consensus_decode(&mut mem_d)?.0
             ),
             "sendheaders" => NetworkMessage::SendHeaders,
+            "wtxidrelay" => NetworkMessage::WtxidRelay,
+            "sendaddrv2" => NetworkMessage::SendAddrV2,
             "getaddr" => NetworkMessage::GetAddr,
             "ping"    => NetworkMessage::Ping(Decodable::consensus_decode(&mut mem_d)?),
             "pong"    => NetworkMessage::Pong(Decodable::consensus_decode(&mut mem_d)?),
             "tx"      => NetworkMessage::Tx(Decodable::consensus_decode(&mut mem_d)?),
+            "filterload" => NetworkMessage::FilterLoad(Decodable::consensus_decode(&mut mem_d)?),
+            "filteradd" => NetworkMessage::FilterAdd(Decodable::consensus_decode(&mut mem_d)?),
+            "filterclear" => NetworkMessage::FilterClear,
+            "merkleblock" => NetworkMessage::MerkleBlock(Decodable::consensus_decode(&mut mem_d)?),
+            "sendcmpct" => NetworkMessage::SendCmpct(Decodable::consensus_decode(&mut mem_d)?),
+            "cmpctblock" => NetworkMessage::CmpctBlock(Decodable::consensus_decode(&mut mem_d)?),
+            "getblocktxn" => NetworkMessage::GetBlockTxn(Decodable::consensus_decode(&mut mem_d)?),
+            "blocktxn" => NetworkMessage::BlockTxn(Decodable::consensus_decode(&mut mem_d)?),
             "getcfilters" => NetworkMessage::GetCFilters(Decodable::consensus_decode(&mut mem_d)?),
             "cfilter" => NetworkMessage::CFilter(Decodable::consensus_decode(&mut mem_d)?),
             "getcfheaders" => NetworkMessage::GetCFHeaders(Decodable::consensus_decode(&mut mem_d)?),
@@ -294,6 +351,9 @@ impl Decodable for RawNetworkMessage {
 mod test {
     use super::{RawNetworkMessage, NetworkMessage, CommandString};
     use consensus::encode::{deserialize, deserialize_partial, serialize};
+    use network::address::{AddrV2, AddrV2Message};
+    use network::constants::ServiceFlags;
+    use util::bloom::{BloomFilter, BloomFlags};
 
     #[test]
     fn serialize_commandstring_test() {
@@ -383,7 +443,7 @@ mod test {
         assert_eq!(msg.magic, 0xd9b4bef9);
         if let NetworkMessage::Version(version_msg) = msg.payload {
             assert_eq!(version_msg.version, 70015);
-            assert_eq!(version_msg.services, 1037);
+            assert_eq!(version_msg.services, ServiceFlags::NETWORK | ServiceFlags::BLOOM | ServiceFlags::WITNESS | ServiceFlags::NETWORK_LIMITED);
             assert_eq!(version_msg.timestamp, 1548554224);
             assert_eq!(version_msg.nonce, 13952548347456104954);
             assert_eq!(version_msg.user_agent, "/Satoshi:0.17.1/");
@@ -420,7 +480,7 @@ mod test {
         assert_eq!(msg.magic, 0xd9b4bef9);
         if let NetworkMessage::Version(version_msg) = msg.payload {
             assert_eq!(version_msg.version, 70015);
-            assert_eq!(version_msg.services, 1037);
+            assert_eq!(version_msg.services, ServiceFlags::NETWORK | ServiceFlags::BLOOM | ServiceFlags::WITNESS | ServiceFlags::NETWORK_LIMITED);
             assert_eq!(version_msg.timestamp, 1548554224);
             assert_eq!(version_msg.nonce, 13952548347456104954);
             assert_eq!(version_msg.user_agent, "/Satoshi:0.17.1/");
@@ -430,4 +490,42 @@ mod test {
             panic!("Wrong message type");
         }
     }
+
+    #[test]
+    fn bloom_messages_roundtrip_test() {
+        let mut filter = BloomFilter::new(3, 0.01, 0, BloomFlags::All);
+        filter.insert(&[0x99, 0x10, 0x8a, 0xd8]);
+        let messages = vec![
+            NetworkMessage::FilterLoad(filter),
+            NetworkMessage::FilterAdd(vec![0xde, 0xad, 0xbe, 0xef]),
+            NetworkMessage::FilterClear,
+        ];
+        for payload in messages {
+            let msg = RawNetworkMessage { magic: 0xd9b4bef9, payload: payload };
+            let decoded: RawNetworkMessage = deserialize(&serialize(&msg)).unwrap();
+            assert_eq!(decoded.command(), msg.command());
+            assert_eq!(decoded.payload, msg.payload);
+        }
+
+        // `filteradd` is a single length-prefixed byte string
+        let msg = RawNetworkMessage { magic: 0xd9b4bef9, payload: NetworkMessage::FilterAdd(vec![0xab; 2]) };
+        assert_eq!(&serialize(&msg)[4..16], b"filteradd\0\0\0");
+        assert_eq!(&serialize(&msg)[24..], &[0x02, 0xab, 0xab]);
+    }
+
+    #[test]
+    fn addrv2_messages_roundtrip_test() {
+        let addrs = vec![AddrV2Message {
+            time: 1610000000,
+            services: ServiceFlags::NETWORK,
+            addr: AddrV2::I2p([0xab; 32]),
+            port: 0,
+        }];
+        for payload in vec![NetworkMessage::SendAddrV2, NetworkMessage::AddrV2(addrs)] {
+            let msg = RawNetworkMessage { magic: 0xd9b4bef9, payload: payload };
+            let decoded: RawNetworkMessage = deserialize(&serialize(&msg)).unwrap();
+            assert_eq!(decoded.command(), msg.command());
+            assert_eq!(decoded.payload, msg.payload);
+        }
+    }
 }
@@ -23,12 +23,16 @@ use std::io;
 use std::error;
 
 pub mod constants;
+pub mod chainparams;
 
 pub mod address;
 pub mod message;
 pub mod message_blockdata;
 pub mod message_network;
 pub mod message_filter;
+#[cfg(feature = "async-codec")]
+pub mod codec;
+pub mod peer;
 pub mod stream_reader;
 
 /// Network error
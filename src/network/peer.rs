@@ -0,0 +1,635 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Peer connection
+//!
+//! This module defines `Peer`, a state machine for a connection to another
+//! node which negotiates the `version`/`verack` handshake and the features
+//! announced during it, keeps the connection alive with `ping`/`pong` and
+//! keeps score of misbehaviour. It does no I/O by itself: received messages
+//! are fed to it together with the current time, and the messages it wants
+//! to send are queued until they are taken or written to a stream.
+//!
+
+use std::{fmt, mem};
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+
+use consensus::encode::{self, Encodable};
+use network::address::Address;
+use network::chainparams::ChainParams;
+use network::constants::{self, Network, ServiceFlags};
+use network::message::{NetworkMessage, RawNetworkMessage};
+use network::message_network::VersionMessage;
+use network::stream_reader::StreamReader;
+
+/// Protocol version from which `sendheaders` is understood
+pub const SENDHEADERS_VERSION: u32 = 70012;
+/// Protocol version from which `wtxidrelay` is understood
+pub const WTXID_RELAY_VERSION: u32 = 70016;
+/// Oldest protocol version we connect to
+pub const MIN_PEER_PROTO_VERSION: u32 = 31800;
+/// Misbehaviour score at which a peer is disconnected
+pub const MISBEHAVIOR_THRESHOLD: u32 = 100;
+
+/// Settings of a peer connection
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PeerConfig {
+    /// The network the peer should be on
+    pub network: Network,
+    /// The magic of the peer's messages, that of `network` unless the chain
+    /// has its own
+    pub magic: u32,
+    /// Our protocol version
+    pub version: u32,
+    /// Services we offer
+    pub services: ServiceFlags,
+    /// Services the peer must offer, or be disconnected
+    pub required_services: ServiceFlags,
+    /// Our user agent
+    pub user_agent: String,
+    /// The height of our best chain
+    pub start_height: i32,
+    /// Whether the peer should relay transactions to us without a filter
+    pub relay: bool,
+    /// Oldest protocol version of the peer we accept
+    pub min_version: u32,
+    /// Whether to ask for block announcements with `headers`
+    pub send_headers: bool,
+    /// Whether to negotiate relaying transactions by wtxid (BIP339)
+    pub wtxid_relay: bool,
+    /// Whether to announce that we understand `addrv2` (BIP155)
+    pub addrv2: bool,
+    /// Seconds to complete the handshake in
+    pub handshake_timeout: u64,
+    /// Seconds between our pings
+    pub ping_interval: u64,
+    /// Seconds to wait for a pong
+    pub ping_timeout: u64,
+}
+
+impl PeerConfig {
+    /// The settings of a typical connection on `network`.
+    pub fn new(network: Network) -> PeerConfig {
+        PeerConfig {
+            network: network,
+            magic: network.magic(),
+            version: WTXID_RELAY_VERSION,
+            services: constants::SERVICES,
+            required_services: ServiceFlags::NONE,
+            user_agent: constants::USER_AGENT.to_owned(),
+            start_height: 0,
+            relay: true,
+            min_version: MIN_PEER_PROTO_VERSION,
+            send_headers: true,
+            wtxid_relay: true,
+            addrv2: true,
+            handshake_timeout: 60,
+            ping_interval: 2 * 60,
+            ping_timeout: 20 * 60,
+        }
+    }
+
+    /// The settings of a typical connection on the chain of `params`.
+    pub fn with_chain_params(params: &ChainParams) -> PeerConfig {
+        let mut config = PeerConfig::new(params.network);
+        config.magic = params.magic;
+        config
+    }
+}
+
+/// Which side opened the connection
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    /// The peer connected to us
+    Inbound,
+    /// We connected to the peer
+    Outbound,
+}
+
+/// Why a peer should be disconnected
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DisconnectReason {
+    /// The handshake took too long
+    HandshakeTimeout,
+    /// The peer did not answer a ping in time
+    PingTimeout,
+    /// The peer sent a message with the magic bytes of another network
+    WrongNetwork(u32),
+    /// The peer's protocol version is too old
+    ObsoleteVersion(u32),
+    /// The peer does not offer the required services
+    MissingServices(ServiceFlags),
+    /// We connected to ourselves
+    SelfConnection,
+    /// The peer broke the protocol
+    ProtocolViolation(&'static str),
+    /// The peer's misbehaviour score reached `MISBEHAVIOR_THRESHOLD`
+    Misbehaving(&'static str),
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DisconnectReason::HandshakeTimeout => f.write_str("handshake timeout"),
+            DisconnectReason::PingTimeout => f.write_str("ping timeout"),
+            DisconnectReason::WrongNetwork(magic) => write!(f, "wrong network magic {:08x}", magic),
+            DisconnectReason::ObsoleteVersion(version) => write!(f, "obsolete protocol version {}", version),
+            DisconnectReason::MissingServices(services) => write!(f, "missing services, offered {}", services),
+            DisconnectReason::SelfConnection => f.write_str("connected to self"),
+            DisconnectReason::ProtocolViolation(reason) => write!(f, "protocol violation: {}", reason),
+            DisconnectReason::Misbehaving(reason) => write!(f, "misbehaving: {}", reason),
+        }
+    }
+}
+
+/// What happened on a peer connection
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    /// The handshake completed
+    Ready,
+    /// A message for the application
+    Message(NetworkMessage),
+    /// The connection should be closed
+    Disconnect(DisconnectReason),
+}
+
+/// The state of a connection to a peer
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Peer {
+    config: PeerConfig,
+    direction: Direction,
+    remote: Address,
+    nonce: u64,
+    connected_at: u64,
+    sent_version: bool,
+    version: Option<VersionMessage>,
+    verack_received: bool,
+    wtxid_relay: bool,
+    addrv2: bool,
+    prefers_headers: bool,
+    /// Nonce and time of the ping awaiting a pong
+    ping: Option<(u64, u64)>,
+    last_ping: Option<u64>,
+    latency: Option<u64>,
+    misbehavior: u32,
+    disconnected: Option<DisconnectReason>,
+    outbound: Vec<NetworkMessage>,
+}
+
+impl Peer {
+    /// Start a connection to `remote` at time `now`, in seconds since the
+    /// epoch. The `nonce` identifies our `version` message to detect
+    /// connections to ourselves. An outbound connection queues our `version`
+    /// right away, an inbound one waits for the peer's.
+    pub fn new(config: PeerConfig, direction: Direction, remote: &SocketAddr, nonce: u64, now: u64) -> Peer {
+        let mut peer = Peer {
+            config: config,
+            direction: direction,
+            remote: Address::new(remote, ServiceFlags::NONE),
+            nonce: nonce,
+            connected_at: now,
+            sent_version: false,
+            version: None,
+            verack_received: false,
+            wtxid_relay: false,
+            addrv2: false,
+            prefers_headers: false,
+            ping: None,
+            last_ping: None,
+            latency: None,
+            misbehavior: 0,
+            disconnected: None,
+            outbound: vec![],
+        };
+        if direction == Direction::Outbound {
+            peer.send_version(now);
+        }
+        peer
+    }
+
+    /// Which side opened the connection
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// The peer's `version` message, once received
+    pub fn version(&self) -> Option<&VersionMessage> {
+        self.version.as_ref()
+    }
+
+    /// Whether the handshake completed and the connection is still up
+    pub fn is_ready(&self) -> bool {
+        self.sent_version && self.version.is_some() && self.verack_received && self.disconnected.is_none()
+    }
+
+    /// Why the connection should be closed, if it should
+    pub fn disconnect_reason(&self) -> Option<&DisconnectReason> {
+        self.disconnected.as_ref()
+    }
+
+    /// Whether transactions are announced by wtxid on this connection
+    pub fn wtxid_relay(&self) -> bool {
+        self.config.wtxid_relay && self.wtxid_relay
+    }
+
+    /// Whether the peer understands `addrv2`
+    pub fn addrv2(&self) -> bool {
+        self.addrv2
+    }
+
+    /// Whether the peer wants blocks announced with `headers`
+    pub fn prefers_headers(&self) -> bool {
+        self.prefers_headers
+    }
+
+    /// Seconds the last ping took to be answered
+    pub fn latency(&self) -> Option<u64> {
+        self.latency
+    }
+
+    /// The misbehaviour score of the peer
+    pub fn misbehavior(&self) -> u32 {
+        self.misbehavior
+    }
+
+    /// Queue a message to the peer.
+    pub fn send(&mut self, message: NetworkMessage) {
+        self.outbound.push(message);
+    }
+
+    /// Take the messages queued to the peer.
+    pub fn take_outbound(&mut self) -> Vec<NetworkMessage> {
+        mem::replace(&mut self.outbound, vec![])
+    }
+
+    /// Add to the misbehaviour score of the peer, disconnecting it once the
+    /// score reaches `MISBEHAVIOR_THRESHOLD`.
+    pub fn misbehaving(&mut self, score: u32, reason: &'static str) -> Option<Event> {
+        self.misbehavior = self.misbehavior.saturating_add(score);
+        if self.misbehavior >= MISBEHAVIOR_THRESHOLD {
+            self.disconnect(DisconnectReason::Misbehaving(reason))
+        } else {
+            None
+        }
+    }
+
+    /// Handle a message received from the peer at time `now`. Messages which
+    /// are not part of the connection management are passed on as
+    /// `Event::Message` once the handshake completed.
+    pub fn receive(&mut self, message: NetworkMessage, now: u64) -> Option<Event> {
+        if self.disconnected.is_some() {
+            return None;
+        }
+        match message {
+            NetworkMessage::Version(version) => self.receive_version(version, now),
+            NetworkMessage::Verack => {
+                if self.version.is_none() {
+                    return self.misbehaving(1, "verack before version");
+                }
+                if self.verack_received {
+                    return None;
+                }
+                self.verack_received = true;
+                let their_version = self.version.as_ref().map_or(0, |v| v.version);
+                if self.config.send_headers && self.config.version >= SENDHEADERS_VERSION && their_version >= SENDHEADERS_VERSION {
+                    self.send(NetworkMessage::SendHeaders);
+                }
+                Some(Event::Ready)
+            }
+            NetworkMessage::WtxidRelay => {
+                if self.version.is_none() {
+                    return self.misbehaving(1, "wtxidrelay before version");
+                }
+                if self.verack_received {
+                    return self.disconnect(DisconnectReason::ProtocolViolation("wtxidrelay after verack"));
+                }
+                self.wtxid_relay = self.version.as_ref().map_or(false, |v| v.version >= WTXID_RELAY_VERSION);
+                None
+            }
+            NetworkMessage::SendAddrV2 => {
+                if self.version.is_none() {
+                    return self.misbehaving(1, "sendaddrv2 before version");
+                }
+                if self.verack_received {
+                    return self.disconnect(DisconnectReason::ProtocolViolation("sendaddrv2 after verack"));
+                }
+                self.addrv2 = true;
+                None
+            }
+            _ if self.version.is_none() => self.misbehaving(1, "message before version"),
+            // Core ignores anything else until the handshake completes
+            _ if !self.verack_received => None,
+            NetworkMessage::Ping(nonce) => {
+                self.send(NetworkMessage::Pong(nonce));
+                None
+            }
+            NetworkMessage::Pong(nonce) => {
+                if let Some((ping_nonce, sent_at)) = self.ping {
+                    if nonce == ping_nonce {
+                        self.latency = Some(now.saturating_sub(sent_at));
+                        self.ping = None;
+                    }
+                }
+                None
+            }
+            NetworkMessage::SendHeaders => {
+                self.prefers_headers = true;
+                None
+            }
+            message => Some(Event::Message(message)),
+        }
+    }
+
+    /// Handle a message received from the peer at time `now`, checking that
+    /// it is for our network.
+    pub fn receive_raw(&mut self, message: RawNetworkMessage, now: u64) -> Option<Event> {
+        if self.disconnected.is_some() {
+            return None;
+        }
+        if message.magic != self.config.magic {
+            return self.disconnect(DisconnectReason::WrongNetwork(message.magic));
+        }
+        self.receive(message.payload, now)
+    }
+
+    /// Check the timers of the connection at time `now`, sending a ping with
+    /// `ping_nonce` when one is due. Like the `version` nonce, it should be
+    /// random so that the peer can not predict it.
+    pub fn tick(&mut self, now: u64, ping_nonce: u64) -> Option<Event> {
+        if self.disconnected.is_some() {
+            return None;
+        }
+        if !self.is_ready() {
+            if now >= self.connected_at + self.config.handshake_timeout {
+                return self.disconnect(DisconnectReason::HandshakeTimeout);
+            }
+            return None;
+        }
+        match self.ping {
+            Some((_, sent_at)) => if now >= sent_at + self.config.ping_timeout {
+                return self.disconnect(DisconnectReason::PingTimeout);
+            },
+            None => {
+                let interval = self.config.ping_interval;
+                if self.last_ping.map_or(true, |last| now >= last + interval) {
+                    self.ping = Some((ping_nonce, now));
+                    self.last_ping = Some(now);
+                    self.send(NetworkMessage::Ping(ping_nonce));
+                }
+            }
+        }
+        None
+    }
+
+    /// Read the next message from the peer and handle it.
+    pub fn read_from<R: Read>(&mut self, reader: &mut StreamReader<R>, now: u64) -> Result<Option<Event>, encode::Error> {
+        let message: RawNetworkMessage = reader.read_message()?;
+        Ok(self.receive_raw(message, now))
+    }
+
+    /// Write the queued messages to the peer, returning the number of bytes
+    /// written.
+    pub fn write_to<W: Write>(&mut self, mut writer: W) -> Result<usize, encode::Error> {
+        let mut len = 0;
+        for payload in self.take_outbound() {
+            let message = RawNetworkMessage {
+                magic: self.config.magic,
+                payload: payload,
+            };
+            len += message.consensus_encode(&mut writer)?;
+        }
+        Ok(len)
+    }
+
+    fn receive_version(&mut self, version: VersionMessage, now: u64) -> Option<Event> {
+        if self.version.is_some() {
+            return self.misbehaving(1, "duplicate version");
+        }
+        if self.direction == Direction::Inbound && version.nonce == self.nonce {
+            return self.disconnect(DisconnectReason::SelfConnection);
+        }
+        if version.version < self.config.min_version {
+            return self.disconnect(DisconnectReason::ObsoleteVersion(version.version));
+        }
+        if !version.services.has(self.config.required_services) {
+            return self.disconnect(DisconnectReason::MissingServices(version.services));
+        }
+
+        if !self.sent_version {
+            self.send_version(now);
+        }
+        // feature negotiation must come between version and verack
+        if self.config.wtxid_relay && self.config.version >= WTXID_RELAY_VERSION && version.version >= WTXID_RELAY_VERSION {
+            self.send(NetworkMessage::WtxidRelay);
+        }
+        if self.config.addrv2 {
+            self.send(NetworkMessage::SendAddrV2);
+        }
+        self.send(NetworkMessage::Verack);
+        self.version = Some(version);
+        None
+    }
+
+    fn send_version(&mut self, now: u64) {
+        let sender = Address::new(&SocketAddr::from(([0, 0, 0, 0], 0)), self.config.services);
+        let mut version = VersionMessage::new(
+            self.config.services,
+            now as i64,
+            self.remote.clone(),
+            sender,
+            self.nonce,
+            self.config.user_agent.clone(),
+            self.config.start_height,
+        );
+        version.version = self.config.version;
+        version.relay = self.config.relay;
+        self.send(NetworkMessage::Version(version));
+        self.sent_version = true;
+    }
+
+    fn disconnect(&mut self, reason: DisconnectReason) -> Option<Event> {
+        self.disconnected = Some(reason.clone());
+        Some(Event::Disconnect(reason))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor};
+    use std::net::SocketAddr;
+
+    use consensus::encode;
+    use network::constants::Network;
+    use network::message::{NetworkMessage, RawNetworkMessage};
+    use network::stream_reader::StreamReader;
+
+    use super::*;
+
+    fn addr() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 8333))
+    }
+
+    /// Write the messages queued at `from` to a buffer and feed them to `to`.
+    fn exchange(from: &mut Peer, to: &mut Peer, now: u64) -> Vec<Event> {
+        let mut buf = vec![];
+        from.write_to(&mut buf).unwrap();
+        let mut reader = StreamReader::new(Cursor::new(buf), None);
+        let mut events = vec![];
+        loop {
+            match to.read_from(&mut reader, now) {
+                Ok(Some(event)) => events.push(event),
+                Ok(None) => {}
+                Err(encode::Error::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => return events,
+                Err(e) => panic!("{}", e),
+            }
+        }
+    }
+
+    fn connect(a_config: PeerConfig, b_config: PeerConfig) -> (Peer, Peer) {
+        let mut a = Peer::new(a_config, Direction::Outbound, &addr(), 1, 0);
+        let mut b = Peer::new(b_config, Direction::Inbound, &addr(), 2, 0);
+        assert!(exchange(&mut a, &mut b, 1).is_empty());
+        assert_eq!(exchange(&mut b, &mut a, 1), vec![Event::Ready]);
+        assert_eq!(exchange(&mut a, &mut b, 1), vec![Event::Ready]);
+        exchange(&mut b, &mut a, 1);
+        (a, b)
+    }
+
+    #[test]
+    fn handshake_test() {
+        let mut config = PeerConfig::new(Network::Bitcoin);
+        config.start_height = 500_000;
+        config.relay = false;
+        let mut a = Peer::new(config.clone(), Direction::Outbound, &addr(), 1, 10);
+        let mut b = Peer::new(PeerConfig::new(Network::Bitcoin), Direction::Inbound, &addr(), 2, 10);
+        assert!(b.take_outbound().is_empty());
+
+        let sent = a.take_outbound();
+        assert_eq!(sent.len(), 1);
+        match sent[0] {
+            NetworkMessage::Version(ref version) => {
+                assert_eq!(version.version, WTXID_RELAY_VERSION);
+                assert_eq!(version.start_height, 500_000);
+                assert!(!version.relay);
+                assert_eq!(version.timestamp, 10);
+                assert_eq!(version.nonce, 1);
+            }
+            _ => panic!("expected a version message"),
+        }
+        assert_eq!(b.receive(sent[0].clone(), 11), None);
+        let reply = b.take_outbound();
+        assert_eq!(reply.len(), 4);
+        assert_eq!(reply[1..], [NetworkMessage::WtxidRelay, NetworkMessage::SendAddrV2, NetworkMessage::Verack]);
+
+        let (a, b) = connect(config, PeerConfig::new(Network::Bitcoin));
+        for peer in &[a, b] {
+            assert!(peer.is_ready());
+            assert!(peer.wtxid_relay());
+            assert!(peer.addrv2());
+            assert!(peer.prefers_headers());
+        }
+    }
+
+    #[test]
+    fn old_peer_test() {
+        let mut old = PeerConfig::new(Network::Bitcoin);
+        old.version = 70001;
+        old.addrv2 = false;
+        let (a, b) = connect(PeerConfig::new(Network::Bitcoin), old);
+        assert!(!a.wtxid_relay() && !b.wtxid_relay());
+        assert!(!a.addrv2() && b.addrv2());
+        assert!(!a.prefers_headers() && !b.prefers_headers());
+        assert_eq!(a.version().unwrap().version, 70001);
+
+        let mut required = PeerConfig::new(Network::Bitcoin);
+        required.required_services = ServiceFlags::NETWORK;
+        let mut a = Peer::new(required, Direction::Outbound, &addr(), 1, 0);
+        let mut b = Peer::new(PeerConfig::new(Network::Bitcoin), Direction::Inbound, &addr(), 2, 0);
+        exchange(&mut a, &mut b, 0);
+        assert_eq!(exchange(&mut b, &mut a, 0), vec![Event::Disconnect(DisconnectReason::MissingServices(ServiceFlags::NONE))]);
+
+        let mut obsolete = PeerConfig::new(Network::Bitcoin);
+        obsolete.version = 209;
+        let mut a = Peer::new(obsolete, Direction::Outbound, &addr(), 1, 0);
+        let mut b = Peer::new(PeerConfig::new(Network::Bitcoin), Direction::Inbound, &addr(), 2, 0);
+        assert_eq!(exchange(&mut a, &mut b, 0), vec![Event::Disconnect(DisconnectReason::ObsoleteVersion(209))]);
+        assert_eq!(b.disconnect_reason(), Some(&DisconnectReason::ObsoleteVersion(209)));
+    }
+
+    #[test]
+    fn ping_test() {
+        let (mut a, mut b) = connect(PeerConfig::new(Network::Bitcoin), PeerConfig::new(Network::Bitcoin));
+        assert_eq!(a.tick(100, 0x1234), None);
+        assert_eq!(a.outbound, vec![NetworkMessage::Ping(0x1234)]);
+        assert_eq!(exchange(&mut a, &mut b, 100), vec![]);
+        exchange(&mut b, &mut a, 103);
+        assert_eq!(a.latency(), Some(3));
+
+        // not due yet
+        assert_eq!(a.tick(200, 7), None);
+        assert!(a.take_outbound().is_empty());
+        assert_eq!(a.tick(220, 0x5678), None);
+        assert_eq!(a.take_outbound(), vec![NetworkMessage::Ping(0x5678)]);
+
+        // a pong with another nonce does not count
+        assert_eq!(a.receive(NetworkMessage::Pong(0x1234), 230), None);
+        assert_eq!(a.tick(220 + 20 * 60 - 1, 7), None);
+        assert_eq!(a.tick(220 + 20 * 60, 7), Some(Event::Disconnect(DisconnectReason::PingTimeout)));
+        assert!(!a.is_ready());
+        assert_eq!(a.tick(5000, 7), None);
+    }
+
+    #[test]
+    fn handshake_timeout_test() {
+        let mut a = Peer::new(PeerConfig::new(Network::Bitcoin), Direction::Outbound, &addr(), 1, 100);
+        assert_eq!(a.tick(159, 7), None);
+        assert_eq!(a.tick(160, 7), Some(Event::Disconnect(DisconnectReason::HandshakeTimeout)));
+    }
+
+    #[test]
+    fn misbehavior_test() {
+        let mut b = Peer::new(PeerConfig::new(Network::Bitcoin), Direction::Inbound, &addr(), 2, 0);
+        assert_eq!(b.receive(NetworkMessage::Ping(1), 0), None);
+        assert_eq!(b.receive(NetworkMessage::Verack, 0), None);
+        assert_eq!(b.misbehavior(), 2);
+        assert_eq!(b.misbehaving(97, "test"), None);
+        assert_eq!(b.misbehaving(1, "test"), Some(Event::Disconnect(DisconnectReason::Misbehaving("test"))));
+        assert_eq!(b.receive(NetworkMessage::Verack, 0), None);
+
+        // messages pass through after the handshake
+        let (mut a, _) = connect(PeerConfig::new(Network::Bitcoin), PeerConfig::new(Network::Bitcoin));
+        assert_eq!(a.receive(NetworkMessage::MemPool, 1), Some(Event::Message(NetworkMessage::MemPool)));
+        assert_eq!(a.receive(NetworkMessage::WtxidRelay, 1),
+                   Some(Event::Disconnect(DisconnectReason::ProtocolViolation("wtxidrelay after verack"))));
+    }
+
+    #[test]
+    fn bad_peer_test() {
+        // connecting to ourselves
+        let mut a = Peer::new(PeerConfig::new(Network::Bitcoin), Direction::Outbound, &addr(), 7, 0);
+        let mut b = Peer::new(PeerConfig::new(Network::Bitcoin), Direction::Inbound, &addr(), 7, 0);
+        assert_eq!(exchange(&mut a, &mut b, 0), vec![Event::Disconnect(DisconnectReason::SelfConnection)]);
+
+        let mut a = Peer::new(PeerConfig::new(Network::Testnet), Direction::Outbound, &addr(), 1, 0);
+        let mut b = Peer::new(PeerConfig::new(Network::Bitcoin), Direction::Inbound, &addr(), 2, 0);
+        let magic = Network::Testnet.magic();
+        assert_eq!(exchange(&mut a, &mut b, 0), vec![Event::Disconnect(DisconnectReason::WrongNetwork(magic))]);
+
+        let mut b = Peer::new(PeerConfig::new(Network::Bitcoin), Direction::Inbound, &addr(), 2, 0);
+        let raw = RawNetworkMessage { magic: Network::Bitcoin.magic(), payload: NetworkMessage::Verack };
+        assert_eq!(b.receive_raw(raw, 0), None);
+        assert_eq!(b.misbehavior(), 1);
+    }
+}
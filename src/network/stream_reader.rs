@@ -19,11 +19,25 @@
 //! messages from multiple packets or dealing with partial or multiple messages in the stream
 //! (like can happen with reading from TCP socket)
 //!
+//! Network messages should be read with `read_message`, which reads the 24-byte
+//! message header first and checks its magic, command and payload length before
+//! reading exactly the announced payload, so a peer can not make us buffer more
+//! than the configured maximum. The generic `read_next` is deprecated as it can
+//! not do such framing.
+//!
 
 use std::fmt;
 use std::io::{self, Read};
 
+use byteorder::{ByteOrder, LittleEndian};
+
 use consensus::{encode, Decodable};
+use consensus::encode::MAX_VEC_SIZE;
+use network::constants::Network;
+use network::message::RawNetworkMessage;
+
+/// Size of the message header: magic, command, payload length and checksum
+const HEADER_SIZE: usize = 24;
 
 /// Struct used to configure stream reader function
 pub struct StreamReader<R: Read> {
@@ -32,7 +46,11 @@ pub struct StreamReader<R: Read> {
     /// I/O buffer
     data: Vec<u8>,
     /// Buffer containing unparsed message part
-    unparsed: Vec<u8>
+    unparsed: Vec<u8>,
+    /// Network magic messages are expected to have, any known network if `None`
+    magic: Option<u32>,
+    /// Maximum accepted payload length
+    max_payload_size: usize,
 }
 
 impl<R: Read> fmt::Debug for StreamReader<R> {
@@ -49,24 +67,48 @@ impl<R: Read> StreamReader<R> {
         StreamReader {
             stream,
             data: vec![0u8; buffer_size.unwrap_or(64 * 1024)],
-            unparsed: vec![]
+            unparsed: vec![],
+            magic: None,
+            max_payload_size: MAX_VEC_SIZE,
         }
     }
 
+    /// Only accept messages for `network` in `read_message`. By default
+    /// messages of any known network are accepted.
+    pub fn with_network(mut self, network: Network) -> StreamReader<R> {
+        self.magic = Some(network.magic());
+        self
+    }
+
+    /// Only accept messages with `magic` in `read_message`, as for chains
+    /// described by `ChainParams` rather than a `Network`.
+    pub fn with_magic(mut self, magic: u32) -> StreamReader<R> {
+        self.magic = Some(magic);
+        self
+    }
+
+    /// Only accept payloads up to `max_payload_size` bytes in `read_message`.
+    /// Defaults to `MAX_VEC_SIZE`.
+    pub fn with_max_payload_size(mut self, max_payload_size: usize) -> StreamReader<R> {
+        self.max_payload_size = max_payload_size;
+        self
+    }
+
     /// Reads stream and parses next message from its current input,
     /// also taking into account previously unparsed partial message (if there was such).
+    ///
+    /// Since the size of `D` is not known in advance, this retries decoding
+    /// the whole unparsed input after every read and buffers whatever the
+    /// stream sends until decoding succeeds, so a peer can make it allocate
+    /// without bound. It is deprecated in favour of `read_message`, which
+    /// frames network messages by their header.
+    #[deprecated(since = "0.21.1", note = "use read_message, which checks the message header before buffering the payload")]
     pub fn read_next<D: Decodable>(&mut self) -> Result<D, encode::Error> {
         loop {
             match encode::deserialize_partial::<D>(&self.unparsed) {
                 // In this case we just have an incomplete data, so we need to read more
                 Err(encode::Error::Io(ref err)) if err.kind () == io::ErrorKind::UnexpectedEof => {
-                    let count = self.stream.read(&mut self.data)?;
-                    if count > 0 {
-                        self.unparsed.extend(self.data[0..count].iter());
-                    }
-                    else {
-                        return Err(encode::Error::Io(io::Error::from(io::ErrorKind::UnexpectedEof)));
-                    }
+                    self.read_more()?;
                 },
                 Err(err) => return Err(err),
                 // We have successfully read from the buffer
@@ -77,9 +119,81 @@ impl<R: Read> StreamReader<R> {
             }
         }
     }
+
+    /// Reads the next network message from the stream.
+    ///
+    /// The header is checked before any of the payload is read: a message
+    /// with an unexpected magic fails with `UnexpectedNetworkMagic` (or
+    /// `UnknownNetworkMagic` if no network was set), one announcing a payload
+    /// above the maximum with `OversizedMessage`, and a malformed
+    /// command with `ParseFailed`. After such an error the stream is no longer
+    /// in sync and should be dropped.
+    pub fn read_message(&mut self) -> Result<RawNetworkMessage, encode::Error> {
+        self.fill(HEADER_SIZE)?;
+
+        let magic = LittleEndian::read_u32(&self.unparsed[0..4]);
+        match self.magic {
+            Some(expected) if expected != magic => {
+                return Err(encode::Error::UnexpectedNetworkMagic {
+                    expected: expected,
+                    actual: magic,
+                });
+            }
+            None if Network::from_magic(magic).is_none() => {
+                return Err(encode::Error::UnknownNetworkMagic(magic));
+            }
+            _ => {}
+        }
+        if !valid_command(&self.unparsed[4..16]) {
+            return Err(encode::Error::ParseFailed("invalid command string"));
+        }
+        let payload_size = LittleEndian::read_u32(&self.unparsed[16..20]) as usize;
+        if payload_size > self.max_payload_size {
+            return Err(encode::Error::OversizedMessage {
+                size: payload_size,
+                max: self.max_payload_size,
+            });
+        }
+
+        let size = HEADER_SIZE + payload_size;
+        self.fill(size)?;
+        // the checksum is verified while decoding
+        let message = encode::deserialize(&self.unparsed[..size]);
+        self.unparsed.drain(..size);
+        message
+    }
+
+    /// Reads from the stream until at least `len` bytes are unparsed. The
+    /// buffer only grows as data arrives, so a header announcing a large
+    /// payload does not make us allocate it up front.
+    fn fill(&mut self, len: usize) -> Result<(), encode::Error> {
+        while self.unparsed.len() < len {
+            self.read_more()?;
+        }
+        Ok(())
+    }
+
+    /// Appends one read from the stream to the unparsed input
+    fn read_more(&mut self) -> Result<(), encode::Error> {
+        let count = self.stream.read(&mut self.data)?;
+        if count > 0 {
+            self.unparsed.extend(self.data[0..count].iter());
+            Ok(())
+        } else {
+            Err(encode::Error::Io(io::Error::from(io::ErrorKind::UnexpectedEof)))
+        }
+    }
+}
+
+/// Checks that a command is printable ASCII padded with zeros
+fn valid_command(command: &[u8]) -> bool {
+    let len = command.iter().position(|&b| b == 0).unwrap_or(command.len());
+    command[..len].iter().all(|&b| b >= 0x20 && b < 0x7f)
+        && command[len..].iter().all(|&b| b == 0)
 }
 
 #[cfg(test)]
+#[allow(deprecated)]
 mod test {
     use std::thread;
     use std::time::Duration;
@@ -88,6 +202,8 @@ mod test {
     use std::thread::JoinHandle;
 
     use super::StreamReader;
+    use consensus::encode;
+    use network::constants::{Network, ServiceFlags};
     use network::message::{NetworkMessage, RawNetworkMessage};
 
     // First, let's define some byte arrays for sample messages - dumps are taken from live
@@ -156,7 +272,7 @@ mod test {
         assert_eq!(msg.magic, 0xd9b4bef9);
         if let NetworkMessage::Version(ref version_msg) = msg.payload {
             assert_eq!(version_msg.version, 70015);
-            assert_eq!(version_msg.services, 1037);
+            assert_eq!(version_msg.services, ServiceFlags::NETWORK | ServiceFlags::BLOOM | ServiceFlags::WITNESS | ServiceFlags::NETWORK_LIMITED);
             assert_eq!(version_msg.timestamp, 1548554224);
             assert_eq!(version_msg.nonce, 13952548347456104954);
             assert_eq!(version_msg.user_agent, "/Satoshi:0.17.1/");
@@ -232,6 +348,88 @@ mod test {
         }
     }
 
+    #[test]
+    fn read_message_test() {
+        let mut stream = MSG_VERSION.to_vec();
+        stream.extend(&MSG_VERACK);
+        stream.extend(&MSG_ALERT);
+        stream.extend(&MSG_PING);
+        // a tiny buffer makes every message span several reads
+        let mut reader = StreamReader::new(stream.as_slice(), Some(7)).with_network(Network::Bitcoin);
+
+        check_version_msg(&reader.read_message().unwrap());
+        assert_eq!(reader.read_message().unwrap().payload, NetworkMessage::Verack);
+        check_alert_msg(&reader.read_message().unwrap());
+        assert_eq!(reader.read_message().unwrap().payload, NetworkMessage::Ping(100));
+        match reader.read_message() {
+            Err(encode::Error::Io(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => {},
+            x => panic!("expected EOF, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn read_message_truncated_test() {
+        let stream = &MSG_ALERT[..100];
+        let mut reader = StreamReader::new(stream, None);
+        match reader.read_message() {
+            Err(encode::Error::Io(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => {},
+            x => panic!("expected EOF, got {:?}", x),
+        }
+
+        // a header announcing a large payload is not allocated for up front
+        let mut msg = MSG_PING;
+        msg[16..20].copy_from_slice(&[0x00, 0x00, 0x20, 0x00]);
+        let mut reader = StreamReader::new(&msg[..], Some(16));
+        match reader.read_message() {
+            Err(encode::Error::Io(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => {},
+            x => panic!("expected EOF, got {:?}", x),
+        }
+        assert!(reader.unparsed.capacity() < 1024);
+    }
+
+    #[test]
+    fn read_message_header_checks_test() {
+        let mut reader = StreamReader::new(&MSG_PING[..], None).with_network(Network::Testnet);
+        match reader.read_message() {
+            Err(encode::Error::UnexpectedNetworkMagic { expected, actual }) => {
+                assert_eq!(expected, Network::Testnet.magic());
+                assert_eq!(actual, 0xd9b4bef9);
+            }
+            x => panic!("expected magic error, got {:?}", x),
+        }
+
+        let mut msg = MSG_PING;
+        msg[0] = 0;
+        let mut reader = StreamReader::new(&msg[..], None);
+        match reader.read_message() {
+            Err(encode::Error::UnknownNetworkMagic(0xd9b4be00)) => {},
+            x => panic!("expected magic error, got {:?}", x),
+        }
+
+        let mut msg = MSG_PING;
+        msg[9] = b'x';
+        let mut reader = StreamReader::new(&msg[..], None);
+        match reader.read_message() {
+            Err(encode::Error::ParseFailed(_)) => {},
+            x => panic!("expected command error, got {:?}", x),
+        }
+
+        // only the header is needed to reject an oversized message
+        let mut reader = StreamReader::new(&MSG_ALERT[..24], None).with_max_payload_size(100);
+        match reader.read_message() {
+            Err(encode::Error::OversizedMessage { size: 168, max: 100 }) => {},
+            x => panic!("expected size error, got {:?}", x),
+        }
+
+        let mut msg = MSG_PING;
+        msg[24] = 0x65;
+        let mut reader = StreamReader::new(&msg[..], None);
+        match reader.read_message() {
+            Err(encode::Error::InvalidChecksum { .. }) => {},
+            x => panic!("expected checksum error, got {:?}", x),
+        }
+    }
+
     // Helper function that set ups emulation of client-server TCP connection for
     // testing message transfer via TCP packets
     fn serve_tcp(pieces: Vec<Vec<u8>>) -> (JoinHandle<()>, BufReader<TcpStream>) {
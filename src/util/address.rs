@@ -48,6 +48,7 @@ use hashes::{hash160, sha256, Hash};
 
 use blockdata::opcodes;
 use blockdata::script;
+use network::chainparams::ChainParams;
 use network::constants::Network;
 use util::base58;
 use util::key;
@@ -361,38 +362,94 @@ impl Address {
     pub fn script_pubkey(&self) -> script::Script {
         self.payload.script_pubkey()
     }
-}
 
-impl Display for Address {
-    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+    /// Format the address with the prefixes of `params` instead of those of
+    /// its network
+    pub fn to_string_with_params(&self, params: &ChainParams) -> String {
+        let mut ret = String::new();
+        let prefixes = &params.base58_prefixes;
+        self.fmt_with_prefixes(&mut ret, prefixes.pubkey_address, prefixes.script_address, &params.bech32_hrp)
+            .expect("writing to a string");
+        ret
+    }
+
+    /// Parse an address with the prefixes of `params`. The address gets the
+    /// network of `params`.
+    pub fn from_str_with_params(s: &str, params: &ChainParams) -> Result<Address, Error> {
+        if find_bech32_prefix(s).to_lowercase() == params.bech32_hrp.to_lowercase() {
+            return Address::from_bech32(s, params.network);
+        }
+
+        let data = base58_payload(s)?;
+        let hash = hash160::Hash::from_slice(&data[1..]).unwrap();
+        let payload = if data[0] == params.base58_prefixes.pubkey_address {
+            Payload::PubkeyHash(hash)
+        } else if data[0] == params.base58_prefixes.script_address {
+            Payload::ScriptHash(hash)
+        } else {
+            return Err(Error::Base58(base58::Error::InvalidVersion(vec![data[0]])));
+        };
+        Ok(Address {
+            network: params.network,
+            payload: payload,
+        })
+    }
+
+    /// Decode a bech32 address for `network`
+    fn from_bech32(s: &str, network: Network) -> Result<Address, Error> {
+        let (_, payload) = bech32::decode(s)?;
+        if payload.is_empty() {
+            return Err(Error::EmptyBech32Payload);
+        }
+
+        // Get the script version and program (converted from 5-bit to 8-bit)
+        let (version, program): (bech32::u5, Vec<u8>) = {
+            let (v, p5) = payload.split_at(1);
+            (v[0], bech32::FromBase32::from_base32(p5)?)
+        };
+
+        // Generic segwit checks.
+        if version.to_u8() > 16 {
+            return Err(Error::InvalidWitnessVersion(version.to_u8()));
+        }
+        if program.len() < 2 || program.len() > 40 {
+            return Err(Error::InvalidWitnessProgramLength(program.len()));
+        }
+
+        // Specific segwit v0 check.
+        if version.to_u8() == 0 && (program.len() != 20 && program.len() != 32) {
+            return Err(Error::InvalidSegwitV0ProgramLength(program.len()));
+        }
+
+        Ok(Address {
+            payload: Payload::WitnessProgram {
+                version: version,
+                program: program,
+            },
+            network: network,
+        })
+    }
+
+    /// Format the address with the given base58 version bytes and bech32
+    /// human readable part
+    fn fmt_with_prefixes(&self, fmt: &mut fmt::Write, p2pkh_prefix: u8, p2sh_prefix: u8, hrp: &str) -> fmt::Result {
         match self.payload {
             Payload::PubkeyHash(ref hash) => {
                 let mut prefixed = [0; 21];
-                prefixed[0] = match self.network {
-                    Network::Bitcoin => 0,
-                    Network::Testnet | Network::Regtest => 111,
-                };
+                prefixed[0] = p2pkh_prefix;
                 prefixed[1..].copy_from_slice(&hash[..]);
-                base58::check_encode_slice_to_fmt(fmt, &prefixed[..])
+                fmt.write_str(&base58::check_encode_slice(&prefixed[..]))
             }
             Payload::ScriptHash(ref hash) => {
                 let mut prefixed = [0; 21];
-                prefixed[0] = match self.network {
-                    Network::Bitcoin => 5,
-                    Network::Testnet | Network::Regtest => 196,
-                };
+                prefixed[0] = p2sh_prefix;
                 prefixed[1..].copy_from_slice(&hash[..]);
-                base58::check_encode_slice_to_fmt(fmt, &prefixed[..])
+                fmt.write_str(&base58::check_encode_slice(&prefixed[..]))
             }
             Payload::WitnessProgram {
                 version: ver,
                 program: ref prog,
             } => {
-                let hrp = match self.network {
-                    Network::Bitcoin => "bc",
-                    Network::Testnet => "tb",
-                    Network::Regtest => "bcrt",
-                };
                 let mut bech32_writer = bech32::Bech32Writer::new(hrp, fmt)?;
                 bech32::WriteBase32::write_u5(&mut bech32_writer, ver)?;
                 bech32::ToBase32::write_base32(&prog, &mut bech32_writer)
@@ -401,6 +458,17 @@ impl Display for Address {
     }
 }
 
+impl Display for Address {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let (p2pkh_prefix, p2sh_prefix, hrp) = match self.network {
+            Network::Bitcoin => (0, 5, "bc"),
+            Network::Testnet | Network::Signet => (111, 196, "tb"),
+            Network::Regtest => (111, 196, "bcrt"),
+        };
+        self.fmt_with_prefixes(fmt, p2pkh_prefix, p2sh_prefix, hrp)
+    }
+}
+
 /// Extract the bech32 prefix.
 /// Returns the same slice when no prefix is found.
 fn find_bech32_prefix(bech32: &str) -> &str {
@@ -424,49 +492,11 @@ impl FromStr for Address {
             _ => None,
         };
         if let Some(network) = bech32_network {
-            // decode as bech32
-            let (_, payload) = bech32::decode(s)?;
-            if payload.len() == 0 {
-                return Err(Error::EmptyBech32Payload);
-            }
-
-            // Get the script version and program (converted from 5-bit to 8-bit)
-            let (version, program): (bech32::u5, Vec<u8>) = {
-                let (v, p5) = payload.split_at(1);
-                (v[0], bech32::FromBase32::from_base32(p5)?)
-            };
-
-            // Generic segwit checks.
-            if version.to_u8() > 16 {
-                return Err(Error::InvalidWitnessVersion(version.to_u8()));
-            }
-            if program.len() < 2 || program.len() > 40 {
-                return Err(Error::InvalidWitnessProgramLength(program.len()));
-            }
-
-            // Specific segwit v0 check.
-            if version.to_u8() == 0 && (program.len() != 20 && program.len() != 32) {
-                return Err(Error::InvalidSegwitV0ProgramLength(program.len()));
-            }
-
-            return Ok(Address {
-                payload: Payload::WitnessProgram {
-                    version: version,
-                    program: program,
-                },
-                network: network,
-            });
+            return Address::from_bech32(s, network);
         }
 
         // Base58
-        if s.len() > 50 {
-            return Err(Error::Base58(base58::Error::InvalidLength(s.len() * 11 / 15)));
-        }
-        let data = base58::from_check(s)?;
-        if data.len() != 21 {
-            return Err(Error::Base58(base58::Error::InvalidLength(data.len())));
-        }
-
+        let data = base58_payload(s)?;
         let (network, payload) = match data[0] {
             0 => (
                 Network::Bitcoin,
@@ -494,6 +524,18 @@ impl FromStr for Address {
     }
 }
 
+/// Decode a base58 address into its version byte and hash
+fn base58_payload(s: &str) -> Result<Vec<u8>, Error> {
+    if s.len() > 50 {
+        return Err(Error::Base58(base58::Error::InvalidLength(s.len() * 11 / 15)));
+    }
+    let data = base58::from_check(s)?;
+    if data.len() != 21 {
+        return Err(Error::Base58(base58::Error::InvalidLength(data.len())));
+    }
+    Ok(data)
+}
+
 impl ::std::fmt::Debug for Address {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
         write!(f, "{}", self.to_string())
@@ -22,11 +22,12 @@
 use hashes::{sha256d, Hash};
 
 use blockdata::script::Script;
-use blockdata::transaction::{Transaction, TxIn};
+use blockdata::transaction::{SigHashType, Transaction, TxIn};
 use consensus::encode::Encodable;
 
 /// Parts of a sighash which are common across inputs or signatures, and which are
-/// sufficient (in conjunction with a private key) to sign the transaction
+/// sufficient (in conjunction with a private key) to sign the transaction. These
+/// only support `SIGHASH_ALL`; use `SigHashCache` for the other sighash types.
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct SighashComponents {
     tx_version: u32,
@@ -99,6 +100,121 @@ impl SighashComponents {
     }
 }
 
+/// A cache of the parts of BIP143 sighashes which are shared between the inputs
+/// of a transaction. Each part is only computed the first time a sighash type
+/// needing it is requested, and reused for all later signatures.
+/// For the generated sighashes to be valid, no fields in the transaction may change
+/// except for script_sig and witnesses.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SigHashCache<'a> {
+    tx: &'a Transaction,
+    hash_prevouts: Option<sha256d::Hash>,
+    hash_sequence: Option<sha256d::Hash>,
+    hash_outputs: Option<sha256d::Hash>,
+}
+
+impl<'a> SigHashCache<'a> {
+    /// Create a new, empty cache for the given transaction
+    pub fn new(tx: &'a Transaction) -> SigHashCache<'a> {
+        SigHashCache {
+            tx: tx,
+            hash_prevouts: None,
+            hash_sequence: None,
+            hash_outputs: None,
+        }
+    }
+
+    /// Hash of all the previous outputs, computed on first use
+    pub fn hash_prevouts(&mut self) -> sha256d::Hash {
+        let tx = self.tx;
+        *self.hash_prevouts.get_or_insert_with(|| {
+            let mut enc = sha256d::Hash::engine();
+            for txin in &tx.input {
+                txin.previous_output.consensus_encode(&mut enc).unwrap();
+            }
+            sha256d::Hash::from_engine(enc)
+        })
+    }
+
+    /// Hash of all the input sequence nos, computed on first use
+    pub fn hash_sequence(&mut self) -> sha256d::Hash {
+        let tx = self.tx;
+        *self.hash_sequence.get_or_insert_with(|| {
+            let mut enc = sha256d::Hash::engine();
+            for txin in &tx.input {
+                txin.sequence.consensus_encode(&mut enc).unwrap();
+            }
+            sha256d::Hash::from_engine(enc)
+        })
+    }
+
+    /// Hash of all the outputs in this transaction, computed on first use
+    pub fn hash_outputs(&mut self) -> sha256d::Hash {
+        let tx = self.tx;
+        *self.hash_outputs.get_or_insert_with(|| {
+            let mut enc = sha256d::Hash::engine();
+            for txout in &tx.output {
+                txout.consensus_encode(&mut enc).unwrap();
+            }
+            sha256d::Hash::from_engine(enc)
+        })
+    }
+
+    /// Compute the BIP143 sighash of the given input for any sighash type.
+    /// `script_code` is the script being executed, without the length prefix,
+    /// and `value` is the amount of the output being spent. Panics if the input
+    /// does not exist.
+    pub fn signature_hash(&mut self, input_index: usize, script_code: &Script, value: u64, sighash_type: SigHashType) -> sha256d::Hash {
+        self.raw_signature_hash(input_index, script_code, value, sighash_type.as_u32())
+    }
+
+    /// Compute the BIP143 sighash of the given input, taking the sighash type as
+    /// the `u32` which is committed to by the signature. Unlike `signature_hash`
+    /// this also supports the undefined types which consensus rules allow, and
+    /// which are treated like the defined type they map to except for being
+    /// committed to as-is.
+    pub fn raw_signature_hash(&mut self, input_index: usize, script_code: &Script, value: u64, sighash_u32: u32) -> sha256d::Hash {
+        assert!(input_index < self.tx.input.len());  // Panic on OOB
+
+        let (sighash, anyone_can_pay) = SigHashType::from_u32(sighash_u32).split_anyonecanpay_flag();
+        let zero_hash = sha256d::Hash::default();
+
+        let hash_prevouts = if !anyone_can_pay {
+            self.hash_prevouts()
+        } else {
+            zero_hash
+        };
+        let hash_sequence = if !anyone_can_pay && sighash == SigHashType::All {
+            self.hash_sequence()
+        } else {
+            zero_hash
+        };
+        let hash_outputs = if sighash == SigHashType::All {
+            self.hash_outputs()
+        } else if sighash == SigHashType::Single && input_index < self.tx.output.len() {
+            let mut enc = sha256d::Hash::engine();
+            self.tx.output[input_index].consensus_encode(&mut enc).unwrap();
+            sha256d::Hash::from_engine(enc)
+        } else {
+            zero_hash
+        };
+
+        let txin = &self.tx.input[input_index];
+        let mut enc = sha256d::Hash::engine();
+        self.tx.version.consensus_encode(&mut enc).unwrap();
+        hash_prevouts.consensus_encode(&mut enc).unwrap();
+        hash_sequence.consensus_encode(&mut enc).unwrap();
+        txin.previous_output.consensus_encode(&mut enc).unwrap();
+        script_code.consensus_encode(&mut enc).unwrap();
+        value.consensus_encode(&mut enc).unwrap();
+        txin.sequence.consensus_encode(&mut enc).unwrap();
+        hash_outputs.consensus_encode(&mut enc).unwrap();
+        self.tx.lock_time.consensus_encode(&mut enc).unwrap();
+        sighash_u32.consensus_encode(&mut enc).unwrap();
+        sha256d::Hash::from_engine(enc)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use blockdata::script::Script;
@@ -155,6 +271,15 @@ mod tests {
             comp.sighash_all(&tx.input[1], &witness_script, value),
             hex_hash!("c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670")
         );
+
+        let mut cache = SigHashCache::new(&tx);
+        assert_eq!(
+            cache.signature_hash(1, &witness_script, value, SigHashType::All),
+            hex_hash!("c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670")
+        );
+        assert_eq!(cache.hash_prevouts(), comp.hash_prevouts);
+        assert_eq!(cache.hash_sequence(), comp.hash_sequence);
+        assert_eq!(cache.hash_outputs(), comp.hash_outputs);
     }
 
     #[test]
@@ -192,6 +317,10 @@ mod tests {
             comp.sighash_all(&tx.input[0], &witness_script, value),
             hex_hash!("64f3b0f4dd2bb3aa1ce8566d220cc74dda9df97d8490cc81d89d735c92e59fb6")
         );
+        assert_eq!(
+            SigHashCache::new(&tx).signature_hash(0, &witness_script, value, SigHashType::All),
+            hex_hash!("64f3b0f4dd2bb3aa1ce8566d220cc74dda9df97d8490cc81d89d735c92e59fb6")
+        );
     }
 
     #[test]
@@ -235,5 +364,55 @@ mod tests {
             comp.sighash_all(&tx.input[0], &witness_script, value),
             hex_hash!("185c0be5263dce5b4bb50a047973c1b6272bfbd0103a89444597dc40b248ee7c")
         );
+
+        // The same input signed with each of the six sighash types
+        let mut cache = SigHashCache::new(&tx);
+        let sighashes = [
+            (SigHashType::All, "185c0be5263dce5b4bb50a047973c1b6272bfbd0103a89444597dc40b248ee7c"),
+            (SigHashType::None, "e9733bc60ea13c95c6527066bb975a2ff29a925e80aa14c213f686cbae5d2f36"),
+            (SigHashType::Single, "1e1f1c303dc025bd664acb72e583e933fae4cff9148bf78c157d1e8f78530aea"),
+            (SigHashType::AllPlusAnyoneCanPay, "2a67f03e63a6a422125878b40b82da593be8d4efaafe88ee528af6e5a9955c6e"),
+            (SigHashType::NonePlusAnyoneCanPay, "781ba15f3779d5542ce8ecb5c18716733a5ee42a6f51488ec96154934e2c890a"),
+            (SigHashType::SinglePlusAnyoneCanPay, "511e8e52ed574121fc1b654970395502128263f62662e076dc6baf05c2e6a99b"),
+        ];
+        for &(sighash_type, expected) in sighashes.iter() {
+            assert_eq!(
+                cache.signature_hash(0, &witness_script, value, sighash_type),
+                hex_hash!(expected)
+            );
+        }
+        assert_eq!(cache, SigHashCache {
+            tx: &tx,
+            hash_prevouts: Some(comp.hash_prevouts),
+            hash_sequence: Some(comp.hash_sequence),
+            hash_outputs: Some(comp.hash_outputs),
+        });
+    }
+
+    #[test]
+    fn bip143_sighash_single_without_output() {
+        // SIGHASH_SINGLE commits to no outputs if the input has no matching output
+        let tx = deserialize::<Transaction>(
+            &hex_bytes(
+                "0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f000000\
+                0000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a01000000\
+                00ffffffff01202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac11000000",
+            ).unwrap()[..],
+        ).unwrap();
+        let script_code = p2pkh_hex("025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee6357");
+
+        let mut cache = SigHashCache::new(&tx);
+        let single = cache.signature_hash(1, &script_code, 600_000_000, SigHashType::Single);
+        let none = cache.signature_hash(1, &script_code, 600_000_000, SigHashType::None);
+        // Both commit to the same data apart from the sighash type itself
+        assert!(single != none);
+        let mut modified = tx.clone();
+        modified.output[0].value += 1;
+        let mut modified_cache = SigHashCache::new(&modified);
+        assert_eq!(modified_cache.signature_hash(1, &script_code, 600_000_000, SigHashType::Single), single);
+        assert!(modified_cache.signature_hash(0, &script_code, 600_000_000, SigHashType::Single) !=
+                cache.signature_hash(0, &script_code, 600_000_000, SigHashType::Single));
+        // Undefined types are committed to as-is
+        assert!(cache.raw_signature_hash(1, &script_code, 600_000_000, 0x43) != single);
     }
 }
@@ -0,0 +1,569 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! BIP152 Compact Blocks
+//!
+//! Implementation of compact block relay, where a block is announced as its
+//! header and short identifiers of its transactions, which the receiver
+//! matches against its mempool to reconstruct the block, as defined at
+//! https://github.com/bitcoin/bips/blob/master/bip-0152.mediawiki
+//!
+
+use std::{error, fmt, io};
+
+use byteorder::{ByteOrder, LittleEndian};
+use hashes::{sha256, sha256d, siphash24, Hash};
+
+use blockdata::block::{Block, BlockHeader};
+use blockdata::constants::{MAX_BLOCK_WEIGHT, MIN_TRANSACTION_WEIGHT};
+use blockdata::transaction::Transaction;
+use consensus::encode::{self, Decodable, Encodable, VarInt};
+use util::hash::{BitcoinHash, MerkleRoot};
+
+/// BIP152 compact block errors
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The compact block has no transactions, too many, or prefilled
+    /// transactions at indexes outside the block
+    InvalidCompactBlock,
+    /// Two transactions of the compact block have the same short id
+    ShortIdCollision,
+    /// The transactions are for a different block
+    BlockHashMismatch,
+    /// The number of transactions does not match the number missing
+    WrongTransactionCount {
+        /// The number of missing transactions
+        expected: usize,
+        /// The number of transactions supplied
+        actual: usize,
+    },
+    /// The reconstructed block does not match the header's merkle root,
+    /// because of a short id collision in the mempool or bad data
+    MerkleRootMismatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::WrongTransactionCount { expected, actual } => write!(f, "{}: expected {}, got {}", error::Error::description(self), expected, actual),
+            _ => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::InvalidCompactBlock => "invalid compact block",
+            Error::ShortIdCollision => "short id collision in compact block",
+            Error::BlockHashMismatch => "block transactions for another block",
+            Error::WrongTransactionCount { .. } => "wrong number of block transactions",
+            Error::MerkleRootMismatch => "reconstructed block merkle root mismatch",
+        }
+    }
+}
+
+/// A 6-byte transaction identifier, keyed so that collisions cannot be
+/// precomputed
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct ShortId(pub [u8; 6]);
+
+impl ShortId {
+    /// The SipHash keys for the short ids of a compact block, derived from
+    /// its header and nonce.
+    pub fn siphash_keys(header: &BlockHeader, nonce: u64) -> (u64, u64) {
+        let mut engine = sha256::Hash::engine();
+        header.consensus_encode(&mut engine).unwrap();
+        nonce.consensus_encode(&mut engine).unwrap();
+        let hash = sha256::Hash::from_engine(engine);
+        (LittleEndian::read_u64(&hash[0..8]), LittleEndian::read_u64(&hash[8..16]))
+    }
+
+    /// The short id of a txid (version 1) or wtxid (version 2) under the
+    /// given keys.
+    pub fn with_siphash_keys(hash: &sha256d::Hash, keys: (u64, u64)) -> ShortId {
+        let siphash = siphash24::Hash::hash_to_u64_with_keys(keys.0, keys.1, &hash[..]);
+        let mut id = [0; 8];
+        LittleEndian::write_u64(&mut id, siphash);
+        ShortId([id[0], id[1], id[2], id[3], id[4], id[5]])
+    }
+}
+
+impl Encodable for ShortId {
+    #[inline]
+    fn consensus_encode<S: io::Write>(&self, s: S) -> Result<usize, encode::Error> {
+        self.0.consensus_encode(s)
+    }
+}
+
+impl Decodable for ShortId {
+    #[inline]
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Ok(ShortId(Decodable::consensus_decode(d)?))
+    }
+}
+
+/// A transaction sent in full with a compact block, usually the coinbase
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PrefilledTransaction {
+    /// Index of the transaction in the block. It is encoded differentially
+    /// to the previous prefilled transaction.
+    pub idx: u16,
+    /// The transaction
+    pub tx: Transaction,
+}
+
+/// A compact block, sent in `cmpctblock`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HeaderAndShortIds {
+    /// The block header
+    pub header: BlockHeader,
+    /// Nonce for the short id keys
+    pub nonce: u64,
+    /// Short ids of the transactions not prefilled, in block order
+    pub short_ids: Vec<ShortId>,
+    /// Prefilled transactions, in block order
+    pub prefilled_txs: Vec<PrefilledTransaction>,
+}
+
+impl HeaderAndShortIds {
+    /// Create a compact block of `version` 1 (short ids from txids) or 2
+    /// (short ids from wtxids, witnesses sent) with the coinbase and the
+    /// transactions at `prefill` sent in full.
+    ///
+    /// # Panics
+    ///
+    /// If `version` is neither 1 nor 2; these are the only versions BIP152
+    /// defines, and which one is used is negotiated in `sendcmpct` rather
+    /// than coming from the block.
+    pub fn from_block(block: &Block, nonce: u64, version: u32, prefill: &[usize]) -> HeaderAndShortIds {
+        assert!(version == 1 || version == 2, "compact block version must be 1 or 2");
+        let keys = ShortId::siphash_keys(&block.header, nonce);
+        let mut short_ids = Vec::with_capacity(block.txdata.len());
+        let mut prefilled_txs = vec![];
+        for (idx, tx) in block.txdata.iter().enumerate() {
+            if idx == 0 || prefill.contains(&idx) {
+                let mut tx = tx.clone();
+                if version == 1 {
+                    for txin in &mut tx.input {
+                        txin.witness.clear();
+                    }
+                }
+                prefilled_txs.push(PrefilledTransaction {
+                    idx: idx as u16,
+                    tx: tx,
+                });
+            } else {
+                let hash = if version == 1 { tx.txid() } else { wtxid(tx) };
+                short_ids.push(ShortId::with_siphash_keys(&hash, keys));
+            }
+        }
+        HeaderAndShortIds {
+            header: block.header,
+            nonce: nonce,
+            short_ids: short_ids,
+            prefilled_txs: prefilled_txs,
+        }
+    }
+
+    /// The short id of a txid or wtxid, depending on the negotiated version,
+    /// for this compact block.
+    pub fn short_id(&self, hash: &sha256d::Hash) -> ShortId {
+        ShortId::with_siphash_keys(hash, ShortId::siphash_keys(&self.header, self.nonce))
+    }
+}
+
+/// The hash of a transaction including its witness
+fn wtxid(tx: &Transaction) -> sha256d::Hash {
+    sha256d::Hash::hash(&encode::serialize(tx))
+}
+
+impl Encodable for HeaderAndShortIds {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let mut len = self.header.consensus_encode(&mut s)?;
+        len += self.nonce.consensus_encode(&mut s)?;
+        len += self.short_ids.consensus_encode(&mut s)?;
+        len += VarInt(self.prefilled_txs.len() as u64).consensus_encode(&mut s)?;
+        // one past the previous index, which is 65536 after the last one
+        let mut next = 0u32;
+        for prefilled in &self.prefilled_txs {
+            let diff = (prefilled.idx as u32).checked_sub(next)
+                .ok_or(encode::Error::ParseFailed("prefilled transaction indexes are not increasing"))?;
+            len += VarInt(diff as u64).consensus_encode(&mut s)?;
+            len += prefilled.tx.consensus_encode(&mut s)?;
+            next = prefilled.idx as u32 + 1;
+        }
+        Ok(len)
+    }
+}
+
+impl Decodable for HeaderAndShortIds {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+        let header = Decodable::consensus_decode(&mut d)?;
+        let nonce = Decodable::consensus_decode(&mut d)?;
+        let short_ids = Decodable::consensus_decode(&mut d)?;
+        let count = VarInt::consensus_decode(&mut d)?.0;
+        let mut prefilled_txs = Vec::with_capacity(count.min(u16::max_value() as u64) as usize);
+        let mut next = 0u64;
+        for _ in 0..count {
+            let idx = next + VarInt::consensus_decode(&mut d)?.0;
+            if idx > u16::max_value() as u64 {
+                return Err(encode::Error::ParseFailed("prefilled transaction index overflows 16 bits"));
+            }
+            prefilled_txs.push(PrefilledTransaction {
+                idx: idx as u16,
+                tx: Decodable::consensus_decode(&mut d)?,
+            });
+            next = idx + 1;
+        }
+        Ok(HeaderAndShortIds {
+            header: header,
+            nonce: nonce,
+            short_ids: short_ids,
+            prefilled_txs: prefilled_txs,
+        })
+    }
+}
+
+/// A request for the transactions of a compact block, sent in `getblocktxn`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BlockTransactionsRequest {
+    /// The hash of the block
+    pub block_hash: sha256d::Hash,
+    /// Indexes of the requested transactions in the block, in increasing
+    /// order. They are encoded differentially.
+    pub indexes: Vec<u64>,
+}
+
+impl Encodable for BlockTransactionsRequest {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let mut len = self.block_hash.consensus_encode(&mut s)?;
+        len += VarInt(self.indexes.len() as u64).consensus_encode(&mut s)?;
+        let mut next = 0;
+        for idx in &self.indexes {
+            let diff = idx.checked_sub(next)
+                .ok_or(encode::Error::ParseFailed("requested transaction indexes are not increasing"))?;
+            len += VarInt(diff).consensus_encode(&mut s)?;
+            next = idx.checked_add(1)
+                .ok_or(encode::Error::ParseFailed("requested transaction index overflows 64 bits"))?;
+        }
+        Ok(len)
+    }
+}
+
+impl Decodable for BlockTransactionsRequest {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+        let block_hash = Decodable::consensus_decode(&mut d)?;
+        let count = VarInt::consensus_decode(&mut d)?.0;
+        let mut indexes = Vec::with_capacity(count.min(u16::max_value() as u64) as usize);
+        let mut next = 0u64;
+        for _ in 0..count {
+            let idx = next + VarInt::consensus_decode(&mut d)?.0;
+            if idx > u16::max_value() as u64 {
+                return Err(encode::Error::ParseFailed("block transaction index overflows 16 bits"));
+            }
+            indexes.push(idx);
+            next = idx + 1;
+        }
+        Ok(BlockTransactionsRequest {
+            block_hash: block_hash,
+            indexes: indexes,
+        })
+    }
+}
+
+/// The requested transactions of a compact block, sent in `blocktxn`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BlockTransactions {
+    /// The hash of the block
+    pub block_hash: sha256d::Hash,
+    /// The requested transactions, in the order of the request
+    pub transactions: Vec<Transaction>,
+}
+impl_consensus_encoding!(BlockTransactions, block_hash, transactions);
+
+/// A block being reconstructed from a compact block
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PartialBlock {
+    header: BlockHeader,
+    txdata: Vec<Option<Transaction>>,
+}
+
+impl PartialBlock {
+    /// Start reconstructing a compact block, looking up the transactions
+    /// which were not prefilled by short id with `lookup`, usually in the
+    /// mempool. See [HeaderAndShortIds::short_id](struct.HeaderAndShortIds.html#method.short_id)
+    /// for computing the short ids.
+    pub fn new<F>(compact: &HeaderAndShortIds, mut lookup: F) -> Result<PartialBlock, Error>
+        where F: FnMut(&ShortId) -> Option<Transaction>
+    {
+        let count = compact.short_ids.len() + compact.prefilled_txs.len();
+        if count == 0 || count > (MAX_BLOCK_WEIGHT / MIN_TRANSACTION_WEIGHT) as usize {
+            return Err(Error::InvalidCompactBlock);
+        }
+
+        let mut txdata = vec![None; count];
+        let mut last = None;
+        for prefilled in &compact.prefilled_txs {
+            let idx = prefilled.idx as usize;
+            if idx >= count || last.map_or(false, |last| idx <= last) {
+                return Err(Error::InvalidCompactBlock);
+            }
+            txdata[idx] = Some(prefilled.tx.clone());
+            last = Some(idx);
+        }
+
+        let mut short_ids = compact.short_ids.clone();
+        short_ids.sort();
+        short_ids.dedup();
+        if short_ids.len() != compact.short_ids.len() {
+            return Err(Error::ShortIdCollision);
+        }
+
+        let slots = txdata.iter_mut().filter(|tx| tx.is_none());
+        for (slot, short_id) in slots.zip(compact.short_ids.iter()) {
+            *slot = lookup(short_id);
+        }
+        Ok(PartialBlock {
+            header: compact.header,
+            txdata: txdata,
+        })
+    }
+
+    /// Indexes of the transactions which still need to be requested.
+    pub fn missing_indexes(&self) -> Vec<u64> {
+        self.txdata.iter().enumerate().filter(|&(_, tx)| tx.is_none()).map(|(idx, _)| idx as u64).collect()
+    }
+
+    /// The `getblocktxn` request for the missing transactions.
+    pub fn request(&self) -> BlockTransactionsRequest {
+        BlockTransactionsRequest {
+            block_hash: self.header.bitcoin_hash(),
+            indexes: self.missing_indexes(),
+        }
+    }
+
+    /// Complete the block with the transactions received in response to
+    /// [request](#method.request), or with none if nothing was missing, and
+    /// check it against the header's merkle root.
+    pub fn fill(mut self, transactions: &BlockTransactions) -> Result<Block, Error> {
+        if transactions.block_hash != self.header.bitcoin_hash() {
+            return Err(Error::BlockHashMismatch);
+        }
+        let missing = self.txdata.iter().filter(|tx| tx.is_none()).count();
+        if transactions.transactions.len() != missing {
+            return Err(Error::WrongTransactionCount {
+                expected: missing,
+                actual: transactions.transactions.len(),
+            });
+        }
+        let slots = self.txdata.iter_mut().filter(|tx| tx.is_none());
+        for (slot, tx) in slots.zip(transactions.transactions.iter()) {
+            *slot = Some(tx.clone());
+        }
+
+        let block = Block {
+            header: self.header,
+            txdata: self.txdata.into_iter().map(|tx| tx.expect("all filled")).collect(),
+        };
+        if block.merkle_root() != block.header.merkle_root {
+            return Err(Error::MerkleRootMismatch);
+        }
+        Ok(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hashes::{sha256, sha256d, siphash24, Hash};
+    use hex::decode as hex_decode;
+
+    use blockdata::block::Block;
+    use blockdata::transaction::Transaction;
+    use consensus::encode::{deserialize, serialize};
+    use util::hash::{BitcoinHash, MerkleRoot};
+
+    use super::*;
+
+    /// A block with a coinbase and `count` dummy spends, with a valid merkle root
+    fn test_block(count: u32) -> Block {
+        let some_block = hex_decode("010000004ddccd549d28f385ab457e98d1b11ce80bfea2c5ab93015ade4973e400000000bf4473e53794beae34e64fccc471dace6ae544180816f89591894e0f417a914cd74d6e49ffff001d323b3a7b0201000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0804ffff001d026e04ffffffff0100f2052a0100000043410446ef0102d1ec5240f0d061a4246c1bdef63fc3dbab7733052fbbf0ecd8f41fc26bf049ebb4f9527f374280259e7cfa99c48b0e3f39c51347a19a5819651503a5ac00000000010000000321f75f3139a013f50f315b23b0c9a2b6eac31e2bec98e5891c924664889942260000000049483045022100cb2c6b346a978ab8c61b18b5e9397755cbd17d6eb2fe0083ef32e067fa6c785a02206ce44e613f31d9a6b0517e46f3db1576e9812cc98d159bfdaf759a5014081b5c01ffffffff79cda0945903627c3da1f85fc95d0b8ee3e76ae0cfdc9a65d09744b1f8fc85430000000049483045022047957cdd957cfd0becd642f6b84d82f49b6cb4c51a91f49246908af7c3cfdf4a022100e96b46621f1bffcf5ea5982f88cef651e9354f5791602369bf5a82a6cd61a62501fffffffffe09f5fe3ffbf5ee97a54eb5e5069e9da6b4856ee86fc52938c2f979b0f38e82000000004847304402204165be9a4cbab8049e1af9723b96199bfd3e85f44c6b4c0177e3962686b26073022028f638da23fc003760861ad481ead4099312c60030d4cb57820ce4d33812a5ce01ffffffff01009d966b01000000434104ea1feff861b51fe3f5f8a3b12d0f4712db80e919548a80839fc47c6a21e66d957e9c5d8cd108c7a2d2324bad71f9904ac0ae7336507d785b17a2c115e427a32fac00000000").unwrap();
+        let mut block: Block = deserialize(&some_block).unwrap();
+        let spend = block.txdata[1].clone();
+        block.txdata.truncate(1);
+        for i in 0..count {
+            let mut tx = spend.clone();
+            tx.lock_time = i;
+            block.txdata.push(tx);
+        }
+        block.header.merkle_root = block.merkle_root();
+        block
+    }
+
+    #[test]
+    fn short_id_test() {
+        let block = test_block(1);
+        let keys = ShortId::siphash_keys(&block.header, 42);
+
+        let mut preimage = serialize(&block.header);
+        preimage.extend_from_slice(&[42, 0, 0, 0, 0, 0, 0, 0]);
+        let hash = sha256::Hash::hash(&preimage);
+        let k0 = hash[0..8].iter().rev().fold(0, |acc, b| acc << 8 | *b as u64);
+        let k1 = hash[8..16].iter().rev().fold(0, |acc, b| acc << 8 | *b as u64);
+        assert_eq!(keys, (k0, k1));
+
+        let txid = block.txdata[1].txid();
+        let siphash = siphash24::Hash::hash_to_u64_with_keys(k0, k1, &txid[..]);
+        let short_id = ShortId::with_siphash_keys(&txid, keys);
+        let value = short_id.0.iter().rev().fold(0, |acc, b| acc << 8 | *b as u64);
+        assert_eq!(value, siphash & 0xffff_ffff_ffff);
+        assert_eq!(serialize(&short_id), short_id.0.to_vec());
+
+        let compact = HeaderAndShortIds::from_block(&block, 42, 1, &[]);
+        assert_eq!(compact.short_id(&txid), short_id);
+        assert_eq!(compact.short_ids, vec![short_id]);
+        assert_ne!(HeaderAndShortIds::from_block(&block, 43, 1, &[]).short_ids, vec![short_id]);
+    }
+
+    #[test]
+    fn compact_block_encoding_test() {
+        let block = test_block(5);
+        let compact = HeaderAndShortIds::from_block(&block, 7, 2, &[2, 5]);
+        assert_eq!(compact.short_ids.len(), 3);
+        let idxs: Vec<u16> = compact.prefilled_txs.iter().map(|p| p.idx).collect();
+        assert_eq!(idxs, vec![0, 2, 5]);
+
+        let bytes = serialize(&compact);
+        // header, nonce, 3 short ids
+        let mut pos = 80 + 8 + 1 + 3 * 6;
+        assert_eq!(bytes[pos], 3);
+        pos += 1;
+        // indexes are differentially encoded: 0, 2 - 0 - 1, 5 - 2 - 1
+        for &(diff, idx) in &[(0u8, 0), (1, 2), (2, 5)] {
+            assert_eq!(bytes[pos], diff);
+            pos += 1 + serialize(&block.txdata[idx]).len();
+        }
+        assert_eq!(pos, bytes.len());
+        assert_eq!(deserialize::<HeaderAndShortIds>(&bytes).unwrap(), compact);
+
+        // out of order or repeated indexes can not be differentially encoded
+        let mut unordered = compact.clone();
+        unordered.prefilled_txs.swap(1, 2);
+        assert!(unordered.consensus_encode(&mut Vec::new()).is_err());
+        let mut repeated = compact.clone();
+        repeated.prefilled_txs[2].idx = 2;
+        assert!(repeated.consensus_encode(&mut Vec::new()).is_err());
+        let mut last = compact.clone();
+        last.prefilled_txs[2].idx = u16::max_value();
+        assert_eq!(deserialize::<HeaderAndShortIds>(&serialize(&last)).unwrap(), last);
+
+        let request = BlockTransactionsRequest {
+            block_hash: block.bitcoin_hash(),
+            indexes: vec![1, 3, 4, 300],
+        };
+        let bytes = serialize(&request);
+        assert_eq!(&bytes[32..], &[4, 1, 1, 0, 0xfd, 0x27, 0x01]);
+        assert_eq!(deserialize::<BlockTransactionsRequest>(&bytes).unwrap(), request);
+
+        // an index beyond 16 bits is rejected
+        let mut bytes = serialize(&block.bitcoin_hash());
+        bytes.extend_from_slice(&[2, 0xfe, 0xff, 0xff, 0, 0, 0]);
+        assert!(deserialize::<BlockTransactionsRequest>(&bytes).is_err());
+
+        let transactions = BlockTransactions {
+            block_hash: block.bitcoin_hash(),
+            transactions: block.txdata[1..3].to_vec(),
+        };
+        assert_eq!(deserialize::<BlockTransactions>(&serialize(&transactions)).unwrap(), transactions);
+    }
+
+    #[test]
+    fn reconstruct_test() {
+        let block = test_block(6);
+        let compact: HeaderAndShortIds = deserialize(&serialize(&HeaderAndShortIds::from_block(&block, 99, 1, &[3]))).unwrap();
+
+        // the mempool has transactions 1, 4 and 6, plus one unrelated
+        let mut unrelated = block.txdata[1].clone();
+        unrelated.lock_time = 1000;
+        let mempool: Vec<Transaction> = vec![block.txdata[1].clone(), block.txdata[4].clone(), block.txdata[6].clone(), unrelated];
+        let partial = PartialBlock::new(&compact, |short_id| {
+            mempool.iter().find(|tx| compact.short_id(&tx.txid()) == *short_id).cloned()
+        }).unwrap();
+        assert_eq!(partial.missing_indexes(), vec![2, 5]);
+
+        let request = partial.request();
+        assert_eq!(request.block_hash, block.bitcoin_hash());
+        let response = BlockTransactions {
+            block_hash: request.block_hash,
+            transactions: request.indexes.iter().map(|i| block.txdata[*i as usize].clone()).collect(),
+        };
+        assert_eq!(partial.clone().fill(&response).unwrap(), block);
+
+        // wrong transactions
+        let bad = BlockTransactions {
+            block_hash: request.block_hash,
+            transactions: vec![block.txdata[5].clone(), block.txdata[2].clone()],
+        };
+        assert_eq!(partial.clone().fill(&bad), Err(Error::MerkleRootMismatch));
+        let bad = BlockTransactions {
+            block_hash: request.block_hash,
+            transactions: vec![block.txdata[2].clone()],
+        };
+        assert_eq!(partial.clone().fill(&bad), Err(Error::WrongTransactionCount { expected: 2, actual: 1 }));
+        let bad = BlockTransactions {
+            block_hash: sha256d::Hash::default(),
+            transactions: response.transactions.clone(),
+        };
+        assert_eq!(partial.fill(&bad), Err(Error::BlockHashMismatch));
+
+        // nothing missing
+        let partial = PartialBlock::new(&compact, |short_id| {
+            block.txdata.iter().find(|tx| compact.short_id(&tx.txid()) == *short_id).cloned()
+        }).unwrap();
+        assert!(partial.missing_indexes().is_empty());
+        let response = BlockTransactions {
+            block_hash: block.bitcoin_hash(),
+            transactions: vec![],
+        };
+        assert_eq!(partial.fill(&response).unwrap(), block);
+    }
+
+    #[test]
+    fn invalid_compact_block_test() {
+        let block = test_block(3);
+        let compact = HeaderAndShortIds::from_block(&block, 0, 1, &[]);
+
+        let mut empty = compact.clone();
+        empty.short_ids.clear();
+        empty.prefilled_txs.clear();
+        assert_eq!(PartialBlock::new(&empty, |_| None), Err(Error::InvalidCompactBlock));
+
+        let mut out_of_range = compact.clone();
+        out_of_range.prefilled_txs[0].idx = 4;
+        assert_eq!(PartialBlock::new(&out_of_range, |_| None), Err(Error::InvalidCompactBlock));
+
+        let mut duplicate = compact.clone();
+        duplicate.prefilled_txs.push(duplicate.prefilled_txs[0].clone());
+        duplicate.short_ids.pop();
+        assert_eq!(PartialBlock::new(&duplicate, |_| None), Err(Error::InvalidCompactBlock));
+
+        let mut collision = compact.clone();
+        collision.short_ids[1] = collision.short_ids[0];
+        assert_eq!(PartialBlock::new(&collision, |_| None), Err(Error::ShortIdCollision));
+    }
+}
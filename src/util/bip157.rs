@@ -0,0 +1,409 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! BIP157 Client Side Block Filtering
+//!
+//! Verification of the filter header chain served by peers in `cfheaders`
+//! and `cfcheckpt` messages, as described at
+//! https://github.com/bitcoin/bips/blob/master/bip-0157.mediawiki
+//!
+//! Each filter header commits to the hash of its block's filter and to the
+//! previous filter header, so a light client that follows one chain of
+//! filter headers only needs to trust the filters it downloads as far as
+//! they match it. When peers serve different chains, the client finds the
+//! first height where they differ and settles it by computing the filter
+//! of that block itself.
+//!
+
+use std::{error, fmt};
+
+use hashes::{sha256d, Hash, HashEngine};
+
+use network::message_filter::{CFCheckpt, CFHeaders};
+use util::bip158::BlockFilter;
+
+/// The number of blocks between the filter headers of a `cfcheckpt`
+pub const CHECKPOINT_INTERVAL: u32 = 1000;
+
+/// The maximum number of filter hashes in a `cfheaders` message
+pub const MAX_CFHEADERS: usize = 2000;
+
+/// Filter header chain verification errors
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The message is for another filter type
+    WrongFilterType(u8),
+    /// The message has more than `MAX_CFHEADERS` filter hashes
+    TooManyHeaders,
+    /// The message starts above the tip of the chain or below its start
+    UnknownHeight(u32),
+    /// The previous filter header of the message is not the one in the chain
+    PreviousMismatch,
+    /// The filter header at this height differs from the one in the chain
+    Conflict(u32),
+    /// The filter header at this height differs from the checkpoint
+    CheckpointMismatch(u32),
+    /// The filter at this height does not match its filter header
+    FilterMismatch(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::WrongFilterType(t) => write!(f, "{}: {}", error::Error::description(self), t),
+            Error::UnknownHeight(h)
+            | Error::Conflict(h)
+            | Error::CheckpointMismatch(h)
+            | Error::FilterMismatch(h) => write!(f, "{} at height {}", error::Error::description(self), h),
+            Error::TooManyHeaders | Error::PreviousMismatch => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::WrongFilterType(_) => "unexpected filter type",
+            Error::TooManyHeaders => "too many filter headers",
+            Error::UnknownHeight(_) => "filter headers do not connect",
+            Error::PreviousMismatch => "previous filter header mismatch",
+            Error::Conflict(_) => "conflicting filter header",
+            Error::CheckpointMismatch(_) => "filter header checkpoint mismatch",
+            Error::FilterMismatch(_) => "filter does not match filter header",
+        }
+    }
+}
+
+/// The filter header committing to `filter_hash` following `previous`.
+/// The previous filter header of the genesis block is all zeros.
+pub fn filter_header(filter_hash: &sha256d::Hash, previous: &sha256d::Hash) -> sha256d::Hash {
+    let mut engine = sha256d::Hash::engine();
+    engine.input(&filter_hash[..]);
+    engine.input(&previous[..]);
+    sha256d::Hash::from_engine(engine)
+}
+
+/// The filter headers a `cfheaders` message commits to, in height order.
+pub fn cfheaders_headers(msg: &CFHeaders) -> Vec<sha256d::Hash> {
+    let mut previous = msg.previous_filter;
+    msg.filter_hashes.iter().map(|filter_hash| {
+        previous = filter_header(filter_hash, &previous);
+        previous
+    }).collect()
+}
+
+/// The height of the first checkpoint where two `cfcheckpt` responses for
+/// the same stop hash differ, if any.
+pub fn checkpoint_conflict(a: &CFCheckpt, b: &CFCheckpt) -> Option<u32> {
+    a.filter_headers.iter().zip(b.filter_headers.iter())
+        .position(|(a, b)| a != b)
+        .map(|i| (i as u32 + 1) * CHECKPOINT_INTERVAL)
+}
+
+/// The height of the first filter header where two `cfheaders` responses
+/// for blocks from `start_height` differ, if any. A difference in the
+/// previous filter header is reported at `start_height - 1`.
+pub fn cfheaders_conflict(start_height: u32, a: &CFHeaders, b: &CFHeaders) -> Option<u32> {
+    if a.previous_filter != b.previous_filter {
+        return Some(start_height.saturating_sub(1));
+    }
+    a.filter_hashes.iter().zip(b.filter_hashes.iter())
+        .position(|(a, b)| a != b)
+        .map(|i| start_height + i as u32)
+}
+
+/// A chain of verified filter headers of one filter type
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FilterHeaderChain {
+    filter_type: u8,
+    /// Height of the first header in `headers`
+    start_height: u32,
+    /// The filter header preceding `start_height`
+    previous: sha256d::Hash,
+    headers: Vec<sha256d::Hash>,
+    /// Checkpoint `i` is the filter header at `(i + 1) * CHECKPOINT_INTERVAL`
+    checkpoints: Vec<sha256d::Hash>,
+}
+
+impl FilterHeaderChain {
+    /// An empty chain starting at the genesis block.
+    pub fn new(filter_type: u8) -> FilterHeaderChain {
+        FilterHeaderChain::with_trusted(filter_type, 0, sha256d::Hash::default())
+    }
+
+    /// An empty chain starting at `start_height` which trusts `previous` as
+    /// the filter header of the block below it.
+    pub fn with_trusted(filter_type: u8, start_height: u32, previous: sha256d::Hash) -> FilterHeaderChain {
+        FilterHeaderChain {
+            filter_type: filter_type,
+            start_height: start_height,
+            previous: previous,
+            headers: vec![],
+            checkpoints: vec![],
+        }
+    }
+
+    /// The filter type of the chain
+    pub fn filter_type(&self) -> u8 {
+        self.filter_type
+    }
+
+    /// The height of the next filter header to be added
+    pub fn next_height(&self) -> u32 {
+        self.start_height + self.headers.len() as u32
+    }
+
+    /// The latest filter header, or the trusted one of an empty chain
+    pub fn tip(&self) -> sha256d::Hash {
+        *self.headers.last().unwrap_or(&self.previous)
+    }
+
+    /// The filter header at `height`, if known
+    pub fn header_at(&self, height: u32) -> Option<sha256d::Hash> {
+        if height < self.start_height {
+            return None;
+        }
+        self.headers.get((height - self.start_height) as usize).cloned()
+    }
+
+    /// The checkpoints received so far
+    pub fn checkpoints(&self) -> &[sha256d::Hash] {
+        &self.checkpoints
+    }
+
+    /// The filter header preceding `height`, if known
+    fn previous_of(&self, height: u32) -> Option<sha256d::Hash> {
+        if height == self.start_height {
+            Some(self.previous)
+        } else if height > self.start_height {
+            self.header_at(height - 1)
+        } else {
+            None
+        }
+    }
+
+    /// The checkpoint at `height`, if any
+    fn checkpoint_at(&self, height: u32) -> Option<&sha256d::Hash> {
+        if height == 0 || height % CHECKPOINT_INTERVAL != 0 {
+            return None;
+        }
+        self.checkpoints.get((height / CHECKPOINT_INTERVAL - 1) as usize)
+    }
+
+    /// Check a `cfheaders` response for the blocks from `start_height`
+    /// against the chain and the checkpoints, returning the filter headers
+    /// it commits to. It may overlap with known filter headers but must
+    /// connect to the chain.
+    pub fn check_cfheaders(&self, start_height: u32, msg: &CFHeaders) -> Result<Vec<sha256d::Hash>, Error> {
+        if msg.filter_type != self.filter_type {
+            return Err(Error::WrongFilterType(msg.filter_type));
+        }
+        if msg.filter_hashes.len() > MAX_CFHEADERS {
+            return Err(Error::TooManyHeaders);
+        }
+        match self.previous_of(start_height) {
+            Some(previous) => if previous != msg.previous_filter {
+                return Err(Error::PreviousMismatch);
+            },
+            None => return Err(Error::UnknownHeight(start_height)),
+        }
+
+        let headers = cfheaders_headers(msg);
+        for (i, header) in headers.iter().enumerate() {
+            let height = start_height + i as u32;
+            if let Some(known) = self.header_at(height) {
+                if known != *header {
+                    return Err(Error::Conflict(height));
+                }
+            }
+            if let Some(checkpoint) = self.checkpoint_at(height) {
+                if checkpoint != header {
+                    return Err(Error::CheckpointMismatch(height));
+                }
+            }
+        }
+        Ok(headers)
+    }
+
+    /// Check a `cfheaders` response as by [check_cfheaders](#method.check_cfheaders)
+    /// and extend the chain with its new filter headers.
+    pub fn add_cfheaders(&mut self, start_height: u32, msg: &CFHeaders) -> Result<(), Error> {
+        let headers = self.check_cfheaders(start_height, msg)?;
+        let known = (self.next_height() - start_height) as usize;
+        if headers.len() > known {
+            self.headers.extend_from_slice(&headers[known..]);
+        }
+        Ok(())
+    }
+
+    /// Check a `cfcheckpt` response against the known filter headers and
+    /// checkpoints, and remember its checkpoints to check later `cfheaders`
+    /// against.
+    pub fn add_checkpoints(&mut self, msg: &CFCheckpt) -> Result<(), Error> {
+        if msg.filter_type != self.filter_type {
+            return Err(Error::WrongFilterType(msg.filter_type));
+        }
+        for (i, checkpoint) in msg.filter_headers.iter().enumerate() {
+            let height = (i as u32 + 1) * CHECKPOINT_INTERVAL;
+            if let Some(known) = self.checkpoints.get(i) {
+                if known != checkpoint {
+                    return Err(Error::CheckpointMismatch(height));
+                }
+            }
+            if let Some(known) = self.header_at(height) {
+                if known != *checkpoint {
+                    return Err(Error::Conflict(height));
+                }
+            }
+        }
+        if msg.filter_headers.len() > self.checkpoints.len() {
+            let known = self.checkpoints.len();
+            self.checkpoints.extend_from_slice(&msg.filter_headers[known..]);
+        }
+        Ok(())
+    }
+
+    /// Check a downloaded filter of the block at `height` against the chain.
+    pub fn check_filter(&self, height: u32, filter: &BlockFilter) -> Result<(), Error> {
+        match (self.previous_of(height), self.header_at(height)) {
+            (Some(previous), Some(header)) => if filter.filter_id(&previous) == header {
+                Ok(())
+            } else {
+                Err(Error::FilterMismatch(height))
+            },
+            _ => Err(Error::UnknownHeight(height)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hashes::{sha256d, Hash};
+    use hashes::hex::FromHex;
+
+    use network::message_filter::{CFCheckpt, CFHeaders};
+    use util::bip158::BlockFilter;
+
+    use super::*;
+
+    fn hash(hex: &str) -> sha256d::Hash {
+        sha256d::Hash::from_hex(hex).unwrap()
+    }
+
+    fn filter_hashes(from: u32, count: u32, salt: u8) -> Vec<sha256d::Hash> {
+        (from..from + count).map(|i| sha256d::Hash::hash(&[i as u8, (i >> 8) as u8, salt])).collect()
+    }
+
+    fn cfheaders(previous: sha256d::Hash, filter_hashes: Vec<sha256d::Hash>) -> CFHeaders {
+        CFHeaders {
+            filter_type: 0,
+            stop_hash: sha256d::Hash::default(),
+            previous_filter: previous,
+            filter_hashes: filter_hashes,
+        }
+    }
+
+    #[test]
+    fn bip158_vectors_test() {
+        // blocks 2 and 3 of the BIP158 test vectors, following the filter header of block 1
+        let filter2 = BlockFilter::new(&[0x01, 0x74, 0xa1, 0x70]);
+        let filter3 = BlockFilter::new(&[0x01, 0x6c, 0xf7, 0xa0]);
+        let mut chain = FilterHeaderChain::with_trusted(0, 2, hash("d7bdac13a59d745b1add0d2ce852f1a0442e8945fc1bf3848d3cbffd88c24fe1"));
+        let msg = cfheaders(chain.tip(), vec![
+            sha256d::Hash::hash(&filter2.content),
+            sha256d::Hash::hash(&filter3.content),
+        ]);
+        chain.add_cfheaders(2, &msg).unwrap();
+        assert_eq!(chain.next_height(), 4);
+        assert_eq!(chain.header_at(2), Some(hash("186afd11ef2b5e7e3504f2e8cbf8df28a1fd251fe53d60dff8b1467d1b386cf0")));
+        assert_eq!(chain.tip(), hash("8d63aadf5ab7257cb6d2316a57b16f517bff1c6388f124ec4c04af1212729d2a"));
+
+        assert_eq!(chain.check_filter(2, &filter2), Ok(()));
+        assert_eq!(chain.check_filter(3, &filter3), Ok(()));
+        assert_eq!(chain.check_filter(2, &filter3), Err(Error::FilterMismatch(2)));
+        assert_eq!(chain.check_filter(4, &filter3), Err(Error::UnknownHeight(4)));
+
+        // the genesis filter header follows all zeros
+        let genesis = BlockFilter::new(&[0x01, 0x9d, 0xfc, 0xa8]);
+        let mut chain = FilterHeaderChain::new(0);
+        chain.add_cfheaders(0, &cfheaders(sha256d::Hash::default(), vec![sha256d::Hash::hash(&genesis.content)])).unwrap();
+        assert_eq!(chain.tip(), hash("21584579b7eb08997773e5aeff3a7f932700042d0ed2a6129012b7d7ae81b750"));
+        assert_eq!(chain.tip(), genesis.filter_id(&sha256d::Hash::default()));
+    }
+
+    #[test]
+    fn cfheaders_test() {
+        let mut chain = FilterHeaderChain::new(0);
+        chain.add_cfheaders(0, &cfheaders(sha256d::Hash::default(), filter_hashes(0, 10, 0))).unwrap();
+        let tip = chain.tip();
+
+        // overlapping responses are fine if they agree
+        let overlap = cfheaders(chain.header_at(4).unwrap(), filter_hashes(5, 10, 0));
+        chain.add_cfheaders(5, &overlap).unwrap();
+        assert_eq!(chain.next_height(), 15);
+        assert_eq!(chain.header_at(9), Some(tip));
+
+        let mut conflict = filter_hashes(5, 10, 0);
+        conflict[7] = sha256d::Hash::default();
+        let conflict = cfheaders(chain.header_at(4).unwrap(), conflict);
+        assert_eq!(chain.check_cfheaders(5, &conflict), Err(Error::Conflict(12)));
+        assert_eq!(cfheaders_conflict(5, &overlap, &conflict), Some(12));
+        assert_eq!(cfheaders_conflict(5, &overlap, &overlap), None);
+
+        assert_eq!(chain.check_cfheaders(5, &cfheaders(tip, filter_hashes(5, 1, 0))), Err(Error::PreviousMismatch));
+        assert_eq!(chain.check_cfheaders(16, &cfheaders(chain.tip(), filter_hashes(16, 1, 0))), Err(Error::UnknownHeight(16)));
+        assert_eq!(chain.check_cfheaders(15, &cfheaders(chain.tip(), filter_hashes(15, 2001, 0))), Err(Error::TooManyHeaders));
+        let mut wrong_type = cfheaders(chain.tip(), filter_hashes(15, 1, 0));
+        wrong_type.filter_type = 1;
+        assert_eq!(chain.check_cfheaders(15, &wrong_type), Err(Error::WrongFilterType(1)));
+    }
+
+    #[test]
+    fn checkpoints_test() {
+        let good = cfheaders(sha256d::Hash::default(), filter_hashes(0, 2000, 0));
+        let good_headers = cfheaders_headers(&good);
+        let checkpoint = CFCheckpt {
+            filter_type: 0,
+            stop_hash: sha256d::Hash::default(),
+            filter_headers: vec![good_headers[1000]],
+        };
+
+        let mut chain = FilterHeaderChain::new(0);
+        chain.add_checkpoints(&checkpoint).unwrap();
+        assert_eq!(chain.checkpoints(), &[good_headers[1000]][..]);
+
+        // a peer serving other filters is caught at the checkpoint
+        let mut bad_hashes = filter_hashes(0, 2000, 0);
+        bad_hashes[500] = sha256d::Hash::default();
+        let bad = cfheaders(sha256d::Hash::default(), bad_hashes);
+        assert_eq!(chain.check_cfheaders(0, &bad), Err(Error::CheckpointMismatch(1000)));
+        assert_eq!(cfheaders_conflict(0, &good, &bad), Some(500));
+        chain.add_cfheaders(0, &good).unwrap();
+
+        // checkpoints disagreeing with the chain or each other
+        let mut other = checkpoint.clone();
+        other.filter_headers = vec![good_headers[1000], good_headers[1999]];
+        assert_eq!(checkpoint_conflict(&checkpoint, &other), None);
+        other.filter_headers[0] = good_headers[999];
+        assert_eq!(checkpoint_conflict(&checkpoint, &other), Some(1000));
+        assert_eq!(chain.add_checkpoints(&other), Err(Error::CheckpointMismatch(1000)));
+        let mut fresh = FilterHeaderChain::new(0);
+        fresh.add_cfheaders(0, &good).unwrap();
+        assert_eq!(fresh.add_checkpoints(&other), Err(Error::Conflict(1000)));
+    }
+}
@@ -26,6 +26,7 @@ use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
 use hashes::{hex, hash160, sha512, Hash, HashEngine, Hmac, HmacEngine};
 use secp256k1::{self, Secp256k1};
 
+use network::chainparams::ChainParams;
 use network::constants::Network;
 use util::base58;
 use util::key::{PublicKey, PrivateKey};
@@ -579,13 +580,114 @@ impl ExtendedPubKey {
     }
 }
 
-impl fmt::Display for ExtendedPrivKey {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+/// The script types SLIP-132 assigns extended key version bytes to, see
+/// https://github.com/satoshilabs/slips/blob/master/slip-0132.md
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ScriptType {
+    /// P2PKH or P2SH (xpub, tpub)
+    P2pkh,
+    /// P2WPKH nested in P2SH (ypub, upub)
+    P2shWpkh,
+    /// P2WSH nested in P2SH (Ypub, Upub)
+    P2shWsh,
+    /// P2WPKH (zpub, vpub)
+    P2wpkh,
+    /// P2WSH (Zpub, Vpub)
+    P2wsh,
+}
+
+/// SLIP-132 version bytes with their network, script type and whether they
+/// are for private keys. Test networks share the testnet versions.
+const VERSIONS: [([u8; 4], Network, ScriptType, bool); 20] = [
+    ([0x04, 0x88, 0xB2, 0x1E], Network::Bitcoin, ScriptType::P2pkh, false),
+    ([0x04, 0x88, 0xAD, 0xE4], Network::Bitcoin, ScriptType::P2pkh, true),
+    ([0x04, 0x9D, 0x7C, 0xB2], Network::Bitcoin, ScriptType::P2shWpkh, false),
+    ([0x04, 0x9D, 0x78, 0x78], Network::Bitcoin, ScriptType::P2shWpkh, true),
+    ([0x02, 0x95, 0xB4, 0x3F], Network::Bitcoin, ScriptType::P2shWsh, false),
+    ([0x02, 0x95, 0xB0, 0x05], Network::Bitcoin, ScriptType::P2shWsh, true),
+    ([0x04, 0xB2, 0x47, 0x46], Network::Bitcoin, ScriptType::P2wpkh, false),
+    ([0x04, 0xB2, 0x43, 0x0C], Network::Bitcoin, ScriptType::P2wpkh, true),
+    ([0x02, 0xAA, 0x7E, 0xD3], Network::Bitcoin, ScriptType::P2wsh, false),
+    ([0x02, 0xAA, 0x7A, 0x99], Network::Bitcoin, ScriptType::P2wsh, true),
+    ([0x04, 0x35, 0x87, 0xCF], Network::Testnet, ScriptType::P2pkh, false),
+    ([0x04, 0x35, 0x83, 0x94], Network::Testnet, ScriptType::P2pkh, true),
+    ([0x04, 0x4A, 0x52, 0x62], Network::Testnet, ScriptType::P2shWpkh, false),
+    ([0x04, 0x4A, 0x4E, 0x28], Network::Testnet, ScriptType::P2shWpkh, true),
+    ([0x02, 0x42, 0x89, 0xEF], Network::Testnet, ScriptType::P2shWsh, false),
+    ([0x02, 0x42, 0x85, 0xB5], Network::Testnet, ScriptType::P2shWsh, true),
+    ([0x04, 0x5F, 0x1C, 0xF6], Network::Testnet, ScriptType::P2wpkh, false),
+    ([0x04, 0x5F, 0x18, 0xBC], Network::Testnet, ScriptType::P2wpkh, true),
+    ([0x02, 0x57, 0x54, 0x83], Network::Testnet, ScriptType::P2wsh, false),
+    ([0x02, 0x57, 0x50, 0x48], Network::Testnet, ScriptType::P2wsh, true),
+];
+
+impl ScriptType {
+    /// The version bytes of extended public keys of this script type on
+    /// `network`
+    pub fn public_version(&self, network: Network) -> [u8; 4] {
+        self.version(network, false)
+    }
+
+    /// The version bytes of extended private keys of this script type on
+    /// `network`
+    pub fn private_version(&self, network: Network) -> [u8; 4] {
+        self.version(network, true)
+    }
+
+    fn version(&self, network: Network, private: bool) -> [u8; 4] {
+        let network = match network {
+            Network::Bitcoin => Network::Bitcoin,
+            Network::Testnet | Network::Signet | Network::Regtest => Network::Testnet,
+        };
+        VERSIONS.iter()
+            .find(|v| v.1 == network && v.2 == *self && v.3 == private)
+            .expect("all versions are listed").0
+    }
+
+    /// The network, script type and whether it is for private keys of the
+    /// version bytes `version`
+    fn from_version(version: &[u8]) -> Option<(Network, ScriptType, bool)> {
+        VERSIONS.iter()
+            .find(|v| v.0[..] == *version)
+            .map(|v| (v.1, v.2, v.3))
+    }
+}
+
+impl ExtendedPrivKey {
+    /// Encode the key with the version bytes of `params` instead of those of
+    /// its network
+    pub fn to_string_with_params(&self, params: &ChainParams) -> String {
+        self.encode_with_version(params.base58_prefixes.ext_secret_key)
+    }
+
+    /// Encode the key with the SLIP-132 version bytes of `script_type`
+    pub fn to_string_with_script_type(&self, script_type: ScriptType) -> String {
+        self.encode_with_version(script_type.private_version(self.network))
+    }
+
+    /// Parse a key with any SLIP-132 version bytes, returning the script
+    /// type they stand for along with the key
+    pub fn from_str_with_script_type(inp: &str) -> Result<(ExtendedPrivKey, ScriptType), base58::Error> {
+        let data = decode_extended_key(inp)?;
+        match ScriptType::from_version(&data[0..4]) {
+            Some((network, script_type, true)) => Ok((ExtendedPrivKey::decode(&data, network)?, script_type)),
+            _ => Err(base58::Error::InvalidVersion(data[0..4].to_vec())),
+        }
+    }
+
+    /// Parse a key with the version bytes of `params`. The key gets the
+    /// network of `params`.
+    pub fn from_str_with_params(inp: &str, params: &ChainParams) -> Result<ExtendedPrivKey, base58::Error> {
+        let data = decode_extended_key(inp)?;
+        if data[0..4] != params.base58_prefixes.ext_secret_key {
+            return Err(base58::Error::InvalidVersion(data[0..4].to_vec()));
+        }
+        ExtendedPrivKey::decode(&data, params.network)
+    }
+
+    fn encode_with_version(&self, version: [u8; 4]) -> String {
         let mut ret = [0; 78];
-        ret[0..4].copy_from_slice(&match self.network {
-            Network::Bitcoin => [0x04, 0x88, 0xAD, 0xE4],
-            Network::Testnet | Network::Regtest => [0x04, 0x35, 0x83, 0x94],
-        }[..]);
+        ret[0..4].copy_from_slice(&version[..]);
         ret[4] = self.depth as u8;
         ret[5..9].copy_from_slice(&self.parent_fingerprint[..]);
 
@@ -594,31 +696,13 @@ impl fmt::Display for ExtendedPrivKey {
         ret[13..45].copy_from_slice(&self.chain_code[..]);
         ret[45] = 0;
         ret[46..78].copy_from_slice(&self.private_key[..]);
-        fmt.write_str(&base58::check_encode_slice(&ret[..]))
+        base58::check_encode_slice(&ret[..])
     }
-}
-
-impl FromStr for ExtendedPrivKey {
-    type Err = base58::Error;
-
-    fn from_str(inp: &str) -> Result<ExtendedPrivKey, base58::Error> {
-        let data = base58::from_check(inp)?;
-
-        if data.len() != 78 {
-            return Err(base58::Error::InvalidLength(data.len()));
-        }
 
+    fn decode(data: &[u8], network: Network) -> Result<ExtendedPrivKey, base58::Error> {
         let cn_int: u32 = Cursor::new(&data[9..13]).read_u32::<BigEndian>().unwrap();
         let child_number: ChildNumber = ChildNumber::from(cn_int);
 
-        let network = if &data[0..4] == [0x04u8, 0x88, 0xAD, 0xE4] {
-            Network::Bitcoin
-        } else if &data[0..4] == [0x04u8, 0x35, 0x83, 0x94] {
-            Network::Testnet
-        } else {
-            return Err(base58::Error::InvalidVersion((&data[0..4]).to_vec()));
-        };
-
         Ok(ExtendedPrivKey {
             network: network,
             depth: data[4],
@@ -638,13 +722,61 @@ impl FromStr for ExtendedPrivKey {
     }
 }
 
-impl fmt::Display for ExtendedPubKey {
+impl fmt::Display for ExtendedPrivKey {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&self.to_string_with_script_type(ScriptType::P2pkh))
+    }
+}
+
+impl FromStr for ExtendedPrivKey {
+    type Err = base58::Error;
+
+    /// Parse an xprv or tprv. Keys with the other SLIP-132 version bytes
+    /// are rejected, parse them with `from_str_with_script_type`.
+    fn from_str(inp: &str) -> Result<ExtendedPrivKey, base58::Error> {
+        let data = decode_extended_key(inp)?;
+        match ScriptType::from_version(&data[0..4]) {
+            Some((network, ScriptType::P2pkh, true)) => ExtendedPrivKey::decode(&data, network),
+            _ => Err(base58::Error::InvalidVersion(data[0..4].to_vec())),
+        }
+    }
+}
+
+impl ExtendedPubKey {
+    /// Encode the key with the version bytes of `params` instead of those of
+    /// its network
+    pub fn to_string_with_params(&self, params: &ChainParams) -> String {
+        self.encode_with_version(params.base58_prefixes.ext_public_key)
+    }
+
+    /// Encode the key with the SLIP-132 version bytes of `script_type`
+    pub fn to_string_with_script_type(&self, script_type: ScriptType) -> String {
+        self.encode_with_version(script_type.public_version(self.network))
+    }
+
+    /// Parse a key with any SLIP-132 version bytes, returning the script
+    /// type they stand for along with the key
+    pub fn from_str_with_script_type(inp: &str) -> Result<(ExtendedPubKey, ScriptType), base58::Error> {
+        let data = decode_extended_key(inp)?;
+        match ScriptType::from_version(&data[0..4]) {
+            Some((network, script_type, false)) => Ok((ExtendedPubKey::decode(&data, network)?, script_type)),
+            _ => Err(base58::Error::InvalidVersion(data[0..4].to_vec())),
+        }
+    }
+
+    /// Parse a key with the version bytes of `params`. The key gets the
+    /// network of `params`.
+    pub fn from_str_with_params(inp: &str, params: &ChainParams) -> Result<ExtendedPubKey, base58::Error> {
+        let data = decode_extended_key(inp)?;
+        if data[0..4] != params.base58_prefixes.ext_public_key {
+            return Err(base58::Error::InvalidVersion(data[0..4].to_vec()));
+        }
+        ExtendedPubKey::decode(&data, params.network)
+    }
+
+    fn encode_with_version(&self, version: [u8; 4]) -> String {
         let mut ret = [0; 78];
-        ret[0..4].copy_from_slice(&match self.network {
-            Network::Bitcoin => [0x04u8, 0x88, 0xB2, 0x1E],
-            Network::Testnet | Network::Regtest => [0x04u8, 0x35, 0x87, 0xCF],
-        }[..]);
+        ret[0..4].copy_from_slice(&version[..]);
         ret[4] = self.depth as u8;
         ret[5..9].copy_from_slice(&self.parent_fingerprint[..]);
 
@@ -652,31 +784,15 @@ impl fmt::Display for ExtendedPubKey {
 
         ret[13..45].copy_from_slice(&self.chain_code[..]);
         ret[45..78].copy_from_slice(&self.public_key.key.serialize()[..]);
-        fmt.write_str(&base58::check_encode_slice(&ret[..]))
+        base58::check_encode_slice(&ret[..])
     }
-}
-
-impl FromStr for ExtendedPubKey {
-    type Err = base58::Error;
-
-    fn from_str(inp: &str) -> Result<ExtendedPubKey, base58::Error> {
-        let data = base58::from_check(inp)?;
-
-        if data.len() != 78 {
-            return Err(base58::Error::InvalidLength(data.len()));
-        }
 
+    fn decode(data: &[u8], network: Network) -> Result<ExtendedPubKey, base58::Error> {
         let cn_int: u32 = Cursor::new(&data[9..13]).read_u32::<BigEndian>().unwrap();
         let child_number: ChildNumber = ChildNumber::from(cn_int);
 
         Ok(ExtendedPubKey {
-            network: if &data[0..4] == [0x04u8, 0x88, 0xB2, 0x1E] {
-                Network::Bitcoin
-            } else if &data[0..4] == [0x04u8, 0x35, 0x87, 0xCF] {
-                Network::Testnet
-            } else {
-                return Err(base58::Error::InvalidVersion((&data[0..4]).to_vec()));
-            },
+            network: network,
             depth: data[4],
             parent_fingerprint: Fingerprint::from(&data[5..9]),
             child_number: child_number,
@@ -688,6 +804,36 @@ impl FromStr for ExtendedPubKey {
     }
 }
 
+impl fmt::Display for ExtendedPubKey {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&self.to_string_with_script_type(ScriptType::P2pkh))
+    }
+}
+
+impl FromStr for ExtendedPubKey {
+    type Err = base58::Error;
+
+    /// Parse an xpub or tpub. Keys with the other SLIP-132 version bytes
+    /// are rejected, parse them with `from_str_with_script_type`.
+    fn from_str(inp: &str) -> Result<ExtendedPubKey, base58::Error> {
+        let data = decode_extended_key(inp)?;
+        match ScriptType::from_version(&data[0..4]) {
+            Some((network, ScriptType::P2pkh, false)) => ExtendedPubKey::decode(&data, network),
+            _ => Err(base58::Error::InvalidVersion(data[0..4].to_vec())),
+        }
+    }
+}
+
+/// Decode a base58 extended key, checking its length
+fn decode_extended_key(inp: &str) -> Result<Vec<u8>, base58::Error> {
+    let data = base58::from_check(inp)?;
+
+    if data.len() != 78 {
+        return Err(base58::Error::InvalidLength(data.len()));
+    }
+    Ok(data)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -862,6 +1008,56 @@ mod tests {
         assert!(iter.next().is_none());
     }
 
+    #[test]
+    fn test_slip132() {
+        use util::bip39::{Language, Mnemonic};
+
+        let secp = Secp256k1::new();
+        let mnemonic = Mnemonic::parse_in(Language::English,
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about").unwrap();
+        let master = ExtendedPrivKey::new_master(Bitcoin, &mnemonic.to_seed("")).unwrap();
+
+        // BIP84 and BIP49 account keys
+        let path = DerivationPath::from_str("m/84'/0'/0'").unwrap();
+        let xpub = ExtendedPubKey::from_private(&secp, &master.derive_priv(&secp, &path).unwrap());
+        let zpub = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+        assert_eq!(xpub.to_string_with_script_type(ScriptType::P2wpkh), zpub);
+        assert_eq!(ExtendedPubKey::from_str_with_script_type(zpub).unwrap(), (xpub, ScriptType::P2wpkh));
+        // plain parsing only takes xpubs, so that the script type is not lost
+        assert_eq!(ExtendedPubKey::from_str(zpub), Err(base58::Error::InvalidVersion(vec![0x04, 0xB2, 0x47, 0x46])));
+        assert_eq!(ExtendedPubKey::from_str(&xpub.to_string()).unwrap(), xpub);
+        assert!(xpub.to_string().starts_with("xpub"));
+
+        let path = DerivationPath::from_str("m/49'/0'/0'").unwrap();
+        let xpub = ExtendedPubKey::from_private(&secp, &master.derive_priv(&secp, &path).unwrap());
+        let ypub = "ypub6Ww3ibxVfGzLrAH1PNcjyAWenMTbbAosGNB6VvmSEgytSER9azLDWCxoJwW7Ke7icmizBMXrzBx9979FfaHxHcrArf3zbeJJJUZPf663zsP";
+        assert_eq!(xpub.to_string_with_script_type(ScriptType::P2shWpkh), ypub);
+
+        let zprv = "zprvAWgYBBk7JR8Gjrh4UJQ2uJdG1r3WNRRfURiABBE3RvMXYSrRJL62XuezvGdPvG6GFBZduosCc1YP5wixPox7zhZLfiUm8aunE96BBa4Kei5";
+        assert_eq!(master.to_string_with_script_type(ScriptType::P2wpkh), zprv);
+        assert_eq!(ExtendedPrivKey::from_str_with_script_type(zprv).unwrap(), (master, ScriptType::P2wpkh));
+        assert!(ExtendedPrivKey::from_str(zprv).is_err());
+        // private versions are not accepted for public keys and the other way round
+        assert!(ExtendedPubKey::from_str(zprv).is_err());
+        assert!(ExtendedPrivKey::from_str(zpub).is_err());
+
+        // every family roundtrips, on test networks with the testnet versions
+        let script_types = [ScriptType::P2pkh, ScriptType::P2shWpkh, ScriptType::P2shWsh, ScriptType::P2wpkh, ScriptType::P2wsh];
+        for network in [Bitcoin, Network::Testnet].iter() {
+            let sk = ExtendedPrivKey::new_master(*network, &[1; 32]).unwrap();
+            let pk = ExtendedPubKey::from_private(&secp, &sk);
+            for script_type in script_types.iter() {
+                let s = sk.to_string_with_script_type(*script_type);
+                assert_eq!(ExtendedPrivKey::from_str_with_script_type(&s).unwrap(), (sk, *script_type));
+                let s = pk.to_string_with_script_type(*script_type);
+                assert_eq!(ExtendedPubKey::from_str_with_script_type(&s).unwrap(), (pk, *script_type));
+            }
+        }
+        let vpub = ExtendedPubKey::from_private(&secp, &ExtendedPrivKey::new_master(Network::Regtest, &[1; 32]).unwrap())
+            .to_string_with_script_type(ScriptType::P2wpkh);
+        assert!(vpub.starts_with("vpub"));
+    }
+
     #[test]
     fn test_vector_1() {
         let secp = Secp256k1::new();
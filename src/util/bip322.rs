@@ -0,0 +1,469 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! BIP322 Generic Signed Message Format
+//!
+//! Proofs that the owner of an address, or of any script, agrees to a
+//! message, as described at
+//! https://github.com/bitcoin/bips/blob/master/bip-0322.mediawiki
+//!
+//! A proof is a signature of the virtual `to_sign` transaction, which spends
+//! the output of the virtual `to_spend` transaction paying to the address and
+//! committing to the message. The "simple" format is the witness of the
+//! `to_sign` input, and so only works for native segwit addresses; the
+//! "full" format is the whole signed `to_sign` transaction. The encodings do
+//! not tell the formats apart, so proofs are best decoded with
+//! `Proof::from_base64_with_format`.
+//!
+//! Signing goes through the PSBT signer and finalizer: `sign` handles single
+//! key addresses directly, while other scripts are signed by filling in the
+//! PSBT returned by `psbt` and turning it into a proof with `Proof::from_psbt`.
+//!
+//! Proofs of funds, which add inputs spending real UTXOs to `to_sign`, are not
+//! supported, as checking them needs the UTXO set.
+//!
+
+use std::{error, fmt};
+
+use base64;
+use secp256k1::{Secp256k1, Signing, Verification};
+
+use blockdata::interpreter::{self, VerifyFlags};
+use blockdata::opcodes;
+use blockdata::script::{Builder, Script};
+use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+use consensus::encode::{deserialize, serialize};
+use hashes::{sha256, Hash, HashEngine};
+use util::address::Address;
+use util::psbt::{self, FinalizeError, PartiallySignedTransaction, SigningKeys};
+
+/// The tag of the message hash
+pub const MESSAGE_TAG: &'static str = "BIP0322-signed-message";
+
+/// BIP322 errors
+#[derive(Debug)]
+pub enum Error {
+    /// The proof is not valid base64, or neither a witness nor a transaction
+    InvalidEncoding,
+    /// The `to_sign` transaction of a full proof does not spend `to_spend`
+    /// or does not have a single empty `OP_RETURN` output
+    InvalidToSign,
+    /// The `to_sign` transaction of a full proof has more than one input
+    ProofOfFunds,
+    /// A simple proof was asked for a script which needs a scriptSig
+    NotSimple,
+    /// The PSBT could not be signed
+    Psbt(psbt::Error),
+    /// The PSBT could not be finalized
+    Finalize(FinalizeError),
+    /// The proof does not satisfy the address
+    Script(interpreter::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Psbt(ref e) => write!(f, "{}: {}", error::Error::description(self), e),
+            Error::Finalize(ref e) => write!(f, "{}: {}", error::Error::description(self), e),
+            Error::Script(ref e) => write!(f, "{}: {}", error::Error::description(self), e),
+            Error::InvalidEncoding
+            | Error::InvalidToSign
+            | Error::ProofOfFunds
+            | Error::NotSimple => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Psbt(ref e) => Some(e),
+            Error::Finalize(ref e) => Some(e),
+            Error::Script(ref e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::InvalidEncoding => "invalid BIP322 proof encoding",
+            Error::InvalidToSign => "invalid BIP322 to_sign transaction",
+            Error::ProofOfFunds => "BIP322 proofs of funds are not supported",
+            Error::NotSimple => "simple BIP322 proofs need a witness-only spend",
+            Error::Psbt(_) => "BIP322 signing error",
+            Error::Finalize(_) => "BIP322 finalizing error",
+            Error::Script(_) => "invalid BIP322 proof",
+        }
+    }
+}
+
+#[doc(hidden)]
+impl From<psbt::Error> for Error {
+    fn from(e: psbt::Error) -> Error {
+        Error::Psbt(e)
+    }
+}
+
+#[doc(hidden)]
+impl From<FinalizeError> for Error {
+    fn from(e: FinalizeError) -> Error {
+        Error::Finalize(e)
+    }
+}
+
+#[doc(hidden)]
+impl From<interpreter::Error> for Error {
+    fn from(e: interpreter::Error) -> Error {
+        Error::Script(e)
+    }
+}
+
+/// The encodings of a proof
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Format {
+    /// The witness of the `to_sign` input
+    Simple,
+    /// The whole `to_sign` transaction
+    Full,
+}
+
+/// The tagged hash of `message` committed to by `to_spend`
+pub fn message_hash(message: &[u8]) -> sha256::Hash {
+    let tag = sha256::Hash::hash(MESSAGE_TAG.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag[..]);
+    engine.input(&tag[..]);
+    engine.input(message);
+    sha256::Hash::from_engine(engine)
+}
+
+/// The virtual transaction whose only output, paying to `script_pubkey`,
+/// is spent by a proof for `message`
+pub fn to_spend(script_pubkey: &Script, message: &[u8]) -> Transaction {
+    Transaction {
+        version: 0,
+        lock_time: 0,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: Builder::new()
+                .push_opcode(opcodes::all::OP_PUSHBYTES_0)
+                .push_slice(&message_hash(message)[..])
+                .into_script(),
+            sequence: 0,
+            witness: vec![],
+        }],
+        output: vec![TxOut {
+            value: 0,
+            script_pubkey: script_pubkey.clone(),
+        }],
+    }
+}
+
+/// The unsigned virtual transaction spending `to_spend`
+pub fn to_sign(to_spend: &Transaction) -> Transaction {
+    Transaction {
+        version: 0,
+        lock_time: 0,
+        input: vec![TxIn {
+            previous_output: OutPoint {
+                txid: to_spend.txid(),
+                vout: 0,
+            },
+            script_sig: Script::new(),
+            sequence: 0,
+            witness: vec![],
+        }],
+        output: vec![TxOut {
+            value: 0,
+            script_pubkey: Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(),
+        }],
+    }
+}
+
+/// A PSBT of the `to_sign` transaction for `message` and `script_pubkey`,
+/// with the UTXO information of its input filled in. Any redeem script,
+/// witness script or key paths the signer needs are to be added by the
+/// caller.
+pub fn psbt(script_pubkey: &Script, message: &[u8]) -> PartiallySignedTransaction {
+    let to_spend = to_spend(script_pubkey, message);
+    let mut psbt = PartiallySignedTransaction::from_unsigned_tx(to_sign(&to_spend))
+        .expect("to_sign is unsigned");
+    psbt.inputs[0].witness_utxo = Some(to_spend.output[0].clone());
+    psbt.inputs[0].non_witness_utxo = Some(to_spend);
+    psbt
+}
+
+/// Sign `message` for `address` with `keys`. Works for the addresses the
+/// PSBT signer needs no further information for, which are P2PKH, P2WPKH
+/// and, given the key in `keys.public_keys`, P2SH-P2WPKH.
+pub fn sign<C: Signing, K: SigningKeys + ?Sized>(
+    secp: &Secp256k1<C>,
+    address: &Address,
+    message: &[u8],
+    keys: &K,
+    format: Format,
+) -> Result<Proof, Error> {
+    let script_pubkey = address.script_pubkey();
+    let mut psbt = psbt(&script_pubkey, message);
+    if script_pubkey.is_p2sh() {
+        psbt.inputs[0].redeem_script = keys.public_keys(secp).iter()
+            .map(|pk| Address::p2wpkh(pk, address.network).script_pubkey())
+            .find(|script| script.to_p2sh() == script_pubkey);
+    }
+    psbt.sign(secp, keys)?;
+    Proof::from_psbt(psbt, format)
+}
+
+/// A BIP322 proof
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Proof {
+    /// The witness of the `to_sign` input
+    Simple(Vec<Vec<u8>>),
+    /// The signed `to_sign` transaction
+    Full(Transaction),
+}
+
+impl Proof {
+    /// Finalize a signed PSBT of the `to_sign` transaction into a proof of
+    /// `format`
+    pub fn from_psbt(mut psbt: PartiallySignedTransaction, format: Format) -> Result<Proof, Error> {
+        psbt.finalize_input(0)?;
+        let tx = psbt.extract_tx();
+        match format {
+            Format::Full => Ok(Proof::Full(tx)),
+            Format::Simple => {
+                if tx.input.len() != 1 || !tx.input[0].script_sig.is_empty() {
+                    return Err(Error::NotSimple);
+                }
+                Ok(Proof::Simple(tx.input[0].witness.clone()))
+            }
+        }
+    }
+
+    /// The format of the proof
+    pub fn format(&self) -> Format {
+        match *self {
+            Proof::Simple(_) => Format::Simple,
+            Proof::Full(_) => Format::Full,
+        }
+    }
+
+    /// Encode the proof in base64
+    pub fn to_base64(&self) -> String {
+        match *self {
+            Proof::Simple(ref witness) => base64::encode(&serialize(witness)),
+            Proof::Full(ref tx) => base64::encode(&serialize(tx)),
+        }
+    }
+
+    /// Decode a base64 encoded proof of `format`
+    pub fn from_base64_with_format(s: &str, format: Format) -> Result<Proof, Error> {
+        let data = base64::decode(s).map_err(|_| Error::InvalidEncoding)?;
+        match format {
+            Format::Simple => deserialize(&data).map(Proof::Simple),
+            Format::Full => deserialize(&data).map(Proof::Full),
+        }.map_err(|_| Error::InvalidEncoding)
+    }
+
+    /// Decode a base64 encoded proof of unknown format, as a witness if it
+    /// is one and as a transaction otherwise.
+    ///
+    /// This is a convenience for proofs of unknown origin: the encodings are
+    /// not self-describing, so the format is guessed from which of them
+    /// happens to decode. Use `from_base64_with_format` when the format is
+    /// known.
+    pub fn from_base64(s: &str) -> Result<Proof, Error> {
+        Proof::from_base64_with_format(s, Format::Simple)
+            .or_else(|_| Proof::from_base64_with_format(s, Format::Full))
+    }
+
+    /// The signed `to_sign` transaction of a proof for `message` and
+    /// `script_pubkey`, checking the structure of full proofs
+    pub fn to_sign(&self, script_pubkey: &Script, message: &[u8]) -> Result<Transaction, Error> {
+        let expected = to_sign(&to_spend(script_pubkey, message));
+        match *self {
+            Proof::Simple(ref witness) => {
+                let mut tx = expected;
+                tx.input[0].witness = witness.clone();
+                Ok(tx)
+            }
+            Proof::Full(ref tx) => {
+                if tx.input.is_empty()
+                    || tx.input[0].previous_output != expected.input[0].previous_output
+                    || tx.output != expected.output {
+                    return Err(Error::InvalidToSign);
+                }
+                if tx.input.len() > 1 {
+                    return Err(Error::ProofOfFunds);
+                }
+                Ok(tx.clone())
+            }
+        }
+    }
+
+    /// Verify the proof for `message` and `address` with the native script
+    /// interpreter and the standard verification flags
+    pub fn verify<C: Verification>(&self, secp: &Secp256k1<C>, address: &Address, message: &[u8]) -> Result<(), Error> {
+        let script_pubkey = address.script_pubkey();
+        let tx = self.to_sign(&script_pubkey, message)?;
+        interpreter::verify_input(secp, &tx, 0, &script_pubkey, 0, VerifyFlags::STANDARD)?;
+        Ok(())
+    }
+
+    /// Verify the proof for `message` and `address` with
+    /// `libbitcoinconsensus`, which checks the consensus rules only. Returns
+    /// whether the proof satisfies the address.
+    #[cfg(feature="bitcoinconsensus")]
+    pub fn verify_with_consensus(&self, address: &Address, message: &[u8]) -> Result<bool, Error> {
+        let script_pubkey = address.script_pubkey();
+        let tx = self.to_sign(&script_pubkey, message)?;
+        Ok(script_pubkey.verify(0, 0, &serialize(&tx)).is_ok())
+    }
+}
+
+impl fmt::Display for Proof {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_base64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use secp256k1::Secp256k1;
+
+    use blockdata::opcodes;
+    use blockdata::script::Builder;
+    use hashes::hex::ToHex;
+    use network::constants::Network;
+    use util::address::Address;
+    use util::key::PrivateKey;
+
+    use super::{message_hash, psbt, sign, to_sign, to_spend, Error, Format, Proof};
+
+    const ADDRESS: &'static str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    const KEY: &'static str = "L3VFeEujGtevx9w18HD1fhRbCH67Az2dpCymeRE1SoPK6XQtaN2k";
+
+    #[test]
+    fn test_vectors() {
+        assert_eq!(message_hash(b"").to_hex(), "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1");
+        assert_eq!(message_hash(b"Hello World").to_hex(), "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a");
+
+        let address = Address::from_str(ADDRESS).unwrap();
+        let spend = to_spend(&address.script_pubkey(), b"");
+        assert_eq!(spend.txid().to_hex(), "c5680aa69bb8d860bf82d4e9cd3504b55dde018de765a91bb566283c545a99a7");
+        assert_eq!(to_sign(&spend).txid().to_hex(), "1e9654e951a5ba44c8604c4de6c67fd78a27e81dcadcfe1edf638ba3aaebaed6");
+        let spend = to_spend(&address.script_pubkey(), b"Hello World");
+        assert_eq!(spend.txid().to_hex(), "b79d196740ad5217771c1098fc4a4b51e0535c32236c71f1ea4d61a2d603352b");
+        assert_eq!(to_sign(&spend).txid().to_hex(), "88737ae86f2077145f93cc4b153ae9a1cb8d56afa511988c149c5c8c9d93bddf");
+
+        let secp = Secp256k1::new();
+        let key = PrivateKey::from_wif(KEY).unwrap();
+        assert_eq!(Address::p2wpkh(&key.public_key(&secp), Network::Bitcoin), address);
+
+        // Bitcoin Core grinds for low R values, so only verify its proofs
+        let proof = Proof::from_base64("AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=").unwrap();
+        proof.verify(&secp, &address, b"").unwrap();
+        assert!(proof.verify(&secp, &address, b"Hello World").is_err());
+        let proof = sign(&secp, &address, b"", &key, Format::Simple).unwrap();
+        Proof::from_base64(&proof.to_string()).unwrap().verify(&secp, &address, b"").unwrap();
+        for s in ["AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=",
+                  "AkgwRQIhAOzyynlqt93lOKJr+wmmxIens//zPzl9tqIOua93wO6MAiBi5n5EyAcPScOjf1lAqIUIQtr3zKNeavYabHyR8eGhowEhAsfxIAMZZEKUPYWI4BruhAQjzFT8FSFSajuFwrDL1Yhy"].iter() {
+            let proof = Proof::from_base64_with_format(s, Format::Simple).unwrap();
+            assert_eq!(Proof::from_base64(s).unwrap(), proof);
+            assert!(Proof::from_base64_with_format(s, Format::Full).is_err());
+            proof.verify(&secp, &address, b"Hello World").unwrap();
+            assert!(proof.verify(&secp, &address, b"").is_err());
+        }
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let secp = Secp256k1::new();
+        let key = PrivateKey::from_wif(KEY).unwrap();
+        let pk = key.public_key(&secp);
+        let other = PrivateKey::from_wif("KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn").unwrap();
+
+        for address in [Address::p2pkh(&pk, Network::Bitcoin), Address::p2shwpkh(&pk, Network::Bitcoin),
+                        Address::p2wpkh(&pk, Network::Bitcoin)].iter() {
+            let proof = sign(&secp, address, b"message", &key, Format::Full).unwrap();
+            let decoded = Proof::from_base64_with_format(&proof.to_base64(), Format::Full).unwrap();
+            assert_eq!(decoded, proof);
+            assert_eq!(Proof::from_base64(&proof.to_base64()).unwrap(), proof);
+            assert!(Proof::from_base64_with_format(&proof.to_base64(), Format::Simple).is_err());
+            decoded.verify(&secp, address, b"message").unwrap();
+            assert!(decoded.verify(&secp, address, b"other message").is_err());
+            // the wrong key does not get as far as a proof
+            match sign(&secp, address, b"message", &other, Format::Full) {
+                Err(Error::Finalize(_)) | Err(Error::Psbt(_)) => {}
+                r => panic!("unexpected {:?}", r),
+            }
+        }
+        match sign(&secp, &Address::p2pkh(&pk, Network::Bitcoin), b"message", &key, Format::Simple) {
+            Err(Error::NotSimple) => {}
+            r => panic!("unexpected {:?}", r),
+        }
+
+        // a 1-of-2 multisig P2WSH, signed through the PSBT
+        let other_pk = other.public_key(&secp);
+        let witness_script = Builder::new()
+            .push_int(1)
+            .push_key(&other_pk)
+            .push_key(&pk)
+            .push_int(2)
+            .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+            .into_script();
+        let address = Address::p2wsh(&witness_script, Network::Bitcoin);
+        let mut psbt = psbt(&address.script_pubkey(), b"message");
+        psbt.inputs[0].witness_script = Some(witness_script);
+        assert_eq!(psbt.sign(&secp, &key).unwrap(), 1);
+        let proof = Proof::from_psbt(psbt, Format::Simple).unwrap();
+        Proof::from_base64(&proof.to_base64()).unwrap().verify(&secp, &address, b"message").unwrap();
+        assert!(proof.verify(&secp, &Address::p2wpkh(&pk, Network::Bitcoin), b"message").is_err());
+    }
+
+    #[test]
+    fn full_structure() {
+        let secp = Secp256k1::new();
+        let key = PrivateKey::from_wif(KEY).unwrap();
+        let address = Address::p2wpkh(&key.public_key(&secp), Network::Bitcoin);
+        let tx = match sign(&secp, &address, b"message", &key, Format::Full).unwrap() {
+            Proof::Full(tx) => tx,
+            p => panic!("unexpected {:?}", p),
+        };
+        #[cfg(feature="bitcoinconsensus")]
+        {
+            assert!(Proof::Full(tx.clone()).verify_with_consensus(&address, b"message").unwrap());
+            assert!(!Proof::Full(tx.clone()).verify_with_consensus(&address, b"other message").unwrap());
+        }
+
+        let mut bad = tx.clone();
+        bad.output[0].value = 1;
+        match Proof::Full(bad).verify(&secp, &address, b"message") {
+            Err(Error::InvalidToSign) => {}
+            r => panic!("unexpected {:?}", r),
+        }
+        let mut bad = tx.clone();
+        bad.input.push(tx.input[0].clone());
+        match Proof::Full(bad).verify(&secp, &address, b"message") {
+            Err(Error::ProofOfFunds) => {}
+            r => panic!("unexpected {:?}", r),
+        }
+        match Proof::from_base64("AAAA!") {
+            Err(Error::InvalidEncoding) => {}
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+}
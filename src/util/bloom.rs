@@ -0,0 +1,453 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! BIP37 Bloom Filters
+//!
+//! Implementation of the bloom filters SPV clients send to full nodes with
+//! `filterload` to have only matching transactions relayed to them, as
+//! defined at https://github.com/bitcoin/bips/blob/master/bip-0037.mediawiki
+//!
+
+use std::cmp;
+use std::f64::consts::LN_2;
+use std::io;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use blockdata::opcodes;
+use blockdata::script::{Instruction, Script};
+use blockdata::transaction::{OutPoint, Transaction};
+use consensus::encode::{self, Decodable, Encodable};
+use util::hash::BitcoinHash;
+
+/// The maximum size of a bloom filter in bytes
+pub const MAX_BLOOM_FILTER_SIZE: usize = 36_000;
+/// The maximum number of hash functions a bloom filter may use
+pub const MAX_HASH_FUNCS: u32 = 50;
+
+const LN2SQUARED: f64 = LN_2 * LN_2;
+
+/// Mask of the `filterload` flag bits that select how a filter is updated;
+/// any other bits are ignored
+pub const BLOOM_UPDATE_MASK: u8 = 3;
+
+/// How a filter is updated when a transaction output matches it
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BloomFlags {
+    /// The filter is never updated
+    None,
+    /// The outpoint of every matching output is added to the filter
+    All,
+    /// The outpoint of matching pay-to-pubkey and bare multisig outputs is
+    /// added to the filter
+    PubkeyOnly,
+}
+
+impl BloomFlags {
+    /// The flags as sent in `filterload`
+    pub fn as_u8(&self) -> u8 {
+        match *self {
+            BloomFlags::None => 0,
+            BloomFlags::All => 1,
+            BloomFlags::PubkeyOnly => 2,
+        }
+    }
+}
+
+/// A BIP37 bloom filter
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BloomFilter {
+    /// The filter bits
+    data: Vec<u8>,
+    /// Number of hash functions
+    hash_funcs: u32,
+    /// Random value added to the hash function seeds
+    tweak: u32,
+    /// How the filter is updated on matches
+    flags: BloomFlags,
+}
+
+impl BloomFilter {
+    /// Create an empty filter sized to hold `elements` items with a false
+    /// positive rate of `fp_rate`, within the limits on size and number of
+    /// hash functions. The `tweak` should be random.
+    pub fn new(elements: u32, fp_rate: f64, tweak: u32, flags: BloomFlags) -> BloomFilter {
+        // The arithmetic, including the integer divisions, follows Bitcoin
+        // Core so filters have the same parameters
+        let elements = cmp::max(elements, 1);
+        let bits = (-1.0 / LN2SQUARED * elements as f64 * fp_rate.ln()) as u32;
+        let size = cmp::min(bits as usize / 8, MAX_BLOOM_FILTER_SIZE);
+        let hash_funcs = ((size * 8) as u32 / elements) as f64 * LN_2;
+        BloomFilter {
+            data: vec![0; size],
+            hash_funcs: cmp::min(hash_funcs as u32, MAX_HASH_FUNCS),
+            tweak: tweak,
+            flags: flags,
+        }
+    }
+
+    /// The filter bits.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The number of hash functions.
+    pub fn hash_funcs(&self) -> u32 {
+        self.hash_funcs
+    }
+
+    /// The tweak of the hash functions.
+    pub fn tweak(&self) -> u32 {
+        self.tweak
+    }
+
+    /// How the filter is updated on matches.
+    pub fn flags(&self) -> BloomFlags {
+        self.flags
+    }
+
+    /// Whether the filter is within the limits peers enforce on `filterload`.
+    pub fn is_within_size_constraints(&self) -> bool {
+        self.data.len() <= MAX_BLOOM_FILTER_SIZE && self.hash_funcs <= MAX_HASH_FUNCS
+    }
+
+    /// The bit index of `data` for hash function `n`
+    fn bit_index(&self, n: u32, data: &[u8]) -> usize {
+        let seed = n.wrapping_mul(0xFBA4_C795).wrapping_add(self.tweak);
+        murmur3(seed, data) as usize % (self.data.len() * 8)
+    }
+
+    /// Add an element to the filter.
+    pub fn insert(&mut self, data: &[u8]) {
+        if self.data.is_empty() {
+            return;
+        }
+        for n in 0..self.hash_funcs {
+            let index = self.bit_index(n, data);
+            self.data[index >> 3] |= 1 << (index & 7);
+        }
+    }
+
+    /// Whether an element may be in the filter. An empty filter matches
+    /// everything.
+    pub fn contains(&self, data: &[u8]) -> bool {
+        if self.data.is_empty() {
+            return true;
+        }
+        (0..self.hash_funcs).all(|n| {
+            let index = self.bit_index(n, data);
+            self.data[index >> 3] & (1 << (index & 7)) != 0
+        })
+    }
+
+    /// Add an outpoint to the filter.
+    pub fn insert_outpoint(&mut self, outpoint: &OutPoint) {
+        self.insert(&encode::serialize(outpoint));
+    }
+
+    /// Whether an outpoint may be in the filter.
+    pub fn contains_outpoint(&self, outpoint: &OutPoint) -> bool {
+        self.contains(&encode::serialize(outpoint))
+    }
+
+    /// Whether a transaction matches the filter: its txid, a data push in
+    /// one of its output scripts, an outpoint it spends or a data push in one
+    /// of its input scripts is in the filter. Depending on the filter flags,
+    /// the outpoints of matching outputs are added to the filter so that
+    /// transactions spending them match too.
+    pub fn is_relevant_and_update(&mut self, tx: &Transaction) -> bool {
+        let txid = tx.bitcoin_hash();
+        let mut found = self.contains(&txid[..]);
+
+        for (vout, txout) in tx.output.iter().enumerate() {
+            if !self.contains_push(&txout.script_pubkey) {
+                continue;
+            }
+            found = true;
+            let update = match self.flags {
+                BloomFlags::None => false,
+                BloomFlags::All => true,
+                BloomFlags::PubkeyOnly => txout.script_pubkey.is_p2pk() || is_bare_multisig(&txout.script_pubkey),
+            };
+            if update {
+                self.insert_outpoint(&OutPoint::new(txid, vout as u32));
+            }
+        }
+        if found {
+            return true;
+        }
+
+        tx.input.iter().any(|txin| {
+            self.contains_outpoint(&txin.previous_output) || self.contains_push(&txin.script_sig)
+        })
+    }
+
+    /// Whether a non-empty data push of the script is in the filter
+    fn contains_push(&self, script: &Script) -> bool {
+        for instruction in script.iter(false) {
+            match instruction {
+                Instruction::PushBytes(data) => if !data.is_empty() && self.contains(data) {
+                    return true;
+                },
+                Instruction::Op(_) => {}
+                Instruction::Error(_) => break,
+            }
+        }
+        false
+    }
+}
+
+/// Whether the script is of the form `<m> <pubkey>... <n> CHECKMULTISIG`
+fn is_bare_multisig(script: &Script) -> bool {
+    let instructions: Vec<Instruction> = script.iter(true).collect();
+    if instructions.len() < 4 {
+        return false;
+    }
+    let n_keys = instructions.len() - 3;
+    let small_int = |instruction: &Instruction| match *instruction {
+        Instruction::Op(op) => match op.classify() {
+            opcodes::Class::PushNum(n) if n >= 1 && n <= 16 => Some(n as usize),
+            _ => None,
+        },
+        _ => None,
+    };
+    let threshold = small_int(&instructions[0]);
+    instructions[instructions.len() - 1] == Instruction::Op(opcodes::all::OP_CHECKMULTISIG) &&
+        small_int(&instructions[n_keys + 1]) == Some(n_keys) &&
+        threshold.map_or(false, |m| m <= n_keys) &&
+        instructions[1..n_keys + 1].iter().all(|instruction| match *instruction {
+            Instruction::PushBytes(key) => key.len() == 33 || key.len() == 65,
+            _ => false,
+        })
+}
+
+/// The 32-bit x86 variant of MurmurHash3
+fn murmur3(seed: u32, data: &[u8]) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let mut h1 = seed;
+    let blocks = data.len() / 4;
+    for i in 0..blocks {
+        let mut k1 = LittleEndian::read_u32(&data[i * 4..i * 4 + 4]);
+        k1 = k1.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h1 ^= k1;
+        h1 = h1.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+
+    let tail = &data[blocks * 4..];
+    let mut k1 = 0u32;
+    for (i, byte) in tail.iter().enumerate() {
+        k1 ^= (*byte as u32) << (8 * i);
+    }
+    if !tail.is_empty() {
+        k1 = k1.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h1 ^= k1;
+    }
+
+    h1 ^= data.len() as u32;
+    h1 ^= h1 >> 16;
+    h1 = h1.wrapping_mul(0x85eb_ca6b);
+    h1 ^= h1 >> 13;
+    h1 = h1.wrapping_mul(0xc2b2_ae35);
+    h1 ^= h1 >> 16;
+    h1
+}
+
+impl Encodable for BloomFilter {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let mut len = self.data.consensus_encode(&mut s)?;
+        len += self.hash_funcs.consensus_encode(&mut s)?;
+        len += self.tweak.consensus_encode(&mut s)?;
+        len += self.flags.as_u8().consensus_encode(&mut s)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for BloomFilter {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+        Ok(BloomFilter {
+            data: Decodable::consensus_decode(&mut d)?,
+            hash_funcs: Decodable::consensus_decode(&mut d)?,
+            tweak: Decodable::consensus_decode(&mut d)?,
+            flags: match u8::consensus_decode(&mut d)? & BLOOM_UPDATE_MASK {
+                1 => BloomFlags::All,
+                2 => BloomFlags::PubkeyOnly,
+                // like Core, treat the unassigned value 3 as never updating
+                _ => BloomFlags::None,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hashes::hex::FromHex;
+
+    use blockdata::script::Builder;
+    use blockdata::opcodes;
+    use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+    use consensus::encode::{deserialize, serialize};
+    use hashes::{hash160, sha256d, Hash};
+    use util::hash::BitcoinHash;
+    use util::key::PrivateKey;
+
+    use super::*;
+
+    // Test vectors from Bitcoin Core's hash_tests.cpp
+    #[test]
+    fn murmur3_test() {
+        let vectors: [(u32, u32, &str); 14] = [
+            (0x00000000, 0x00000000, ""),
+            (0x6a396f08, 0xFBA4C795, ""),
+            (0x81f16f39, 0xffffffff, ""),
+            (0x514e28b7, 0x00000000, "00"),
+            (0xea3f0b17, 0xFBA4C795, "00"),
+            (0xfd6cf10d, 0x00000000, "ff"),
+            (0x16c6b7ab, 0x00000000, "0011"),
+            (0x8eb51c3d, 0x00000000, "001122"),
+            (0xb4471bf8, 0x00000000, "00112233"),
+            (0xe2301fa8, 0x00000000, "0011223344"),
+            (0xfc2e4a15, 0x00000000, "001122334455"),
+            (0xb074502c, 0x00000000, "00112233445566"),
+            (0x8034d2a0, 0x00000000, "0011223344556677"),
+            (0xb4698def, 0x00000000, "001122334455667788"),
+        ];
+        for &(expected, seed, data) in vectors.iter() {
+            assert_eq!(murmur3(seed, &Vec::<u8>::from_hex(data).unwrap()), expected);
+        }
+    }
+
+    // Test vectors from Bitcoin Core's bloom_tests.cpp
+    #[test]
+    fn insert_serialize_test() {
+        for &(tweak, expected) in [(0, "03614e9b050000000000000001"), (2147483649, "03ce4299050000000100008001")].iter() {
+            let mut filter = BloomFilter::new(3, 0.01, tweak, BloomFlags::All);
+            let first = Vec::<u8>::from_hex("99108ad8ed9bb6274d3980bab5a85c048f0950c8").unwrap();
+            filter.insert(&first);
+            assert!(filter.contains(&first));
+            assert!(!filter.contains(&Vec::<u8>::from_hex("19108ad8ed9bb6274d3980bab5a85c048f0950c8").unwrap()));
+            filter.insert(&Vec::<u8>::from_hex("b5a2c786d9ef4658287ced5914b37a1b4aa32eee").unwrap());
+            assert!(filter.contains(&Vec::<u8>::from_hex("b5a2c786d9ef4658287ced5914b37a1b4aa32eee").unwrap()));
+            filter.insert(&Vec::<u8>::from_hex("b9300670b4c5366e95b2699e8b18bc75e5f729c5").unwrap());
+            assert!(filter.contains(&Vec::<u8>::from_hex("b9300670b4c5366e95b2699e8b18bc75e5f729c5").unwrap()));
+
+            assert_eq!(serialize(&filter), Vec::<u8>::from_hex(expected).unwrap());
+            assert_eq!(deserialize::<BloomFilter>(&serialize(&filter)).unwrap(), filter);
+        }
+
+        let key = PrivateKey::from_wif("5Kg1gnAjaLfKiwhhPpGS3QfRg2m6awQvaj98JCZBZQ5SuS2F15C").unwrap();
+        let pubkey = key.public_key(&::secp256k1::Secp256k1::new()).to_bytes();
+        let mut filter = BloomFilter::new(2, 0.001, 0, BloomFlags::All);
+        filter.insert(&pubkey);
+        filter.insert(&hash160::Hash::hash(&pubkey)[..]);
+        assert_eq!(serialize(&filter), Vec::<u8>::from_hex("038fc16b080000000000000001").unwrap());
+
+        // bits outside the update mask are ignored, as in Core
+        let masked = deserialize::<BloomFilter>(&Vec::<u8>::from_hex("038fc16b080000000000000082").unwrap()).unwrap();
+        assert_eq!(masked.flags(), BloomFlags::PubkeyOnly);
+        let unassigned = deserialize::<BloomFilter>(&Vec::<u8>::from_hex("038fc16b080000000000000003").unwrap()).unwrap();
+        assert_eq!(unassigned.flags(), BloomFlags::None);
+    }
+
+    #[test]
+    fn sizing_test() {
+        let filter = BloomFilter::new(1_000_000, 0.0001, 0, BloomFlags::None);
+        assert_eq!(filter.data().len(), MAX_BLOOM_FILTER_SIZE);
+        assert_eq!(filter.hash_funcs(), 0);
+        let filter = BloomFilter::new(1, 1e-30, 0, BloomFlags::None);
+        assert_eq!(filter.hash_funcs(), MAX_HASH_FUNCS);
+        assert!(filter.is_within_size_constraints());
+
+        // An empty filter matches everything
+        let filter = BloomFilter::new(1, 0.9, 0, BloomFlags::None);
+        assert!(filter.data().is_empty());
+        assert!(filter.contains(&[1, 2, 3]));
+    }
+
+    #[test]
+    fn relevant_and_update_test() {
+        let key = [2; 33];
+        let p2pk = Builder::new().push_slice(&key).push_opcode(opcodes::all::OP_CHECKSIG).into_script();
+        let p2pkh = Builder::new().push_opcode(opcodes::all::OP_DUP)
+                                  .push_opcode(opcodes::all::OP_HASH160)
+                                  .push_slice(&hash160::Hash::hash(&key)[..])
+                                  .push_opcode(opcodes::all::OP_EQUALVERIFY)
+                                  .push_opcode(opcodes::all::OP_CHECKSIG)
+                                  .into_script();
+        let multisig = Builder::new().push_int(1)
+                                     .push_slice(&key)
+                                     .push_int(1)
+                                     .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+                                     .into_script();
+        assert!(is_bare_multisig(&multisig));
+        assert!(!is_bare_multisig(&p2pkh));
+
+        let tx = Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(sha256d::Hash::hash(&[1]), 3),
+                script_sig: Builder::new().push_slice(&[0xaa; 71]).into_script(),
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            output: vec![
+                TxOut { value: 1, script_pubkey: p2pkh },
+                TxOut { value: 2, script_pubkey: p2pk },
+                TxOut { value: 3, script_pubkey: multisig },
+            ],
+        };
+        let txid = tx.bitcoin_hash();
+        let spend = |vout: u32| Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(txid, vout),
+                script_sig: Script::new(),
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            output: vec![],
+        };
+
+        // Matches on the txid, the spent outpoint and a script_sig push
+        let mut filter = BloomFilter::new(10, 0.000001, 0, BloomFlags::All);
+        assert!(!filter.is_relevant_and_update(&tx));
+        filter.insert(&txid[..]);
+        assert!(filter.is_relevant_and_update(&tx));
+        let mut filter = BloomFilter::new(10, 0.000001, 0, BloomFlags::All);
+        filter.insert_outpoint(&tx.input[0].previous_output);
+        assert!(filter.is_relevant_and_update(&tx));
+        let mut filter = BloomFilter::new(10, 0.000001, 0, BloomFlags::All);
+        filter.insert(&[0xaa; 71]);
+        assert!(filter.is_relevant_and_update(&tx));
+
+        // Matching the key adds outpoints according to the flags
+        for &(flags, spent) in [
+            (BloomFlags::None, [false, false, false]),
+            (BloomFlags::All, [true, true, true]),
+            (BloomFlags::PubkeyOnly, [false, true, true]),
+        ].iter() {
+            let mut filter = BloomFilter::new(10, 0.000001, 0, flags);
+            filter.insert(&key);
+            filter.insert(&hash160::Hash::hash(&key)[..]);
+            assert!(filter.is_relevant_and_update(&tx));
+            for vout in 0..3 {
+                assert_eq!(filter.contains_outpoint(&OutPoint::new(txid, vout)), spent[vout as usize]);
+                assert_eq!(filter.is_relevant_and_update(&spend(vout)), spent[vout as usize]);
+            }
+        }
+    }
+}
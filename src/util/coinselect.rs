@@ -0,0 +1,403 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Coin Selection
+//!
+//! Algorithms choosing which UTXOs fund a transaction, as used by Bitcoin
+//! Core: Branch and Bound, which looks for a selection needing no change
+//! output, single random draw and largest first.
+//!
+//! UTXOs are compared by their effective value, their value minus the fee of
+//! spending them at the current fee rate. Selections are compared by their
+//! waste: the extra fee paid by spending the inputs now rather than at the
+//! long term fee rate, plus either the cost of creating and later spending
+//! a change output, or the excess given up to the fee if there is none.
+//!
+//! The target of a selection is the value of the recipients plus the fee of
+//! the transaction without its inputs. The selected UTXOs can then be spent
+//! with the `TxBuilder` of `util::txbuilder`.
+//!
+
+use std::cmp;
+
+use util::amount::{Amount, SignedAmount};
+use util::txbuilder::{FeeRate, Utxo, P2WPKH_SATISFACTION_WEIGHT};
+
+/// Weight of an input with empty scriptSig and witness: outpoint, scriptSig
+/// length and sequence
+const BASE_INPUT_WEIGHT: usize = 4 * (32 + 4 + 1 + 4);
+/// Weight of a P2WPKH output
+const P2WPKH_OUTPUT_WEIGHT: usize = 4 * (8 + 1 + 22);
+/// Number of branches Branch and Bound explores before giving up
+const BNB_TOTAL_TRIES: usize = 100_000;
+
+/// The fee rates and change output shape selections are made for
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CoinSelectionParams {
+    /// The fee rate of the transaction
+    pub fee_rate: FeeRate,
+    /// The fee rate the UTXOs are expected to be spendable at eventually
+    pub long_term_fee_rate: FeeRate,
+    /// Weight of the change output
+    pub change_output_weight: usize,
+    /// Weight of the input spending the change output later
+    pub change_spend_weight: usize,
+}
+
+impl CoinSelectionParams {
+    /// Parameters for a P2WPKH change output
+    pub fn new(fee_rate: FeeRate, long_term_fee_rate: FeeRate) -> CoinSelectionParams {
+        CoinSelectionParams {
+            fee_rate: fee_rate,
+            long_term_fee_rate: long_term_fee_rate,
+            change_output_weight: P2WPKH_OUTPUT_WEIGHT,
+            change_spend_weight: BASE_INPUT_WEIGHT + P2WPKH_SATISFACTION_WEIGHT,
+        }
+    }
+
+    /// The fee of spending `utxo` at the fee rate
+    pub fn input_fee(&self, utxo: &Utxo) -> Amount {
+        self.fee_rate.fee_weight(BASE_INPUT_WEIGHT + utxo.satisfaction_weight)
+    }
+
+    /// The fee of spending `utxo` at the long term fee rate
+    pub fn long_term_input_fee(&self, utxo: &Utxo) -> Amount {
+        self.long_term_fee_rate.fee_weight(BASE_INPUT_WEIGHT + utxo.satisfaction_weight)
+    }
+
+    /// The value of `utxo` minus the fee of spending it, which is negative
+    /// for UTXOs costing more to spend than they are worth
+    pub fn effective_value(&self, utxo: &Utxo) -> SignedAmount {
+        SignedAmount::from_sat(utxo.txout.value as i64 - self.input_fee(utxo).as_sat() as i64)
+    }
+
+    /// The fee of the change output
+    pub fn change_fee(&self) -> Amount {
+        self.fee_rate.fee_weight(self.change_output_weight)
+    }
+
+    /// The cost of creating the change output now and spending it at the
+    /// long term fee rate. Excess up to this is better left to the fee.
+    pub fn cost_of_change(&self) -> Amount {
+        self.change_fee() + self.long_term_fee_rate.fee_weight(self.change_spend_weight)
+    }
+
+    /// The waste of spending `utxos` for `target`, with a change output if
+    /// their effective value exceeds the target by more than the cost of
+    /// change
+    pub fn waste(&self, utxos: &[&Utxo], target: Amount) -> SignedAmount {
+        let mut waste = 0;
+        let mut value = 0;
+        for utxo in utxos {
+            waste += self.input_fee(utxo).as_sat() as i64 - self.long_term_input_fee(utxo).as_sat() as i64;
+            value += self.effective_value(utxo).as_sat();
+        }
+        let excess = value - target.as_sat() as i64;
+        let cost_of_change = self.cost_of_change().as_sat() as i64;
+        SignedAmount::from_sat(waste + if excess > cost_of_change { cost_of_change } else { excess })
+    }
+}
+
+/// A selection of UTXOs
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Selection {
+    /// Indexes of the selected UTXOs in the pool they were selected from
+    pub indexes: Vec<usize>,
+    /// The sum of the effective values of the selected UTXOs
+    pub effective_value: Amount,
+    /// The waste of the selection
+    pub waste: SignedAmount,
+}
+
+impl Selection {
+    fn new(utxos: &[Utxo], mut indexes: Vec<usize>, target: Amount, params: &CoinSelectionParams) -> Selection {
+        indexes.sort();
+        let selected: Vec<&Utxo> = indexes.iter().map(|&i| &utxos[i]).collect();
+        let value = selected.iter().map(|u| params.effective_value(u).as_sat()).sum::<i64>();
+        Selection {
+            effective_value: Amount::from_sat(value as u64),
+            waste: params.waste(&selected, target),
+            indexes: indexes,
+        }
+    }
+}
+
+/// Indexes of the UTXOs with a positive effective value, with their
+/// effective values
+fn positive_utxos(utxos: &[Utxo], params: &CoinSelectionParams) -> Vec<(usize, u64)> {
+    utxos.iter()
+        .enumerate()
+        .map(|(i, u)| (i, params.effective_value(u).as_sat()))
+        .filter(|&(_, value)| value > 0)
+        .map(|(i, value)| (i, value as u64))
+        .collect()
+}
+
+/// Branch and Bound: the selection with the least waste among those whose
+/// effective value is at least `target` and exceeds it by at most the cost
+/// of change, so that no change output is needed. Returns `None` if there is
+/// no such selection, or none is found within a bounded number of tries.
+pub fn branch_and_bound(utxos: &[Utxo], target: Amount, params: &CoinSelectionParams) -> Option<Selection> {
+    let mut pool = positive_utxos(utxos, params);
+    pool.sort_by_key(|&(_, value)| cmp::Reverse(value));
+    let waste: Vec<i64> = pool.iter().map(|&(i, _)| {
+        params.input_fee(&utxos[i]).as_sat() as i64 - params.long_term_input_fee(&utxos[i]).as_sat() as i64
+    }).collect();
+
+    let target = target.as_sat();
+    let upper = target + params.cost_of_change().as_sat();
+    let mut available: u64 = pool.iter().map(|&(_, value)| value).sum();
+    if available < target {
+        return None;
+    }
+
+    // whether each UTXO of the pool up to the current depth is included
+    let mut selection: Vec<bool> = Vec::with_capacity(pool.len());
+    let mut value = 0;
+    let mut curr_waste = 0;
+    let mut best: Option<(Vec<bool>, i64)> = None;
+
+    for _ in 0..BNB_TOTAL_TRIES {
+        let mut backtrack = false;
+        let best_waste = best.as_ref().map(|b| b.1);
+        if value + available < target || value > upper
+            // with rising fees adding inputs only adds waste, so this branch can
+            // at best tie with the best selection found so far
+            || (best_waste.map_or(false, |w| curr_waste >= w) && !waste.is_empty() && waste[0] > 0) {
+            backtrack = true;
+        } else if value >= target {
+            let total_waste = curr_waste + (value - target) as i64;
+            if best_waste.map_or(true, |w| total_waste <= w) {
+                best = Some((selection.clone(), total_waste));
+            }
+            backtrack = true;
+        }
+
+        if backtrack {
+            // undo the omitted UTXOs at the end of the branch
+            while selection.last() == Some(&false) {
+                selection.pop();
+                available += pool[selection.len()].1;
+            }
+            let depth = selection.len();
+            if depth == 0 {
+                // every branch was explored
+                break;
+            }
+            // try the branch omitting the last included UTXO
+            selection[depth - 1] = false;
+            value -= pool[depth - 1].1;
+            curr_waste -= waste[depth - 1];
+        } else {
+            let depth = selection.len();
+            available -= pool[depth].1;
+            // omitting an equivalent UTXO to the one just omitted gives the
+            // same selections again
+            if depth > 0 && !selection[depth - 1]
+                && pool[depth].1 == pool[depth - 1].1 && waste[depth] == waste[depth - 1] {
+                selection.push(false);
+            } else {
+                selection.push(true);
+                value += pool[depth].1;
+                curr_waste += waste[depth];
+            }
+        }
+    }
+
+    best.map(|(selection, _)| {
+        let indexes = selection.iter()
+            .zip(pool.iter())
+            .filter(|&(included, _)| *included)
+            .map(|(_, &(i, _))| i)
+            .collect();
+        Selection::new(utxos, indexes, Amount::from_sat(target), params)
+    })
+}
+
+/// A splitmix64 generator, enough to shuffle UTXOs reproducibly
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// Select UTXOs from `pool` in order until their effective value covers
+/// `target` and the fee of a change output
+fn select_in_order(utxos: &[Utxo], pool: &[(usize, u64)], target: Amount, params: &CoinSelectionParams) -> Option<Selection> {
+    let needed = (target + params.change_fee()).as_sat();
+    let mut value = 0;
+    let mut indexes = vec![];
+    for &(i, utxo_value) in pool {
+        indexes.push(i);
+        value += utxo_value;
+        if value >= needed {
+            return Some(Selection::new(utxos, indexes, target, params));
+        }
+    }
+    None
+}
+
+/// Single random draw: UTXOs picked at random until their effective value
+/// covers `target` and the fee of a change output. The draw is determined
+/// by `seed`, which should be random outside of tests.
+pub fn single_random_draw(utxos: &[Utxo], target: Amount, params: &CoinSelectionParams, seed: u64) -> Option<Selection> {
+    let mut pool = positive_utxos(utxos, params);
+    let mut rng = Rng(seed);
+    // Fisher-Yates shuffle
+    for i in (1..pool.len()).rev() {
+        let j = (rng.next() % (i as u64 + 1)) as usize;
+        pool.swap(i, j);
+    }
+    select_in_order(utxos, &pool, target, params)
+}
+
+/// Largest first: the UTXOs with the largest effective values until they
+/// cover `target` and the fee of a change output
+pub fn largest_first(utxos: &[Utxo], target: Amount, params: &CoinSelectionParams) -> Option<Selection> {
+    let mut pool = positive_utxos(utxos, params);
+    pool.sort_by_key(|&(_, value)| cmp::Reverse(value));
+    select_in_order(utxos, &pool, target, params)
+}
+
+/// The selection with the least waste found by Branch and Bound, single
+/// random draw with `seed` and largest first, preferring a changeless
+/// selection of Branch and Bound when the waste is the same
+pub fn select_coins(utxos: &[Utxo], target: Amount, params: &CoinSelectionParams, seed: u64) -> Option<Selection> {
+    let candidates = vec![
+        branch_and_bound(utxos, target, params),
+        single_random_draw(utxos, target, params, seed),
+        largest_first(utxos, target, params),
+    ];
+    candidates.into_iter()
+        .enumerate()
+        .filter_map(|(order, s)| s.map(|s| (order, s)))
+        .min_by_key(|&(order, ref s)| (s.waste, order))
+        .map(|(_, s)| s)
+}
+
+#[cfg(test)]
+mod tests {
+    use blockdata::script::Script;
+    use blockdata::transaction::{OutPoint, TxOut};
+    use util::amount::{Amount, SignedAmount};
+    use util::txbuilder::{FeeRate, Utxo, P2WPKH_SATISFACTION_WEIGHT};
+
+    use super::{branch_and_bound, largest_first, select_coins, single_random_draw, CoinSelectionParams};
+
+    /// 10 sat/vbyte now and 5 in the long term: a P2WPKH input of 69
+    /// vbytes costs 690, or 345 in the long term
+    fn params() -> CoinSelectionParams {
+        CoinSelectionParams::new(FeeRate::from_sat_per_vb(10), FeeRate::from_sat_per_vb(5))
+    }
+
+    /// P2WPKH UTXOs with the given effective values at `fee_rate` sat/vbyte
+    fn utxos(effective_values: &[u64], fee_rate: u64) -> Vec<Utxo> {
+        effective_values.iter().enumerate().map(|(i, value)| Utxo::new(
+            OutPoint { txid: Default::default(), vout: i as u32 },
+            TxOut {
+                value: value + 69 * fee_rate,
+                script_pubkey: Script::new(),
+            },
+            P2WPKH_SATISFACTION_WEIGHT,
+        )).collect()
+    }
+
+    fn values(utxos: &[Utxo], indexes: &[usize]) -> Vec<u64> {
+        indexes.iter().map(|&i| utxos[i].txout.value - 690).collect()
+    }
+
+    #[test]
+    fn params_test() {
+        let params = params();
+        let pool = utxos(&[1000, 0], 10);
+        assert_eq!(params.effective_value(&pool[0]), SignedAmount::from_sat(1000));
+        assert_eq!(params.input_fee(&pool[0]), Amount::from_sat(690));
+        assert_eq!(params.long_term_input_fee(&pool[0]), Amount::from_sat(345));
+        assert_eq!(params.change_fee(), Amount::from_sat(310));
+        assert_eq!(params.cost_of_change(), Amount::from_sat(655));
+
+        // changeless, the excess is waste
+        assert_eq!(params.waste(&[&pool[0]], Amount::from_sat(900)), SignedAmount::from_sat(345 + 100));
+        // with change, its cost is
+        assert_eq!(params.waste(&[&pool[0]], Amount::from_sat(100)), SignedAmount::from_sat(345 + 655));
+        // at low fee rates spending more inputs saves fees
+        let low = CoinSelectionParams::new(FeeRate::from_sat_per_vb(1), FeeRate::from_sat_per_vb(5));
+        assert_eq!(low.waste(&[&pool[0], &pool[1]], Amount::from_sat(2000)), SignedAmount::from_sat(-2 * 276 + 242));
+    }
+
+    #[test]
+    fn branch_and_bound_test() {
+        let params = params();
+        let pool = utxos(&[1000, 2000, 3000, 4000, 5000], 10);
+
+        let selection = branch_and_bound(&pool, Amount::from_sat(6000), &params).unwrap();
+        assert_eq!(selection.indexes.len(), 2);
+        assert_eq!(selection.effective_value, Amount::from_sat(6000));
+        assert_eq!(selection.waste, SignedAmount::from_sat(690));
+        // within the cost of change
+        let selection = branch_and_bound(&pool, Amount::from_sat(4500), &params).unwrap();
+        assert_eq!(values(&pool, &selection.indexes), vec![5000]);
+        assert_eq!(selection.waste, SignedAmount::from_sat(345 + 500));
+        // no selection close enough, or not enough funds
+        assert_eq!(branch_and_bound(&utxos(&[1000, 5000], 10), Amount::from_sat(3000), &params), None);
+        assert_eq!(branch_and_bound(&pool, Amount::from_sat(16000), &params), None);
+
+        // at low fee rates, more inputs are better
+        let low = CoinSelectionParams::new(FeeRate::from_sat_per_vb(1), FeeRate::from_sat_per_vb(5));
+        let pool = utxos(&[1000, 2000, 3000, 4000, 5000], 1);
+        let selection = branch_and_bound(&pool, Amount::from_sat(6000), &low).unwrap();
+        assert_eq!(selection.indexes, vec![0, 1, 2]);
+        assert_eq!(selection.waste, SignedAmount::from_sat(-3 * 276));
+
+        // UTXOs not worth spending are ignored
+        let mut pool = utxos(&[1000, 2000], 10);
+        pool[0].txout.value = 100;
+        assert_eq!(branch_and_bound(&pool, Amount::from_sat(2000), &params).unwrap().indexes, vec![1]);
+    }
+
+    #[test]
+    fn random_and_largest_first_test() {
+        let params = params();
+        let pool = utxos(&[1000, 2000, 3000, 4000, 5000, 6000, 7000, 8000], 10);
+        let target = Amount::from_sat(10000);
+
+        let selection = largest_first(&pool, target, &params).unwrap();
+        assert_eq!(values(&pool, &selection.indexes), vec![7000, 8000]);
+        assert_eq!(selection.waste, SignedAmount::from_sat(2 * 345 + 655));
+
+        let selection = single_random_draw(&pool, target, &params, 42).unwrap();
+        assert_eq!(single_random_draw(&pool, target, &params, 42).unwrap(), selection);
+        assert!(selection.effective_value >= target + params.change_fee());
+        let draws: Vec<_> = (0..10).map(|seed| single_random_draw(&pool, target, &params, seed).unwrap()).collect();
+        assert!(draws.iter().any(|s| s.indexes != draws[0].indexes));
+
+        assert_eq!(largest_first(&pool, Amount::from_sat(40000), &params), None);
+        assert_eq!(single_random_draw(&pool, Amount::from_sat(40000), &params, 42), None);
+
+        // Branch and Bound wins with an exact match
+        let selection = select_coins(&pool, target, &params, 42).unwrap();
+        assert_eq!(selection.waste, SignedAmount::from_sat(690));
+        assert_eq!(selection.effective_value, target);
+        // otherwise the least waste wins
+        let target = Amount::from_sat(35000);
+        let selection = select_coins(&pool, target, &params, 42).unwrap();
+        assert!(selection.waste <= largest_first(&pool, target, &params).unwrap().waste);
+    }
+}
@@ -0,0 +1,924 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Output Script Descriptors
+//!
+//! Parsing, printing and derivation of output script descriptors as used by
+//! Bitcoin Core and specified in BIP380 and following. Supported are `pk()`,
+//! `pkh()`, `wpkh()`, `sh(wpkh())`, `multi()` and `sortedmulti()` bare or
+//! wrapped in `sh()`, `wsh()` and `sh(wsh())`, `addr()` and `raw()`, with
+//! hex public keys or extended public keys and optional key origins.
+//!
+
+use std::collections::BTreeMap;
+use std::{error, fmt};
+use std::str::FromStr;
+
+use hex;
+use secp256k1::{Secp256k1, Verification};
+
+use blockdata::opcodes;
+use blockdata::script::{Builder, Script};
+use network::constants::Network;
+use util::address::{self, Address};
+use util::bip32::{self, ChildNumber, DerivationPath, ExtendedPubKey, Fingerprint};
+use util::key::PublicKey;
+use util::psbt;
+
+/// Maximum number of keys in a `multi()` or `sortedmulti()` descriptor
+pub const MAX_MULTISIG_KEYS: usize = 16;
+
+/// Characters allowed in a descriptor, in the order the checksum uses them
+const INPUT_CHARSET: &'static str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+
+/// Characters the checksum is written in
+const CHECKSUM_CHARSET: &'static [u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Descriptor parsing and derivation errors
+#[derive(Debug)]
+pub enum Error {
+    /// A character which may not appear in a descriptor
+    InvalidCharacter(char),
+    /// The checksum after `#` does not match the descriptor
+    BadChecksum {
+        /// The checksum of the descriptor
+        expected: String,
+        /// The checksum given
+        actual: String,
+    },
+    /// A fragment of the descriptor is malformed
+    Syntax(String),
+    /// A script function which is unknown or not allowed where it appears
+    InvalidFunction(String),
+    /// A key expression could not be parsed or cannot be derived from
+    InvalidKey(String),
+    /// An uncompressed public key inside a segwit descriptor
+    UncompressedKey,
+    /// A multisig threshold of zero, or larger than the number of keys, or too
+    /// many keys
+    InvalidMultisig {
+        /// The number of signatures required
+        threshold: usize,
+        /// The number of keys
+        keys: usize,
+    },
+    /// A P2SH redeem script would be larger than 520 bytes
+    ScriptTooLarge,
+    /// BIP32 derivation error
+    Bip32(bip32::Error),
+    /// Invalid address in `addr()`
+    Address(address::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidCharacter(c) => write!(f, "{}: {:?}", error::Error::description(self), c),
+            Error::BadChecksum { ref expected, ref actual } => write!(f, "{}: expected {}, actual {}", error::Error::description(self), expected, actual),
+            Error::Syntax(ref s) => write!(f, "{}: {}", error::Error::description(self), s),
+            Error::InvalidFunction(ref s) => write!(f, "{}: {}", error::Error::description(self), s),
+            Error::InvalidKey(ref s) => write!(f, "{}: {}", error::Error::description(self), s),
+            Error::InvalidMultisig { threshold, keys } => write!(f, "{}: {} of {}", error::Error::description(self), threshold, keys),
+            Error::Bip32(ref e) => fmt::Display::fmt(e, f),
+            Error::Address(ref e) => fmt::Display::fmt(e, f),
+            Error::UncompressedKey | Error::ScriptTooLarge => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Bip32(ref e) => Some(e),
+            Error::Address(ref e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::InvalidCharacter(..) => "invalid character in descriptor",
+            Error::BadChecksum { .. } => "descriptor checksum mismatch",
+            Error::Syntax(..) => "invalid descriptor syntax",
+            Error::InvalidFunction(..) => "invalid script function",
+            Error::InvalidKey(..) => "invalid key expression",
+            Error::UncompressedKey => "uncompressed key in segwit descriptor",
+            Error::InvalidMultisig { .. } => "invalid multisig threshold or key count",
+            Error::ScriptTooLarge => "redeem script larger than 520 bytes",
+            Error::Bip32(ref e) => e.description(),
+            Error::Address(ref e) => e.description(),
+        }
+    }
+}
+
+#[doc(hidden)]
+impl From<bip32::Error> for Error {
+    fn from(e: bip32::Error) -> Error {
+        Error::Bip32(e)
+    }
+}
+
+/// Compute the 8 character checksum of a descriptor, without the `#`.
+pub fn checksum(desc: &str) -> Result<String, Error> {
+    const GENERATOR: [u64; 5] = [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd];
+
+    fn polymod(chk: u64, value: u64) -> u64 {
+        let top = chk >> 35;
+        let mut chk = (chk & 0x7ffffffff) << 5 ^ value;
+        for (i, gen) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= *gen;
+            }
+        }
+        chk
+    }
+
+    let mut chk = 1;
+    let mut class = 0;
+    let mut class_count = 0;
+    for c in desc.chars() {
+        let pos = match INPUT_CHARSET.find(c) {
+            Some(pos) => pos as u64,
+            None => return Err(Error::InvalidCharacter(c)),
+        };
+        // Symbols are the position within a group of 32, with every three
+        // symbols followed by the groups they came from.
+        chk = polymod(chk, pos & 31);
+        class = class * 3 + (pos >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            chk = polymod(chk, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        chk = polymod(chk, class);
+    }
+    for _ in 0..8 {
+        chk = polymod(chk, 0);
+    }
+    chk ^= 1;
+
+    Ok((0..8).map(|i| CHECKSUM_CHARSET[((chk >> (5 * (7 - i))) & 31) as usize] as char).collect())
+}
+
+/// The public key part of a key expression
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DescriptorPublicKey {
+    /// A single hex-encoded public key
+    Single(PublicKey),
+    /// An extended public key, derived along `path` and then, if `wildcard`
+    /// is set, at the index the descriptor is derived at
+    Extended {
+        /// The extended public key
+        xpub: ExtendedPubKey,
+        /// Unhardened derivation steps from `xpub`
+        path: DerivationPath,
+        /// Whether the path ends in `/*`
+        wildcard: bool,
+    },
+}
+
+/// A key expression: a public key with its optional origin
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DescriptorKey {
+    /// Fingerprint of the master key and derivation path from it to `key`
+    pub origin: Option<(Fingerprint, DerivationPath)>,
+    /// The key itself
+    pub key: DescriptorPublicKey,
+}
+
+impl DescriptorKey {
+    /// Whether the key is derived at the descriptor's index.
+    pub fn is_ranged(&self) -> bool {
+        match self.key {
+            DescriptorPublicKey::Single(..) => false,
+            DescriptorPublicKey::Extended { wildcard, .. } => wildcard,
+        }
+    }
+
+    /// Whether the key serializes to 33 bytes.
+    fn is_compressed(&self) -> bool {
+        match self.key {
+            DescriptorPublicKey::Single(ref pk) => pk.compressed,
+            DescriptorPublicKey::Extended { .. } => true,
+        }
+    }
+
+    /// Derive the public key at `index`, along with its master fingerprint
+    /// and full derivation path when they are known.
+    pub fn derive<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        index: u32,
+    ) -> Result<(PublicKey, Option<(Fingerprint, DerivationPath)>), Error> {
+        match self.key {
+            DescriptorPublicKey::Single(pk) => Ok((pk, self.origin.clone())),
+            DescriptorPublicKey::Extended { ref xpub, ref path, wildcard } => {
+                let mut steps: Vec<ChildNumber> = path.as_ref().to_vec();
+                if wildcard {
+                    steps.push(ChildNumber::from_normal_idx(index)?);
+                }
+                let derived = xpub.derive_pub(secp, &steps)?;
+
+                let origin = match self.origin {
+                    Some((fingerprint, ref origin_path)) => {
+                        let mut full: Vec<ChildNumber> = origin_path.as_ref().to_vec();
+                        full.extend(steps);
+                        (fingerprint, full.into())
+                    }
+                    None => (xpub.fingerprint(), steps.into()),
+                };
+                Ok((derived.public_key, Some(origin)))
+            }
+        }
+    }
+}
+
+impl fmt::Display for DescriptorKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((ref fingerprint, ref path)) = self.origin {
+            write!(f, "[{}", fingerprint)?;
+            for child in path {
+                write!(f, "/{}", child)?;
+            }
+            f.write_str("]")?;
+        }
+        match self.key {
+            DescriptorPublicKey::Single(ref pk) => write!(f, "{}", pk),
+            DescriptorPublicKey::Extended { ref xpub, ref path, wildcard } => {
+                write!(f, "{}", xpub)?;
+                for child in path {
+                    write!(f, "/{}", child)?;
+                }
+                if wildcard {
+                    f.write_str("/*")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for DescriptorKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<DescriptorKey, Error> {
+        let invalid = || Error::InvalidKey(s.to_owned());
+
+        let (origin, key) = if s.starts_with('[') {
+            let end = s.find(']').ok_or_else(invalid)?;
+            let mut parts = s[1..end].split('/');
+            let fingerprint = Fingerprint::from_str(parts.next().unwrap()).map_err(|_| invalid())?;
+            let path: Result<Vec<ChildNumber>, _> = parts.map(ChildNumber::from_str).collect();
+            (Some((fingerprint, path.map_err(|_| invalid())?.into())), &s[end + 1..])
+        } else {
+            (None, s)
+        };
+
+        let key = if key.len() == 66 || key.len() == 130 {
+            DescriptorPublicKey::Single(PublicKey::from_str(key).map_err(|_| invalid())?)
+        } else {
+            let mut parts: Vec<&str> = key.split('/').collect();
+            let xpub = ExtendedPubKey::from_str(parts.remove(0)).map_err(|_| invalid())?;
+            let wildcard = parts.last() == Some(&"*");
+            if wildcard {
+                parts.pop();
+            }
+            let mut path = Vec::with_capacity(parts.len());
+            for part in parts {
+                // Hardened steps, including hardened wildcards, need the
+                // private key.
+                match ChildNumber::from_str(part) {
+                    Ok(child @ ChildNumber::Normal { .. }) => path.push(child),
+                    _ => return Err(invalid()),
+                }
+            }
+            DescriptorPublicKey::Extended {
+                xpub: xpub,
+                path: path.into(),
+                wildcard: wildcard,
+            }
+        };
+
+        Ok(DescriptorKey {
+            origin: origin,
+            key: key,
+        })
+    }
+}
+
+/// The keys and threshold of a `multi()` or `sortedmulti()` descriptor
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Multisig {
+    /// The number of signatures required
+    pub threshold: usize,
+    /// The keys, in the order given
+    pub keys: Vec<DescriptorKey>,
+    /// Whether the derived keys are sorted in the script, as `sortedmulti()`
+    pub sorted: bool,
+}
+
+impl Multisig {
+    /// The CHECKMULTISIG script with the keys derived at `index`.
+    fn script<C: Verification>(&self, secp: &Secp256k1<C>, index: u32, keypaths: &mut Keypaths) -> Result<Script, Error> {
+        let mut pubkeys = Vec::with_capacity(self.keys.len());
+        for key in &self.keys {
+            pubkeys.push(derive_key(secp, key, index, keypaths)?);
+        }
+        if self.sorted {
+            pubkeys.sort_by_key(|pk| pk.to_bytes());
+        }
+
+        let mut builder = Builder::new().push_int(self.threshold as i64);
+        for pk in &pubkeys {
+            builder = builder.push_key(pk);
+        }
+        Ok(builder
+            .push_int(pubkeys.len() as i64)
+            .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+            .into_script())
+    }
+}
+
+impl fmt::Display for Multisig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}({}", if self.sorted { "sortedmulti" } else { "multi" }, self.threshold)?;
+        for key in &self.keys {
+            write!(f, ",{}", key)?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// An output script descriptor
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Descriptor {
+    /// `pk(KEY)`: pay to public key
+    Pk(DescriptorKey),
+    /// `pkh(KEY)`: pay to public key hash
+    Pkh(DescriptorKey),
+    /// `wpkh(KEY)`: pay to witness public key hash
+    Wpkh(DescriptorKey),
+    /// `sh(wpkh(KEY))`: P2WPKH nested in P2SH
+    ShWpkh(DescriptorKey),
+    /// Bare `multi()` or `sortedmulti()`
+    Multi(Multisig),
+    /// `sh(multi())` or `sh(sortedmulti())`
+    Sh(Multisig),
+    /// `wsh(multi())` or `wsh(sortedmulti())`
+    Wsh(Multisig),
+    /// `sh(wsh(multi()))` or `sh(wsh(sortedmulti()))`
+    ShWsh(Multisig),
+    /// `addr(ADDR)`: the scriptPubKey of an address
+    Addr(Address),
+    /// `raw(HEX)`: a literal scriptPubKey
+    Raw(Script),
+}
+
+/// Derived keys with their origins, as in PSBT `hd_keypaths`
+type Keypaths = BTreeMap<PublicKey, (Fingerprint, DerivationPath)>;
+
+/// Derive `key` at `index`, recording its origin in `keypaths`.
+fn derive_key<C: Verification>(secp: &Secp256k1<C>, key: &DescriptorKey, index: u32, keypaths: &mut Keypaths) -> Result<PublicKey, Error> {
+    let (pk, origin) = key.derive(secp, index)?;
+    if let Some(origin) = origin {
+        keypaths.insert(pk, origin);
+    }
+    Ok(pk)
+}
+
+/// The scripts of a descriptor derived at some index
+struct Derived {
+    script_pubkey: Script,
+    redeem_script: Option<Script>,
+    witness_script: Option<Script>,
+    keypaths: Keypaths,
+}
+
+impl Descriptor {
+    /// Whether the descriptor contains keys derived at an index, that is
+    /// extended keys ending in `/*`.
+    pub fn is_ranged(&self) -> bool {
+        match *self {
+            Descriptor::Pk(ref key)
+            | Descriptor::Pkh(ref key)
+            | Descriptor::Wpkh(ref key)
+            | Descriptor::ShWpkh(ref key) => key.is_ranged(),
+            Descriptor::Multi(ref ms)
+            | Descriptor::Sh(ref ms)
+            | Descriptor::Wsh(ref ms)
+            | Descriptor::ShWsh(ref ms) => ms.keys.iter().any(DescriptorKey::is_ranged),
+            Descriptor::Addr(..) | Descriptor::Raw(..) => false,
+        }
+    }
+
+    fn derive<C: Verification>(&self, secp: &Secp256k1<C>, index: u32) -> Result<Derived, Error> {
+        let mut keypaths = BTreeMap::new();
+        let (script_pubkey, redeem_script, witness_script) = match *self {
+            Descriptor::Pk(ref key) => {
+                let pk = derive_key(secp, key, index, &mut keypaths)?;
+                let script = Builder::new().push_key(&pk).push_opcode(opcodes::all::OP_CHECKSIG).into_script();
+                (script, None, None)
+            }
+            Descriptor::Pkh(ref key) => {
+                let pk = derive_key(secp, key, index, &mut keypaths)?;
+                (Address::p2pkh(&pk, Network::Bitcoin).script_pubkey(), None, None)
+            }
+            Descriptor::Wpkh(ref key) => {
+                let pk = derive_key(secp, key, index, &mut keypaths)?;
+                (Address::p2wpkh(&pk, Network::Bitcoin).script_pubkey(), None, None)
+            }
+            Descriptor::ShWpkh(ref key) => {
+                let pk = derive_key(secp, key, index, &mut keypaths)?;
+                let redeem_script = Address::p2wpkh(&pk, Network::Bitcoin).script_pubkey();
+                (redeem_script.to_p2sh(), Some(redeem_script), None)
+            }
+            Descriptor::Multi(ref ms) => (ms.script(secp, index, &mut keypaths)?, None, None),
+            Descriptor::Sh(ref ms) => {
+                let redeem_script = ms.script(secp, index, &mut keypaths)?;
+                (redeem_script.to_p2sh(), Some(redeem_script), None)
+            }
+            Descriptor::Wsh(ref ms) => {
+                let witness_script = ms.script(secp, index, &mut keypaths)?;
+                (witness_script.to_v0_p2wsh(), None, Some(witness_script))
+            }
+            Descriptor::ShWsh(ref ms) => {
+                let witness_script = ms.script(secp, index, &mut keypaths)?;
+                let redeem_script = witness_script.to_v0_p2wsh();
+                (redeem_script.to_p2sh(), Some(redeem_script), Some(witness_script))
+            }
+            Descriptor::Addr(ref addr) => (addr.script_pubkey(), None, None),
+            Descriptor::Raw(ref script) => (script.clone(), None, None),
+        };
+
+        Ok(Derived {
+            script_pubkey: script_pubkey,
+            redeem_script: redeem_script,
+            witness_script: witness_script,
+            keypaths: keypaths,
+        })
+    }
+
+    /// The scriptPubKey with keys derived at `index`.
+    pub fn script_pubkey<C: Verification>(&self, secp: &Secp256k1<C>, index: u32) -> Result<Script, Error> {
+        Ok(self.derive(secp, index)?.script_pubkey)
+    }
+
+    /// The address of the scriptPubKey with keys derived at `index`, or
+    /// `None` for scripts without an address form such as bare multisig.
+    pub fn address<C: Verification>(&self, secp: &Secp256k1<C>, index: u32, network: Network) -> Result<Option<Address>, Error> {
+        if let Descriptor::Addr(ref addr) = *self {
+            return Ok(Some(addr.clone()));
+        }
+        Ok(Address::from_script(&self.script_pubkey(secp, index)?, network))
+    }
+
+    /// The keys derived at `index` which have a known origin, mapped to their
+    /// master fingerprint and derivation path.
+    pub fn hd_keypaths<C: Verification>(&self, secp: &Secp256k1<C>, index: u32) -> Result<BTreeMap<PublicKey, (Fingerprint, DerivationPath)>, Error> {
+        Ok(self.derive(secp, index)?.keypaths)
+    }
+
+    /// Fill in the redeem script, witness script and key origins of a PSBT
+    /// input spending the output this descriptor derives at `index`.
+    pub fn update_psbt_input<C: Verification>(&self, secp: &Secp256k1<C>, index: u32, input: &mut psbt::Input) -> Result<(), Error> {
+        let derived = self.derive(secp, index)?;
+        if derived.redeem_script.is_some() {
+            input.redeem_script = derived.redeem_script;
+        }
+        if derived.witness_script.is_some() {
+            input.witness_script = derived.witness_script;
+        }
+        input.hd_keypaths.extend(derived.keypaths);
+        Ok(())
+    }
+
+    /// Fill in the redeem script, witness script and key origins of a PSBT
+    /// output paying to the output this descriptor derives at `index`.
+    pub fn update_psbt_output<C: Verification>(&self, secp: &Secp256k1<C>, index: u32, output: &mut psbt::Output) -> Result<(), Error> {
+        let derived = self.derive(secp, index)?;
+        if derived.redeem_script.is_some() {
+            output.redeem_script = derived.redeem_script;
+        }
+        if derived.witness_script.is_some() {
+            output.witness_script = derived.witness_script;
+        }
+        output.hd_keypaths.extend(derived.keypaths);
+        Ok(())
+    }
+
+    /// Write the descriptor without its checksum.
+    fn fmt_body(&self, f: &mut fmt::Write) -> fmt::Result {
+        match *self {
+            Descriptor::Pk(ref key) => write!(f, "pk({})", key),
+            Descriptor::Pkh(ref key) => write!(f, "pkh({})", key),
+            Descriptor::Wpkh(ref key) => write!(f, "wpkh({})", key),
+            Descriptor::ShWpkh(ref key) => write!(f, "sh(wpkh({}))", key),
+            Descriptor::Multi(ref ms) => write!(f, "{}", ms),
+            Descriptor::Sh(ref ms) => write!(f, "sh({})", ms),
+            Descriptor::Wsh(ref ms) => write!(f, "wsh({})", ms),
+            Descriptor::ShWsh(ref ms) => write!(f, "sh(wsh({}))", ms),
+            Descriptor::Addr(ref addr) => write!(f, "addr({})", addr),
+            Descriptor::Raw(ref script) => write!(f, "raw({:x})", script),
+        }
+    }
+}
+
+impl fmt::Display for Descriptor {
+    /// Formats the descriptor followed by `#` and its checksum.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut body = String::new();
+        self.fmt_body(&mut body)?;
+        let checksum = checksum(&body).map_err(|_| fmt::Error)?;
+        write!(f, "{}#{}", body, checksum)
+    }
+}
+
+/// Split `name(args)` into `name` and `args`.
+fn split_function(s: &str) -> Result<(&str, &str), Error> {
+    match s.find('(') {
+        Some(open) if s.ends_with(')') => Ok((&s[..open], &s[open + 1..s.len() - 1])),
+        _ => Err(Error::Syntax(s.to_owned())),
+    }
+}
+
+/// Split the arguments of a function at the commas outside parentheses.
+fn split_args(s: &str) -> Result<Vec<&str>, Error> {
+    let mut args = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1).ok_or_else(|| Error::Syntax(s.to_owned()))?,
+            ',' if depth == 0 => {
+                args.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(Error::Syntax(s.to_owned()));
+    }
+    args.push(&s[start..]);
+    Ok(args)
+}
+
+/// Parse a key which must be compressed if `segwit` is set.
+fn parse_key(s: &str, segwit: bool) -> Result<DescriptorKey, Error> {
+    let key = DescriptorKey::from_str(s)?;
+    if segwit && !key.is_compressed() {
+        return Err(Error::UncompressedKey);
+    }
+    Ok(key)
+}
+
+/// Parse `multi(...)` or `sortedmulti(...)`, or return `None` for any other
+/// function.
+fn parse_multisig(s: &str, segwit: bool, p2sh: bool) -> Result<Option<Multisig>, Error> {
+    let (name, args) = split_function(s)?;
+    let sorted = match name {
+        "multi" => false,
+        "sortedmulti" => true,
+        _ => return Ok(None),
+    };
+
+    let args = split_args(args)?;
+    let threshold = usize::from_str(args[0]).map_err(|_| Error::Syntax(args[0].to_owned()))?;
+    let mut keys = Vec::with_capacity(args.len() - 1);
+    for arg in &args[1..] {
+        keys.push(parse_key(arg, segwit)?);
+    }
+    if threshold == 0 || threshold > keys.len() || keys.len() > MAX_MULTISIG_KEYS {
+        return Err(Error::InvalidMultisig {
+            threshold: threshold,
+            keys: keys.len(),
+        });
+    }
+
+    // A redeem script is limited to a single 520 byte push.
+    if p2sh {
+        let len = 3 + keys.iter().map(|k| if k.is_compressed() { 34 } else { 66 }).sum::<usize>();
+        if len > 520 {
+            return Err(Error::ScriptTooLarge);
+        }
+    }
+
+    Ok(Some(Multisig {
+        threshold: threshold,
+        keys: keys,
+        sorted: sorted,
+    }))
+}
+
+impl FromStr for Descriptor {
+    type Err = Error;
+
+    /// Parses a descriptor, checking its checksum if one is given after `#`.
+    fn from_str(s: &str) -> Result<Descriptor, Error> {
+        let body = match s.rfind('#') {
+            Some(pos) => {
+                let expected = checksum(&s[..pos])?;
+                if s[pos + 1..] != expected[..] {
+                    return Err(Error::BadChecksum {
+                        expected: expected,
+                        actual: s[pos + 1..].to_owned(),
+                    });
+                }
+                &s[..pos]
+            }
+            None => {
+                checksum(s)?;
+                s
+            }
+        };
+
+        let (name, args) = split_function(body)?;
+        let invalid = |name: &str| Error::InvalidFunction(name.to_owned());
+        match name {
+            "pk" => Ok(Descriptor::Pk(parse_key(args, false)?)),
+            "pkh" => Ok(Descriptor::Pkh(parse_key(args, false)?)),
+            "wpkh" => Ok(Descriptor::Wpkh(parse_key(args, true)?)),
+            "sh" => {
+                let (inner, inner_args) = split_function(args)?;
+                match inner {
+                    "wpkh" => Ok(Descriptor::ShWpkh(parse_key(inner_args, true)?)),
+                    "wsh" => match parse_multisig(inner_args, true, false)? {
+                        Some(ms) => Ok(Descriptor::ShWsh(ms)),
+                        None => Err(invalid(split_function(inner_args)?.0)),
+                    },
+                    _ => match parse_multisig(args, false, true)? {
+                        Some(ms) => Ok(Descriptor::Sh(ms)),
+                        None => Err(invalid(inner)),
+                    },
+                }
+            }
+            "wsh" => match parse_multisig(args, true, false)? {
+                Some(ms) => Ok(Descriptor::Wsh(ms)),
+                None => Err(invalid(split_function(args)?.0)),
+            },
+            "multi" | "sortedmulti" => match parse_multisig(body, false, false)? {
+                Some(ms) => Ok(Descriptor::Multi(ms)),
+                None => Err(invalid(name)),
+            },
+            "addr" => Ok(Descriptor::Addr(Address::from_str(args).map_err(Error::Address)?)),
+            "raw" => {
+                let bytes = hex::decode(args).map_err(|_| Error::Syntax(args.to_owned()))?;
+                Ok(Descriptor::Raw(Script::from(bytes)))
+            }
+            _ => Err(invalid(name)),
+        }
+    }
+}
+
+serde_string_impl!(Descriptor, "an output script descriptor");
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use secp256k1::{Secp256k1, SecretKey};
+
+    use blockdata::opcodes;
+    use blockdata::script::{Builder, Script};
+    use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+    use network::constants::Network;
+    use util::address::Address;
+    use util::bip32::{ChildNumber, ExtendedPrivKey, ExtendedPubKey, ScriptType};
+    use util::key::{PrivateKey, PublicKey};
+    use util::psbt::PartiallySignedTransaction;
+
+    use super::{checksum, Descriptor, Error};
+
+    // BIP32 test vector 2: the master key and its child m/0
+    const XPUB: &'static str = "xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB";
+    const XPUB_0: &'static str = "xpub69H7F5d8KSRgmmdJg2KhpAK8SR3DjMwAdkxj3ZuxV27CprR9LgpeyGmXUbC6wb7ERfvrnKZjXoUmmDznezpbZb7ap6r1D3tgFxHmwMkQTPH";
+
+    fn pubkeys() -> Vec<PublicKey> {
+        let secp = Secp256k1::new();
+        (1..4u8).map(|i| PrivateKey {
+            compressed: true,
+            network: Network::Bitcoin,
+            key: SecretKey::from_slice(&[i; 32]).unwrap(),
+        }.public_key(&secp)).collect()
+    }
+
+    fn desc(s: &str) -> Descriptor {
+        Descriptor::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert_eq!(
+            checksum("pkh([d34db33f/44'/0'/0']xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL/1/*)").unwrap(),
+            "ml40v0wf"
+        );
+        match checksum("raw(deadbeef)\n") {
+            Err(Error::InvalidCharacter('\n')) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        assert_eq!(desc("raw(deadbeef)#89f8spxm"), desc("raw(deadbeef)"));
+        match Descriptor::from_str("raw(deadbeef)#89f8spxx") {
+            Err(Error::BadChecksum { ref expected, ref actual }) if expected == "89f8spxm" && actual == "89f8spxx" => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(Descriptor::from_str("raw(deadbeef)#").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let pks = pubkeys();
+        let strings = vec![
+            "raw(deadbeef)".to_owned(),
+            "addr(1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2)".to_owned(),
+            format!("pk({})", pks[0]),
+            format!("pkh([d34db33f/44'/0'/0']{}/1/*)", XPUB),
+            format!("wpkh({}/0)", XPUB),
+            format!("sh(wpkh([00000000]{}))", pks[1]),
+            format!("multi(1,{},{})", pks[0], pks[1]),
+            format!("sh(sortedmulti(2,{},{},{}))", pks[2], pks[0], pks[1]),
+            format!("wsh(multi(2,[d34db33f/48'/0'/0'/2']{}/0/*,{}))", XPUB, pks[0]),
+            format!("sh(wsh(sortedmulti(1,{}/*,{})))", XPUB, pks[1]),
+        ];
+        for s in strings {
+            let d = desc(&s);
+            let with_checksum = format!("{}#{}", s, checksum(&s).unwrap());
+            assert_eq!(d.to_string(), with_checksum);
+            assert_eq!(desc(&with_checksum), d);
+        }
+
+        // Hardened steps may be written with `h` but print with `'`
+        assert_eq!(
+            desc(&format!("pkh([d34db33f/44h/0h/0h]{}/1/*)", XPUB)),
+            desc(&format!("pkh([d34db33f/44'/0'/0']{}/1/*)", XPUB))
+        );
+    }
+
+    #[test]
+    fn derive() {
+        let secp = Secp256k1::new();
+        let pks = pubkeys();
+        let child = ExtendedPubKey::from_str(XPUB_0).unwrap().public_key;
+
+        let d = desc(&format!("pkh({}/*)", XPUB));
+        assert!(d.is_ranged());
+        assert_eq!(d.script_pubkey(&secp, 0).unwrap(), Address::p2pkh(&child, Network::Bitcoin).script_pubkey());
+        assert!(d.script_pubkey(&secp, 1).unwrap() != d.script_pubkey(&secp, 0).unwrap());
+        assert!(d.script_pubkey(&secp, 1 << 31).is_err());
+
+        let d = desc(&format!("sh(wpkh({}/0))", XPUB));
+        assert!(!d.is_ranged());
+        assert_eq!(d.address(&secp, 5, Network::Testnet).unwrap(), Some(Address::p2shwpkh(&child, Network::Testnet)));
+
+        let d = desc(&format!("pk({})", pks[0]));
+        assert_eq!(
+            d.script_pubkey(&secp, 0).unwrap(),
+            Builder::new().push_key(&pks[0]).push_opcode(opcodes::all::OP_CHECKSIG).into_script()
+        );
+        assert_eq!(d.address(&secp, 0, Network::Bitcoin).unwrap(), None);
+
+        let addr = Address::from_str("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").unwrap();
+        let d = desc(&format!("addr({})", addr));
+        assert_eq!(d.address(&secp, 0, Network::Bitcoin).unwrap(), Some(addr.clone()));
+        assert_eq!(d.script_pubkey(&secp, 0).unwrap(), addr.script_pubkey());
+
+        let d = desc("raw(6a)");
+        assert_eq!(d.script_pubkey(&secp, 0).unwrap(), Script::from(vec![0x6a]));
+    }
+
+    #[test]
+    fn multisig() {
+        let secp = Secp256k1::new();
+        let pks = pubkeys();
+        let mut sorted = pks.clone();
+        sorted.sort_by_key(|pk| pk.to_bytes());
+        assert!(sorted != vec![pks[2], pks[0], pks[1]]);
+
+        let script = |keys: &[PublicKey]| Builder::new()
+            .push_int(2)
+            .push_key(&keys[0])
+            .push_key(&keys[1])
+            .push_key(&keys[2])
+            .push_int(3)
+            .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+            .into_script();
+        let unsorted = script(&[pks[2], pks[0], pks[1]]);
+        let sorted = script(&sorted);
+
+        let keys = format!("2,{},{},{}", pks[2], pks[0], pks[1]);
+        let d = desc(&format!("multi({})", keys));
+        assert_eq!(d.script_pubkey(&secp, 0).unwrap(), unsorted);
+        let d = desc(&format!("sortedmulti({})", keys));
+        assert_eq!(d.script_pubkey(&secp, 0).unwrap(), sorted);
+
+        let d = desc(&format!("sh(sortedmulti({}))", keys));
+        assert_eq!(d.address(&secp, 0, Network::Bitcoin).unwrap(), Some(Address::p2sh(&sorted, Network::Bitcoin)));
+        let d = desc(&format!("wsh(multi({}))", keys));
+        assert_eq!(d.address(&secp, 0, Network::Bitcoin).unwrap(), Some(Address::p2wsh(&unsorted, Network::Bitcoin)));
+        let d = desc(&format!("sh(wsh(multi({})))", keys));
+        assert_eq!(d.address(&secp, 0, Network::Bitcoin).unwrap(), Some(Address::p2shwsh(&unsorted, Network::Bitcoin)));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let pks = pubkeys();
+        let uncompressed = PublicKey { compressed: false, key: pks[0].key };
+
+        macro_rules! assert_err {
+            ($s:expr, $pat:pat) => {
+                match Descriptor::from_str(&$s) {
+                    Err($pat) => {},
+                    res => panic!("unexpected result for {}: {:?}", $s, res),
+                }
+            };
+        }
+
+        assert_err!(format!("pkh({})", uncompressed).replace("pkh", "wpkh"), Error::UncompressedKey);
+        assert_err!(format!("wsh(multi(1,{}))", uncompressed), Error::UncompressedKey);
+        desc(&format!("sh(multi(1,{}))", uncompressed));
+        assert_err!(format!("pkh({}/0'/*)", XPUB), Error::InvalidKey(..));
+        assert_err!(format!("pkh({}/*')", XPUB), Error::InvalidKey(..));
+        assert_err!(format!("pkh([d34db33f/x]{})", XPUB), Error::InvalidKey(..));
+        assert_err!(format!("pkh({})", &XPUB[1..]), Error::InvalidKey(..));
+        // like in Core, only xpubs and tpubs are accepted, not SLIP-132 keys
+        // declaring a script type of their own
+        let zpub = ExtendedPubKey::from_str(XPUB).unwrap().to_string_with_script_type(ScriptType::P2wpkh);
+        assert_err!(format!("wpkh({})", zpub), Error::InvalidKey(..));
+        assert_err!(format!("pkh({})", zpub), Error::InvalidKey(..));
+        assert_err!(format!("multi(0,{})", pks[0]), Error::InvalidMultisig { threshold: 0, keys: 1 });
+        assert_err!(format!("multi(2,{})", pks[0]), Error::InvalidMultisig { threshold: 2, keys: 1 });
+        let keys: Vec<String> = (0..17).map(|_| pks[0].to_string()).collect();
+        assert_err!(format!("wsh(multi(1,{}))", keys.join(",")), Error::InvalidMultisig { threshold: 1, keys: 17 });
+        let keys: Vec<String> = (0..8).map(|_| uncompressed.to_string()).collect();
+        assert_err!(format!("sh(multi(1,{}))", keys.join(",")), Error::ScriptTooLarge);
+        assert_err!(format!("sh(pkh({}))", pks[0]), Error::InvalidFunction(..));
+        assert_err!(format!("wsh(wpkh({}))", pks[0]), Error::InvalidFunction(..));
+        assert_err!(format!("foo({})", pks[0]), Error::InvalidFunction(..));
+        assert_err!(format!("pkh({}", pks[0]), Error::Syntax(..));
+        assert_err!(format!("wsh(multi(1,{})", pks[0]), Error::Syntax(..));
+        assert_err!("raw(xx)", Error::Syntax(..));
+        assert_err!("addr(1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN3)", Error::Address(..));
+    }
+
+    #[test]
+    fn psbt_keypaths() {
+        let secp = Secp256k1::new();
+        let master = ExtendedPrivKey::from_str("xprv9s21ZrQH143K31xYSDQpPDxsXRTUcvj2iNHm5NUtrGiGG5e2DtALGdso3pGz6ssrdK4PFmM8NSpSBHNqPqm55Qn3LqFtT2emdEXVYsCzC2U").unwrap();
+        let fingerprint = master.fingerprint(&secp);
+        let pks = pubkeys();
+
+        let d = desc(&format!("wsh(multi(1,[{}/0]{}/*,{}))", fingerprint, XPUB_0, pks[0]));
+        let keypaths = d.hd_keypaths(&secp, 7).unwrap();
+        assert_eq!(keypaths.len(), 1);
+        let (pk, &(fp, ref path)) = keypaths.iter().next().unwrap();
+        assert_eq!(fp, fingerprint);
+        let expected_path = vec![ChildNumber::from_normal_idx(0).unwrap(), ChildNumber::from_normal_idx(7).unwrap()];
+        assert_eq!(path.as_ref(), &expected_path[..]);
+        assert_eq!(*pk, ExtendedPubKey::from_private(&secp, &master.derive_priv(&secp, &expected_path).unwrap()).public_key);
+
+        // Without an origin the extended key itself is the master
+        let d = desc(&format!("wpkh({}/*)", XPUB_0));
+        let keypaths = d.hd_keypaths(&secp, 7).unwrap();
+        let &(fp, ref path) = keypaths.values().next().unwrap();
+        assert_eq!(fp, ExtendedPubKey::from_str(XPUB_0).unwrap().fingerprint());
+        assert_eq!(path.as_ref(), &expected_path[1..]);
+
+        // The filled in PSBT can be signed with the master key
+        let d = desc(&format!("sh(wsh(multi(1,[{}/0]{}/*,{})))", fingerprint, XPUB_0, pks[0]));
+        let utxo = TxOut { value: 1000, script_pubkey: d.script_pubkey(&secp, 7).unwrap() };
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                script_sig: Script::new(),
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            output: vec![utxo.clone()],
+        }).unwrap();
+        psbt.inputs[0].witness_utxo = Some(utxo);
+        d.update_psbt_input(&secp, 7, &mut psbt.inputs[0]).unwrap();
+        d.update_psbt_output(&secp, 7, &mut psbt.outputs[0]).unwrap();
+        assert_eq!(psbt.inputs[0].hd_keypaths.len(), 1);
+        assert_eq!(psbt.outputs[0].witness_script, psbt.inputs[0].witness_script);
+        assert_eq!(psbt.sign(&secp, &master).unwrap(), 1);
+        psbt.finalize().unwrap();
+    }
+}
@@ -22,6 +22,7 @@ use std::str::FromStr;
 
 use secp256k1::{self, Secp256k1};
 use consensus::encode;
+use network::chainparams::ChainParams;
 use network::constants::Network;
 use util::base58;
 
@@ -125,11 +126,22 @@ impl PrivateKey {
 
     /// Format the private key to WIF format.
     pub fn fmt_wif(&self, fmt: &mut fmt::Write) -> fmt::Result {
-        let mut ret = [0; 34];
-        ret[0] = match self.network {
+        let version = match self.network {
             Network::Bitcoin => 128,
-            Network::Testnet | Network::Regtest => 239,
+            Network::Testnet | Network::Signet | Network::Regtest => 239,
         };
+        self.fmt_wif_with_version(fmt, version)
+    }
+
+    /// Format the private key to WIF format with the version byte of
+    /// `params` instead of that of its network.
+    pub fn fmt_wif_with_params(&self, fmt: &mut fmt::Write, params: &ChainParams) -> fmt::Result {
+        self.fmt_wif_with_version(fmt, params.base58_prefixes.secret_key)
+    }
+
+    fn fmt_wif_with_version(&self, fmt: &mut fmt::Write, version: u8) -> fmt::Result {
+        let mut ret = [0; 34];
+        ret[0] = version;
         ret[1..33].copy_from_slice(&self.key[..]);
         let privkey = if self.compressed {
             ret[33] = 1;
@@ -150,15 +162,8 @@ impl PrivateKey {
 
     /// Parse WIF encoded private key.
     pub fn from_wif(wif: &str) -> Result<PrivateKey, encode::Error> {
-        let data = base58::from_check(wif)?;
-
-        let compressed = match data.len() {
-            33 => false,
-            34 => true,
-            _ => { return Err(encode::Error::Base58(base58::Error::InvalidLength(data.len()))); }
-        };
-
-        let network = match data[0] {
+        let (version, compressed, key) = decode_wif(wif)?;
+        let network = match version {
             128 => Network::Bitcoin,
             239 => Network::Testnet,
             x   => { return Err(encode::Error::Base58(base58::Error::InvalidVersion(vec![x]))); }
@@ -167,11 +172,39 @@ impl PrivateKey {
         Ok(PrivateKey {
             compressed: compressed,
             network: network,
-            key: secp256k1::SecretKey::from_slice(&data[1..33])?,
+            key: key,
+        })
+    }
+
+    /// Parse WIF encoded private key with the version byte of `params`. The
+    /// key gets the network of `params`.
+    pub fn from_wif_with_params(wif: &str, params: &ChainParams) -> Result<PrivateKey, encode::Error> {
+        let (version, compressed, key) = decode_wif(wif)?;
+        if version != params.base58_prefixes.secret_key {
+            return Err(encode::Error::Base58(base58::Error::InvalidVersion(vec![version])));
+        }
+
+        Ok(PrivateKey {
+            compressed: compressed,
+            network: params.network,
+            key: key,
         })
     }
 }
 
+/// Split a WIF encoded private key into version byte, compression flag and key
+fn decode_wif(wif: &str) -> Result<(u8, bool, secp256k1::SecretKey), encode::Error> {
+    let data = base58::from_check(wif)?;
+
+    let compressed = match data.len() {
+        33 => false,
+        34 => true,
+        _ => { return Err(encode::Error::Base58(base58::Error::InvalidLength(data.len()))); }
+    };
+
+    Ok((data[0], compressed, secp256k1::SecretKey::from_slice(&data[1..33])?))
+}
+
 impl fmt::Display for PrivateKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.fmt_wif(f)
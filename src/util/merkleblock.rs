@@ -62,6 +62,7 @@ use hashes::{sha256d, Hash};
 
 use blockdata::constants::{MAX_BLOCK_WEIGHT, MIN_TRANSACTION_WEIGHT};
 use consensus::encode::{self, Decodable, Encodable};
+use util::bloom::BloomFilter;
 use util::hash::BitcoinHash;
 use util::merkleblock::MerkleBlockError::*;
 use {Block, BlockHeader};
@@ -450,7 +451,27 @@ impl MerkleBlock {
         }
 
         let pmt = PartialMerkleTree::from_txids(&hashes, &matches);
-        MerkleBlock { header, txn: pmt }
+        MerkleBlock { header: header, txn: pmt }
+    }
+
+    /// Create a MerkleBlock from a block, that should contain proofs for the
+    /// transactions matching a BIP37 bloom filter.
+    ///
+    /// The filter is updated with the matched outputs according to its flags,
+    /// as a peer serving `filterload` requests would.
+    pub fn from_block_with_filter(block: &Block, filter: &mut BloomFilter) -> Self {
+        let header = block.header;
+
+        let mut matches: Vec<bool> = Vec::with_capacity(block.txdata.len());
+        let mut hashes: Vec<sha256d::Hash> = Vec::with_capacity(block.txdata.len());
+
+        for tx in &block.txdata {
+            matches.push(filter.is_relevant_and_update(tx));
+            hashes.push(tx.bitcoin_hash());
+        }
+
+        let pmt = PartialMerkleTree::from_txids(&hashes, &matches);
+        MerkleBlock { header: header, txn: pmt }
     }
 
     /// Extract the matching txid's represented by this partial merkle tree
@@ -501,6 +522,7 @@ mod tests {
 
     use consensus::encode::{deserialize, serialize};
     use util::hash::{bitcoin_merkle_root, BitcoinHash};
+    use util::bloom::{BloomFilter, BloomFlags};
     use util::merkleblock::{MerkleBlock, PartialMerkleTree};
     use {hex, Block};
 
@@ -691,6 +713,35 @@ mod tests {
         assert_eq!(index.len(), 0);
     }
 
+    /// Create a MerkleBlock matching transactions through a bloom filter,
+    /// from Bitcoin Core's merkle_block_1 test
+    #[test]
+    fn merkleblock_construct_from_filter() {
+        let block = get_block_13b8a();
+        let txid1 = sha256d::Hash::from_hex("74d681e0e03bafa802c8aa084379aa98d9fcd632ddc2ed9782b586ec87451f20").unwrap();
+        let txid2 = sha256d::Hash::from_hex("dd1fd2a6fc16404faf339881a90adbde7f4f728691ac62e8f168809cdfae1053").unwrap();
+
+        let mut filter = BloomFilter::new(10, 0.000001, 0, BloomFlags::All);
+        filter.insert(&txid1[..]);
+        let merkle_block = MerkleBlock::from_block_with_filter(&block, &mut filter);
+        assert_eq!(merkle_block.header.bitcoin_hash(), block.bitcoin_hash());
+
+        let mut matches: Vec<sha256d::Hash> = vec![];
+        let mut index: Vec<u32> = vec![];
+        assert!(merkle_block.extract_matches(&mut matches, &mut index).is_ok());
+        assert_eq!(matches, vec![txid1]);
+        assert_eq!(index, vec![8]);
+
+        // Also match the 8th transaction
+        filter.insert(&txid2[..]);
+        let merkle_block = MerkleBlock::from_block_with_filter(&block, &mut filter);
+        let mut matches: Vec<sha256d::Hash> = vec![];
+        let mut index: Vec<u32> = vec![];
+        assert!(merkle_block.extract_matches(&mut matches, &mut index).is_ok());
+        assert_eq!(matches, vec![txid2, txid1]);
+        assert_eq!(index, vec![7, 8]);
+    }
+
     impl PartialMerkleTree {
         /// Flip one bit in one of the hashes - this should break the authentication
         fn damage(&mut self, rng: &mut ThreadRng) {
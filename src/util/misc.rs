@@ -122,6 +122,211 @@ pub fn signed_msg_hash(msg: &str) -> sha256d::Hash {
     )
 }
 
+#[cfg(feature = "secp-recovery")]
+pub use self::message_signing::{verify_message, MessageSignature, MessageSignatureError};
+
+/// Signing and verification of messages in the "Bitcoin Signed Message"
+/// format, with the header byte variants of BIP137 for segwit addresses
+#[cfg(feature = "secp-recovery")]
+mod message_signing {
+    use std::{error, fmt};
+    use std::str::FromStr;
+
+    use base64;
+    use hashes::sha256d;
+    use secp256k1::{self, Message, Secp256k1, Signing, Verification};
+    use secp256k1::recovery::{RecoverableSignature, RecoveryId};
+
+    use util::address::{Address, AddressType};
+    use util::key::{PrivateKey, PublicKey};
+
+    use super::signed_msg_hash;
+
+    /// Message signature errors
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum MessageSignatureError {
+        /// The signature is not 65 bytes long
+        InvalidLength,
+        /// The header byte is not one of BIP137
+        InvalidHeader(u8),
+        /// The signature is not valid base64
+        InvalidBase64,
+        /// The signature could not be parsed or no key could be recovered
+        InvalidEncoding(secp256k1::Error),
+        /// Messages can not be signed for or verified against this address type
+        UnsupportedAddressType(AddressType),
+        /// Segwit addresses need compressed keys
+        UncompressedKey,
+    }
+
+    impl fmt::Display for MessageSignatureError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                MessageSignatureError::InvalidHeader(h) => write!(f, "{}: {}", error::Error::description(self), h),
+                MessageSignatureError::InvalidEncoding(ref e) => write!(f, "{}: {}", error::Error::description(self), e),
+                MessageSignatureError::UnsupportedAddressType(t) => write!(f, "{}: {}", error::Error::description(self), t),
+                MessageSignatureError::InvalidLength
+                | MessageSignatureError::InvalidBase64
+                | MessageSignatureError::UncompressedKey => f.write_str(error::Error::description(self)),
+            }
+        }
+    }
+
+    impl error::Error for MessageSignatureError {
+        fn cause(&self) -> Option<&error::Error> {
+            match *self {
+                MessageSignatureError::InvalidEncoding(ref e) => Some(e),
+                _ => None,
+            }
+        }
+
+        fn description(&self) -> &str {
+            match *self {
+                MessageSignatureError::InvalidLength => "message signature is not 65 bytes long",
+                MessageSignatureError::InvalidHeader(_) => "invalid message signature header byte",
+                MessageSignatureError::InvalidBase64 => "message signature is not valid base64",
+                MessageSignatureError::InvalidEncoding(_) => "invalid message signature",
+                MessageSignatureError::UnsupportedAddressType(_) => "unsupported address type for message signatures",
+                MessageSignatureError::UncompressedKey => "segwit message signatures need a compressed key",
+            }
+        }
+    }
+
+    #[doc(hidden)]
+    impl From<secp256k1::Error> for MessageSignatureError {
+        fn from(e: secp256k1::Error) -> MessageSignatureError {
+            MessageSignatureError::InvalidEncoding(e)
+        }
+    }
+
+    /// A signature of a message, serialized as 65 bytes: a header byte
+    /// followed by the compact recoverable signature.
+    ///
+    /// The header byte is 27 plus the recovery id, plus 4 for compressed
+    /// keys, plus 8 for P2SH-P2WPKH or 12 for P2WPKH addresses (BIP137).
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub struct MessageSignature {
+        /// The signature
+        pub signature: RecoverableSignature,
+        /// Whether the signing key is compressed
+        pub compressed: bool,
+        /// The address type the header byte stands for, `P2sh` meaning
+        /// P2SH-P2WPKH
+        pub address_type: AddressType,
+    }
+
+    impl MessageSignature {
+        /// Sign `msg` with `key` for an address of `address_type`, which is
+        /// P2PKH, P2SH-P2WPKH (given as `P2sh`) or P2WPKH.
+        pub fn sign<C: Signing>(secp: &Secp256k1<C>, msg: &str, key: &PrivateKey, address_type: AddressType) -> Result<MessageSignature, MessageSignatureError> {
+            match address_type {
+                AddressType::P2pkh => {}
+                AddressType::P2sh | AddressType::P2wpkh => if !key.compressed {
+                    return Err(MessageSignatureError::UncompressedKey);
+                },
+                t => return Err(MessageSignatureError::UnsupportedAddressType(t)),
+            }
+            let msg = Message::from_slice(&signed_msg_hash(msg)[..]).expect("32 bytes");
+            Ok(MessageSignature {
+                signature: secp.sign_recoverable(&msg, &key.key),
+                compressed: key.compressed,
+                address_type: address_type,
+            })
+        }
+
+        /// Parse a signature from its 65 bytes
+        pub fn from_slice(bytes: &[u8]) -> Result<MessageSignature, MessageSignatureError> {
+            if bytes.len() != 65 {
+                return Err(MessageSignatureError::InvalidLength);
+            }
+            let (compressed, address_type) = match bytes[0] {
+                27...30 => (false, AddressType::P2pkh),
+                31...34 => (true, AddressType::P2pkh),
+                35...38 => (true, AddressType::P2sh),
+                39...42 => (true, AddressType::P2wpkh),
+                h => return Err(MessageSignatureError::InvalidHeader(h)),
+            };
+            let recid = RecoveryId::from_i32(((bytes[0] - 27) & 3) as i32)?;
+            Ok(MessageSignature {
+                signature: RecoverableSignature::from_compact(&bytes[1..], recid)?,
+                compressed: compressed,
+                address_type: address_type,
+            })
+        }
+
+        /// Serialize the signature to its 65 bytes
+        pub fn serialize(&self) -> [u8; 65] {
+            let (recid, sig) = self.signature.serialize_compact();
+            let mut ret = [0; 65];
+            ret[0] = 27 + recid.to_i32() as u8 + match (self.address_type, self.compressed) {
+                (AddressType::P2pkh, false) => 0,
+                (AddressType::P2sh, _) => 8,
+                (AddressType::P2wpkh, _) => 12,
+                _ => 4,
+            };
+            ret[1..].copy_from_slice(&sig[..]);
+            ret
+        }
+
+        /// Parse a base64 encoded signature
+        pub fn from_base64(s: &str) -> Result<MessageSignature, MessageSignatureError> {
+            let bytes = base64::decode(s).map_err(|_| MessageSignatureError::InvalidBase64)?;
+            MessageSignature::from_slice(&bytes)
+        }
+
+        /// Encode the signature in base64
+        pub fn to_base64(&self) -> String {
+            base64::encode(&self.serialize()[..])
+        }
+
+        /// Recover the public key which signed the message with hash
+        /// `msg_hash`
+        pub fn recover_pubkey<C: Verification>(&self, secp: &Secp256k1<C>, msg_hash: sha256d::Hash) -> Result<PublicKey, MessageSignatureError> {
+            let msg = Message::from_slice(&msg_hash[..]).expect("32 bytes");
+            Ok(PublicKey {
+                compressed: self.compressed,
+                key: secp.recover(&msg, &self.signature)?,
+            })
+        }
+
+        /// Whether the message with hash `msg_hash` was signed by the key of
+        /// the P2PKH, P2SH-P2WPKH or P2WPKH `address`. The address type of
+        /// the header byte is not checked, as not all wallets set it.
+        pub fn is_signed_by_address<C: Verification>(&self, secp: &Secp256k1<C>, address: &Address, msg_hash: sha256d::Hash) -> Result<bool, MessageSignatureError> {
+            let address_type = match address.address_type() {
+                Some(t) => t,
+                None => return Ok(false),
+            };
+            let pubkey = self.recover_pubkey(secp, msg_hash)?;
+            Ok(match address_type {
+                AddressType::P2pkh => Address::p2pkh(&pubkey, address.network) == *address,
+                AddressType::P2sh => pubkey.compressed && Address::p2shwpkh(&pubkey, address.network) == *address,
+                AddressType::P2wpkh => pubkey.compressed && Address::p2wpkh(&pubkey, address.network) == *address,
+                t => return Err(MessageSignatureError::UnsupportedAddressType(t)),
+            })
+        }
+    }
+
+    impl fmt::Display for MessageSignature {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str(&self.to_base64())
+        }
+    }
+
+    impl FromStr for MessageSignature {
+        type Err = MessageSignatureError;
+
+        fn from_str(s: &str) -> Result<MessageSignature, MessageSignatureError> {
+            MessageSignature::from_base64(s)
+        }
+    }
+
+    /// Verify the base64 encoded `signature` of `msg` against `address`
+    pub fn verify_message<C: Verification>(secp: &Secp256k1<C>, address: &Address, signature: &str, msg: &str) -> Result<bool, MessageSignatureError> {
+        MessageSignature::from_base64(signature)?.is_signed_by_address(secp, address, signed_msg_hash(msg))
+    }
+}
+
 #[cfg(all(test, feature="unstable"))]
 mod benches {
     use secp256k1::rand::{Rng, thread_rng};
@@ -231,5 +436,65 @@ mod tests {
         let hash = signed_msg_hash("test");
         assert_eq!(hash.to_hex(), "a6f87fe6d58a032c320ff8d1541656f0282c2c7bfcc69d61af4c8e8ed528e49c");
     }
+
+    #[test]
+    #[cfg(feature = "secp-recovery")]
+    fn test_message_signature() {
+        use std::str::FromStr;
+        use secp256k1::Secp256k1;
+        use network::constants::Network;
+        use util::address::{Address, AddressType};
+        use util::key::PrivateKey;
+        use super::{verify_message, MessageSignature, MessageSignatureError};
+
+        let secp = Secp256k1::new();
+        let message = "This is just a test message";
+        let key = PrivateKey::from_wif("cUeKHd5orzT3mz8P9pxyREHfsWtVfgsfDjiZZBcjUBAaGk1BTj7N").unwrap();
+        let pk = key.public_key(&secp);
+
+        // Bitcoin Core signs deterministically, so the signature matches
+        let address = Address::from_str("mpLQjfK79b7CCV4VMJWEWAj5Mpx8Up5zxB").unwrap();
+        let sig = MessageSignature::sign(&secp, message, &key, AddressType::P2pkh).unwrap();
+        assert_eq!(sig.to_string(), "INbVnW4e6PeRmsv2Qgu8NuopvrVjkcxob+sX8OcZG0SALhWybUjzMLPdAsXI46YZGb0KQTRii+wWIQzRpG/U+S0=");
+        assert_eq!(verify_message(&secp, &address, &sig.to_string(), message), Ok(true));
+        assert_eq!(verify_message(&secp, &address, &sig.to_string(), "another message"), Ok(false));
+
+        let addresses = [
+            (AddressType::P2pkh, Address::p2pkh(&pk, Network::Testnet), 31),
+            (AddressType::P2sh, Address::p2shwpkh(&pk, Network::Testnet), 35),
+            (AddressType::P2wpkh, Address::p2wpkh(&pk, Network::Testnet), 39),
+        ];
+        for &(address_type, ref address, header) in addresses.iter() {
+            let sig = MessageSignature::sign(&secp, message, &key, address_type).unwrap();
+            let bytes = sig.serialize();
+            assert!(bytes[0] >= header && bytes[0] < header + 4);
+            assert_eq!(MessageSignature::from_slice(&bytes[..]), Ok(sig));
+            assert_eq!(MessageSignature::from_str(&sig.to_string()), Ok(sig));
+            assert_eq!(sig.is_signed_by_address(&secp, address, signed_msg_hash(message)), Ok(true));
+            // the address type of the header is not binding
+            for &(_, ref other, _) in addresses.iter() {
+                assert_eq!(verify_message(&secp, other, &sig.to_base64(), message), Ok(true));
+            }
+            let other = Address::p2pkh(&PrivateKey::from_wif("cVt4o7BGAig1UXywgGSmARhxMdzP5qvQsxKkSsc1XEkw3tDTQFpy").unwrap().public_key(&secp), Network::Testnet);
+            assert_eq!(verify_message(&secp, &other, &sig.to_base64(), message), Ok(false));
+        }
+
+        let mut uncompressed = key;
+        uncompressed.compressed = false;
+        let sig = MessageSignature::sign(&secp, message, &uncompressed, AddressType::P2pkh).unwrap();
+        assert!(sig.serialize()[0] < 31);
+        assert_eq!(verify_message(&secp, &Address::p2pkh(&uncompressed.public_key(&secp), Network::Testnet), &sig.to_base64(), message), Ok(true));
+        assert_eq!(verify_message(&secp, &addresses[0].1, &sig.to_base64(), message), Ok(false));
+        assert_eq!(MessageSignature::sign(&secp, message, &uncompressed, AddressType::P2wpkh),
+                   Err(MessageSignatureError::UncompressedKey));
+        assert_eq!(MessageSignature::sign(&secp, message, &key, AddressType::P2wsh),
+                   Err(MessageSignatureError::UnsupportedAddressType(AddressType::P2wsh)));
+
+        let mut bytes = sig.serialize();
+        bytes[0] = 43;
+        assert_eq!(MessageSignature::from_slice(&bytes[..]), Err(MessageSignatureError::InvalidHeader(43)));
+        assert_eq!(MessageSignature::from_slice(&bytes[1..]), Err(MessageSignatureError::InvalidLength));
+        assert_eq!(MessageSignature::from_base64("not base64!"), Err(MessageSignatureError::InvalidBase64));
+    }
 }
 
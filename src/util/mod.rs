@@ -21,12 +21,22 @@ pub mod address;
 pub mod amount;
 pub mod base58;
 pub mod bip32;
+pub mod bip322;
 pub mod bip143;
+pub mod bip152;
+pub mod bip157;
+pub mod bip39;
+pub mod bloom;
+pub mod coinselect;
 pub mod contracthash;
+pub mod descriptor;
 pub mod hash;
 pub mod merkleblock;
 pub mod misc;
 pub mod psbt;
+pub mod signet;
+pub(crate) mod sha3;
+pub mod txbuilder;
 pub mod uint;
 pub mod bip158;
 
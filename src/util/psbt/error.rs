@@ -48,6 +48,22 @@ pub enum Error {
     },
     /// Unable to parse as a standard SigHash type.
     NonStandardSigHashType(u32),
+    /// An input has keys to sign with but no UTXO information, or only a
+    /// witness UTXO for an output which is not segwit.
+    MissingUtxo,
+    /// The UTXO information of an input does not match the output spent by
+    /// the corresponding input of the unsigned transaction.
+    UtxoMismatch,
+    /// An input spending a P2SH output has no redeem script.
+    MissingRedeemScript,
+    /// An input spending a P2WSH output has no witness script.
+    MissingWitnessScript,
+    /// The redeem script or witness script of an input does not match the
+    /// scriptPubKey of the output it spends.
+    ScriptMismatch,
+    /// An input spends a witness program of a version we do not know how to
+    /// sign.
+    UnsupportedWitnessProgram,
 }
 
 impl fmt::Display for Error {
@@ -62,7 +78,13 @@ impl fmt::Display for Error {
             | Error::UnsignedTxHasScriptSigs
             | Error::UnsignedTxHasScriptWitnesses
             | Error::MustHaveUnsignedTx
-            | Error::NoMorePairs => f.write_str(error::Error::description(self))
+            | Error::NoMorePairs
+            | Error::MissingUtxo
+            | Error::UtxoMismatch
+            | Error::MissingRedeemScript
+            | Error::MissingWitnessScript
+            | Error::ScriptMismatch
+            | Error::UnsupportedWitnessProgram => f.write_str(error::Error::description(self))
         }
     }
 }
@@ -82,6 +104,12 @@ impl error::Error for Error {
             Error::NoMorePairs => "no more key-value pairs for this psbt map",
             Error::UnexpectedUnsignedTx { .. } => "different unsigned transaction",
             Error::NonStandardSigHashType(..) =>  "non-standard sighash type",
+            Error::MissingUtxo => "input is missing UTXO information",
+            Error::UtxoMismatch => "UTXO does not match the spent prevout",
+            Error::MissingRedeemScript => "input spending P2SH output has no redeem script",
+            Error::MissingWitnessScript => "input spending P2WSH output has no witness script",
+            Error::ScriptMismatch => "script does not match the spent scriptPubKey",
+            Error::UnsupportedWitnessProgram => "unsupported witness program version",
         }
     }
 }
@@ -0,0 +1,428 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # PSBT Finalizer
+//!
+//! Implementation of the Input Finalizer role of BIP174 for the standard
+//! script types: turning the partial signatures of an input into its final
+//! scriptSig and scriptWitness.
+//!
+
+use std::error;
+use std::fmt;
+
+use blockdata::opcodes;
+use blockdata::script::{Builder, Instruction, Script};
+use hashes::{hash160, Hash};
+use util::key::PublicKey;
+use util::psbt::{Error, Input, PartiallySignedTransaction};
+use util::psbt::sign::spend_info;
+
+/// Reasons an input of a PSBT cannot be finalized.
+#[derive(Debug)]
+pub enum FinalizeError {
+    /// The input's UTXO information or scripts are missing or do not match
+    /// the unsigned transaction.
+    Psbt(Error),
+    /// There is no signature for a public key the script needs one for.
+    MissingSignature(PublicKey),
+    /// There is no signature, nor a public key, for the key hash a P2PKH or
+    /// P2WPKH script pays to.
+    MissingKeyHashSignature(hash160::Hash),
+    /// Fewer signatures are available than a multisig script requires.
+    NotEnoughSignatures {
+        /// The number of signatures the script requires
+        required: usize,
+        /// The number of signatures available for keys in the script
+        available: usize,
+    },
+    /// The script is not one of the standard types we can finalize.
+    UnsupportedScript(Script),
+}
+
+impl fmt::Display for FinalizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FinalizeError::Psbt(ref e) => write!(f, "{}: {}", error::Error::description(self), e),
+            FinalizeError::MissingSignature(ref pk) => write!(f, "{}: {}", error::Error::description(self), pk),
+            FinalizeError::MissingKeyHashSignature(ref hash) => write!(f, "{}: {}", error::Error::description(self), hash),
+            FinalizeError::NotEnoughSignatures { required, available } => {
+                write!(f, "{}: {} of {}", error::Error::description(self), available, required)
+            }
+            FinalizeError::UnsupportedScript(ref script) => write!(f, "{}: {}", error::Error::description(self), script),
+        }
+    }
+}
+
+impl error::Error for FinalizeError {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            FinalizeError::Psbt(ref e) => Some(e),
+            FinalizeError::MissingSignature(..)
+            | FinalizeError::MissingKeyHashSignature(..)
+            | FinalizeError::NotEnoughSignatures { .. }
+            | FinalizeError::UnsupportedScript(..) => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            FinalizeError::Psbt(..) => "invalid input",
+            FinalizeError::MissingSignature(..) => "missing signature for public key",
+            FinalizeError::MissingKeyHashSignature(..) => "missing signature for key hash",
+            FinalizeError::NotEnoughSignatures { .. } => "not enough signatures",
+            FinalizeError::UnsupportedScript(..) => "unsupported script",
+        }
+    }
+}
+
+#[doc(hidden)]
+impl From<Error> for FinalizeError {
+    fn from(e: Error) -> FinalizeError {
+        FinalizeError::Psbt(e)
+    }
+}
+
+/// Parse a bare `m <pubkey>... n CHECKMULTISIG` script into the number of
+/// signatures required and the public keys.
+fn parse_multisig(script: &Script) -> Option<(usize, Vec<PublicKey>)> {
+    let instructions: Vec<Instruction> = script.iter(true).collect();
+    if instructions.len() < 4 {
+        return None;
+    }
+    let last = instructions.len() - 1;
+    if instructions[last] != Instruction::Op(opcodes::all::OP_CHECKMULTISIG) {
+        return None;
+    }
+
+    let small_int = |instruction: &Instruction| match *instruction {
+        Instruction::Op(op) => match op.classify() {
+            opcodes::Class::PushNum(n) => Some(n as usize),
+            _ => None,
+        },
+        _ => None,
+    };
+    let required = small_int(&instructions[0])?;
+    let total = small_int(&instructions[last - 1])?;
+
+    let mut pubkeys = Vec::with_capacity(total);
+    for instruction in &instructions[1..last - 1] {
+        match *instruction {
+            Instruction::PushBytes(data) => pubkeys.push(PublicKey::from_slice(data).ok()?),
+            _ => return None,
+        }
+    }
+
+    if required == 0 || required > total || pubkeys.len() != total {
+        return None;
+    }
+    Some((required, pubkeys))
+}
+
+/// The stack items which satisfy `script` using the signatures in `input`.
+fn satisfy(script: &Script, input: &Input) -> Result<Vec<Vec<u8>>, FinalizeError> {
+    if script.is_p2pkh() {
+        let hash = &script.as_bytes()[3..23];
+        let matches_hash = |pk: &&PublicKey| hash160::Hash::hash(&pk.to_bytes())[..] == *hash;
+
+        if let Some((pk, sig)) = input.partial_sigs.iter().find(|&(pk, _)| matches_hash(&pk)) {
+            return Ok(vec![sig.clone(), pk.to_bytes()]);
+        }
+        return match input.hd_keypaths.keys().find(matches_hash) {
+            Some(pk) => Err(FinalizeError::MissingSignature(*pk)),
+            None => Err(FinalizeError::MissingKeyHashSignature(hash160::Hash::from_slice(hash).expect("20 bytes"))),
+        };
+    }
+
+    if script.is_p2pk() {
+        let pk = PublicKey::from_slice(&script.as_bytes()[1..script.len() - 1])
+            .map_err(|_| FinalizeError::UnsupportedScript(script.clone()))?;
+        return match input.partial_sigs.get(&pk) {
+            Some(sig) => Ok(vec![sig.clone()]),
+            None => Err(FinalizeError::MissingSignature(pk)),
+        };
+    }
+
+    if let Some((required, pubkeys)) = parse_multisig(script) {
+        // The dummy element consumed by CHECKMULTISIG, then signatures in the
+        // order of their public keys in the script.
+        let mut stack = vec![vec![]];
+        stack.extend(pubkeys.iter()
+            .filter_map(|pk| input.partial_sigs.get(pk).cloned())
+            .take(required));
+        if stack.len() - 1 < required {
+            return Err(FinalizeError::NotEnoughSignatures {
+                required: required,
+                available: stack.len() - 1,
+            });
+        }
+        return Ok(stack);
+    }
+
+    Err(FinalizeError::UnsupportedScript(script.clone()))
+}
+
+/// Finalize input `index` of `psbt`.
+fn finalize_input(psbt: &PartiallySignedTransaction, index: usize) -> Result<Input, FinalizeError> {
+    let input = &psbt.inputs[index];
+    let info = match spend_info(&psbt.global.unsigned_tx, index, input)? {
+        Some(info) => info,
+        None => return Err(Error::MissingUtxo.into()),
+    };
+    let stack = satisfy(&info.script_code, input)?;
+
+    let mut script_sig = Builder::new();
+    let mut witness = Vec::new();
+    if info.segwit {
+        witness = stack;
+        if let Some(ref witness_script) = input.witness_script {
+            witness.push(witness_script.to_bytes());
+        }
+    } else {
+        for item in &stack {
+            script_sig = script_sig.push_slice(item);
+        }
+    }
+    if let Some(ref redeem_script) = input.redeem_script {
+        script_sig = script_sig.push_slice(redeem_script.as_bytes());
+    }
+
+    // Everything but the UTXO and unknown fields is dropped once final.
+    Ok(Input {
+        non_witness_utxo: input.non_witness_utxo.clone(),
+        witness_utxo: input.witness_utxo.clone(),
+        final_script_sig: if script_sig.is_empty() { None } else { Some(script_sig.into_script()) },
+        final_script_witness: if witness.is_empty() { None } else { Some(witness) },
+        unknown: input.unknown.clone(),
+        ..Default::default()
+    })
+}
+
+impl PartiallySignedTransaction {
+    /// Finalize a single input, replacing its partial signatures and scripts
+    /// with the final scriptSig and scriptWitness. Supports P2PK, P2PKH and
+    /// bare multisig scripts, directly or wrapped in any of P2SH, P2WSH and
+    /// P2SH-P2WSH, as well as P2WPKH and P2SH-P2WPKH. Inputs which are
+    /// already finalized are left alone.
+    ///
+    /// On error the input is left unchanged.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of range.
+    pub fn finalize_input(&mut self, index: usize) -> Result<(), FinalizeError> {
+        if self.inputs[index].final_script_sig.is_some() || self.inputs[index].final_script_witness.is_some() {
+            return Ok(());
+        }
+        let finalized = finalize_input(self, index)?;
+        self.inputs[index] = finalized;
+        Ok(())
+    }
+
+    /// Finalize every input which can be, see
+    /// [finalize_input](#method.finalize_input). If some inputs cannot be
+    /// finalized, returns the index of each along with what it is missing.
+    pub fn finalize(&mut self) -> Result<(), Vec<(usize, FinalizeError)>> {
+        let mut errors = Vec::new();
+        for index in 0..self.inputs.len() {
+            if let Err(e) = self.finalize_input(index) {
+                errors.push((index, e));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::Secp256k1;
+
+    use blockdata::interpreter::{verify_input, VerifyFlags};
+    use blockdata::opcodes;
+    use blockdata::script::{Builder, Script};
+    use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+    use network::constants::Network;
+    use util::address::Address;
+    use util::key::{PrivateKey, PublicKey};
+    use util::psbt::{Error, FinalizeError, Input, PartiallySignedTransaction};
+
+    fn keys() -> (Vec<PrivateKey>, Vec<PublicKey>) {
+        let secp = Secp256k1::new();
+        let keys: Vec<PrivateKey> = (1..4u8).map(|i| PrivateKey {
+            compressed: true,
+            network: Network::Bitcoin,
+            key: ::secp256k1::SecretKey::from_slice(&[i; 32]).unwrap(),
+        }).collect();
+        let pubkeys = keys.iter().map(|sk| sk.public_key(&secp)).collect();
+        (keys, pubkeys)
+    }
+
+    fn multisig(pubkeys: &[PublicKey]) -> Script {
+        Builder::new()
+            .push_int(2)
+            .push_key(&pubkeys[0])
+            .push_key(&pubkeys[1])
+            .push_key(&pubkeys[2])
+            .push_int(3)
+            .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+            .into_script()
+    }
+
+    /// A PSBT with one input for each supported script type, the funding
+    /// transaction it spends, and the inputs' redeem and witness scripts.
+    fn psbt(pubkeys: &[PublicKey]) -> (PartiallySignedTransaction, Transaction) {
+        let p2wpkh = Address::p2wpkh(&pubkeys[1], Network::Bitcoin).script_pubkey();
+        let ms = multisig(pubkeys);
+        let scripts: Vec<(Script, Option<Script>, Option<Script>)> = vec![
+            (Address::p2pkh(&pubkeys[0], Network::Bitcoin).script_pubkey(), None, None),
+            (Builder::new().push_key(&pubkeys[2]).push_opcode(opcodes::all::OP_CHECKSIG).into_script(), None, None),
+            (p2wpkh.clone(), None, None),
+            (p2wpkh.to_p2sh(), Some(p2wpkh.clone()), None),
+            (ms.clone(), None, None),
+            (ms.to_p2sh(), Some(ms.clone()), None),
+            (ms.to_v0_p2wsh(), None, Some(ms.clone())),
+            (ms.to_v0_p2wsh().to_p2sh(), Some(ms.to_v0_p2wsh()), Some(ms.clone())),
+        ];
+
+        let funding = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                script_sig: Script::new(),
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            output: scripts.iter().enumerate().map(|(i, &(ref spk, _, _))| TxOut {
+                value: 1000 * (i as u64 + 1),
+                script_pubkey: spk.clone(),
+            }).collect(),
+        };
+        let unsigned = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: (0..scripts.len() as u32).map(|vout| TxIn {
+                previous_output: OutPoint::new(funding.txid(), vout),
+                script_sig: Script::new(),
+                sequence: 0xffffffff,
+                witness: vec![],
+            }).collect(),
+            output: vec![TxOut { value: 30_000, script_pubkey: scripts[0].0.clone() }],
+        };
+
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned).unwrap();
+        for (i, (input, (_, redeem_script, witness_script))) in psbt.inputs.iter_mut().zip(scripts).enumerate() {
+            if witness_script.is_some() || i == 2 || i == 3 {
+                input.witness_utxo = Some(funding.output[i].clone());
+            } else {
+                input.non_witness_utxo = Some(funding.clone());
+            }
+            input.redeem_script = redeem_script;
+            input.witness_script = witness_script;
+        }
+        (psbt, funding)
+    }
+
+    #[test]
+    fn finalize_standard_scripts() {
+        let secp = Secp256k1::new();
+        let (keys, pubkeys) = keys();
+        let (mut psbt, funding) = psbt(&pubkeys);
+
+        // Sign the multisig inputs with the first and last keys, so the
+        // signatures must be ordered by key position rather than by key.
+        psbt.sign(&secp, &keys[..]).unwrap();
+        for input in psbt.inputs.iter_mut().skip(4) {
+            input.partial_sigs.remove(&pubkeys[1]);
+        }
+
+        psbt.finalize().unwrap();
+        for input in &psbt.inputs {
+            assert!(input.partial_sigs.is_empty());
+            assert!(input.redeem_script.is_none());
+            assert!(input.witness_script.is_none());
+            assert!(input.non_witness_utxo.is_some() || input.witness_utxo.is_some());
+        }
+        assert!(psbt.inputs[2].final_script_sig.is_none());
+        assert!(psbt.inputs[6].final_script_sig.is_none());
+        assert!(psbt.inputs[0].final_script_witness.is_none());
+        assert!(psbt.inputs[5].final_script_witness.is_none());
+
+        // Finalizing again changes nothing
+        let finalized = psbt.clone();
+        psbt.finalize().unwrap();
+        assert_eq!(psbt, finalized);
+
+        let tx = psbt.extract_tx();
+        for (index, out) in funding.output.iter().enumerate() {
+            verify_input(&secp, &tx, index, &out.script_pubkey, out.value, VerifyFlags::STANDARD).unwrap();
+        }
+    }
+
+    #[test]
+    fn finalize_errors() {
+        let secp = Secp256k1::new();
+        let (keys, pubkeys) = keys();
+        let (mut psbt, _) = psbt(&pubkeys);
+
+        // Nothing signed yet: for P2PKH we only know the key hash until the
+        // key appears in the input
+        match psbt.finalize_input(0) {
+            Err(FinalizeError::MissingKeyHashSignature(..)) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+        psbt.inputs[0].hd_keypaths.insert(pubkeys[0], (Default::default(), vec![].into()));
+        match psbt.finalize_input(0) {
+            Err(FinalizeError::MissingSignature(pk)) => assert_eq!(pk, pubkeys[0]),
+            res => panic!("unexpected result {:?}", res),
+        }
+        match psbt.finalize_input(1) {
+            Err(FinalizeError::MissingSignature(pk)) => assert_eq!(pk, pubkeys[2]),
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        psbt.sign_input(&secp, 6, &keys[1]).unwrap();
+        let unfinalized = psbt.inputs[6].clone();
+        match psbt.finalize_input(6) {
+            Err(FinalizeError::NotEnoughSignatures { required: 2, available: 1 }) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert_eq!(psbt.inputs[6], unfinalized);
+
+        psbt.inputs[7].witness_utxo = None;
+        match psbt.finalize_input(7) {
+            Err(FinalizeError::Psbt(Error::MissingUtxo)) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // Every input reports what it is missing
+        let errors = psbt.finalize().unwrap_err();
+        assert_eq!(errors.iter().map(|&(i, _)| i).collect::<Vec<_>>(), (0..8).collect::<Vec<_>>());
+
+        // Scripts which are not a standard template
+        let script = Builder::new().push_opcode(opcodes::all::OP_PUSHNUM_1).into_script();
+        psbt.inputs[7] = Input {
+            witness_utxo: Some(TxOut { value: 1, script_pubkey: script.to_v0_p2wsh() }),
+            witness_script: Some(script.clone()),
+            ..Default::default()
+        };
+        match psbt.finalize_input(7) {
+            Err(FinalizeError::UnsupportedScript(s)) => assert_eq!(s, script),
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
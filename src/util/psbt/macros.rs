@@ -100,6 +100,63 @@ macro_rules! impl_psbtmap_consensus_enc_dec_oding {
     };
 }
 
+macro_rules! impl_psbtmap_serde {
+    ($thing:ident) => {
+        #[cfg(feature = "serde")]
+        impl<'de> ::serde::Deserialize<'de> for $thing {
+            fn deserialize<D>(deserializer: D) -> Result<$thing, D::Error>
+            where
+                D: ::serde::de::Deserializer<'de>,
+            {
+                use std::fmt::{self, Formatter};
+
+                struct Visitor;
+                impl<'de> ::serde::de::Visitor<'de> for Visitor {
+                    type Value = $thing;
+
+                    fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+                        formatter.write_str(concat!("a hex-encoded PSBT ", stringify!($thing), " map"))
+                    }
+
+                    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+                    where
+                        E: ::serde::de::Error,
+                    {
+                        let bytes = ::hex::decode(v).map_err(E::custom)?;
+                        ::consensus::deserialize(&bytes).map_err(E::custom)
+                    }
+
+                    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Self::Value, E>
+                    where
+                        E: ::serde::de::Error,
+                    {
+                        self.visit_str(v)
+                    }
+
+                    fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
+                    where
+                        E: ::serde::de::Error,
+                    {
+                        self.visit_str(&v)
+                    }
+                }
+
+                deserializer.deserialize_str(Visitor)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl ::serde::Serialize for $thing {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: ::serde::Serializer,
+            {
+                serializer.serialize_str(&::consensus::encode::serialize_hex(self))
+            }
+        }
+    };
+}
+
 #[cfg_attr(rustfmt, rustfmt_skip)]
 macro_rules! impl_psbt_insert_pair {
     ($slf:ident.$unkeyed_name:ident <= <$raw_key:ident: _>|<$raw_value:ident: $unkeyed_value_type:ty>) => {
@@ -119,6 +119,7 @@ impl Map for Global {
 }
 
 impl_psbtmap_consensus_encoding!(Global);
+impl_psbtmap_serde!(Global);
 
 impl Decodable for Global {
     fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
@@ -195,3 +195,4 @@ impl Map for Input {
 }
 
 impl_psbtmap_consensus_enc_dec_oding!(Input);
+impl_psbtmap_serde!(Input);
@@ -110,3 +110,4 @@ impl Map for Output {
 }
 
 impl_psbtmap_consensus_enc_dec_oding!(Output);
+impl_psbtmap_serde!(Output);
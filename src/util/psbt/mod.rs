@@ -18,11 +18,14 @@
 //! defined at https://github.com/bitcoin/bips/blob/master/bip-0174.mediawiki
 //! except we define PSBTs containing non-standard SigHash types as invalid.
 
+use base64;
+
 use blockdata::script::Script;
 use blockdata::transaction::Transaction;
 use consensus::{encode, Encodable, Decodable};
 
-use std::io;
+use std::{fmt, io};
+use std::str::FromStr;
 
 mod error;
 pub use self::error::Error;
@@ -37,6 +40,12 @@ pub mod serialize;
 mod map;
 pub use self::map::{Map, Global, Input, Output};
 
+mod sign;
+pub use self::sign::SigningKeys;
+
+mod finalize;
+pub use self::finalize::FinalizeError;
+
 /// A Partially Signed Transaction.
 #[derive(Debug, Clone, PartialEq)]
 pub struct PartiallySignedTransaction {
@@ -90,6 +99,24 @@ impl PartiallySignedTransaction {
     }
 }
 
+impl fmt::Display for PartiallySignedTransaction {
+    /// Formats the PSBT as base64, as exchanged between wallets and over the
+    /// Bitcoin Core RPC interface.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&base64::encode(&encode::serialize(self)))
+    }
+}
+
+impl FromStr for PartiallySignedTransaction {
+    type Err = encode::Error;
+
+    fn from_str(s: &str) -> Result<PartiallySignedTransaction, encode::Error> {
+        encode::deserialize(&base64::decode(s)?)
+    }
+}
+
+serde_string_impl!(PartiallySignedTransaction, "a base64-encoded partially signed transaction");
+
 impl Encodable for PartiallySignedTransaction {
     fn consensus_encode<S: io::Write>(
         &self,
@@ -174,13 +201,13 @@ mod tests {
     use blockdata::script::Script;
     use blockdata::transaction::{Transaction, TxIn, TxOut, OutPoint};
     use network::constants::Network::Bitcoin;
-    use consensus::encode::{deserialize, serialize, serialize_hex};
+    use consensus::encode::{self, deserialize, serialize, serialize_hex};
     use util::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey, Fingerprint};
     use util::key::PublicKey;
     use util::psbt::map::{Global, Output};
     use util::psbt::raw;
 
-    use super::PartiallySignedTransaction;
+    use super::{Error, PartiallySignedTransaction};
 
     #[test]
     fn trivial_psbt() {
@@ -309,6 +336,51 @@ mod tests {
         assert_eq!(hex, serialize_hex(&psbt));
     }
 
+    #[test]
+    fn base64_psbt() {
+        let trivial: PartiallySignedTransaction = hex_psbt!("70736274ff01000a0200000000000000000000").unwrap();
+        assert_eq!(trivial.to_string(), "cHNidP8BAAoCAAAAAAAAAAAAAA==");
+
+        // `walletprocesspsbt` output for the PSBT with two partial signatures
+        let base64 = "cHNidP8BAIkCAAAAASB66YXXh9/mFD1cWPrXnMcQXg55n88DO38roX5i17MgAAAAAAD/////AlY9AwAAAAAAIgAgGYmVNLmgEQQ8DdV8P/mjgcNSLF8nxqQjGQhbVspUOh1q3AIAAAAAACIAIGGLR6B+vsyk4VbtsbnqfCS97gE5/ASSN5Zf/a9W1e5zAAAAAAABASuAGgYAAAAAACIAIBFI6T6TFeN9vtISG+UjklevNa3AP/38XZFLCDr6RNq4IgICX+c3E3bVPPiieDkXwovzC9aQsKTUogdpAJPKK5IO4HZHMEQCIAfgazYuiZEqvUZh9HlFQwc5sAaoXRsqFsAdwaS9B6yrAiBhV216qDSYi3q5TvIdjuvZlupZ6iBSmhmxXwyc6+PYrAEiAgKz/pNTACCoKU8OUn4z+9/xhPBH62taFVijUvYsKZcvikcwRAIgAnh/km1oF1BEMe4oEYO4EZtoRb+qa++uReE7bUMMnS8CIChZ8UmmzSauLwOhB+fzPH2Rcw2t4wX+B3uuZ3tdRJUqAQEFR1IhArP+k1MAIKgpTw5SfjP73/GE8Efra1oVWKNS9iwply+KIQJf5zcTdtU8+KJ4ORfCi/ML1pCwpNSiB2kAk8orkg7gdlKuAAEBR1IhAoPvdlN/LViuOqOkvYrkHD8jDMrf+xoL08pQTYcc/wXnIQNT15zAyxOW9M4njQBfFtlI4Cpq7J7REJ8TdH7LFQeze1KuAAEBR1IhArOTckF3e2Zl4NaU5S+cGxiEM2Qd+FLab8Qhh7XYo2ijIQNM3UdPAcxap/+DSti8yIKofoVK/8d1SGvCqfYuj0m9eFKuAA==";
+        let psbt: PartiallySignedTransaction = base64.parse().unwrap();
+        assert_eq!(psbt.inputs[0].partial_sigs.len(), 2);
+        assert_eq!(psbt, hex_psbt!("70736274ff0100890200000001207ae985d787dfe6143d5c58fad79cc7105e0e799fcf033b7f2ba17e62d7b3200000000000ffffffff02563d03000000000022002019899534b9a011043c0dd57c3ff9a381c3522c5f27c6a42319085b56ca543a1d6adc020000000000220020618b47a07ebecca4e156edb1b9ea7c24bdee0139fc049237965ffdaf56d5ee73000000000001012b801a0600000000002200201148e93e9315e37dbed2121be5239257af35adc03ffdfc5d914b083afa44dab82202025fe7371376d53cf8a2783917c28bf30bd690b0a4d4a207690093ca2b920ee076473044022007e06b362e89912abd4661f47945430739b006a85d1b2a16c01dc1a4bd07acab022061576d7aa834988b7ab94ef21d8eebd996ea59ea20529a19b15f0c9cebe3d8ac01220202b3fe93530020a8294f0e527e33fbdff184f047eb6b5a1558a352f62c29972f8a473044022002787f926d6817504431ee281183b8119b6845bfaa6befae45e13b6d430c9d2f02202859f149a6cd26ae2f03a107e7f33c7d91730dade305fe077bae677b5d44952a01010547522102b3fe93530020a8294f0e527e33fbdff184f047eb6b5a1558a352f62c29972f8a21025fe7371376d53cf8a2783917c28bf30bd690b0a4d4a207690093ca2b920ee07652ae0001014752210283ef76537f2d58ae3aa3a4bd8ae41c3f230ccadffb1a0bd3ca504d871cff05e7210353d79cc0cb1396f4ce278d005f16d948e02a6aec9ed1109f13747ecb1507b37b52ae00010147522102b3937241777b6665e0d694e52f9c1b188433641df852da6fc42187b5d8a368a321034cdd474f01cc5aa7ff834ad8bcc882a87e854affc775486bc2a9f62e8f49bd7852ae00").unwrap());
+        assert_eq!(psbt.to_string(), base64);
+
+        match "cHNidP8BAAoCAAAA*AAAAAAAAA==".parse::<PartiallySignedTransaction>() {
+            Err(encode::Error::Base64(..)) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+        // Valid base64, but not a PSBT
+        match "AQID".parse::<PartiallySignedTransaction>() {
+            Err(encode::Error::Psbt(Error::InvalidMagic)) | Err(encode::Error::Io(..)) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_psbt() {
+        use serde_json;
+
+        let psbt: PartiallySignedTransaction = hex_psbt!("70736274ff01000a0200000000000000000000").unwrap();
+        assert_eq!(serde_json::to_value(&psbt).unwrap(), serde_json::Value::String(psbt.to_string()));
+        serde_round_trip!(psbt);
+
+        let output = Output {
+            redeem_script: Some(hex_script!("76a914d0c59903c5bac2868760e90fd521a4665aa7652088ac")),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(&output).unwrap(),
+            serde_json::Value::String(serialize_hex(&output))
+        );
+        serde_round_trip!(output);
+        serde_round_trip!(psbt.global);
+        serde_round_trip!(super::Input::default());
+    }
+
     mod bip_vectors {
         use std::collections::BTreeMap;
 
@@ -0,0 +1,663 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # PSBT Signer
+//!
+//! Implementation of the Signer role of BIP174: producing signatures for the
+//! inputs of a PSBT with keys held by the caller and storing them in the
+//! input's partial signatures.
+//!
+
+use secp256k1::{Message, Secp256k1, Signing};
+
+use blockdata::opcodes;
+use blockdata::script::{Builder, Instruction, Script};
+use blockdata::transaction::{SigHashType, Transaction, TxOut};
+use hashes::{hash160, Hash};
+use util::bip143::SigHashCache;
+use util::bip32::{DerivationPath, ExtendedPrivKey, ExtendedPubKey, Fingerprint};
+use util::key::{PrivateKey, PublicKey};
+use util::psbt::{Error, Input, PartiallySignedTransaction};
+
+/// A source of private keys the PSBT signer may sign with.
+pub trait SigningKeys {
+    /// Return the private key for `pubkey`, if known. `origin` is the master
+    /// key fingerprint and derivation path recorded for it in the input's
+    /// `hd_keypaths`, if any.
+    fn private_key<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        pubkey: &PublicKey,
+        origin: Option<&(Fingerprint, DerivationPath)>,
+    ) -> Option<PrivateKey>;
+
+    /// The public keys of all private keys held, used to find keys which
+    /// appear in an input's script but not in its `hd_keypaths`. Key sources
+    /// which can only be searched by derivation path return nothing.
+    fn public_keys<C: Signing>(&self, _secp: &Secp256k1<C>) -> Vec<PublicKey> {
+        Vec::new()
+    }
+}
+
+impl SigningKeys for ExtendedPrivKey {
+    fn private_key<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        pubkey: &PublicKey,
+        origin: Option<&(Fingerprint, DerivationPath)>,
+    ) -> Option<PrivateKey> {
+        let &(ref fingerprint, ref path) = origin?;
+        if *fingerprint != self.fingerprint(secp) {
+            return None;
+        }
+        let derived = self.derive_priv(secp, path).ok()?;
+        if ExtendedPubKey::from_private(secp, &derived).public_key == *pubkey {
+            Some(derived.private_key)
+        } else {
+            None
+        }
+    }
+}
+
+impl SigningKeys for [PrivateKey] {
+    fn private_key<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        pubkey: &PublicKey,
+        _origin: Option<&(Fingerprint, DerivationPath)>,
+    ) -> Option<PrivateKey> {
+        self.iter().find(|sk| sk.public_key(secp) == *pubkey).cloned()
+    }
+
+    fn public_keys<C: Signing>(&self, secp: &Secp256k1<C>) -> Vec<PublicKey> {
+        self.iter().map(|sk| sk.public_key(secp)).collect()
+    }
+}
+
+impl SigningKeys for PrivateKey {
+    fn private_key<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        pubkey: &PublicKey,
+        _origin: Option<&(Fingerprint, DerivationPath)>,
+    ) -> Option<PrivateKey> {
+        if self.public_key(secp) == *pubkey {
+            Some(*self)
+        } else {
+            None
+        }
+    }
+
+    fn public_keys<C: Signing>(&self, secp: &Secp256k1<C>) -> Vec<PublicKey> {
+        vec![self.public_key(secp)]
+    }
+}
+
+/// What is needed to compute the signature hash for an input.
+pub struct SpendInfo {
+    /// The output being spent.
+    pub utxo: TxOut,
+    /// The script which signatures commit to.
+    pub script_code: Script,
+    /// Whether the input is signed with the BIP143 signature hash.
+    pub segwit: bool,
+}
+
+/// Find the output spent by input `index` of `tx`, checking that the UTXO
+/// information in `input` agrees with the prevout.
+fn spent_output(tx: &Transaction, index: usize, input: &Input) -> Result<Option<TxOut>, Error> {
+    let prevout = tx.input[index].previous_output;
+
+    let from_tx = match input.non_witness_utxo {
+        Some(ref utxo_tx) => {
+            if utxo_tx.txid() != prevout.txid {
+                return Err(Error::UtxoMismatch);
+            }
+            match utxo_tx.output.get(prevout.vout as usize) {
+                Some(out) => Some(out.clone()),
+                None => return Err(Error::UtxoMismatch),
+            }
+        }
+        None => None,
+    };
+
+    match (from_tx, input.witness_utxo.clone()) {
+        (Some(from_tx), Some(witness_utxo)) => {
+            if from_tx != witness_utxo {
+                return Err(Error::UtxoMismatch);
+            }
+            Ok(Some(from_tx))
+        }
+        (from_tx, witness_utxo) => Ok(from_tx.or(witness_utxo)),
+    }
+}
+
+/// Work out how input `index` of `tx` is signed from the UTXO and scripts in
+/// `input`. Returns `None` if the input has no UTXO information at all.
+pub fn spend_info(tx: &Transaction, index: usize, input: &Input) -> Result<Option<SpendInfo>, Error> {
+    let utxo = match spent_output(tx, index, input)? {
+        Some(utxo) => utxo,
+        None => return Ok(None),
+    };
+
+    let script = if utxo.script_pubkey.is_p2sh() {
+        match input.redeem_script {
+            Some(ref redeem_script) => {
+                if redeem_script.to_p2sh() != utxo.script_pubkey {
+                    return Err(Error::ScriptMismatch);
+                }
+                redeem_script.clone()
+            }
+            None => return Err(Error::MissingRedeemScript),
+        }
+    } else {
+        if input.redeem_script.is_some() {
+            return Err(Error::ScriptMismatch);
+        }
+        utxo.script_pubkey.clone()
+    };
+
+    let (script_code, segwit) = if script.is_v0_p2wsh() {
+        match input.witness_script {
+            Some(ref witness_script) => {
+                if witness_script.to_v0_p2wsh() != script {
+                    return Err(Error::ScriptMismatch);
+                }
+                (witness_script.clone(), true)
+            }
+            None => return Err(Error::MissingWitnessScript),
+        }
+    } else if script.is_v0_p2wpkh() {
+        if input.witness_script.is_some() {
+            return Err(Error::ScriptMismatch);
+        }
+        let script_code = Builder::new()
+            .push_opcode(opcodes::all::OP_DUP)
+            .push_opcode(opcodes::all::OP_HASH160)
+            .push_slice(&script.as_bytes()[2..])
+            .push_opcode(opcodes::all::OP_EQUALVERIFY)
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script();
+        (script_code, true)
+    } else if script.is_witness_program() {
+        return Err(Error::UnsupportedWitnessProgram);
+    } else {
+        if input.witness_script.is_some() {
+            return Err(Error::ScriptMismatch);
+        }
+        (script, false)
+    };
+
+    Ok(Some(SpendInfo {
+        utxo: utxo,
+        script_code: script_code,
+        segwit: segwit,
+    }))
+}
+
+/// Whether `pubkey` or its hash is pushed by `script`.
+fn script_uses_key(script: &Script, pubkey: &PublicKey) -> bool {
+    let key_bytes = pubkey.to_bytes();
+    let key_hash = hash160::Hash::hash(&key_bytes);
+    script.iter(false).any(|instruction| match instruction {
+        Instruction::PushBytes(data) => data == &key_bytes[..] || data == &key_hash[..],
+        _ => false,
+    })
+}
+
+/// The scripts of `input` which may contain its keys, gathered without
+/// checking that they are consistent with each other or the prevout.
+fn candidate_scripts<'a>(tx: &Transaction, index: usize, input: &'a Input) -> Vec<&'a Script> {
+    let mut scripts = vec![];
+    if let Some(ref script) = input.witness_script {
+        scripts.push(script);
+    }
+    if let Some(ref script) = input.redeem_script {
+        scripts.push(script);
+    }
+    if let Some(ref utxo) = input.witness_utxo {
+        scripts.push(&utxo.script_pubkey);
+    }
+    if let Some(ref utxo_tx) = input.non_witness_utxo {
+        if let Some(out) = utxo_tx.output.get(tx.input[index].previous_output.vout as usize) {
+            scripts.push(&out.script_pubkey);
+        }
+    }
+    scripts
+}
+
+/// Produce the signatures of input `index` of `tx`, whose PSBT map is
+/// `input`, for every key in `keys` it needs. The UTXO information and
+/// scripts are only checked if we hold a key for the input.
+fn sign_input<C: Signing, K: SigningKeys + ?Sized>(
+    secp: &Secp256k1<C>,
+    tx: &Transaction,
+    cache: &mut SigHashCache,
+    index: usize,
+    input: &Input,
+    keys: &K,
+) -> Result<Vec<(PublicKey, Vec<u8>)>, Error> {
+    if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+        return Ok(vec![]);
+    }
+
+    let mut signing_keys: Vec<(PublicKey, PrivateKey)> = input.hd_keypaths
+        .iter()
+        .filter_map(|(pubkey, origin)| {
+            keys.private_key(secp, pubkey, Some(origin)).map(|sk| (*pubkey, sk))
+        })
+        .collect();
+    let mut searched_keys = vec![];
+    let scripts = candidate_scripts(tx, index, input);
+    for pubkey in keys.public_keys(secp) {
+        if input.hd_keypaths.contains_key(&pubkey) || !scripts.iter().any(|s| script_uses_key(s, &pubkey)) {
+            continue;
+        }
+        if let Some(sk) = keys.private_key(secp, &pubkey, None) {
+            searched_keys.push((pubkey, sk));
+        }
+    }
+    if signing_keys.is_empty() && searched_keys.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let info = match spend_info(tx, index, input)? {
+        Some(info) => info,
+        None => return Err(Error::MissingUtxo),
+    };
+    // The amount is not committed to by legacy signatures, so the whole
+    // previous transaction is needed to know what is being spent.
+    if !info.segwit && input.non_witness_utxo.is_none() {
+        return Err(Error::MissingUtxo);
+    }
+    signing_keys.extend(searched_keys.into_iter().filter(|key| script_uses_key(&info.script_code, &key.0)));
+
+    let sighash_type = input.sighash_type.unwrap_or(SigHashType::All);
+    let sighash = if info.segwit {
+        cache.signature_hash(index, &info.script_code, info.utxo.value, sighash_type)
+    } else {
+        tx.signature_hash(index, &info.script_code, sighash_type.as_u32())
+    };
+    let msg = Message::from_slice(&sighash[..]).expect("sighashes are 32 bytes");
+
+    Ok(signing_keys.iter().map(|&(pubkey, ref sk)| {
+        let mut sig = secp.sign(&msg, &sk.key).serialize_der().to_vec();
+        sig.push(sighash_type.as_u32() as u8);
+        (pubkey, sig)
+    }).collect())
+}
+
+impl PartiallySignedTransaction {
+    /// Sign every input we hold keys for, adding the signatures to the
+    /// inputs' partial signatures. Keys are looked up by the fingerprints and
+    /// derivation paths in each input's `hd_keypaths` and, for key sources
+    /// which can list their public keys, by searching the scripts of the
+    /// input. Inputs which are already finalized, or which we hold no keys
+    /// for, are left alone.
+    ///
+    /// Returns the number of signatures added, or an error if an input we
+    /// would sign has UTXO information or scripts inconsistent with the
+    /// unsigned transaction, in which case no signature is added at all.
+    pub fn sign<C: Signing, K: SigningKeys + ?Sized>(
+        &mut self,
+        secp: &Secp256k1<C>,
+        keys: &K,
+    ) -> Result<usize, Error> {
+        let sigs = {
+            let tx = &self.global.unsigned_tx;
+            let mut cache = SigHashCache::new(tx);
+            let mut sigs = Vec::with_capacity(self.inputs.len());
+            for (index, input) in self.inputs.iter().enumerate() {
+                sigs.push(sign_input(secp, tx, &mut cache, index, input, keys)?);
+            }
+            sigs
+        };
+        let mut count = 0;
+        for (input, input_sigs) in self.inputs.iter_mut().zip(sigs) {
+            count += input_sigs.len();
+            input.partial_sigs.extend(input_sigs);
+        }
+        Ok(count)
+    }
+
+    /// Sign a single input, see [sign](#method.sign).
+    ///
+    /// # Panics
+    /// Panics if `index` is out of range.
+    pub fn sign_input<C: Signing, K: SigningKeys + ?Sized>(
+        &mut self,
+        secp: &Secp256k1<C>,
+        index: usize,
+        keys: &K,
+    ) -> Result<usize, Error> {
+        let sigs = {
+            let tx = &self.global.unsigned_tx;
+            let mut cache = SigHashCache::new(tx);
+            sign_input(secp, tx, &mut cache, index, &self.inputs[index], keys)?
+        };
+        let count = sigs.len();
+        self.inputs[index].partial_sigs.extend(sigs);
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::Secp256k1;
+
+    use blockdata::interpreter::{verify_input, VerifyFlags};
+    use blockdata::opcodes;
+    use blockdata::script::{Builder, Script};
+    use blockdata::transaction::{OutPoint, SigHashType, Transaction, TxIn, TxOut};
+    use network::constants::Network;
+    use util::address::Address;
+    use util::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey};
+    use util::key::{PrivateKey, PublicKey};
+    use util::psbt::{Error, PartiallySignedTransaction};
+
+    struct Fixture {
+        master: ExtendedPrivKey,
+        paths: Vec<DerivationPath>,
+        keys: Vec<PrivateKey>,
+        pubkeys: Vec<PublicKey>,
+        funding: Transaction,
+        redeem_script: Script,
+        witness_script: Script,
+    }
+
+    fn fixture() -> Fixture {
+        let secp = Secp256k1::new();
+        let master = ExtendedPrivKey::new_master(Network::Bitcoin, &[7; 32]).unwrap();
+        let paths: Vec<DerivationPath> = (0..2).map(|i| vec![
+            ChildNumber::from_hardened_idx(0).unwrap(),
+            ChildNumber::from_normal_idx(i).unwrap(),
+        ].into()).collect();
+        let keys: Vec<PrivateKey> = paths.iter()
+            .map(|path| master.derive_priv(&secp, path).unwrap().private_key)
+            .collect();
+        let pubkeys: Vec<PublicKey> = keys.iter().map(|sk| sk.public_key(&secp)).collect();
+
+        let redeem_script = Address::p2wpkh(&pubkeys[1], Network::Bitcoin).script_pubkey();
+        let witness_script = Builder::new()
+            .push_int(2)
+            .push_key(&pubkeys[0])
+            .push_key(&pubkeys[1])
+            .push_int(2)
+            .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+            .into_script();
+
+        let funding = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                script_sig: Script::new(),
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            output: vec![
+                TxOut {
+                    value: 10_000,
+                    script_pubkey: Address::p2pkh(&pubkeys[0], Network::Bitcoin).script_pubkey(),
+                },
+                TxOut { value: 20_000, script_pubkey: redeem_script.to_p2sh() },
+                TxOut { value: 30_000, script_pubkey: witness_script.to_v0_p2wsh() },
+            ],
+        };
+
+        Fixture {
+            master: master,
+            paths: paths,
+            keys: keys,
+            pubkeys: pubkeys,
+            funding: funding,
+            redeem_script: redeem_script,
+            witness_script: witness_script,
+        }
+    }
+
+    /// A PSBT spending all three outputs of the fixture's funding
+    /// transaction, with key origins filled in if `with_keypaths` is set.
+    fn psbt(f: &Fixture, with_keypaths: bool) -> PartiallySignedTransaction {
+        let secp = Secp256k1::new();
+        let fingerprint = f.master.fingerprint(&secp);
+        let txid = f.funding.txid();
+        let unsigned = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: (0..3).map(|vout| TxIn {
+                previous_output: OutPoint::new(txid, vout),
+                script_sig: Script::new(),
+                sequence: 0xffffffff,
+                witness: vec![],
+            }).collect(),
+            output: vec![TxOut {
+                value: 59_000,
+                script_pubkey: Address::p2pkh(&f.pubkeys[0], Network::Bitcoin).script_pubkey(),
+            }],
+        };
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned).unwrap();
+
+        psbt.inputs[0].non_witness_utxo = Some(f.funding.clone());
+        psbt.inputs[1].witness_utxo = Some(f.funding.output[1].clone());
+        psbt.inputs[1].redeem_script = Some(f.redeem_script.clone());
+        psbt.inputs[2].witness_utxo = Some(f.funding.output[2].clone());
+        psbt.inputs[2].witness_script = Some(f.witness_script.clone());
+
+        if with_keypaths {
+            let origin = |i: usize| (f.pubkeys[i], (fingerprint, f.paths[i].clone()));
+            psbt.inputs[0].hd_keypaths.extend(vec![origin(0)]);
+            psbt.inputs[1].hd_keypaths.extend(vec![origin(1)]);
+            psbt.inputs[2].hd_keypaths.extend(vec![origin(0), origin(1)]);
+        }
+        psbt
+    }
+
+    /// Assemble the partial signatures by hand and run the spending
+    /// transaction through the script interpreter.
+    fn assert_valid(f: &Fixture, psbt: &PartiallySignedTransaction) {
+        let secp = Secp256k1::new();
+        let mut tx = psbt.global.unsigned_tx.clone();
+        let sig = |input: usize, key: usize| psbt.inputs[input].partial_sigs[&f.pubkeys[key]].clone();
+
+        tx.input[0].script_sig = Builder::new()
+            .push_slice(&sig(0, 0))
+            .push_key(&f.pubkeys[0])
+            .into_script();
+        tx.input[1].script_sig = Builder::new().push_slice(f.redeem_script.as_bytes()).into_script();
+        tx.input[1].witness = vec![sig(1, 1), f.pubkeys[1].to_bytes()];
+        tx.input[2].witness = vec![vec![], sig(2, 0), sig(2, 1), f.witness_script.to_bytes()];
+
+        for (index, out) in f.funding.output.iter().enumerate() {
+            verify_input(&secp, &tx, index, &out.script_pubkey, out.value, VerifyFlags::STANDARD).unwrap();
+        }
+    }
+
+    #[test]
+    fn sign_with_xpriv() {
+        let secp = Secp256k1::new();
+        let f = fixture();
+        let mut psbt = psbt(&f, true);
+
+        assert_eq!(psbt.sign(&secp, &f.master).unwrap(), 4);
+        assert_valid(&f, &psbt);
+
+        // A different master key finds nothing to sign
+        let other = ExtendedPrivKey::new_master(Network::Bitcoin, &[8; 32]).unwrap();
+        let mut unsigned = self::psbt(&f, true);
+        assert_eq!(unsigned.sign(&secp, &other).unwrap(), 0);
+        assert!(unsigned.inputs.iter().all(|input| input.partial_sigs.is_empty()));
+
+        // Without key origins an extended key has nothing to go on
+        let mut unsigned = self::psbt(&f, false);
+        assert_eq!(unsigned.sign(&secp, &f.master).unwrap(), 0);
+    }
+
+    #[test]
+    fn sign_with_private_keys() {
+        let secp = Secp256k1::new();
+        let f = fixture();
+
+        // Keys are found in the scripts being signed
+        let mut psbt = psbt(&f, false);
+        assert_eq!(psbt.sign(&secp, &f.keys[..]).unwrap(), 4);
+        assert_valid(&f, &psbt);
+
+        let mut with_keypaths = self::psbt(&f, true);
+        assert_eq!(with_keypaths.sign(&secp, &f.keys[..]).unwrap(), 4);
+        assert_eq!(psbt.inputs, with_keypaths.inputs.iter().map(|input| {
+            let mut input = input.clone();
+            input.hd_keypaths.clear();
+            input
+        }).collect::<Vec<_>>());
+
+        // A single key only signs where it is used
+        let mut psbt = self::psbt(&f, false);
+        assert_eq!(psbt.sign_input(&secp, 2, &f.keys[1]).unwrap(), 1);
+        assert_eq!(psbt.sign_input(&secp, 0, &f.keys[1]).unwrap(), 0);
+        assert!(psbt.inputs[2].partial_sigs.contains_key(&f.pubkeys[1]));
+        assert!(psbt.inputs[0].partial_sigs.is_empty());
+        assert!(psbt.inputs[1].partial_sigs.is_empty());
+    }
+
+    #[test]
+    fn sign_with_sighash_type() {
+        let secp = Secp256k1::new();
+        let f = fixture();
+        let mut psbt = psbt(&f, true);
+        for input in psbt.inputs.iter_mut() {
+            input.sighash_type = Some(SigHashType::SinglePlusAnyoneCanPay);
+        }
+        // SIGHASH_SINGLE only commits to the output at the same index
+        psbt.global.unsigned_tx.output = vec![psbt.global.unsigned_tx.output[0].clone(); 3];
+        psbt.outputs = vec![Default::default(); 3];
+
+        assert_eq!(psbt.sign(&secp, &f.master).unwrap(), 4);
+        for input in &psbt.inputs {
+            for sig in input.partial_sigs.values() {
+                assert_eq!(*sig.last().unwrap(), 0x83);
+            }
+        }
+        assert_valid(&f, &psbt);
+    }
+
+    #[test]
+    fn skip_finalized_and_unknown() {
+        let secp = Secp256k1::new();
+        let f = fixture();
+        let mut psbt = psbt(&f, true);
+        psbt.inputs[0].final_script_sig = Some(Script::new());
+        psbt.inputs[1] = Default::default();
+        assert_eq!(psbt.sign(&secp, &f.master).unwrap(), 2);
+        assert!(psbt.inputs[0].partial_sigs.is_empty());
+        assert!(psbt.inputs[1].partial_sigs.is_empty());
+        assert_eq!(psbt.inputs[2].partial_sigs.len(), 2);
+    }
+
+    #[test]
+    fn refuse_inconsistent_utxo() {
+        let secp = Secp256k1::new();
+        let f = fixture();
+
+        // Keys but no UTXO
+        let mut psbt = psbt(&f, true);
+        psbt.inputs[0].non_witness_utxo = None;
+        match psbt.sign(&secp, &f.master) {
+            Err(Error::MissingUtxo) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // A legacy input with only a witness UTXO
+        let mut psbt = self::psbt(&f, true);
+        psbt.inputs[0].non_witness_utxo = None;
+        psbt.inputs[0].witness_utxo = Some(f.funding.output[0].clone());
+        match psbt.sign_input(&secp, 0, &f.master) {
+            Err(Error::MissingUtxo) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // Previous transaction which is not the one being spent
+        let mut psbt = self::psbt(&f, true);
+        let mut wrong = f.funding.clone();
+        wrong.lock_time = 1;
+        psbt.inputs[0].non_witness_utxo = Some(wrong);
+        match psbt.sign_input(&secp, 0, &f.master) {
+            Err(Error::UtxoMismatch) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // Witness UTXO disagreeing with the previous transaction
+        let mut psbt = self::psbt(&f, true);
+        psbt.inputs[2].non_witness_utxo = Some(f.funding.clone());
+        psbt.inputs[2].witness_utxo.as_mut().unwrap().value += 1;
+        match psbt.sign_input(&secp, 2, &f.master) {
+            Err(Error::UtxoMismatch) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // Redeem script not matching the P2SH output
+        let mut psbt = self::psbt(&f, true);
+        psbt.inputs[1].redeem_script = Some(f.witness_script.to_v0_p2wsh());
+        match psbt.sign_input(&secp, 1, &f.master) {
+            Err(Error::ScriptMismatch) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // Missing scripts
+        let mut psbt = self::psbt(&f, true);
+        psbt.inputs[1].redeem_script = None;
+        psbt.inputs[2].witness_script = None;
+        match psbt.sign_input(&secp, 1, &f.master) {
+            Err(Error::MissingRedeemScript) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+        match psbt.sign_input(&secp, 2, &f.master) {
+            Err(Error::MissingWitnessScript) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(psbt.inputs.iter().all(|input| input.partial_sigs.is_empty()));
+    }
+
+    #[test]
+    fn ignore_inputs_of_other_signers() {
+        let secp = Secp256k1::new();
+        let f = fixture();
+
+        // Inputs without our keys are not checked
+        let mut psbt = psbt(&f, false);
+        psbt.inputs[0].non_witness_utxo = None;
+        psbt.inputs[0].witness_utxo = Some(f.funding.output[0].clone());
+        psbt.inputs[1].redeem_script = None;
+        psbt.inputs[1].witness_utxo = None;
+        assert_eq!(psbt.sign(&secp, &f.keys[1]).unwrap(), 1);
+        assert!(psbt.inputs[0].partial_sigs.is_empty());
+        assert!(psbt.inputs[1].partial_sigs.is_empty());
+        assert!(psbt.inputs[2].partial_sigs.contains_key(&f.pubkeys[1]));
+    }
+
+    #[test]
+    fn no_partial_signing_on_error() {
+        let secp = Secp256k1::new();
+        let f = fixture();
+
+        // The last input fails after the first ones could be signed
+        let mut psbt = psbt(&f, true);
+        psbt.inputs[2].witness_script = None;
+        match psbt.sign(&secp, &f.master) {
+            Err(Error::MissingWitnessScript) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(psbt.inputs.iter().all(|input| input.partial_sigs.is_empty()));
+    }
+}
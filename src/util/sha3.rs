@@ -0,0 +1,107 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! SHA3
+//!
+//! A plain implementation of SHA3-256, which Tor v3 onion addresses use for
+//! their checksum. It is private to the crate and not meant for hashing large
+//! amounts of data.
+//!
+
+use byteorder::{ByteOrder, LittleEndian};
+
+const KECCAK_ROUND_CONSTANTS: [u64; 24] = [
+    0x0000000000000001, 0x0000000000008082, 0x800000000000808a, 0x8000000080008000,
+    0x000000000000808b, 0x0000000080000001, 0x8000000080008081, 0x8000000000008009,
+    0x000000000000008a, 0x0000000000000088, 0x0000000080008009, 0x000000008000000a,
+    0x000000008000808b, 0x800000000000008b, 0x8000000000008089, 0x8000000000008003,
+    0x8000000000008002, 0x8000000000000080, 0x000000000000800a, 0x800000008000000a,
+    0x8000000080008081, 0x8000000000008080, 0x0000000080000001, 0x8000000080008008,
+];
+const KECCAK_ROTATIONS: [u32; 24] = [1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44];
+const KECCAK_PI_LANES: [usize; 24] = [10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1];
+
+fn keccak_f(state: &mut [u64; 25]) {
+    for round_constant in KECCAK_ROUND_CONSTANTS.iter() {
+        // theta
+        let mut c = [0u64; 5];
+        for x in 0..5 {
+            c[x] = state[x] ^ state[x + 5] ^ state[x + 10] ^ state[x + 15] ^ state[x + 20];
+        }
+        for x in 0..5 {
+            let d = c[(x + 4) % 5] ^ c[(x + 1) % 5].rotate_left(1);
+            for y in 0..5 {
+                state[y * 5 + x] ^= d;
+            }
+        }
+        // rho and pi
+        let mut last = state[1];
+        for i in 0..24 {
+            let lane = KECCAK_PI_LANES[i];
+            let tmp = state[lane];
+            state[lane] = last.rotate_left(KECCAK_ROTATIONS[i]);
+            last = tmp;
+        }
+        // chi
+        for y in 0..5 {
+            let mut row = [0u64; 5];
+            row.copy_from_slice(&state[y * 5..y * 5 + 5]);
+            for x in 0..5 {
+                state[y * 5 + x] = row[x] ^ (!row[(x + 1) % 5] & row[(x + 2) % 5]);
+            }
+        }
+        // iota
+        state[0] ^= *round_constant;
+    }
+}
+
+/// SHA3-256 of `data`, as defined in FIPS 202
+pub(crate) fn sha3_256(data: &[u8]) -> [u8; 32] {
+    const RATE: usize = 136;
+    let mut padded = data.to_vec();
+    padded.push(0x06);
+    while padded.len() % RATE != 0 {
+        padded.push(0);
+    }
+    let last = padded.len() - 1;
+    padded[last] |= 0x80;
+
+    let mut state = [0u64; 25];
+    for block in padded.chunks(RATE) {
+        for i in 0..RATE / 8 {
+            state[i] ^= LittleEndian::read_u64(&block[i * 8..i * 8 + 8]);
+        }
+        keccak_f(&mut state);
+    }
+    let mut ret = [0; 32];
+    for i in 0..4 {
+        LittleEndian::write_u64(&mut ret[i * 8..i * 8 + 8], state[i]);
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use hex::encode as hex_encode;
+
+    use super::sha3_256;
+
+    #[test]
+    fn sha3_test() {
+        assert_eq!(hex_encode(sha3_256(b"")), "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a");
+        assert_eq!(hex_encode(sha3_256(b"abc")), "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532");
+        // more than one block
+        assert_eq!(hex_encode(sha3_256(&[0x61; 200])), "cce34485baf2bf2aca99b94833892a4f52896d3d153f7b840cc4f9fe695f1387");
+    }
+}
@@ -0,0 +1,422 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! BIP325 Signet
+//!
+//! Signet is a test network where blocks are valid only if they are signed
+//! by the network's challenge script, as described at
+//! https://github.com/bitcoin/bips/blob/master/bip-0325.mediawiki
+//!
+//! `Network::Signet` is the default signet. A custom signet shares its
+//! genesis block, consensus parameters and address prefixes, but has its own
+//! challenge and message magic; it is represented by a `Signet` built from
+//! that challenge.
+//!
+//! The block solution is a scriptSig and witness spending the challenge. It
+//! is pushed, after the 4-byte `SIGNET_HEADER`, into the coinbase output
+//! carrying the witness commitment. It signs a virtual transaction which
+//! commits to the block header fields other than the nonce and bits, and to
+//! the merkle root of the block with the solution removed.
+//!
+
+use std::{error, fmt};
+use std::io::Cursor;
+
+use secp256k1::{Secp256k1, Verification};
+
+use blockdata::block::Block;
+use blockdata::constants::genesis_block;
+use blockdata::interpreter::{self, VerifyFlags};
+use blockdata::opcodes;
+use blockdata::script::{Builder, Instruction, Script};
+use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+use consensus::encode::{self, serialize, Decodable, Encodable};
+use consensus::params::Params;
+use hashes::{sha256d, Hash};
+use network::chainparams::ChainParams;
+use network::constants::Network;
+use util::hash::{bitcoin_merkle_root, BitcoinHash};
+use util::misc::hex_bytes;
+
+/// The bytes which start the push of the block solution in the coinbase
+pub const SIGNET_HEADER: [u8; 4] = [0xec, 0xc7, 0xda, 0xa2];
+
+/// The challenge of the default signet, a 1-of-2 multisig
+const DEFAULT_CHALLENGE: &'static str = "512103ad5e0edad18cb1f0fc0d28a3d4f1f3e445640337489abb10404f2d1e086be430210359ef5021964fe22d6f8e05b2463c9540ce96883fe3b278760f048f5189f2e6c452ae";
+
+/// Signet block solution errors
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The block has no transactions
+    NoCoinbase,
+    /// The coinbase has no witness commitment output
+    NoWitnessCommitment,
+    /// The block solution is not a scriptSig followed by a witness
+    InvalidSolution,
+    /// The block solution does not satisfy the challenge
+    Script(interpreter::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Script(ref e) => write!(f, "{}: {}", error::Error::description(self), e),
+            Error::NoCoinbase
+            | Error::NoWitnessCommitment
+            | Error::InvalidSolution => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Script(ref e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::NoCoinbase => "block has no coinbase",
+            Error::NoWitnessCommitment => "coinbase has no witness commitment",
+            Error::InvalidSolution => "malformed signet block solution",
+            Error::Script(_) => "signet block solution does not satisfy the challenge",
+        }
+    }
+}
+
+#[doc(hidden)]
+impl From<interpreter::Error> for Error {
+    fn from(e: interpreter::Error) -> Error {
+        Error::Script(e)
+    }
+}
+
+/// The virtual transactions a block solution is checked with
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SignetTxs {
+    /// Transaction whose only output is the challenge, and whose input
+    /// commits to the block
+    pub to_spend: Transaction,
+    /// Transaction spending `to_spend`, with the block solution as its
+    /// scriptSig and witness
+    pub to_sign: Transaction,
+}
+
+/// A signet, defined by its challenge script
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Signet {
+    challenge: Script,
+}
+
+impl Default for Signet {
+    fn default() -> Signet {
+        Signet::new(Script::from(hex_bytes(DEFAULT_CHALLENGE).unwrap()))
+    }
+}
+
+impl Signet {
+    /// A signet whose blocks must satisfy `challenge`
+    pub fn new(challenge: Script) -> Signet {
+        Signet {
+            challenge: challenge,
+        }
+    }
+
+    /// The challenge script of this signet
+    pub fn challenge(&self) -> &Script {
+        &self.challenge
+    }
+
+    /// The network magic of this signet: the first four bytes of the double
+    /// SHA256 of the serialized challenge, read little-endian like
+    /// `Network::magic`.
+    pub fn magic(&self) -> u32 {
+        let hash = sha256d::Hash::hash(&serialize(&self.challenge));
+        encode::deserialize(&hash[0..4]).unwrap()
+    }
+
+    /// The consensus parameters, which are the same for every signet
+    pub fn params(&self) -> Params {
+        Params::new(Network::Signet)
+    }
+
+    /// The genesis block, which is the same for every signet
+    pub fn genesis_block(&self) -> Block {
+        genesis_block(Network::Signet)
+    }
+
+    /// The chain parameters of this signet, those of `Network::Signet` with
+    /// the magic of this challenge. The DNS seeds are only kept for the
+    /// default signet.
+    pub fn chain_params(&self) -> ChainParams {
+        let mut params = ChainParams::new(Network::Signet);
+        if *self != Signet::default() {
+            params.magic = self.magic();
+            params.dns_seeds.clear();
+        }
+        params
+    }
+
+    /// Build the virtual transactions checking the solution of `block`. A
+    /// block without a solution is checked with an empty scriptSig and
+    /// witness, which allows trivially true challenges.
+    pub fn signet_txs(&self, block: &Block) -> Result<SignetTxs, Error> {
+        if block.txdata.is_empty() {
+            return Err(Error::NoCoinbase);
+        }
+        let mut coinbase = block.txdata[0].clone();
+        let idx = match witness_commitment_index(&coinbase) {
+            Some(idx) => idx,
+            None => return Err(Error::NoWitnessCommitment),
+        };
+
+        let mut script_sig = Script::new();
+        let mut witness = vec![];
+        if let Some((stripped, solution)) = take_solution(&coinbase.output[idx].script_pubkey) {
+            let mut decoder = Cursor::new(&solution[..]);
+            script_sig = Decodable::consensus_decode(&mut decoder).map_err(|_| Error::InvalidSolution)?;
+            witness = Decodable::consensus_decode(&mut decoder).map_err(|_| Error::InvalidSolution)?;
+            if decoder.position() != solution.len() as u64 {
+                return Err(Error::InvalidSolution);
+            }
+            coinbase.output[idx].script_pubkey = stripped;
+        }
+
+        let mut txids = vec![coinbase.txid()];
+        txids.extend(block.txdata[1..].iter().map(|tx| tx.txid()));
+        let merkle_root = bitcoin_merkle_root(txids);
+
+        let mut block_data = vec![];
+        block.header.version.consensus_encode(&mut block_data).unwrap();
+        block.header.prev_blockhash.consensus_encode(&mut block_data).unwrap();
+        merkle_root.consensus_encode(&mut block_data).unwrap();
+        block.header.time.consensus_encode(&mut block_data).unwrap();
+
+        let to_spend = Transaction {
+            version: 0,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Builder::new()
+                    .push_opcode(opcodes::all::OP_PUSHBYTES_0)
+                    .push_slice(&block_data)
+                    .into_script(),
+                sequence: 0,
+                witness: vec![],
+            }],
+            output: vec![TxOut {
+                value: 0,
+                script_pubkey: self.challenge.clone(),
+            }],
+        };
+        let to_sign = Transaction {
+            version: 0,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: to_spend.txid(),
+                    vout: 0,
+                },
+                script_sig: script_sig,
+                sequence: 0,
+                witness: witness,
+            }],
+            output: vec![TxOut {
+                value: 0,
+                script_pubkey: Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(),
+            }],
+        };
+        Ok(SignetTxs {
+            to_spend: to_spend,
+            to_sign: to_sign,
+        })
+    }
+
+    /// Check that the solution of `block` satisfies the challenge. The
+    /// genesis block needs no solution.
+    pub fn check_block_solution<C: Verification>(&self, secp: &Secp256k1<C>, block: &Block) -> Result<(), Error> {
+        if block.bitcoin_hash() == self.genesis_block().bitcoin_hash() {
+            return Ok(());
+        }
+        let txs = self.signet_txs(block)?;
+        let flags = VerifyFlags::P2SH | VerifyFlags::DERSIG | VerifyFlags::NULLDUMMY | VerifyFlags::WITNESS;
+        interpreter::verify_input(secp, &txs.to_sign, 0, &self.challenge, 0, flags)?;
+        Ok(())
+    }
+}
+
+/// The index of the coinbase output carrying the witness commitment
+fn witness_commitment_index(coinbase: &Transaction) -> Option<usize> {
+    coinbase.output.iter().rposition(|o| {
+        o.script_pubkey.len() >= 38 &&
+        o.script_pubkey[0..6] == [0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed]
+    })
+}
+
+/// Find the first push starting with `SIGNET_HEADER` and followed by more
+/// data, returning the script with the push cut back to the header and the
+/// data after the header
+fn take_solution(script: &Script) -> Option<(Script, Vec<u8>)> {
+    let mut builder = Builder::new();
+    let mut solution = None;
+    for instruction in script.iter(false) {
+        match instruction {
+            Instruction::PushBytes(data) => {
+                if solution.is_none() && data.len() > SIGNET_HEADER.len() && data[..SIGNET_HEADER.len()] == SIGNET_HEADER {
+                    solution = Some(data[SIGNET_HEADER.len()..].to_vec());
+                    builder = builder.push_slice(&SIGNET_HEADER);
+                } else {
+                    builder = builder.push_slice(data);
+                }
+            }
+            Instruction::Op(op) => builder = builder.push_opcode(op),
+            Instruction::Error(_) => break,
+        }
+    }
+    solution.map(|solution| (builder.into_script(), solution))
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::{Message, Secp256k1, SecretKey};
+
+    use blockdata::block::{Block, BlockHeader};
+    use blockdata::opcodes;
+    use blockdata::script::{Builder, Script};
+    use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+    use consensus::encode::serialize;
+    use network::constants::Network;
+    use util::hash::bitcoin_merkle_root;
+    use util::key::PublicKey;
+
+    use super::{Error, Signet, SIGNET_HEADER};
+
+    /// A block with a coinbase whose witness commitment output ends with
+    /// `extra`, and one more transaction
+    fn block(extra: &[u8]) -> Block {
+        let mut commitment = vec![0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
+        commitment.extend(&[0x11; 32]);
+        if !extra.is_empty() {
+            commitment.extend(Builder::new().push_slice(extra).into_script().as_bytes());
+        }
+        let coinbase = Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Builder::new().push_int(1).push_int(2).into_script(),
+                sequence: 0xffffffff,
+                witness: vec![vec![0; 32]],
+            }],
+            output: vec![
+                TxOut { value: 50 * 100_000_000, script_pubkey: Builder::new().push_opcode(opcodes::all::OP_PUSHNUM_1).into_script() },
+                TxOut { value: 0, script_pubkey: Script::from(commitment) },
+            ],
+        };
+        let spend = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: Default::default(), vout: 0 },
+                script_sig: Script::new(),
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            output: vec![TxOut { value: 1, script_pubkey: Script::new() }],
+        };
+        let txdata = vec![coinbase, spend];
+        Block {
+            header: BlockHeader {
+                version: 0x20000000,
+                prev_blockhash: Default::default(),
+                merkle_root: bitcoin_merkle_root(txdata.iter().map(|tx| tx.txid()).collect()),
+                time: 1598918500,
+                bits: 0x1e0377ae,
+                nonce: 0,
+            },
+            txdata: txdata,
+        }
+    }
+
+    #[test]
+    fn magic_test() {
+        assert_eq!(Signet::default().magic(), Network::Signet.magic());
+        let custom = Signet::new(Builder::new().push_opcode(opcodes::all::OP_PUSHNUM_1).into_script());
+        assert!(custom.magic() != Network::Signet.magic());
+        assert_eq!(custom.genesis_block(), Signet::default().genesis_block());
+        assert_eq!(custom.chain_params().magic, custom.magic());
+        assert!(custom.chain_params().dns_seeds.is_empty());
+        assert_eq!(Signet::default().chain_params().magic, Network::Signet.magic());
+    }
+
+    #[test]
+    fn trivial_challenge_test() {
+        let secp = Secp256k1::verification_only();
+        let signet = Signet::new(Builder::new().push_opcode(opcodes::all::OP_PUSHNUM_1).into_script());
+        assert_eq!(signet.check_block_solution(&secp, &block(&[])), Ok(()));
+        assert_eq!(signet.check_block_solution(&secp, &signet.genesis_block()), Ok(()));
+
+        let mut no_commitment = block(&[]);
+        no_commitment.txdata[0].output.pop();
+        assert_eq!(signet.check_block_solution(&secp, &no_commitment), Err(Error::NoWitnessCommitment));
+
+        // trailing data after the witness
+        let mut extra = SIGNET_HEADER.to_vec();
+        extra.extend(&[0x00, 0x00, 0x00]);
+        assert_eq!(signet.check_block_solution(&secp, &block(&extra)), Err(Error::InvalidSolution));
+
+        let signet = Signet::new(Builder::new().push_opcode(opcodes::all::OP_PUSHBYTES_0).into_script());
+        match signet.check_block_solution(&secp, &block(&[])) {
+            Err(Error::Script(_)) => {},
+            x => panic!("expected script failure, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn signed_block_test() {
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let pk = PublicKey { compressed: true, key: ::secp256k1::PublicKey::from_secret_key(&secp, &sk) };
+        let signet = Signet::new(Builder::new()
+            .push_key(&pk)
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script());
+
+        // the solution is not part of what is signed
+        let unsigned = block(&SIGNET_HEADER);
+        let txs = signet.signet_txs(&unsigned).unwrap();
+        let sighash = txs.to_sign.signature_hash(0, signet.challenge(), 1);
+        let mut sig = secp.sign(&Message::from_slice(&sighash[..]).unwrap(), &sk).serialize_der().to_vec();
+        sig.push(1);
+
+        let mut solution = SIGNET_HEADER.to_vec();
+        solution.extend(serialize(&Builder::new().push_slice(&sig).into_script()));
+        solution.extend(serialize(&Vec::<Vec<u8>>::new()));
+        let mut signed = block(&solution);
+        assert_eq!(signet.signet_txs(&signed).unwrap().to_spend, txs.to_spend);
+        assert_eq!(signet.check_block_solution(&secp, &signed), Ok(()));
+
+        signed.header.time += 1;
+        match signet.check_block_solution(&secp, &signed) {
+            Err(Error::Script(_)) => {},
+            x => panic!("expected script failure, got {:?}", x),
+        }
+        // the nonce is not signed, so blocks can be mined after signing
+        signed.header.time -= 1;
+        signed.header.nonce = 12345;
+        assert_eq!(signet.check_block_solution(&secp, &signed), Ok(()));
+    }
+}
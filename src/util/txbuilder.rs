@@ -0,0 +1,593 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Transaction Builder
+//!
+//! Building unsigned transactions which spend a set of UTXOs to a set of
+//! recipients at a given fee rate, sending what is left to a change script.
+//!
+//! The fee is computed from an estimate of the weight of the signed
+//! transaction, for which every UTXO comes with the weight its scriptSig and
+//! witness will add. Change which is not worth spending, by the dust rule of
+//! Bitcoin Core, is left to the fee instead. Without a change script, that is
+//! the dust value of a P2WPKH output.
+//!
+//! # Example
+//!
+//! ```rust
+//! use bitcoin::blockdata::transaction::{OutPoint, TxOut};
+//! use bitcoin::network::constants::Network;
+//! use bitcoin::util::address::Address;
+//! use bitcoin::util::amount::Amount;
+//! use bitcoin::util::key::PrivateKey;
+//! use bitcoin::util::txbuilder::{FeeRate, TxBuilder, Utxo, P2WPKH_SATISFACTION_WEIGHT};
+//! use bitcoin::secp256k1::Secp256k1;
+//!
+//! let secp = Secp256k1::new();
+//! let key = PrivateKey::from_wif("cVt4o7BGAig1UXywgGSmARhxMdzP5qvQsxKkSsc1XEkw3tDTQFpy").unwrap();
+//! let address = Address::p2wpkh(&key.public_key(&secp), Network::Testnet);
+//!
+//! let tx = TxBuilder::new()
+//!     .add_utxo(Utxo::new(OutPoint::default(), TxOut {
+//!         value: 100_000,
+//!         script_pubkey: address.script_pubkey(),
+//!     }, P2WPKH_SATISFACTION_WEIGHT))
+//!     .add_recipient(&address, Amount::from_sat(50_000))
+//!     .fee_rate(FeeRate::from_sat_per_vb(2))
+//!     .change_script(address.script_pubkey())
+//!     .enable_rbf()
+//!     .build_tx()
+//!     .unwrap();
+//! assert_eq!(tx.output.len(), 2);
+//! ```
+//!
+
+use std::{error, fmt};
+
+use blockdata::constants::WITNESS_SCALE_FACTOR;
+use blockdata::opcodes;
+use blockdata::script::{Builder, Script};
+use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+use consensus::encode::serialize;
+use util::address::Address;
+use util::amount::Amount;
+use util::psbt::PartiallySignedTransaction;
+
+/// Satisfaction weight of a P2PKH output: a scriptSig pushing a signature
+/// of at most 73 bytes and a compressed public key
+pub const P2PKH_SATISFACTION_WEIGHT: usize = 4 * (1 + 73 + 1 + 33);
+/// Satisfaction weight of a P2WPKH output: a witness of a signature of at
+/// most 73 bytes and a compressed public key
+pub const P2WPKH_SATISFACTION_WEIGHT: usize = 1 + 1 + 73 + 1 + 33;
+/// Satisfaction weight of a P2SH-P2WPKH output: the P2WPKH witness and a
+/// scriptSig pushing the 22-byte witness program
+pub const P2SH_P2WPKH_SATISFACTION_WEIGHT: usize = 4 * (1 + 22) + P2WPKH_SATISFACTION_WEIGHT;
+
+/// The fee rate of Bitcoin Core's dust rule, in satoshi per 1000 vbytes
+pub const DUST_RELAY_FEE: u64 = 3000;
+
+/// Sequence number of inputs signalling replaceability (BIP125)
+const SEQUENCE_RBF: u32 = 0xFFFFFFFD;
+/// Sequence number of inputs which enable the locktime
+const SEQUENCE_LOCKTIME: u32 = 0xFFFFFFFE;
+/// Sequence number of inputs which disable the locktime
+const SEQUENCE_FINAL: u32 = 0xFFFFFFFF;
+
+/// A fee rate, in satoshi per 1000 virtual bytes
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct FeeRate(u64);
+
+impl FeeRate {
+    /// A fee rate of `sat` satoshi per 1000 vbytes
+    pub fn from_sat_per_kvb(sat: u64) -> FeeRate {
+        FeeRate(sat)
+    }
+
+    /// A fee rate of `sat` satoshi per vbyte
+    pub fn from_sat_per_vb(sat: u64) -> FeeRate {
+        FeeRate(sat * 1000)
+    }
+
+    /// The fee rate in satoshi per 1000 vbytes
+    pub fn as_sat_per_kvb(self) -> u64 {
+        self.0
+    }
+
+    /// The fee of `vsize` vbytes, rounded up
+    pub fn fee_vsize(self, vsize: usize) -> Amount {
+        Amount::from_sat((self.0 * vsize as u64 + 999) / 1000)
+    }
+
+    /// The fee of `weight` weight units, rounded up
+    pub fn fee_weight(self, weight: usize) -> Amount {
+        let scale = WITNESS_SCALE_FACTOR as usize;
+        self.fee_vsize((weight + scale - 1) / scale)
+    }
+}
+
+impl fmt::Display for FeeRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} sat/kvB", self.0)
+    }
+}
+
+/// The smallest value an output paying to `script_pubkey` must have to not
+/// be dust: spending it must cost at most a third of its value at
+/// `DUST_RELAY_FEE`. Unspendable outputs are never dust.
+pub fn dust_value(script_pubkey: &Script) -> Amount {
+    if script_pubkey.is_provably_unspendable() {
+        return Amount::from_sat(0);
+    }
+    let output_size = serialize(&TxOut {
+        value: 0,
+        script_pubkey: script_pubkey.clone(),
+    }).len();
+    // outpoint, scriptSig length, sequence and the satisfaction of a
+    // P2PKH or P2WPKH output
+    let input_size = if script_pubkey.is_witness_program() {
+        32 + 4 + 1 + 107 / WITNESS_SCALE_FACTOR as usize + 4
+    } else {
+        32 + 4 + 1 + 107 + 4
+    };
+    FeeRate::from_sat_per_kvb(DUST_RELAY_FEE).fee_vsize(output_size + input_size)
+}
+
+/// Transaction builder errors
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// There is neither a recipient nor a change script
+    NoRecipients,
+    /// The UTXOs do not cover the recipients and the fee
+    InsufficientFunds {
+        /// The value of the recipients and the fee
+        needed: Amount,
+        /// The value of the UTXOs
+        available: Amount,
+    },
+    /// A recipient is paid less than the dust value of its script
+    DustOutput(Script),
+    /// The change would not be dust as a P2WPKH output, but there is no
+    /// change script
+    NoChangeScript(Amount),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InsufficientFunds { needed, available } => {
+                write!(f, "{}: needed {}, available {}", error::Error::description(self), needed, available)
+            }
+            Error::DustOutput(ref script) => write!(f, "{}: {}", error::Error::description(self), script),
+            Error::NoChangeScript(change) => write!(f, "{}: {}", error::Error::description(self), change),
+            Error::NoRecipients => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::NoRecipients => "no recipients nor change script",
+            Error::InsufficientFunds { .. } => "insufficient funds",
+            Error::DustOutput(_) => "recipient output is dust",
+            Error::NoChangeScript(_) => "change left but no change script",
+        }
+    }
+}
+
+/// An output to be spent, with what is needed to estimate the weight of its
+/// spend and to sign it
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Utxo {
+    /// The location of the output
+    pub outpoint: OutPoint,
+    /// The output
+    pub txout: TxOut,
+    /// The weight the scriptSig and witness satisfying the output add to an
+    /// input with empty ones: four times the scriptSig size, plus the size
+    /// of the witness with its item count
+    pub satisfaction_weight: usize,
+    /// The transaction of the output, needed to sign legacy spends
+    pub previous_tx: Option<Transaction>,
+    /// Whether the output is a P2SH-wrapped witness program, such as
+    /// P2SH-P2WPKH, which can not be told from its script
+    pub nested_segwit: bool,
+}
+
+impl Utxo {
+    /// A UTXO without its previous transaction
+    pub fn new(outpoint: OutPoint, txout: TxOut, satisfaction_weight: usize) -> Utxo {
+        Utxo {
+            outpoint: outpoint,
+            txout: txout,
+            satisfaction_weight: satisfaction_weight,
+            previous_tx: None,
+            nested_segwit: false,
+        }
+    }
+
+    /// Output `vout` of `previous_tx`
+    ///
+    /// # Panics
+    /// Panics if `vout` is out of range.
+    pub fn from_tx(previous_tx: Transaction, vout: u32, satisfaction_weight: usize) -> Utxo {
+        Utxo {
+            outpoint: OutPoint {
+                txid: previous_tx.txid(),
+                vout: vout,
+            },
+            txout: previous_tx.output[vout as usize].clone(),
+            satisfaction_weight: satisfaction_weight,
+            previous_tx: Some(previous_tx),
+            nested_segwit: false,
+        }
+    }
+
+    /// Whether the output is spent with a witness
+    fn is_segwit(&self) -> bool {
+        self.txout.script_pubkey.is_witness_program() || self.nested_segwit
+    }
+
+    /// Whether the output may be spent with a witness, in which case the
+    /// weight estimate accounts for the segwit serialization. Any P2SH output
+    /// may wrap a witness program, so these count too.
+    fn maybe_segwit(&self) -> bool {
+        self.is_segwit() || self.txout.script_pubkey.is_p2sh()
+    }
+}
+
+/// Builds a transaction spending all the UTXOs added to it. Recipients are
+/// paid in the order they are added, followed by the change output if any.
+#[derive(Clone, Debug)]
+pub struct TxBuilder {
+    utxos: Vec<Utxo>,
+    recipients: Vec<TxOut>,
+    fee_rate: FeeRate,
+    change_script: Option<Script>,
+    version: u32,
+    lock_time: u32,
+    rbf: bool,
+}
+
+impl Default for TxBuilder {
+    fn default() -> TxBuilder {
+        TxBuilder::new()
+    }
+}
+
+impl TxBuilder {
+    /// A builder of version 2 transactions, without locktime, paying
+    /// 1 sat/vbyte
+    pub fn new() -> TxBuilder {
+        TxBuilder {
+            utxos: vec![],
+            recipients: vec![],
+            fee_rate: FeeRate::from_sat_per_vb(1),
+            change_script: None,
+            version: 2,
+            lock_time: 0,
+            rbf: false,
+        }
+    }
+
+    /// Spend `utxo`
+    pub fn add_utxo(mut self, utxo: Utxo) -> TxBuilder {
+        self.utxos.push(utxo);
+        self
+    }
+
+    /// Pay `amount` to `address`
+    pub fn add_recipient(mut self, address: &Address, amount: Amount) -> TxBuilder {
+        self.recipients.push(TxOut {
+            value: amount.as_sat(),
+            script_pubkey: address.script_pubkey(),
+        });
+        self
+    }
+
+    /// Pay `amount` to `script_pubkey`
+    pub fn add_output(mut self, script_pubkey: Script, amount: Amount) -> TxBuilder {
+        self.recipients.push(TxOut {
+            value: amount.as_sat(),
+            script_pubkey: script_pubkey,
+        });
+        self
+    }
+
+    /// Set the fee rate
+    pub fn fee_rate(mut self, fee_rate: FeeRate) -> TxBuilder {
+        self.fee_rate = fee_rate;
+        self
+    }
+
+    /// Send the change to `script_pubkey`
+    pub fn change_script(mut self, script_pubkey: Script) -> TxBuilder {
+        self.change_script = Some(script_pubkey);
+        self
+    }
+
+    /// Set the transaction version
+    pub fn version(mut self, version: u32) -> TxBuilder {
+        self.version = version;
+        self
+    }
+
+    /// Set the locktime, which is enforced by giving the inputs a
+    /// non-final sequence number
+    pub fn lock_time(mut self, lock_time: u32) -> TxBuilder {
+        self.lock_time = lock_time;
+        self
+    }
+
+    /// Signal replaceability of the transaction (BIP125)
+    pub fn enable_rbf(mut self) -> TxBuilder {
+        self.rbf = true;
+        self
+    }
+
+    /// The estimated weight of `tx` once its inputs are satisfied
+    fn estimate_weight(&self, tx: &Transaction) -> usize {
+        let mut weight = tx.get_weight();
+        weight += self.utxos.iter().map(|u| u.satisfaction_weight).sum::<usize>();
+        if self.utxos.iter().any(Utxo::maybe_segwit) {
+            // marker, flag and the item count of empty witnesses
+            weight += 2 + self.utxos.iter().filter(|u| !u.maybe_segwit()).count();
+        }
+        weight
+    }
+
+    /// Build the unsigned transaction
+    pub fn build_tx(&self) -> Result<Transaction, Error> {
+        if self.recipients.is_empty() && self.change_script.is_none() {
+            return Err(Error::NoRecipients);
+        }
+        for out in &self.recipients {
+            if Amount::from_sat(out.value) < dust_value(&out.script_pubkey) {
+                return Err(Error::DustOutput(out.script_pubkey.clone()));
+            }
+        }
+
+        let sequence = if self.rbf {
+            SEQUENCE_RBF
+        } else if self.lock_time != 0 {
+            SEQUENCE_LOCKTIME
+        } else {
+            SEQUENCE_FINAL
+        };
+        let mut tx = Transaction {
+            version: self.version,
+            lock_time: self.lock_time,
+            input: self.utxos.iter().map(|utxo| TxIn {
+                previous_output: utxo.outpoint,
+                script_sig: Script::new(),
+                sequence: sequence,
+                witness: vec![],
+            }).collect(),
+            output: self.recipients.clone(),
+        };
+
+        let available = Amount::from_sat(self.utxos.iter().map(|u| u.txout.value).sum());
+        let sent = Amount::from_sat(self.recipients.iter().map(|o| o.value).sum());
+        let needed = sent + self.fee_rate.fee_weight(self.estimate_weight(&tx));
+        if available < needed || (available == needed && tx.output.is_empty()) {
+            return Err(Error::InsufficientFunds {
+                needed: needed,
+                available: available,
+            });
+        }
+
+        // without a change script, size the change as a P2WPKH output
+        let change_script = self.change_script.clone().unwrap_or_else(|| {
+            Builder::new().push_opcode(opcodes::all::OP_PUSHBYTES_0).push_slice(&[0; 20]).into_script()
+        });
+        let change_dust = dust_value(&change_script);
+        tx.output.push(TxOut {
+            value: 0,
+            script_pubkey: change_script,
+        });
+        let needed_with_change = sent + self.fee_rate.fee_weight(self.estimate_weight(&tx));
+        let change = if available > needed_with_change {
+            available - needed_with_change
+        } else {
+            Amount::from_sat(0)
+        };
+
+        if change >= change_dust && change.as_sat() > 0 {
+            if self.change_script.is_none() {
+                // a change output would be worth spending, so don't give
+                // it to the fee
+                return Err(Error::NoChangeScript(change));
+            }
+            tx.output.last_mut().expect("change output").value = change.as_sat();
+        } else {
+            tx.output.pop();
+            if tx.output.is_empty() {
+                return Err(Error::InsufficientFunds {
+                    needed: needed_with_change + change_dust,
+                    available: available,
+                });
+            }
+        }
+        Ok(tx)
+    }
+
+    /// Build a PSBT of the unsigned transaction, with the UTXO of every
+    /// input: the previous transaction if known, and the output for native
+    /// witness programs and UTXOs marked as `nested_segwit`.
+    pub fn build_psbt(&self) -> Result<PartiallySignedTransaction, Error> {
+        let tx = self.build_tx()?;
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx)
+            .expect("the transaction is unsigned");
+        for (input, utxo) in psbt.inputs.iter_mut().zip(self.utxos.iter()) {
+            if utxo.is_segwit() {
+                input.witness_utxo = Some(utxo.txout.clone());
+            }
+            input.non_witness_utxo = utxo.previous_tx.clone();
+        }
+        Ok(psbt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::Secp256k1;
+
+    use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+    use network::constants::Network;
+    use util::address::Address;
+    use util::amount::Amount;
+    use util::key::PrivateKey;
+
+    use super::{dust_value, Error, FeeRate, TxBuilder, Utxo, P2PKH_SATISFACTION_WEIGHT,
+                P2SH_P2WPKH_SATISFACTION_WEIGHT, P2WPKH_SATISFACTION_WEIGHT};
+
+    fn key() -> PrivateKey {
+        PrivateKey::from_wif("cVt4o7BGAig1UXywgGSmARhxMdzP5qvQsxKkSsc1XEkw3tDTQFpy").unwrap()
+    }
+
+    fn funding_tx(addresses: &[Address], value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                script_sig: Default::default(),
+                sequence: 0xFFFFFFFF,
+                witness: vec![],
+            }],
+            output: addresses.iter().map(|a| TxOut {
+                value: value,
+                script_pubkey: a.script_pubkey(),
+            }).collect(),
+        }
+    }
+
+    #[test]
+    fn dust_test() {
+        let pk = key().public_key(&Secp256k1::new());
+        assert_eq!(dust_value(&Address::p2pkh(&pk, Network::Bitcoin).script_pubkey()), Amount::from_sat(546));
+        assert_eq!(dust_value(&Address::p2wpkh(&pk, Network::Bitcoin).script_pubkey()), Amount::from_sat(294));
+        assert_eq!(FeeRate::from_sat_per_vb(2).fee_weight(561), Amount::from_sat(282));
+        assert_eq!(FeeRate::from_sat_per_kvb(1500).fee_vsize(141), Amount::from_sat(212));
+    }
+
+    #[test]
+    fn build_and_sign() {
+        let secp = Secp256k1::new();
+        let key = key();
+        let pk = key.public_key(&secp);
+        let addresses = [Address::p2pkh(&pk, Network::Testnet), Address::p2shwpkh(&pk, Network::Testnet),
+                         Address::p2wpkh(&pk, Network::Testnet)];
+        let funding = funding_tx(&addresses, 100_000);
+        let recipient = Address::p2wsh(&addresses[0].script_pubkey(), Network::Testnet);
+        let fee_rate = FeeRate::from_sat_per_vb(10);
+
+        let mut nested = Utxo::from_tx(funding.clone(), 1, P2SH_P2WPKH_SATISFACTION_WEIGHT);
+        nested.nested_segwit = true;
+        let builder = TxBuilder::new()
+            .add_utxo(Utxo::from_tx(funding.clone(), 0, P2PKH_SATISFACTION_WEIGHT))
+            .add_utxo(nested)
+            .add_utxo(Utxo::new(OutPoint { txid: funding.txid(), vout: 2 }, funding.output[2].clone(), P2WPKH_SATISFACTION_WEIGHT))
+            .add_recipient(&recipient, Amount::from_sat(250_000))
+            .fee_rate(fee_rate)
+            .change_script(addresses[2].script_pubkey());
+        let tx = builder.build_tx().unwrap();
+        assert_eq!(tx.output.len(), 2);
+        assert_eq!(tx.output[0].script_pubkey, recipient.script_pubkey());
+        assert_eq!(tx.output[1].script_pubkey, addresses[2].script_pubkey());
+        assert!(tx.input.iter().all(|i| i.sequence == 0xFFFFFFFF));
+        let fee = 300_000 - 250_000 - tx.output[1].value;
+
+        // an unmarked P2SH output is not assumed to be segwit
+        let unmarked = builder.clone()
+            .add_utxo(Utxo::from_tx(funding.clone(), 1, P2SH_P2WPKH_SATISFACTION_WEIGHT))
+            .build_psbt()
+            .unwrap();
+        assert!(unmarked.inputs[3].witness_utxo.is_none());
+        assert_eq!(unmarked.inputs[3].non_witness_utxo.as_ref(), Some(&funding));
+
+        let mut psbt = builder.build_psbt().unwrap();
+        assert_eq!(psbt.global.unsigned_tx, tx);
+        assert!(psbt.inputs[0].witness_utxo.is_none());
+        assert_eq!(psbt.inputs[1].witness_utxo.as_ref(), Some(&funding.output[1]));
+        assert!(psbt.inputs[2].non_witness_utxo.is_none());
+        psbt.inputs[1].redeem_script = Some(addresses[2].script_pubkey());
+        assert_eq!(psbt.sign(&secp, &key).unwrap(), 3);
+        psbt.finalize().unwrap();
+        let signed = psbt.extract_tx();
+
+        // the estimate covers the signed transaction, by at most a few bytes
+        let actual = fee_rate.fee_weight(signed.get_weight()).as_sat();
+        assert!(fee >= actual && fee <= actual + 50, "fee {} for {}", fee, actual);
+    }
+
+    #[test]
+    fn change_handling() {
+        let pk = key().public_key(&Secp256k1::new());
+        let addresses = [Address::p2wpkh(&pk, Network::Testnet)];
+        let address = &addresses[0];
+        let funding = funding_tx(&addresses, 10_000);
+        let builder = TxBuilder::new()
+            .add_utxo(Utxo::from_tx(funding, 0, P2WPKH_SATISFACTION_WEIGHT))
+            .fee_rate(FeeRate::from_sat_per_vb(1))
+            .lock_time(600_000);
+
+        // 110 vbytes without change, 141 with
+        let tx = builder.clone().add_recipient(address, Amount::from_sat(9_500)).build_tx();
+        assert_eq!(tx, Err(Error::NoChangeScript(Amount::from_sat(359))));
+        let tx = builder.clone()
+            .add_recipient(address, Amount::from_sat(9_500))
+            .change_script(address.script_pubkey())
+            .build_tx()
+            .unwrap();
+        assert_eq!(tx.output[1].value, 359);
+        assert_eq!(tx.input[0].sequence, 0xFFFFFFFE);
+        assert_eq!(tx.lock_time, 600_000);
+
+        // change of 9 below dust goes to the fee
+        let tx = builder.clone()
+            .add_recipient(address, Amount::from_sat(9_600))
+            .change_script(address.script_pubkey())
+            .enable_rbf()
+            .build_tx()
+            .unwrap();
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.input[0].sequence, 0xFFFFFFFD);
+
+        // without a change script, change below the P2WPKH dust value of 294
+        // goes to the fee too
+        let tx = builder.clone().add_recipient(address, Amount::from_sat(9_570)).build_tx().unwrap();
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].value, 9_570);
+
+        match builder.clone().add_recipient(address, Amount::from_sat(9_900)).build_tx() {
+            Err(Error::InsufficientFunds { needed, available }) => {
+                assert_eq!((needed.as_sat(), available.as_sat()), (10_010, 10_000));
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        assert_eq!(builder.clone().add_recipient(address, Amount::from_sat(100)).build_tx(),
+                   Err(Error::DustOutput(address.script_pubkey())));
+        assert_eq!(builder.clone().build_tx(), Err(Error::NoRecipients));
+
+        // sweep to the change script
+        let tx = builder.change_script(address.script_pubkey()).build_tx().unwrap();
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].value, 10_000 - 110);
+    }
+}